@@ -6,7 +6,7 @@ use defmt::info;
 use defmt_rtt as _;
 use embassy_buddy::{
     BoardBuilder, BuddyDisplay, BuddyFilamentSensor, BuddyPinda, BuddyRotaryButton,
-    BuddyRotaryEncoder, BuddyStepperExti, BuddyThermistor, Direction,
+    BuddyRotaryEncoder, BuddyStepperExti, BuddyThermistor, Direction, NetworkConfig,
 };
 use embassy_executor::Spawner;
 use embassy_futures::join::{join3, join5};
@@ -55,7 +55,7 @@ async fn main(spawner: Spawner) {
     info!("Booting...");
     let mac_addr = [0x00, 0x00, 0xDE, 0xAD, 0xBE, 0xEF];
     let board = BoardBuilder::new()
-        .ethernet(&spawner, mac_addr)
+        .ethernet(&spawner, mac_addr, NetworkConfig::default())
         .build()
         .await;
 
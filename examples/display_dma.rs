@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_buddy::BoardBuilder;
+use embassy_executor::Spawner;
+use embassy_time::{Instant, Timer};
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*, primitives::Rectangle};
+use panic_probe as _;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    info!("Booting...");
+    let board = BoardBuilder::default().display(true).build().await;
+    let display = board.display.unwrap();
+    let mut display = display.lock().await;
+
+    let screen = Rectangle::new(Point::zero(), Size::new(240, 320));
+    let start = Instant::now();
+    display.fill_region(screen, Rgb565::BLACK).await.unwrap();
+    info!("Cleared the screen in {}ms", start.elapsed().as_millis());
+
+    // A 40x40 checkerboard tile, which divides the screen evenly.
+    let mut tile = [Rgb565::BLACK; 40 * 40];
+    for (i, pixel) in tile.iter_mut().enumerate() {
+        if ((i % 40) / 10 + (i / 40) / 10) % 2 == 0 {
+            *pixel = Rgb565::CSS_ORANGE;
+        }
+    }
+
+    loop {
+        for y in (0..320).step_by(40) {
+            for x in (0..240).step_by(40) {
+                let area = Rectangle::new(Point::new(x, y), Size::new(40, 40));
+                display.blit(area, &tile).await.unwrap();
+            }
+        }
+        Timer::after_secs(1).await;
+        display.fill_region(screen, Rgb565::BLACK).await.unwrap();
+        Timer::after_secs(1).await;
+    }
+}
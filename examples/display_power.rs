@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_buddy::{BoardBuilder, ui::IdleTimer};
+use embassy_executor::Spawner;
+use embassy_futures::{
+    join::join3,
+    select::{Either, select},
+};
+use embassy_time::{Duration, Timer};
+use embedded_graphics::{
+    mono_font::{MonoTextStyle, ascii::FONT_10X20},
+    pixelcolor::Rgb565,
+    prelude::*,
+    text::{Alignment, Text},
+};
+use panic_probe as _;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    info!("Booting...");
+    let board = BoardBuilder::default()
+        .display(true)
+        .rotary_encoder(true)
+        .rotary_button(true)
+        .build()
+        .await;
+    let display = board.display.unwrap();
+    let encoder = board.rotary_encoder.unwrap();
+    let button = board.rotary_button.unwrap();
+
+    {
+        let mut display = display.lock().await;
+        display.clear(Rgb565::BLACK).unwrap();
+        Text::with_alignment(
+            "Turn to dim",
+            Point::new(120, 160),
+            MonoTextStyle::new(&FONT_10X20, Rgb565::WHITE),
+            Alignment::Center,
+        )
+        .draw(&mut *display)
+        .unwrap();
+        // Fade in.
+        for brightness in (0..=255).step_by(15) {
+            display.set_brightness(brightness).await.unwrap();
+            Timer::after_millis(20).await;
+        }
+    }
+
+    let idle = IdleTimer::new(&display, Duration::from_secs(10));
+    let ui = async {
+        let mut brightness: i32 = 255;
+        loop {
+            let event = select(encoder.changed(), button.next_event()).await;
+            if idle.on_input() {
+                info!("Woken");
+                encoder.delta_since_last();
+                continue;
+            }
+            if let Either::First(_) = event {
+                brightness = (brightness + encoder.delta_since_last() * 15).clamp(0, 255);
+                info!("Brightness: {}", brightness);
+                let mut display = display.lock().await;
+                display.set_brightness(brightness as u8).await.unwrap();
+            }
+        }
+    };
+    join3(encoder.run(), idle.run(), ui).await;
+}
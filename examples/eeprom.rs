@@ -34,4 +34,12 @@ async fn main(_spawner: Spawner) {
         .await
         .unwrap();
     info!("System Memory Bytes: {}", bytes);
+
+    eeprom
+        .write(Memory::User, 496, b"embassy-buddy")
+        .await
+        .unwrap();
+    let mut bytes: [u8; 13] = [0; 13];
+    eeprom.read(Memory::User, 496, &mut bytes).await.unwrap();
+    info!("User Memory Bytes: {}", bytes);
 }
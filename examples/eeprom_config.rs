@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_buddy::{
+    BoardBuilder,
+    components::eeprom::{BOARD_CONFIG_ADDR, BoardConfig, ConfigSource, ConfigStore},
+};
+use embassy_executor::Spawner;
+use panic_probe as _;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    info!("Booting...");
+    let board = BoardBuilder::default().load_config(true).build().await;
+    let eeprom = board.eeprom.unwrap();
+
+    let store = ConfigStore::new(&eeprom, BOARD_CONFIG_ADDR);
+    let (config, source) = store.load::<BoardConfig>().await.unwrap();
+    info!("Config Source: {}", source);
+    info!("MAC Address: {}", config.mac_addr);
+    info!("Hotend Beta: {}", config.hotend_thermistor.beta);
+
+    if source == ConfigSource::Default {
+        info!("Saving the default config");
+        store.save(&config).await.unwrap();
+        let (_, source) = store.load::<BoardConfig>().await.unwrap();
+        info!("Config Source: {}", source);
+    }
+}
@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_buddy::{BoardBuilder, components::eeprom::MailboxChannel};
+use embassy_executor::Spawner;
+use embassy_time::Duration;
+use panic_probe as _;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    info!("Booting...");
+    let board = BoardBuilder::default().eeprom(true).build().await;
+    let eeprom = board.eeprom.unwrap();
+
+    // The factory password is zero.
+    eeprom.present_password(0).await.unwrap();
+    eeprom.set_mailbox_mode(true).await.unwrap();
+    eeprom.mailbox_enable().await.unwrap();
+
+    info!("Waiting for requests");
+    let channel = MailboxChannel::new(&eeprom, Duration::from_millis(50));
+    channel
+        .serve(|request, response| {
+            info!("Request: {}", request);
+            // Echo the request back to the reader.
+            response[..request.len()].copy_from_slice(request);
+            request.len()
+        })
+        .await
+        .unwrap();
+}
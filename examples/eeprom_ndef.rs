@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_buddy::{
+    BoardBuilder,
+    components::eeprom::{NDEF_AREA_SIZE, Record, uri_prefix},
+};
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+use panic_probe as _;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    info!("Booting...");
+    let board = BoardBuilder::default().eeprom(true).build().await;
+    let eeprom = board.eeprom.unwrap();
+
+    eeprom
+        .publish_ndef(&[
+            Record::uri("http://192.168.1.20/"),
+            Record::text("en", "Prusa Mini"),
+        ])
+        .await
+        .unwrap();
+    info!("Published. Tap the board with a phone.");
+
+    loop {
+        let mut buf: [u8; NDEF_AREA_SIZE as usize] = [0; NDEF_AREA_SIZE as usize];
+        match eeprom.read_ndef(&mut buf).await {
+            Ok(message) => {
+                for record in message.records() {
+                    match record {
+                        Ok(Record::Uri { prefix, uri }) => {
+                            info!("URI: {}{}", uri_prefix(prefix), uri)
+                        }
+                        Ok(Record::Text { text, .. }) => info!("Text: {}", text),
+                        Ok(Record::Mime { mime_type, .. }) => info!("MIME: {}", mime_type),
+                        Ok(_) => info!("Other record"),
+                        Err(_) => info!("Malformed record"),
+                    }
+                }
+            }
+            Err(_) => info!("No NDEF message"),
+        }
+        Timer::after_secs(5).await;
+    }
+}
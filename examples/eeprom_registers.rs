@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_buddy::{
+    BoardBuilder,
+    components::eeprom::{AreaProtection, IcRef, MemSize, RfMngtDyn},
+};
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+use panic_probe as _;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    info!("Booting...");
+    let board = BoardBuilder::default().eeprom(true).build().await;
+    let eeprom = board.eeprom.unwrap();
+
+    let uid = eeprom.uid().await.unwrap();
+    info!("UID: {:x}", uid);
+
+    let mut ic_ref = IcRef::default();
+    eeprom.read_register(&mut ic_ref).await.unwrap();
+    info!("IC Ref: {}", ic_ref);
+
+    let mut mem_size = MemSize::default();
+    eeprom.read_register(&mut mem_size).await.unwrap();
+    info!("Memory Size: {}", mem_size);
+
+    info!("Turning RF off");
+    eeprom.rf_off().await.unwrap();
+    let mut rf = RfMngtDyn::default();
+    eeprom.read_register(&mut rf).await.unwrap();
+    info!("RF: {}", rf);
+    Timer::after_secs(5).await;
+    eeprom.rf_on().await.unwrap();
+    eeprom.read_register(&mut rf).await.unwrap();
+    info!("RF: {}", rf);
+
+    // The factory password is zero.
+    eeprom.present_password(0).await.unwrap();
+    eeprom
+        .set_i2c_area_protection(2, AreaProtection::None)
+        .await
+        .unwrap();
+    info!("Area 2 is unprotected");
+}
@@ -2,7 +2,7 @@
 #![no_main]
 
 use defmt::*;
-use embassy_buddy::BoardBuilder;
+use embassy_buddy::{BoardBuilder, NetworkConfig};
 use embassy_executor::Spawner;
 use embassy_time::Duration;
 use picoserve::{make_static, routing::get};
@@ -12,7 +12,7 @@ use {defmt_rtt as _, panic_probe as _};
 async fn main(spawner: Spawner) {
     let mac_addr = [0x00, 0x00, 0xDE, 0xAD, 0xBE, 0xEF];
     let board = BoardBuilder::default()
-        .ethernet(&spawner, mac_addr)
+        .ethernet(&spawner, mac_addr, NetworkConfig::default())
         .build()
         .await;
     let stack = board.stack.unwrap();
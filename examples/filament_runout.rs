@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_buddy::{
+    BoardBuilder, BuddyBuzzer,
+    components::filament_sensor::{RunoutPolicy, RunoutState},
+};
+use embassy_executor::Spawner;
+use embassy_futures::select::{Either, select};
+use embassy_time::Timer;
+use panic_probe as _;
+
+/// A stand in for a print controller that logs each step of the runout.
+struct Printer<'a, 'b> {
+    buzzer: &'a BuddyBuzzer<'b>,
+}
+
+impl RunoutPolicy for Printer<'_, '_> {
+    async fn pause(&mut self) {
+        info!("Pausing");
+    }
+
+    async fn park(&mut self) {
+        info!("Parking the head");
+    }
+
+    async fn alert(&mut self) {
+        for _ in 0..3 {
+            self.buzzer.set_duty_cycle_fraction(1, 2).await;
+            Timer::after_millis(100).await;
+            self.buzzer.set_duty_cycle_fully_off().await;
+            Timer::after_millis(100).await;
+        }
+        info!("Waiting for filament");
+    }
+
+    async fn purge(&mut self) {
+        info!("Purging");
+    }
+
+    async fn resume(&mut self) {
+        info!("Resuming");
+    }
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    info!("Booting...");
+    let board = BoardBuilder::default()
+        .filament_sensor(true)
+        .buzzer(true)
+        .build()
+        .await;
+    let sensor = board.filament_sensor.unwrap();
+    let buzzer = board.buzzer.unwrap();
+    let mut printer = Printer { buzzer: &buzzer };
+
+    let mut detector = sensor.runout_detector().await;
+    loop {
+        // Pretend the extruder feeds 1mm every 100ms.
+        match select(sensor.next_change(), Timer::after_millis(100)).await {
+            Either::First(change) => {
+                info!("Filament: {}", change);
+                detector.on_change(change);
+            }
+            Either::Second(_) => {
+                if detector.on_extruded(1.0) {
+                    info!("Filament ran out");
+                    sensor.handle_runout(&mut printer).await;
+                    detector = sensor.runout_detector().await;
+                }
+            }
+        }
+        if let RunoutState::Draining(remaining) = detector.state() {
+            info!("{}mm until runout", remaining);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_buddy::{
+    BoardBuilder,
+    components::flash::{
+        FILESYSTEM_START, FLASH_CAPACITY, FixedTimeSource, FlashBlockDevice,
+        embedded_sdmmc::{Mode, VolumeIdx, VolumeManager},
+    },
+};
+use embassy_executor::Spawner;
+use panic_probe as _;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    info!("Booting...");
+
+    let board = BoardBuilder::default().flash(true).build().await;
+    let flash = board.flash.unwrap();
+
+    let device: FlashBlockDevice<_> =
+        FlashBlockDevice::new(flash, FILESYSTEM_START, FLASH_CAPACITY - FILESYSTEM_START).unwrap();
+    let mut volume_mgr = VolumeManager::new(device, FixedTimeSource);
+
+    match volume_mgr.open_raw_volume(VolumeIdx(0)) {
+        Ok(volume) => volume_mgr.close_volume(volume).unwrap(),
+        Err(_) => {
+            info!("Formatting the flash");
+            volume_mgr.device().format().unwrap();
+        }
+    }
+
+    {
+        let mut volume = volume_mgr.open_volume(VolumeIdx(0)).unwrap();
+        let mut root = volume.open_root_dir().unwrap();
+
+        let mut file = root
+            .open_file_in_dir("JOB.GCO", Mode::ReadWriteCreateOrTruncate)
+            .unwrap();
+        file.write(b"G28\nG1 X10 Y10 F3000\nM84\n").unwrap();
+        file.close().unwrap();
+
+        root.iterate_dir(|entry| {
+            info!("{} ({} bytes)", entry.name.base_name(), entry.size);
+        })
+        .unwrap();
+
+        let mut file = root.open_file_in_dir("JOB.GCO", Mode::ReadOnly).unwrap();
+        let mut buf: [u8; 16] = [0; 16];
+        while !file.is_eof() {
+            let n = file.read(&mut buf).unwrap();
+            info!("{}", core::str::from_utf8(&buf[..n]).unwrap());
+        }
+        file.close().unwrap();
+
+        root.delete_file_in_dir("JOB.GCO").unwrap();
+    }
+
+    volume_mgr.device().flush().unwrap();
+    info!("Done");
+}
@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use embassy_buddy::{
+    BoardBuilder, NetworkConfig,
+    net::{GcodeError, GcodeSink, console},
+};
+use embassy_executor::Spawner;
+use {defmt_rtt as _, panic_probe as _};
+
+/// A stand in for a print controller that logs the G-code.
+struct Printer;
+
+impl GcodeSink for Printer {
+    async fn send(&mut self, line: &str) -> Result<(), GcodeError> {
+        info!("G-code: {}", line);
+        Ok(())
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let mac_addr = [0x00, 0x00, 0xDE, 0xAD, 0xBE, 0xEF];
+    let board = BoardBuilder::new()
+        .display(false)
+        .ethernet(&spawner, mac_addr, NetworkConfig::default())
+        .build()
+        .await;
+    let stack = board.stack.unwrap();
+
+    stack.wait_config_up().await;
+    if let Some(config) = stack.config_v4() {
+        info!("Console on {}:23", config.address);
+    }
+
+    let mut rx_buffer = [0; 1024];
+    let mut tx_buffer = [0; 512];
+    let mut line_buffer = [0; 96];
+    console::serve(
+        stack,
+        23,
+        &mut Printer,
+        &mut rx_buffer,
+        &mut tx_buffer,
+        &mut line_buffer,
+    )
+    .await
+}
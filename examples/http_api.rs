@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use embassy_buddy::{
+    Board, BoardBuilder, NetworkConfig,
+    net::{GcodeError, GcodeSink, HeaterId, PrinterApi, PrinterStatus, RestApi, http},
+};
+use embassy_executor::Spawner;
+use {defmt_rtt as _, panic_probe as _};
+
+/// A stand in for a print controller that remembers the targets and logs the G-code.
+struct Printer<'a, 'b> {
+    board: &'a Board<'b>,
+    bed_target: f64,
+    hotend_target: f64,
+}
+
+impl GcodeSink for Printer<'_, '_> {
+    async fn send(&mut self, line: &str) -> Result<(), GcodeError> {
+        info!("G-code: {}", line);
+        Ok(())
+    }
+}
+
+impl PrinterApi for Printer<'_, '_> {
+    async fn status(&mut self) -> PrinterStatus {
+        let mut status = PrinterStatus::read(self.board).await;
+        status.bed.target = self.bed_target;
+        status.hotend.target = self.hotend_target;
+        status
+    }
+
+    async fn set_target(&mut self, heater: HeaterId, target: f64) {
+        info!("{} target: {}", heater, target);
+        match heater {
+            HeaterId::Bed => self.bed_target = target,
+            HeaterId::Hotend => self.hotend_target = target,
+        }
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let mac_addr = [0x00, 0x00, 0xDE, 0xAD, 0xBE, 0xEF];
+    let board = BoardBuilder::new()
+        .display(false)
+        .ethernet(&spawner, mac_addr, NetworkConfig::default())
+        .build()
+        .await;
+    let stack = board.stack.unwrap();
+
+    stack.wait_config_up().await;
+    if let Some(config) = stack.config_v4() {
+        info!("Serving on {}", config.address);
+    }
+
+    let mut api = RestApi::new(Printer {
+        board: &board,
+        bed_target: 0.0,
+        hotend_target: 0.0,
+    });
+
+    let mut rx_buffer = [0; 1024];
+    let mut tx_buffer = [0; 1024];
+    let mut request_buffer = [0; 1024];
+    let mut response_buffer = [0; 1024];
+    http::serve(
+        stack,
+        80,
+        &mut api,
+        &mut rx_buffer,
+        &mut tx_buffer,
+        &mut request_buffer,
+        &mut response_buffer,
+    )
+    .await
+}
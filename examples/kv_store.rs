@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_buddy::{
+    BoardBuilder,
+    components::flash::{KV_STORE_SECTORS, KvStore},
+};
+use embassy_executor::Spawner;
+use panic_probe as _;
+
+const BOOT_COUNT: u16 = 0;
+const Z_OFFSET: u16 = 1;
+const HOSTNAME: u16 = 2;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    info!("Booting...");
+
+    let board = BoardBuilder::default().flash(true).build().await;
+    let flash = board.flash.unwrap();
+
+    let mut store = KvStore::mount(flash, 0, KV_STORE_SECTORS).await.unwrap();
+
+    let boot_count = store.get_u32(BOOT_COUNT).await.unwrap().unwrap_or(0) + 1;
+    store.set_u32(BOOT_COUNT, boot_count).await.unwrap();
+    info!("Boot Count: {}", boot_count);
+
+    if store.get_f32(Z_OFFSET).await.unwrap().is_none() {
+        store.set_f32(Z_OFFSET, -0.85).await.unwrap();
+    }
+    info!("Z Offset: {}", store.get_f32(Z_OFFSET).await.unwrap());
+
+    store.set_bytes(HOSTNAME, b"buddy").await.unwrap();
+    let mut buf: [u8; 32] = [0; 32];
+    let n = store.get_bytes(HOSTNAME, &mut buf).await.unwrap().unwrap();
+    info!("Hostname: {}", core::str::from_utf8(&buf[..n]).unwrap());
+}
@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use embassy_buddy::{
+    BoardBuilder, NetworkConfig,
+    net::mdns::{self, Responder, Service},
+};
+use embassy_executor::Spawner;
+use embassy_net::udp::PacketMetadata;
+use {defmt_rtt as _, panic_probe as _};
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let mac_addr = [0x00, 0x00, 0xDE, 0xAD, 0xBE, 0xEF];
+    let board = BoardBuilder::new()
+        .display(false)
+        .ethernet(&spawner, mac_addr, NetworkConfig::dhcp("buddy").unwrap())
+        .build()
+        .await;
+    let stack = board.stack.unwrap();
+    info!("Advertising buddy.local");
+
+    let services = [
+        Service {
+            instance: "Buddy",
+            service: "_http._tcp",
+            port: 80,
+            txt: &["path=/"],
+        },
+        Service {
+            instance: "Buddy",
+            service: "_octoprint._tcp",
+            port: 80,
+            txt: &["path=/", "version=1.1.0", "api=0.1"],
+        },
+        Service {
+            instance: "Buddy",
+            service: "_prusa-link._tcp",
+            port: 80,
+            txt: &["path=/"],
+        },
+    ];
+    let responder = Responder::new("buddy", &services);
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; 1024];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0; 1024];
+    let mut packet_buffer = [0; 512];
+    let mut response_buffer = [0; 512];
+    mdns::run(
+        stack,
+        &responder,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+        &mut packet_buffer,
+        &mut response_buffer,
+    )
+    .await
+}
@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use embassy_buddy::{
+    Board, BoardBuilder, NetworkConfig,
+    net::{Metrics, MetricsSource, NotFound, PrinterMetrics, http},
+};
+use embassy_executor::Spawner;
+use {defmt_rtt as _, panic_probe as _};
+
+/// A stand in for a print controller that reports what the board can read.
+struct Printer<'a, 'b> {
+    board: &'a Board<'b>,
+}
+
+impl MetricsSource for Printer<'_, '_> {
+    async fn metrics(&mut self) -> PrinterMetrics {
+        PrinterMetrics::read(self.board).await
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let mac_addr = [0x00, 0x00, 0xDE, 0xAD, 0xBE, 0xEF];
+    let board = BoardBuilder::new()
+        .display(false)
+        .ethernet(&spawner, mac_addr, NetworkConfig::default())
+        .build()
+        .await;
+    let stack = board.stack.unwrap();
+
+    stack.wait_config_up().await;
+    if let Some(config) = stack.config_v4() {
+        info!("Metrics on http://{}/metrics", config.address.address());
+    }
+
+    let mut metrics = Metrics::new(Printer { board: &board }, NotFound);
+
+    let mut rx_buffer = [0; 1024];
+    let mut tx_buffer = [0; 1024];
+    let mut request_buffer = [0; 1024];
+    // The metrics take about 2.5 kB.
+    let mut response_buffer = [0; 3072];
+    http::serve(
+        stack,
+        80,
+        &mut metrics,
+        &mut rx_buffer,
+        &mut tx_buffer,
+        &mut request_buffer,
+        &mut response_buffer,
+    )
+    .await
+}
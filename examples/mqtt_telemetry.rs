@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use embassy_buddy::{
+    BoardBuilder, NetworkConfig,
+    net::{
+        GcodeError, GcodeSink,
+        mqtt::{self, ConnectOptions, TelemetryTopics},
+    },
+};
+use embassy_executor::Spawner;
+use embassy_net::{IpEndpoint, Ipv4Address};
+use embassy_time::Duration;
+use {defmt_rtt as _, panic_probe as _};
+
+/// A stand in for a print controller that logs the G-code.
+struct Printer;
+
+impl GcodeSink for Printer {
+    async fn send(&mut self, line: &str) -> Result<(), GcodeError> {
+        info!("G-code: {}", line);
+        Ok(())
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let mac_addr = [0x00, 0x00, 0xDE, 0xAD, 0xBE, 0xEF];
+    let board = BoardBuilder::new()
+        .display(false)
+        .ethernet(&spawner, mac_addr, NetworkConfig::default())
+        .build()
+        .await;
+    let stack = board.stack.unwrap();
+
+    stack.wait_config_up().await;
+    if let Some(config) = stack.config_v4() {
+        info!("Address: {}", config.address);
+    }
+
+    let broker = IpEndpoint::new(Ipv4Address::new(192, 168, 0, 10).into(), 1883);
+    let options = ConnectOptions {
+        client_id: "buddy",
+        username: None,
+        password: None,
+        keep_alive: 60,
+        clean_session: true,
+    };
+
+    let mut rx_buffer = [0; 1024];
+    let mut tx_buffer = [0; 1024];
+    let mut mqtt_rx_buffer = [0; 512];
+    let mut mqtt_tx_buffer = [0; 512];
+    mqtt::run(
+        stack,
+        broker,
+        &options,
+        &TelemetryTopics::default(),
+        Duration::from_secs(5),
+        &board,
+        &mut Printer,
+        &mut rx_buffer,
+        &mut tx_buffer,
+        &mut mqtt_rx_buffer,
+        &mut mqtt_tx_buffer,
+    )
+    .await
+}
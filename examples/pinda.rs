@@ -19,8 +19,8 @@ async fn main(_spawner: Spawner) {
 
 async fn pinda_interrupt(sensor: BuddyPinda<'_>) -> ! {
     loop {
-        if let Ok(change) = sensor.try_on_change().await {
-            info!("[PINDA] {}", change);
+        if let Ok(edge) = sensor.try_on_edge().await {
+            info!("[PINDA] {} at {}", edge.change, edge.at);
         } else {
             info!("[PINDA] Error");
         }
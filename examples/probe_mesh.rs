@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_buddy::{
+    BoardBuilder,
+    probing::{BedMesh, ProbeConfig, Prober},
+};
+use embassy_executor::Spawner;
+use panic_probe as _;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    info!("Booting...");
+    let board = BoardBuilder::default()
+        .pinda(true)
+        .z_stepper(true)
+        .rotary_button(true)
+        .build()
+        .await;
+    let pinda = board.pinda.unwrap();
+    let z = board.z_stepper.unwrap();
+    let button = board.rotary_button.unwrap();
+
+    z.enable().await;
+    let config = ProbeConfig {
+        samples: 2,
+        ..Default::default()
+    };
+    let prober = Prober::new(&pinda, &z, config);
+    let mut mesh: BedMesh<3, 3> = BedMesh::new((20.0, 20.0), (160.0, 160.0));
+
+    // There is no XY motion yet so the head is moved by hand to each point.
+    let move_to = async |x: f32, y: f32| {
+        info!("Move the head to ({}, {}) and click", x, y);
+        button.on_click().await;
+    };
+    prober.probe_mesh(&mut mesh, move_to).await.unwrap();
+    z.disable().await;
+
+    info!("Range: {}mm", mesh.range());
+    mesh.normalise();
+    for iy in 0..3 {
+        info!(
+            "{} {} {}",
+            mesh.get(0, iy),
+            mesh.get(1, iy),
+            mesh.get(2, iy)
+        );
+    }
+    info!(
+        "Correction at the centre: {}mm",
+        mesh.interpolate(90.0, 90.0)
+    );
+}
@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_buddy::{
+    BoardBuilder,
+    probing::{ProbeConfig, Prober},
+};
+use embassy_executor::Spawner;
+use panic_probe as _;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    info!("Booting...");
+    let board = BoardBuilder::default()
+        .pinda(true)
+        .z_stepper(true)
+        .build()
+        .await;
+    let pinda = board.pinda.unwrap();
+    let z = board.z_stepper.unwrap();
+
+    z.enable().await;
+    let prober = Prober::new(&pinda, &z, ProbeConfig::default());
+    let result = prober.repeatability::<10>().await.unwrap();
+    z.disable().await;
+
+    for (i, sample) in result.samples.iter().enumerate() {
+        info!("{}: {}mm", i + 1, sample);
+    }
+    info!("Range: {}mm", result.stats.range());
+    info!("Std Dev: {}mm", result.stats.std_dev());
+}
@@ -0,0 +1,119 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use embassy_buddy::{
+    Board, BoardBuilder, NetworkConfig,
+    components::flash::{
+        FILESYSTEM_START, FLASH_CAPACITY, FixedTimeSource, FlashBlockDevice,
+        embedded_sdmmc::{VolumeIdx, VolumeManager},
+    },
+    net::{
+        FlashFileStore, GcodeError, GcodeSink, HeaterId, JobInfo, PrinterApi, PrinterState,
+        PrinterStatus, PrusaLink, PrusaLinkConfig, PrusaLinkPrinter, http,
+    },
+};
+use embassy_executor::Spawner;
+use {defmt_rtt as _, panic_probe as _};
+
+/// A stand in for a print controller that is always idle.
+struct Printer<'a, 'b> {
+    board: &'a Board<'b>,
+    bed_target: f64,
+    hotend_target: f64,
+}
+
+impl GcodeSink for Printer<'_, '_> {
+    async fn send(&mut self, line: &str) -> Result<(), GcodeError> {
+        info!("G-code: {}", line);
+        Ok(())
+    }
+}
+
+impl PrinterApi for Printer<'_, '_> {
+    async fn status(&mut self) -> PrinterStatus {
+        let mut status = PrinterStatus::read(self.board).await;
+        status.bed.target = self.bed_target;
+        status.hotend.target = self.hotend_target;
+        status
+    }
+
+    async fn set_target(&mut self, heater: HeaterId, target: f64) {
+        match heater {
+            HeaterId::Bed => self.bed_target = target,
+            HeaterId::Hotend => self.hotend_target = target,
+        }
+    }
+}
+
+impl PrusaLinkPrinter for Printer<'_, '_> {
+    fn state(&self) -> PrinterState {
+        PrinterState::Idle
+    }
+
+    fn job(&self) -> Option<JobInfo<'_>> {
+        None
+    }
+
+    async fn print(&mut self, name: &str) -> Result<(), GcodeError> {
+        info!("Printing {}", name);
+        Ok(())
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let mac_addr = [0x00, 0x00, 0xDE, 0xAD, 0xBE, 0xEF];
+    let mut board = BoardBuilder::new()
+        .display(false)
+        .ethernet(&spawner, mac_addr, NetworkConfig::default())
+        .build()
+        .await;
+    let stack = board.stack.unwrap();
+    let flash = board.flash.take().unwrap();
+
+    let device: FlashBlockDevice<_> =
+        FlashBlockDevice::new(flash, FILESYSTEM_START, FLASH_CAPACITY - FILESYSTEM_START).unwrap();
+    let mut volume_mgr = VolumeManager::new(device, FixedTimeSource);
+    match volume_mgr.open_raw_volume(VolumeIdx(0)) {
+        Ok(volume) => volume_mgr.close_volume(volume).unwrap(),
+        Err(_) => {
+            info!("Formatting the flash");
+            volume_mgr.device().format().unwrap();
+        }
+    }
+    let files = FlashFileStore::open(volume_mgr).unwrap();
+
+    stack.wait_config_up().await;
+    if let Some(config) = stack.config_v4() {
+        info!("Serving on {}", config.address);
+    }
+
+    let printer = Printer {
+        board: &board,
+        bed_target: 0.0,
+        hotend_target: 0.0,
+    };
+    let config = PrusaLinkConfig {
+        api_key: "buddy",
+        hostname: "buddy",
+        storage: "usb",
+        nozzle_diameter: 0.4,
+    };
+    let mut link = PrusaLink::new(printer, files, config);
+
+    let mut rx_buffer = [0; 2048];
+    let mut tx_buffer = [0; 1024];
+    let mut request_buffer = [0; 1024];
+    let mut response_buffer = [0; 1024];
+    http::serve(
+        stack,
+        80,
+        &mut link,
+        &mut rx_buffer,
+        &mut tx_buffer,
+        &mut request_buffer,
+        &mut response_buffer,
+    )
+    .await
+}
@@ -19,7 +19,7 @@ async fn main(_spawner: Spawner) {
 async fn click(btn: BuddyRotaryButton<'_>) -> ! {
     info!("Initialising Click");
     loop {
-        btn.try_on_click().await.unwrap();
-        info!("click");
+        let event = btn.try_next_event().await.unwrap();
+        info!("[BUTTON]: {}", event);
     }
 }
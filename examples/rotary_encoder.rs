@@ -5,6 +5,7 @@ use defmt::info;
 use defmt_rtt as _;
 use embassy_buddy::{BoardBuilder, BuddyRotaryEncoder};
 use embassy_executor::Spawner;
+use embassy_futures::join::join;
 use panic_probe as _;
 
 #[embassy_executor::main]
@@ -12,14 +13,14 @@ async fn main(_spawner: Spawner) {
     info!("Booting...");
     let board = BoardBuilder::default().rotary_encoder(true).build().await;
     let encoder = board.rotary_encoder.unwrap();
-    let fut = spun(encoder);
-    fut.await;
+    join(encoder.run(), changed(&encoder)).await;
 }
 
-async fn spun(rotary: BuddyRotaryEncoder<'_>) -> ! {
-    info!("Initialising Click");
+async fn changed(rotary: &BuddyRotaryEncoder<'_>) -> ! {
+    info!("Initialising Encoder");
     loop {
-        let dir = rotary.try_spun().await.unwrap();
-        info!("[ROTARY]: {}", dir);
+        let position = rotary.changed().await;
+        let delta = rotary.delta_since_last();
+        info!("[ROTARY]: Position {}, Delta {}", position, delta);
     }
 }
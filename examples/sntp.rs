@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use embassy_buddy::{BoardBuilder, NetworkConfig, net::sntp};
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_net::{IpEndpoint, Ipv4Address, udp::PacketMetadata};
+use embassy_time::{Duration, Timer};
+use {defmt_rtt as _, panic_probe as _};
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let mac_addr = [0x00, 0x00, 0xDE, 0xAD, 0xBE, 0xEF];
+    let board = BoardBuilder::new()
+        .display(false)
+        .ethernet(&spawner, mac_addr, NetworkConfig::default())
+        .build()
+        .await;
+    let stack = board.stack.unwrap();
+
+    // time.cloudflare.com
+    let server = IpEndpoint::new(Ipv4Address::new(162, 159, 200, 1).into(), sntp::NTP_PORT);
+    let mut rx_meta = [PacketMetadata::EMPTY; 2];
+    let mut rx_buffer = [0; 256];
+    let mut tx_meta = [PacketMetadata::EMPTY; 2];
+    let mut tx_buffer = [0; 256];
+    let fut_1 = sntp::run(
+        stack,
+        server,
+        Duration::from_secs(600),
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    let fut_2 = async {
+        loop {
+            match sntp::now_utc() {
+                Some(now) => info!("It is {}", now),
+                None => info!("Waiting for the time"),
+            }
+            if let Some(drift) = sntp::clock().and_then(|c| c.drift_ppm()) {
+                info!("The clock drifts {} ppm", drift);
+            }
+            Timer::after_secs(10).await;
+        }
+    };
+    join(fut_1, fut_2).await;
+}
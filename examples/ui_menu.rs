@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+use core::cell::Cell;
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_buddy::{
+    BoardBuilder,
+    ui::{Menu, MenuEvent, MenuItem},
+};
+use embassy_executor::Spawner;
+use embassy_futures::{
+    join::join,
+    select::{Either, select},
+};
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
+use panic_probe as _;
+
+const HOME: u16 = 1;
+const FAN: u16 = 2;
+const LIGHTS: u16 = 3;
+const Z_OFFSET: u16 = 4;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    info!("Booting...");
+    let board = BoardBuilder::default()
+        .display(true)
+        .rotary_encoder(true)
+        .rotary_button(true)
+        .build()
+        .await;
+    let display = board.display.unwrap();
+    let encoder = board.rotary_encoder.unwrap();
+    let button = board.rotary_button.unwrap();
+
+    let fan = Cell::new(50.0);
+    let lights = Cell::new(true);
+    let z_offset = Cell::new(-0.8);
+    let calibration = [
+        MenuItem::number(Z_OFFSET, "Z Offset", &z_offset, -2.0, 2.0, 0.025, "mm"),
+        MenuItem::back(),
+    ];
+    let items = [
+        MenuItem::action(HOME, "Home Axes"),
+        MenuItem::number(FAN, "Fan", &fan, 0.0, 100.0, 5.0, "%"),
+        MenuItem::toggle(LIGHTS, "Lights", &lights),
+        MenuItem::submenu("Calibration", &calibration),
+    ];
+    let mut menu = Menu::new("Settings", &items);
+
+    let ui = async {
+        {
+            let mut display = display.lock().await;
+            display.clear(Rgb565::BLACK).unwrap();
+            menu.draw(&mut *display).unwrap();
+        }
+        loop {
+            let event = match select(encoder.changed(), button.next_event()).await {
+                Either::First(_) => menu.on_scroll(encoder.delta_since_last()),
+                Either::Second(event) => menu.on_button(event),
+            };
+            match event {
+                Some(MenuEvent::Action(id)) => info!("Action: {}", id),
+                Some(MenuEvent::Changed(id)) => info!("Changed: {}", id),
+                Some(MenuEvent::Exit) => menu.reset(),
+                None => {}
+            }
+            let mut display = display.lock().await;
+            menu.draw(&mut *display).unwrap();
+        }
+    };
+    join(encoder.run(), ui).await;
+}
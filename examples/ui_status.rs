@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_buddy::{BoardBuilder, ui::StatusScreen};
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
+use panic_probe as _;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    info!("Booting...");
+    let board = BoardBuilder::default()
+        .display(true)
+        .hotend_thermistor(true, None, None, None)
+        .bed_thermistor(true, None, None, None)
+        .filament_sensor(true)
+        .build()
+        .await;
+    let display = board.display.unwrap();
+    let hotend = board.hotend_thermistor.unwrap();
+    let bed = board.bed_thermistor.unwrap();
+    let filament = board.filament_sensor.unwrap();
+
+    let mut screen = StatusScreen::new();
+    display.lock().await.clear(Rgb565::BLACK).unwrap();
+
+    let mut elapsed = 0;
+    loop {
+        screen.hotend.set(hotend.read().await, 215.0);
+        screen.bed.set(bed.read().await, 60.0);
+        screen.filament.set(filament.available().await);
+        screen.fan.set(100);
+        screen.axes.set(125.0, 105.0, 0.2);
+        screen
+            .progress
+            .set((elapsed / 36) as u8, Some(3600 - elapsed));
+        screen.fault.set(None);
+
+        let drawn = screen.draw(&mut *display.lock().await).unwrap();
+        info!("Redrew {} widgets", drawn);
+
+        Timer::after_secs(1).await;
+        elapsed = (elapsed + 1) % 3600;
+    }
+}
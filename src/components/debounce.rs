@@ -0,0 +1,160 @@
+//! Debouncing of digital inputs, shared by the rotary button and the filament sensor.
+use core::convert::Infallible;
+
+use embassy_futures::select::select;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal::digital::InputPin;
+use embedded_hal_async::digital::Wait;
+
+/// A level that only changes once the raw level has been stable for the debounce time. It is fed with each raw level
+/// and polled for changes.
+#[derive(Debug, Clone)]
+pub struct Debounced {
+    debounce: Duration,
+    /// The last raw level and when it changed.
+    raw: bool,
+    raw_changed: Instant,
+    /// The debounced level.
+    level: bool,
+}
+
+impl Debounced {
+    /// Create a debounced level starting from a known level.
+    pub fn new(debounce: Duration, level: bool) -> Self {
+        Self {
+            debounce,
+            raw: level,
+            raw_changed: Instant::from_ticks(0),
+            level,
+        }
+    }
+
+    /// Return the debounced level.
+    pub fn level(&self) -> bool {
+        self.level
+    }
+
+    /// Return when the raw level last changed. Once a change has settled this is when it started.
+    pub fn changed_at(&self) -> Instant {
+        self.raw_changed
+    }
+
+    /// Record the raw level at a point in time.
+    pub fn on_level(&mut self, level: bool, now: Instant) {
+        if level != self.raw {
+            self.raw = level;
+            self.raw_changed = now;
+        }
+    }
+
+    /// Return the new level if a change has settled by `now`.
+    pub fn poll(&mut self, now: Instant) -> Option<bool> {
+        if self.raw == self.level || now < self.raw_changed + self.debounce {
+            return None;
+        }
+        self.level = self.raw;
+        Some(self.level)
+    }
+
+    /// Return when a change will have settled if the raw level does not change again.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        (self.raw != self.level).then_some(self.raw_changed + self.debounce)
+    }
+}
+
+/// A state machine fed with the raw level of an input, such as a [`Debounced`] level or the gestures of a button.
+pub trait DebouncedInput {
+    type Event;
+
+    /// Record the raw level at a point in time.
+    fn on_level(&mut self, level: bool, now: Instant);
+
+    /// Return the next event that has occurred by `now`.
+    fn poll(&mut self, now: Instant) -> Option<Self::Event>;
+
+    /// Return when the state machine next needs polling if the level does not change.
+    fn next_deadline(&self) -> Option<Instant>;
+}
+
+impl DebouncedInput for Debounced {
+    type Event = bool;
+
+    fn on_level(&mut self, level: bool, now: Instant) {
+        Debounced::on_level(self, level, now);
+    }
+
+    fn poll(&mut self, now: Instant) -> Option<bool> {
+        Debounced::poll(self, now)
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        Debounced::next_deadline(self)
+    }
+}
+
+/// Feed the level of `pin` into `input` until it has an event, sleeping until the next edge or deadline in between.
+/// The level is inverted for inputs that pull the pin low when active.
+pub async fn wait_debounced<P, I>(pin: &mut P, active_low: bool, input: &mut I) -> I::Event
+where
+    P: InputPin<Error = Infallible> + Wait<Error = Infallible>,
+    I: DebouncedInput,
+{
+    loop {
+        input.on_level(pin.is_high().unwrap() != active_low, Instant::now());
+        if let Some(event) = input.poll(Instant::now()) {
+            return event;
+        }
+        match input.next_deadline() {
+            Some(deadline) => {
+                select(pin.wait_for_any_edge(), Timer::at(deadline)).await;
+            }
+            None => pin.wait_for_any_edge().await.unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::MockPin;
+
+    #[test]
+    fn settles_after_the_debounce_time() {
+        let mut level = Debounced::new(Duration::from_millis(20), false);
+        assert_eq!(level.next_deadline(), None);
+        level.on_level(true, Instant::from_millis(100));
+        assert_eq!(level.next_deadline(), Some(Instant::from_millis(120)));
+        assert_eq!(level.poll(Instant::from_millis(119)), None);
+        assert!(!level.level());
+        assert_eq!(level.poll(Instant::from_millis(120)), Some(true));
+        assert!(level.level());
+        assert_eq!(level.changed_at(), Instant::from_millis(100));
+        assert_eq!(level.poll(Instant::from_millis(200)), None);
+        assert_eq!(level.next_deadline(), None);
+    }
+
+    #[test]
+    fn restarts_on_bounce() {
+        let mut level = Debounced::new(Duration::from_millis(20), false);
+        level.on_level(true, Instant::from_millis(100));
+        level.on_level(false, Instant::from_millis(110));
+        // Back where it started, so there is nothing to settle.
+        assert_eq!(level.next_deadline(), None);
+        level.on_level(true, Instant::from_millis(115));
+        assert_eq!(level.poll(Instant::from_millis(130)), None);
+        assert_eq!(level.poll(Instant::from_millis(135)), Some(true));
+    }
+
+    #[test]
+    fn waits_for_the_pin_to_settle() {
+        let mut pin = MockPin { high: true };
+        // Active low, so a high pin is inactive.
+        let mut level = Debounced::new(Duration::from_millis(10), true);
+        let started = Instant::now();
+        assert!(!block_on(wait_debounced(&mut pin, true, &mut level)));
+        assert!(started.elapsed() >= Duration::from_millis(10));
+        assert!(!level.level());
+    }
+}
@@ -1,44 +1,70 @@
 #![doc = include_str!("../../docs/lcd.md")]
 use embassy_stm32::{
     gpio::{Level, Output, Speed},
-    mode::Blocking,
-    peripherals::{PB10, PC2, PC3, PC8, PC9, PD11, SPI2},
+    mode::Async,
+    peripherals::{DMA1_CH3, DMA1_CH4, PB10, PC2, PC3, PC8, PC9, PD11, SPI2},
     spi::{Config, MODE_3, Spi},
     time::Hertz,
 };
-use embassy_sync::{
-    blocking_mutex::raw::{RawMutex, ThreadModeRawMutex},
-    mutex::Mutex,
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+use embassy_time::{Delay, Duration, Timer};
+use embedded_graphics::{
+    pixelcolor::{Rgb565, raw::ToBytes},
+    prelude::*,
+    primitives::Rectangle,
 };
-use embassy_time::Delay;
 use embedded_hal::{
     delay::DelayNs,
     digital::OutputPin,
-    spi::{ErrorKind, ErrorType, Operation, SpiBus, SpiDevice},
+    spi::{Error as _, ErrorKind, ErrorType, Operation, SpiBus, SpiDevice},
 };
-use mipidsi::{Builder, Display, interface::SpiInterface, models::ST7789, options::Orientation};
+use embedded_hal_async::spi::{SpiBus as AsyncSpiBus, SpiDevice as AsyncSpiDevice};
+use mipidsi::{Builder, Display, interface::Interface, models::ST7789, options::Orientation};
 use static_cell::StaticCell;
+use thiserror::Error;
 
-pub type BuddyLcd<'a> = LcdDevice<ThreadModeRawMutex, Spi<'a, Blocking>, Output<'a>>;
-pub type BuddyDisplay<'a> = Mutex<
-    ThreadModeRawMutex,
-    Display<SpiInterface<'a, BuddyLcd<'a>, Output<'a>>, ST7789, Output<'a>>,
->;
+use crate::ui::BlankableDisplay;
 
+/// The ST7789 commands used to stream pixels into a region.
+const CASET: u8 = 0x2A;
+const RASET: u8 = 0x2B;
+const RAMWR: u8 = 0x2C;
+
+/// The ST7789 commands used to manage power and brightness.
+const SLPIN: u8 = 0x10;
+const SLPOUT: u8 = 0x11;
+const DISPOFF: u8 = 0x28;
+const DISPON: u8 = 0x29;
+const WRDISBV: u8 = 0x51;
+const WRCTRLD: u8 = 0x53;
+
+/// Enables the brightness control block and the backlight output.
+const WRCTRLD_BRIGHTNESS: u8 = 0x2C;
+
+/// How long the controller needs after entering or leaving sleep before it accepts the next sleep command.
+const SLEEP_SETTLE: Duration = Duration::from_millis(120);
+
+pub type BuddyLcd<'a> = LcdDevice<Spi<'a, Async>, Output<'a>>;
+pub type BuddyDisplay<'a> =
+    Mutex<ThreadModeRawMutex, Lcd<'a, BuddyLcd<'a>, Output<'a>, Output<'a>>>;
+
+#[allow(clippy::too_many_arguments)]
 pub fn build_display<'a>(
     peri: SPI2,
     sck: PB10,
     mosi: PC3,
     miso: PC2,
+    tx_dma: DMA1_CH4,
+    rx_dma: DMA1_CH3,
     cs: PC9,
     dc: PD11,
     rst: PC8,
-) -> BuddyDisplay<'a> {
+) -> Result<BuddyDisplay<'a>, LcdError> {
     // Initialise the SPI
     let mut config = Config::default();
     config.mode = MODE_3;
     config.frequency = Hertz(16_000_000);
-    let spi = Spi::new_blocking(peri, sck, mosi, miso, config);
+    let spi = Spi::new(peri, sck, mosi, miso, tx_dma, rx_dma, config);
 
     // Create the device.
     let cs = Output::new(cs, Level::High, Speed::VeryHigh);
@@ -48,7 +74,7 @@ pub fn build_display<'a>(
     static BUFFER: StaticCell<[u8; 1024]> = StaticCell::new();
     let buf = BUFFER.init([0u8; 1024]);
     let dc = Output::new(dc, Level::Low, Speed::VeryHigh);
-    let di = SpiInterface::new(lcd_device, dc, buf);
+    let di = LcdInterface::new(lcd_device, dc, buf);
 
     // Create the display
     let rst = Output::new(rst, Level::Low, Speed::VeryHigh);
@@ -58,75 +84,418 @@ pub fn build_display<'a>(
         .reset_pin(rst)
         .orientation(Orientation::new().flip_vertical().flip_horizontal())
         .init(&mut delay)
-        .unwrap();
-    Mutex::new(display)
+        .map_err(|_| LcdError::Init)?;
+    Ok(Mutex::new(Lcd::new(display)))
+}
+
+/// The set of errors that may occur when driving the LCD.
+#[derive(Debug, Error)]
+pub enum LcdError {
+    #[error("SPI Error: {0:?}")]
+    Spi(ErrorKind),
+    #[error("Failed to set the data/command pin.")]
+    DataCommand,
+    #[error("Failed to initialise the display.")]
+    Init,
+    #[error("The region is outside the display.")]
+    OutOfBounds,
+    #[error(
+        "The number of pixels does not fill the region. Expected: {expected}, Received: {received}"
+    )]
+    PixelCount { expected: usize, received: usize },
 }
 
-pub struct LcdDevice<M: RawMutex, T: SpiBus, O: OutputPin> {
-    spi: Mutex<M, T>,
-    cs: Mutex<M, O>,
+/// The LCD on the SPI bus. The device owns the bus so each transaction runs to completion without contention.
+pub struct LcdDevice<T, O> {
+    spi: T,
+    cs: O,
 }
 
-impl<M: RawMutex, T: SpiBus, O: OutputPin> LcdDevice<M, T, O> {
+impl<T, O: OutputPin> LcdDevice<T, O> {
     pub fn new(spi: T, cs: O) -> Self {
-        Self {
-            spi: Mutex::new(spi),
-            cs: Mutex::new(cs),
-        }
+        Self { spi, cs }
+    }
+
+    fn select(&mut self) -> Result<(), ErrorKind> {
+        self.cs.set_low().map_err(|_| ErrorKind::ChipSelectFault)
+    }
+
+    fn deselect(&mut self) -> Result<(), ErrorKind> {
+        self.cs.set_high().map_err(|_| ErrorKind::ChipSelectFault)
     }
 }
 
-impl<M: RawMutex, T: SpiBus, O: OutputPin> ErrorType for LcdDevice<M, T, O> {
+impl<T, O> ErrorType for LcdDevice<T, O> {
     // Use their in-built ErrorKind.
     // Could abstract away in the future if needed.
     type Error = ErrorKind;
 }
 
-impl<M: RawMutex, T: SpiBus, O: OutputPin> SpiDevice for LcdDevice<M, T, O> {
-    fn transaction(
+impl<T: SpiBus, O: OutputPin> SpiDevice for LcdDevice<T, O> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.select()?;
+        let res = operations.iter_mut().try_for_each(|op| match op {
+            Operation::Read(read) => self.spi.read(read),
+            Operation::Write(write) => self.spi.write(write),
+            Operation::Transfer(read, write) => self.spi.transfer(read, write),
+            Operation::TransferInPlace(rw) => self.spi.transfer_in_place(rw),
+            Operation::DelayNs(ns) => {
+                Delay.delay_ns(*ns);
+                Ok(())
+            }
+        });
+        let res = res.and_then(|_| self.spi.flush()).map_err(|e| e.kind());
+        // Always release the chip select, even if the bus failed.
+        self.deselect()?;
+        res
+    }
+}
+
+impl<T: AsyncSpiBus, O: OutputPin> AsyncSpiDevice for LcdDevice<T, O> {
+    async fn transaction(
         &mut self,
-        operations: &mut [embedded_hal::spi::Operation<'_, u8>],
+        operations: &mut [Operation<'_, u8>],
     ) -> Result<(), Self::Error> {
-        match (self.cs.try_lock(), self.spi.try_lock()) {
-            (Ok(mut cs), Ok(mut spi)) => {
-                cs.set_low().unwrap();
-                for op in operations {
-                    match op {
-                        Operation::Read(read) => {
-                            if spi.read(read).is_err() {
-                                cs.set_high().unwrap();
-                                return Err(ErrorKind::Other);
-                            }
-                        }
-                        Operation::Write(write) => {
-                            if spi.write(write).is_err() {
-                                cs.set_high().unwrap();
-                                return Err(ErrorKind::Other);
-                            }
-                        }
-                        Operation::DelayNs(ns) => {
-                            let mut d = Delay;
-                            d.delay_ns(*ns);
-                        }
-                        Operation::Transfer(read, write) => {
-                            if spi.transfer(read, write).is_err() {
-                                cs.set_high().unwrap();
-                                return Err(ErrorKind::Other);
-                            }
-                        }
-                        Operation::TransferInPlace(rw) => {
-                            if spi.transfer_in_place(rw).is_err() {
-                                cs.set_high().unwrap();
-                                return Err(ErrorKind::Other);
-                            }
-                        }
-                    }
+        self.select()?;
+        let mut res = Ok(());
+        for op in operations {
+            res = match op {
+                Operation::Read(read) => self.spi.read(read).await,
+                Operation::Write(write) => self.spi.write(write).await,
+                Operation::Transfer(read, write) => self.spi.transfer(read, write).await,
+                Operation::TransferInPlace(rw) => self.spi.transfer_in_place(rw).await,
+                Operation::DelayNs(ns) => {
+                    Timer::after_nanos(*ns as u64).await;
+                    Ok(())
                 }
-                cs.set_high().unwrap();
+            };
+            if res.is_err() {
+                break;
+            }
+        }
+        if res.is_ok() {
+            res = self.spi.flush().await;
+        }
+        let res = res.map_err(|e| e.kind());
+        self.deselect()?;
+        res
+    }
+}
+
+/// The command/data interface to the ST7789. Pixels are gathered into the buffer and written in batches.
+pub struct LcdInterface<'a, D, DC> {
+    spi: D,
+    dc: DC,
+    buffer: &'a mut [u8],
+}
+
+impl<'a, D, DC: OutputPin> LcdInterface<'a, D, DC> {
+    pub fn new(spi: D, dc: DC, buffer: &'a mut [u8]) -> Self {
+        Self { spi, dc, buffer }
+    }
+
+    fn set_dc(&mut self, data: bool) -> Result<(), LcdError> {
+        let res = if data {
+            self.dc.set_high()
+        } else {
+            self.dc.set_low()
+        };
+        res.map_err(|_| LcdError::DataCommand)
+    }
+}
+
+impl<D: SpiDevice, DC: OutputPin> Interface for LcdInterface<'_, D, DC> {
+    type Word = u8;
+    type Error = LcdError;
+
+    fn send_command(&mut self, command: u8, args: &[u8]) -> Result<(), Self::Error> {
+        self.set_dc(false)?;
+        SpiDevice::write(&mut self.spi, &[command]).map_err(|e| LcdError::Spi(e.kind()))?;
+        self.set_dc(true)?;
+        SpiDevice::write(&mut self.spi, args).map_err(|e| LcdError::Spi(e.kind()))
+    }
+
+    fn send_pixels<const N: usize>(
+        &mut self,
+        pixels: impl IntoIterator<Item = [u8; N]>,
+    ) -> Result<(), Self::Error> {
+        let mut pixels = pixels.into_iter().peekable();
+        while pixels.peek().is_some() {
+            let mut len = 0;
+            for (chunk, pixel) in self
+                .buffer
+                .as_chunks_mut::<N>()
+                .0
+                .iter_mut()
+                .zip(&mut pixels)
+            {
+                *chunk = pixel;
+                len += N;
+            }
+            SpiDevice::write(&mut self.spi, &self.buffer[..len])
+                .map_err(|e| LcdError::Spi(e.kind()))?;
+        }
+        Ok(())
+    }
+
+    fn send_repeated_pixel<const N: usize>(
+        &mut self,
+        pixel: [u8; N],
+        count: u32,
+    ) -> Result<(), Self::Error> {
+        let mut remaining = count as usize * N;
+        let filled = fill_repeated(self.buffer, &pixel, remaining);
+        while remaining > 0 {
+            let len = remaining.min(filled);
+            SpiDevice::write(&mut self.spi, &self.buffer[..len])
+                .map_err(|e| LcdError::Spi(e.kind()))?;
+            remaining -= len;
+        }
+        Ok(())
+    }
+}
+
+impl<D: AsyncSpiDevice, DC: OutputPin> LcdInterface<'_, D, DC> {
+    async fn command(&mut self, command: u8, args: &[u8]) -> Result<(), LcdError> {
+        self.set_dc(false)?;
+        AsyncSpiDevice::write(&mut self.spi, &[command])
+            .await
+            .map_err(|e| LcdError::Spi(e.kind()))?;
+        self.set_dc(true)?;
+        AsyncSpiDevice::write(&mut self.spi, args)
+            .await
+            .map_err(|e| LcdError::Spi(e.kind()))
+    }
+
+    /// Set the address window to the region and start a memory write.
+    async fn start_region(&mut self, area: &Rectangle) -> Result<(), LcdError> {
+        // Callers have checked the region is on the display so it is not empty.
+        let bottom_right = area.bottom_right().ok_or(LcdError::OutOfBounds)?;
+        let (sx, sy) = (area.top_left.x as u16, area.top_left.y as u16);
+        let (ex, ey) = (bottom_right.x as u16, bottom_right.y as u16);
+        let [sx0, sx1] = sx.to_be_bytes();
+        let [ex0, ex1] = ex.to_be_bytes();
+        self.command(CASET, &[sx0, sx1, ex0, ex1]).await?;
+        let [sy0, sy1] = sy.to_be_bytes();
+        let [ey0, ey1] = ey.to_be_bytes();
+        self.command(RASET, &[sy0, sy1, ey0, ey1]).await?;
+        self.command(RAMWR, &[]).await
+    }
+
+    async fn fill_async(&mut self, area: &Rectangle, color: Rgb565) -> Result<(), LcdError> {
+        self.start_region(area).await?;
+        let mut remaining = area.size.width as usize * area.size.height as usize * 2;
+        let filled = fill_repeated(self.buffer, &color.to_be_bytes(), remaining);
+        while remaining > 0 {
+            let len = remaining.min(filled);
+            AsyncSpiDevice::write(&mut self.spi, &self.buffer[..len])
+                .await
+                .map_err(|e| LcdError::Spi(e.kind()))?;
+            remaining -= len;
+        }
+        Ok(())
+    }
+
+    async fn blit_async(&mut self, area: &Rectangle, pixels: &[Rgb565]) -> Result<(), LcdError> {
+        self.start_region(area).await?;
+        for chunk in pixels.chunks(self.buffer.len() / 2) {
+            for (bytes, pixel) in self.buffer.as_chunks_mut::<2>().0.iter_mut().zip(chunk) {
+                *bytes = pixel.to_be_bytes();
+            }
+            AsyncSpiDevice::write(&mut self.spi, &self.buffer[..chunk.len() * 2])
+                .await
+                .map_err(|e| LcdError::Spi(e.kind()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Fill as much of the buffer as needed with whole copies of the pixel, returning the number of bytes filled.
+fn fill_repeated(buffer: &mut [u8], pixel: &[u8], len: usize) -> usize {
+    let filled = len.min(buffer.len() - buffer.len() % pixel.len());
+    for chunk in buffer[..filled].chunks_exact_mut(pixel.len()) {
+        chunk.copy_from_slice(pixel);
+    }
+    filled
+}
+
+/// The ST7789 display. It can be drawn to with `embedded-graphics` using blocking transfers or have regions filled and
+/// blitted over DMA without blocking the executor.
+pub struct Lcd<'a, D: SpiDevice, DC: OutputPin, RST: OutputPin> {
+    display: Display<LcdInterface<'a, D, DC>, ST7789, RST>,
+    sleeping: bool,
+    display_on: bool,
+}
+
+impl<'a, D, DC, RST> Lcd<'a, D, DC, RST>
+where
+    D: SpiDevice + AsyncSpiDevice,
+    DC: OutputPin,
+    RST: OutputPin,
+{
+    pub fn new(display: Display<LcdInterface<'a, D, DC>, ST7789, RST>) -> Self {
+        Self {
+            display,
+            sleeping: false,
+            display_on: true,
+        }
+    }
+
+    fn check_region(&self, area: &Rectangle) -> Result<(), LcdError> {
+        let screen = self.display.bounding_box();
+        match area.bottom_right() {
+            Some(bottom_right)
+                if screen.contains(area.top_left) && screen.contains(bottom_right) =>
+            {
                 Ok(())
             }
-            (Err(_), _) => Err(ErrorKind::Other),
-            (_, Err(_)) => Err(ErrorKind::Other),
+            _ => Err(LcdError::OutOfBounds),
+        }
+    }
+
+    fn interface(&mut self) -> &mut LcdInterface<'a, D, DC> {
+        // SAFETY: Only the address window, memory write, power and brightness commands are sent through the interface.
+        // mipidsi sets the address window again before every draw and does not depend on the power or brightness
+        // state, so its view of the controller is unchanged.
+        unsafe { self.display.dcs() }
+    }
+
+    /// Fill a region with a single colour.
+    pub async fn fill_region(&mut self, area: Rectangle, color: Rgb565) -> Result<(), LcdError> {
+        self.check_region(&area)?;
+        self.interface().fill_async(&area, color).await
+    }
+
+    /// Copy pixels into a region, row by row from the top left.
+    pub async fn blit(&mut self, area: Rectangle, pixels: &[Rgb565]) -> Result<(), LcdError> {
+        self.check_region(&area)?;
+        let expected = area.size.width as usize * area.size.height as usize;
+        if pixels.len() != expected {
+            return Err(LcdError::PixelCount {
+                expected,
+                received: pixels.len(),
+            });
+        }
+        self.interface().blit_async(&area, pixels).await
+    }
+
+    pub fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    pub fn is_display_on(&self) -> bool {
+        self.display_on
+    }
+
+    /// Turn the panel output on or off. The frame memory is kept and can still be drawn to while the panel is off.
+    pub async fn set_display_on(&mut self, on: bool) -> Result<(), LcdError> {
+        let command = if on { DISPON } else { DISPOFF };
+        self.interface().command(command, &[]).await?;
+        self.display_on = on;
+        Ok(())
+    }
+
+    /// Put the controller into its low power sleep mode.
+    pub async fn sleep(&mut self) -> Result<(), LcdError> {
+        if self.sleeping {
+            return Ok(());
         }
+        self.interface().command(SLPIN, &[]).await?;
+        self.sleeping = true;
+        Timer::after(SLEEP_SETTLE).await;
+        Ok(())
+    }
+
+    /// Bring the controller out of sleep mode.
+    pub async fn wake(&mut self) -> Result<(), LcdError> {
+        if !self.sleeping {
+            return Ok(());
+        }
+        self.interface().command(SLPOUT, &[]).await?;
+        self.sleeping = false;
+        Timer::after(SLEEP_SETTLE).await;
+        Ok(())
+    }
+
+    /// Turn the panel off and put the controller to sleep.
+    pub async fn blank(&mut self) -> Result<(), LcdError> {
+        self.set_display_on(false).await?;
+        self.sleep().await
+    }
+
+    /// Wake the controller and turn the panel back on.
+    pub async fn unblank(&mut self) -> Result<(), LcdError> {
+        self.wake().await?;
+        self.set_display_on(true).await
+    }
+
+    /// Set the backlight brightness from 0 (off) to 255 (full).
+    ///
+    /// The Buddy board has no PWM pin for the backlight so this sets the brightness register of the ST7789, which
+    /// only dims panels that take their backlight from the controller.
+    pub async fn set_brightness(&mut self, brightness: u8) -> Result<(), LcdError> {
+        let interface = self.interface();
+        interface.command(WRCTRLD, &[WRCTRLD_BRIGHTNESS]).await?;
+        interface.command(WRDISBV, &[brightness]).await
+    }
+}
+
+impl<D, DC, RST> BlankableDisplay for Lcd<'_, D, DC, RST>
+where
+    D: SpiDevice + AsyncSpiDevice,
+    DC: OutputPin,
+    RST: OutputPin,
+{
+    type Error = LcdError;
+
+    async fn blank(&mut self) -> Result<(), LcdError> {
+        Lcd::blank(self).await
+    }
+
+    async fn unblank(&mut self) -> Result<(), LcdError> {
+        Lcd::unblank(self).await
+    }
+}
+
+impl<D, DC, RST> OriginDimensions for Lcd<'_, D, DC, RST>
+where
+    D: SpiDevice,
+    DC: OutputPin,
+    RST: OutputPin,
+{
+    fn size(&self) -> Size {
+        self.display.size()
+    }
+}
+
+impl<D, DC, RST> DrawTarget for Lcd<'_, D, DC, RST>
+where
+    D: SpiDevice,
+    DC: OutputPin,
+    RST: OutputPin,
+{
+    type Color = Rgb565;
+    type Error = LcdError;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.display.draw_iter(pixels)
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        self.display.fill_contiguous(area, colors)
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.display.fill_solid(area, color)
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.display.clear(color)
     }
 }
@@ -0,0 +1,467 @@
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal_async::i2c::I2c;
+
+use crate::{
+    components::eeprom::{
+        error::St25dvError,
+        ndef::NDEF_AREA_SIZE,
+        st25dv::{Memory, St25dv, USER_MEMORY_SIZE},
+    },
+    components::ethernet::NetworkConfig,
+    crc::crc32,
+};
+
+/// The user memory address of the [`BoardConfig`], following the NDEF area.
+pub const BOARD_CONFIG_ADDR: u16 = NDEF_AREA_SIZE;
+
+/// Marks the start of a configuration block ("BD").
+const MAGIC: u16 = 0x4244;
+
+/// Block layout: magic (2), version (2), payload length (2), payload (n), crc of the previous bytes (4).
+const HEADER_LEN: usize = 6;
+const CRC_LEN: usize = 4;
+
+/// A configuration that can be persisted by a [`ConfigStore`].
+pub trait ConfigBlock: Default {
+    /// The schema version written alongside the payload. Bump it whenever the encoding changes.
+    const VERSION: u16;
+
+    /// The length of the encoded payload in bytes.
+    const SIZE: usize;
+
+    /// Encode the configuration into `buf`, which is [`ConfigBlock::SIZE`] bytes long.
+    fn encode(&self, buf: &mut [u8]);
+
+    /// Decode a payload written with the current [`ConfigBlock::VERSION`].
+    fn decode(buf: &[u8]) -> Option<Self>;
+
+    /// Upgrade a payload written by an older version of the schema. Returning `None` falls back to the defaults.
+    fn migrate(_version: u16, _buf: &[u8]) -> Option<Self> {
+        None
+    }
+}
+
+/// Where a loaded configuration came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ConfigSource {
+    /// Read back as written.
+    Stored,
+    /// Upgraded from an older schema version and saved in the current version.
+    Migrated,
+    /// Nothing valid was stored so the defaults were used.
+    Default,
+}
+
+/// Persists a versioned, CRC protected configuration block in the EEPROM user memory.
+pub struct ConfigStore<'a, T: I2c, M: RawMutex> {
+    eeprom: &'a St25dv<T, M>,
+    /// The user memory address of the block.
+    addr: u16,
+}
+
+impl<'a, T: I2c, M: RawMutex> ConfigStore<'a, T, M> {
+    /// Create a store for a configuration block at `addr` in the user memory.
+    pub fn new(eeprom: &'a St25dv<T, M>, addr: u16) -> Self {
+        Self { eeprom, addr }
+    }
+
+    /// Load the configuration. A missing or corrupt block falls back to the defaults and an older schema is migrated and saved back.
+    pub async fn load<C: ConfigBlock>(&self) -> Result<(C, ConfigSource), St25dvError> {
+        let max = self.max_payload();
+        let mut buf: [u8; USER_MEMORY_SIZE as usize] = [0; USER_MEMORY_SIZE as usize];
+
+        let header = &mut buf[..HEADER_LEN];
+        self.eeprom.read(Memory::User, self.addr, header).await?;
+        let magic = u16::from_le_bytes([header[0], header[1]]);
+        let version = u16::from_le_bytes([header[2], header[3]]);
+        let len = u16::from_le_bytes([header[4], header[5]]) as usize;
+        if magic != MAGIC || len > max {
+            return Ok((C::default(), ConfigSource::Default));
+        }
+
+        let total = HEADER_LEN + len + CRC_LEN;
+        self.eeprom
+            .read(
+                Memory::User,
+                self.addr + HEADER_LEN as u16,
+                &mut buf[HEADER_LEN..total],
+            )
+            .await?;
+        let crc = u32::from_le_bytes([
+            buf[total - 4],
+            buf[total - 3],
+            buf[total - 2],
+            buf[total - 1],
+        ]);
+        if crc != crc32(&buf[..total - CRC_LEN]) {
+            return Ok((C::default(), ConfigSource::Default));
+        }
+
+        let payload = &buf[HEADER_LEN..HEADER_LEN + len];
+        if version == C::VERSION && len == C::SIZE {
+            if let Some(config) = C::decode(payload) {
+                return Ok((config, ConfigSource::Stored));
+            }
+        } else if version < C::VERSION
+            && let Some(config) = C::migrate(version, payload)
+        {
+            self.save(&config).await?;
+            return Ok((config, ConfigSource::Migrated));
+        }
+        Ok((C::default(), ConfigSource::Default))
+    }
+
+    /// Save the configuration in the current schema version.
+    pub async fn save<C: ConfigBlock>(&self, config: &C) -> Result<(), St25dvError> {
+        let max = self.max_payload();
+        if C::SIZE > max {
+            return Err(St25dvError::ConfigTooLarge {
+                max,
+                received: C::SIZE,
+            });
+        }
+        let mut buf: [u8; USER_MEMORY_SIZE as usize] = [0; USER_MEMORY_SIZE as usize];
+        let total = HEADER_LEN + C::SIZE + CRC_LEN;
+        buf[..2].copy_from_slice(&MAGIC.to_le_bytes());
+        buf[2..4].copy_from_slice(&C::VERSION.to_le_bytes());
+        buf[4..6].copy_from_slice(&(C::SIZE as u16).to_le_bytes());
+        config.encode(&mut buf[HEADER_LEN..HEADER_LEN + C::SIZE]);
+        let crc = crc32(&buf[..total - CRC_LEN]);
+        buf[total - CRC_LEN..total].copy_from_slice(&crc.to_le_bytes());
+
+        self.eeprom
+            .write(Memory::User, self.addr, &buf[..total])
+            .await?;
+        Ok(())
+    }
+
+    fn max_payload(&self) -> usize {
+        (USER_MEMORY_SIZE as usize).saturating_sub(self.addr as usize + HEADER_LEN + CRC_LEN)
+    }
+}
+
+/// The calibration of a thermistor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermistorCalibration {
+    /// The beta value of the thermistor.
+    pub beta: f64,
+    /// The reference resistance of the thermistor.
+    pub r_ref: f64,
+    /// The reference temperature (usually 25C).
+    pub t_ref: f64,
+}
+
+impl ThermistorCalibration {
+    const SIZE: usize = 24;
+
+    fn encode(&self, buf: &mut [u8]) {
+        buf[..8].copy_from_slice(&self.beta.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.r_ref.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.t_ref.to_le_bytes());
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        let f = |i: usize| f64::from_le_bytes(buf[i..i + 8].try_into().unwrap());
+        Self {
+            beta: f(0),
+            r_ref: f(8),
+            t_ref: f(16),
+        }
+    }
+}
+
+/// Board specific settings persisted in the EEPROM and applied by the [`BoardBuilder`](crate::BoardBuilder).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoardConfig {
+    pub mac_addr: [u8; 6],
+    pub bed_thermistor: ThermistorCalibration,
+    pub board_thermistor: ThermistorCalibration,
+    pub hotend_thermistor: ThermistorCalibration,
+    pub network: NetworkConfig,
+}
+
+impl Default for BoardConfig {
+    fn default() -> Self {
+        Self {
+            mac_addr: [0x00, 0x00, 0xDE, 0xAD, 0xBE, 0xEF],
+            bed_thermistor: ThermistorCalibration {
+                beta: 4_092.0,
+                r_ref: 100_000.0,
+                t_ref: 25.0,
+            },
+            board_thermistor: ThermistorCalibration {
+                beta: 4_550.0,
+                r_ref: 100_000.0,
+                t_ref: 25.0,
+            },
+            hotend_thermistor: ThermistorCalibration {
+                beta: 4_267.0,
+                r_ref: 100_000.0,
+                t_ref: 25.0,
+            },
+            network: NetworkConfig::default(),
+        }
+    }
+}
+
+impl BoardConfig {
+    /// The payload of version 1, which had no network config.
+    const V1_SIZE: usize = 6 + 3 * ThermistorCalibration::SIZE;
+}
+
+impl ConfigBlock for BoardConfig {
+    const VERSION: u16 = 2;
+    const SIZE: usize = Self::V1_SIZE + NetworkConfig::SIZE;
+
+    fn encode(&self, buf: &mut [u8]) {
+        buf[..6].copy_from_slice(&self.mac_addr);
+        let (bed, rest) = buf[6..].split_at_mut(ThermistorCalibration::SIZE);
+        let (board, rest) = rest.split_at_mut(ThermistorCalibration::SIZE);
+        let (hotend, network) = rest.split_at_mut(ThermistorCalibration::SIZE);
+        self.bed_thermistor.encode(bed);
+        self.board_thermistor.encode(board);
+        self.hotend_thermistor.encode(hotend);
+        self.network.encode(network);
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        let mut config = Self::migrate(1, &buf[..Self::V1_SIZE])?;
+        config.network = NetworkConfig::decode(&buf[Self::V1_SIZE..])?;
+        Some(config)
+    }
+
+    fn migrate(version: u16, buf: &[u8]) -> Option<Self> {
+        if version != 1 || buf.len() != Self::V1_SIZE {
+            return None;
+        }
+        let t = |n: usize| {
+            let start = 6 + n * ThermistorCalibration::SIZE;
+            ThermistorCalibration::decode(&buf[start..start + ThermistorCalibration::SIZE])
+        };
+        Some(Self {
+            mac_addr: buf[..6].try_into().ok()?,
+            bed_thermistor: t(0),
+            board_thermistor: t(1),
+            hotend_thermistor: t(2),
+            network: NetworkConfig::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::mock::MockI2c;
+
+    const USER: u8 = Memory::User.device_select_code();
+
+    type Eeprom = St25dv<MockI2c, NoopRawMutex>;
+
+    fn eeprom() -> Eeprom {
+        St25dv::new(MockI2c::new(&[USER]))
+    }
+
+    /// A schema holding one `u16`.
+    #[derive(Debug, Default, PartialEq)]
+    struct SettingsV1 {
+        speed: u16,
+    }
+
+    impl ConfigBlock for SettingsV1 {
+        const VERSION: u16 = 1;
+        const SIZE: usize = 2;
+
+        fn encode(&self, buf: &mut [u8]) {
+            buf.copy_from_slice(&self.speed.to_le_bytes());
+        }
+
+        fn decode(buf: &[u8]) -> Option<Self> {
+            Some(Self {
+                speed: u16::from_le_bytes([buf[0], buf[1]]),
+            })
+        }
+    }
+
+    /// The next version of [`SettingsV1`], adding a flag.
+    #[derive(Debug, Default, PartialEq)]
+    struct SettingsV2 {
+        speed: u16,
+        quiet: bool,
+    }
+
+    impl ConfigBlock for SettingsV2 {
+        const VERSION: u16 = 2;
+        const SIZE: usize = 3;
+
+        fn encode(&self, buf: &mut [u8]) {
+            buf[..2].copy_from_slice(&self.speed.to_le_bytes());
+            buf[2] = self.quiet as u8;
+        }
+
+        fn decode(buf: &[u8]) -> Option<Self> {
+            Some(Self {
+                speed: u16::from_le_bytes([buf[0], buf[1]]),
+                quiet: buf[2] != 0,
+            })
+        }
+
+        fn migrate(version: u16, buf: &[u8]) -> Option<Self> {
+            let v1 = (version == 1).then(|| SettingsV1::decode(buf))??;
+            Some(Self {
+                speed: v1.speed,
+                quiet: true,
+            })
+        }
+    }
+
+    /// A schema that fills the whole user memory.
+    #[derive(Default)]
+    struct Huge;
+
+    impl ConfigBlock for Huge {
+        const VERSION: u16 = 1;
+        const SIZE: usize = USER_MEMORY_SIZE as usize;
+
+        fn encode(&self, _buf: &mut [u8]) {}
+
+        fn decode(_buf: &[u8]) -> Option<Self> {
+            Some(Self)
+        }
+    }
+
+    fn board_config() -> BoardConfig {
+        BoardConfig {
+            mac_addr: [0x02, 0x11, 0x22, 0x33, 0x44, 0x55],
+            hotend_thermistor: ThermistorCalibration {
+                beta: 4_100.0,
+                r_ref: 99_500.0,
+                t_ref: 25.0,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let eeprom = eeprom();
+        let store = ConfigStore::new(&eeprom, BOARD_CONFIG_ADDR);
+        block_on(store.save(&board_config())).unwrap();
+        let (config, source) = block_on(store.load::<BoardConfig>()).unwrap();
+        assert_eq!(source, ConfigSource::Stored);
+        assert_eq!(config, board_config());
+
+        // The block is laid out as magic, version, length, payload and CRC.
+        let i2c = eeprom.bus();
+        let block = i2c.mem(
+            USER,
+            BOARD_CONFIG_ADDR,
+            HEADER_LEN + BoardConfig::SIZE + CRC_LEN,
+        );
+        assert_eq!(block[..4], [0x44, 0x42, 0x02, 0x00]);
+        assert_eq!(block[4..6], (BoardConfig::SIZE as u16).to_le_bytes());
+        let crc = crc32(&block[..block.len() - CRC_LEN]);
+        assert_eq!(block[block.len() - CRC_LEN..], crc.to_le_bytes());
+    }
+
+    #[test]
+    fn blank_memory_loads_the_defaults() {
+        let eeprom = eeprom();
+        let store = ConfigStore::new(&eeprom, BOARD_CONFIG_ADDR);
+        let (config, source) = block_on(store.load::<BoardConfig>()).unwrap();
+        assert_eq!(source, ConfigSource::Default);
+        assert_eq!(config, BoardConfig::default());
+    }
+
+    #[test]
+    fn crc_mismatch_loads_the_defaults() {
+        let eeprom = eeprom();
+        let store = ConfigStore::new(&eeprom, 0);
+        block_on(store.save(&SettingsV1 { speed: 1234 })).unwrap();
+        {
+            let mut i2c = eeprom.bus();
+            let byte = i2c.mem(USER, HEADER_LEN as u16, 1)[0];
+            i2c.set(USER, HEADER_LEN as u16, &[byte ^ 0x01]);
+        }
+        let (config, source) = block_on(store.load::<SettingsV1>()).unwrap();
+        assert_eq!(source, ConfigSource::Default);
+        assert_eq!(config, SettingsV1::default());
+    }
+
+    #[test]
+    fn bad_magic_loads_the_defaults() {
+        let eeprom = eeprom();
+        let store = ConfigStore::new(&eeprom, 0);
+        block_on(store.save(&SettingsV1 { speed: 1234 })).unwrap();
+        eeprom.bus().set(USER, 0, &[0x42, 0x44]);
+        let (_, source) = block_on(store.load::<SettingsV1>()).unwrap();
+        assert_eq!(source, ConfigSource::Default);
+    }
+
+    #[test]
+    fn newer_version_loads_the_defaults() {
+        let eeprom = eeprom();
+        let store = ConfigStore::new(&eeprom, 0);
+        block_on(store.save(&SettingsV2 {
+            speed: 1234,
+            quiet: false,
+        }))
+        .unwrap();
+        // Firmware that only knows version 1 must not guess at a newer layout, nor overwrite it.
+        let (config, source) = block_on(store.load::<SettingsV1>()).unwrap();
+        assert_eq!(source, ConfigSource::Default);
+        assert_eq!(config, SettingsV1::default());
+        let (_, source) = block_on(store.load::<SettingsV2>()).unwrap();
+        assert_eq!(source, ConfigSource::Stored);
+    }
+
+    #[test]
+    fn older_version_is_migrated_and_saved() {
+        let eeprom = eeprom();
+        let store = ConfigStore::new(&eeprom, 0);
+        block_on(store.save(&SettingsV1 { speed: 1234 })).unwrap();
+        let expected = SettingsV2 {
+            speed: 1234,
+            quiet: true,
+        };
+        let (config, source) = block_on(store.load::<SettingsV2>()).unwrap();
+        assert_eq!(source, ConfigSource::Migrated);
+        assert_eq!(config, expected);
+        let (config, source) = block_on(store.load::<SettingsV2>()).unwrap();
+        assert_eq!(source, ConfigSource::Stored);
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn board_config_migrates_from_version_1() {
+        let eeprom = eeprom();
+        let store = ConfigStore::new(&eeprom, BOARD_CONFIG_ADDR);
+        let old = board_config();
+        let mut payload = [0; BoardConfig::SIZE];
+        old.encode(&mut payload);
+        // A version 1 block is the current payload without the network config.
+        let mut block = Vec::new();
+        block.extend_from_slice(&MAGIC.to_le_bytes());
+        block.extend_from_slice(&1u16.to_le_bytes());
+        block.extend_from_slice(&(BoardConfig::V1_SIZE as u16).to_le_bytes());
+        block.extend_from_slice(&payload[..BoardConfig::V1_SIZE]);
+        block.extend_from_slice(&crc32(&block).to_le_bytes());
+        eeprom.bus().set(USER, BOARD_CONFIG_ADDR, &block);
+
+        let (config, source) = block_on(store.load::<BoardConfig>()).unwrap();
+        assert_eq!(source, ConfigSource::Migrated);
+        assert_eq!(config, old);
+    }
+
+    #[test]
+    fn oversized_config_is_rejected() {
+        let eeprom = eeprom();
+        let store = ConfigStore::new(&eeprom, BOARD_CONFIG_ADDR);
+        assert!(matches!(
+            block_on(store.save(&Huge)),
+            Err(St25dvError::ConfigTooLarge { max: 118, .. })
+        ));
+    }
+}
@@ -0,0 +1,59 @@
+use thiserror::Error;
+
+/// The set of errors that may occur when interacting with the EEPROM.
+#[derive(Debug, Error)]
+pub enum St25dvError {
+    #[error("Read Error")]
+    ReadError,
+    #[error("Write Error")]
+    WriteError,
+    #[error(
+        "The size (const N) used in the sequential write is too small to hold your payload + byte address."
+    )]
+    SequentialWritePayloadTooSmall,
+    #[error("The address range is outside the memory.")]
+    OutOfBounds,
+    #[error("The device did not finish writing in time.")]
+    WriteTimeout,
+    #[error("Failed to pack the register.")]
+    PackingError,
+    #[error("Failed to unpack the register.")]
+    UnpackingError,
+    #[error("The password was not accepted.")]
+    InvalidPassword,
+    #[error("Area {0} does not exist. Areas are numbered 1 to 4.")]
+    InvalidArea(u8),
+    #[error("The mailbox is disabled.")]
+    MailboxDisabled,
+    #[error("The mailbox holds a message that has not been read.")]
+    MailboxBusy,
+    #[error("The mailbox does not hold a message from the reader.")]
+    MailboxEmpty,
+    #[error("The reader did not collect the message before the watchdog released it.")]
+    MailboxMessageMissed,
+    #[error("The message does not fit in the mailbox or buffer. Max: {max}, Received: {received}")]
+    MailboxMessageSize { max: usize, received: usize },
+    #[error("NDEF Error: {0}")]
+    Ndef(#[from] NdefError),
+    #[error("The configuration is too large for the user memory. Max: {max}, Received: {received}")]
+    ConfigTooLarge { max: usize, received: usize },
+}
+
+/// The set of errors that may occur when encoding or decoding NDEF messages.
+#[derive(Debug, Error)]
+pub enum NdefError {
+    #[error("The buffer is too small to hold the message.")]
+    BufferTooSmall,
+    #[error("The tag memory does not start with a valid capability container.")]
+    InvalidCapabilityContainer,
+    #[error("The tag memory does not contain an NDEF message.")]
+    NoMessage,
+    #[error("The record is malformed.")]
+    Malformed,
+    #[error("Chunked records are not supported.")]
+    Chunked,
+    #[error("The record contains invalid UTF-8.")]
+    InvalidUtf8,
+    #[error("UTF-16 text records are not supported.")]
+    Utf16Text,
+}
@@ -0,0 +1,359 @@
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::{Duration, Timer};
+use embedded_hal_async::i2c::I2c;
+
+use crate::components::eeprom::{
+    error::St25dvError,
+    registers::{MbCtrlDyn, MbLenDyn, MbMode},
+    st25dv::{Memory, St25dv},
+};
+
+/// The address of the mailbox buffer.
+pub const MAILBOX_ADDR: u16 = 0x2008;
+
+/// The size of the mailbox buffer.
+pub const MAILBOX_SIZE: usize = 256;
+
+impl<T: I2c, M: RawMutex> St25dv<T, M> {
+    /// Allow or prevent the mailbox from being enabled. This is persisted in the system configuration so requires an open security session.
+    pub async fn set_mailbox_mode(&self, allowed: bool) -> Result<(), St25dvError> {
+        self.write_register(&MbMode { mb_mode: allowed }).await
+    }
+
+    /// Enable the mailbox. The mailbox must be allowed by [`St25dv::set_mailbox_mode`].
+    pub async fn mailbox_enable(&self) -> Result<(), St25dvError> {
+        self.write_register(&MbCtrlDyn {
+            mb_en: true,
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Disable the mailbox, discarding any message it holds.
+    pub async fn mailbox_disable(&self) -> Result<(), St25dvError> {
+        self.write_register(&MbCtrlDyn::default()).await
+    }
+
+    /// Read the status of the mailbox.
+    pub async fn mailbox_status(&self) -> Result<MbCtrlDyn, St25dvError> {
+        let mut status = MbCtrlDyn::default();
+        self.read_register(&mut status).await?;
+        Ok(status)
+    }
+
+    /// Read the length of the message in the mailbox.
+    pub async fn mailbox_len(&self) -> Result<usize, St25dvError> {
+        let mut len = MbLenDyn::default();
+        self.read_register(&mut len).await?;
+        Ok(len.mb_len as usize + 1)
+    }
+
+    /// Put a message in the mailbox for the reader. The message is written in a single transfer as the mailbox treats each write as a new message.
+    pub async fn mailbox_write(&self, data: &[u8]) -> Result<(), St25dvError> {
+        if data.is_empty() || data.len() > MAILBOX_SIZE {
+            return Err(St25dvError::MailboxMessageSize {
+                max: MAILBOX_SIZE,
+                received: data.len(),
+            });
+        }
+        let status = self.mailbox_status().await?;
+        if !status.mb_en {
+            return Err(St25dvError::MailboxDisabled);
+        }
+        if status.host_put_msg || status.rf_put_msg {
+            return Err(St25dvError::MailboxBusy);
+        }
+        self.sequential_write::<{ MAILBOX_SIZE + 2 }>(Memory::Dynamic, MAILBOX_ADDR, data)
+            .await
+    }
+
+    /// Read the message the reader put in the mailbox, returning its length. Reading the message frees the mailbox.
+    pub async fn mailbox_read(&self, buf: &mut [u8]) -> Result<usize, St25dvError> {
+        let status = self.mailbox_status().await?;
+        if !status.mb_en {
+            return Err(St25dvError::MailboxDisabled);
+        }
+        if !status.rf_put_msg {
+            return Err(St25dvError::MailboxEmpty);
+        }
+        let len = self.mailbox_len().await?;
+        if len > buf.len() {
+            return Err(St25dvError::MailboxMessageSize {
+                max: buf.len(),
+                received: len,
+            });
+        }
+        self.read(Memory::Dynamic, MAILBOX_ADDR, &mut buf[..len])
+            .await?;
+        Ok(len)
+    }
+}
+
+/// A request/response channel over the mailbox. The reader puts a request in the mailbox and collects the response written back by the firmware, e.g. for a tap-to-configure workflow.
+pub struct MailboxChannel<'a, T: I2c, M: RawMutex> {
+    eeprom: &'a St25dv<T, M>,
+    /// How often to check the mailbox.
+    poll_interval: Duration,
+}
+
+impl<'a, T: I2c, M: RawMutex> MailboxChannel<'a, T, M> {
+    /// Create a new channel. The mailbox should be enabled before it is used.
+    pub fn new(eeprom: &'a St25dv<T, M>, poll_interval: Duration) -> Self {
+        Self {
+            eeprom,
+            poll_interval,
+        }
+    }
+
+    /// Wait for a request from the reader and read it into `buf`, returning its length.
+    pub async fn receive(&self, buf: &mut [u8]) -> Result<usize, St25dvError> {
+        loop {
+            let status = self.eeprom.mailbox_status().await?;
+            if !status.mb_en {
+                return Err(St25dvError::MailboxDisabled);
+            }
+            if status.rf_put_msg {
+                return self.eeprom.mailbox_read(buf).await;
+            }
+            Timer::after(self.poll_interval).await;
+        }
+    }
+
+    /// Send a response and wait until the reader has collected it.
+    pub async fn respond(&self, data: &[u8]) -> Result<(), St25dvError> {
+        self.eeprom.mailbox_write(data).await?;
+        loop {
+            Timer::after(self.poll_interval).await;
+            let status = self.eeprom.mailbox_status().await?;
+            if status.host_miss_msg {
+                return Err(St25dvError::MailboxMessageMissed);
+            }
+            if !status.host_put_msg {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Answer requests with the response written into the buffer by `handler`, which returns the length of the response (zero to not respond). Only returns on an error.
+    pub async fn serve<F: FnMut(&[u8], &mut [u8]) -> usize>(
+        &self,
+        mut handler: F,
+    ) -> Result<(), St25dvError> {
+        let mut request: [u8; MAILBOX_SIZE] = [0; MAILBOX_SIZE];
+        let mut response: [u8; MAILBOX_SIZE] = [0; MAILBOX_SIZE];
+        loop {
+            let len = self.receive(&mut request).await?;
+            let n = handler(&request[..len], &mut response).min(MAILBOX_SIZE);
+            if n == 0 {
+                continue;
+            }
+            match self.respond(&response[..n]).await {
+                Ok(()) | Err(St25dvError::MailboxMessageMissed) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::mock::MockI2c;
+
+    const DYNAMIC: u8 = Memory::Dynamic.device_select_code();
+    const MB_CTRL: usize = 0x2006;
+    const MB_LEN: usize = 0x2007;
+    const MB_EN: u8 = 0x01;
+    const HOST_PUT_MSG: u8 = 0x02;
+    const RF_PUT_MSG: u8 = 0x04;
+    const HOST_MISS_MSG: u8 = 0x10;
+
+    /// An NFC reader on the other side of the mailbox.
+    #[derive(Default)]
+    struct Reader {
+        /// Requests put in the mailbox once the firmware has twice seen it free, so never between the firmware checking the mailbox and writing its response. The reader switches the mailbox off once they run out.
+        requests: VecDeque<Vec<u8>>,
+        /// The responses collected from the firmware.
+        responses: Vec<Vec<u8>>,
+        /// How many status polls pass before the reader collects a response.
+        collect_after: usize,
+        /// Leave responses for the watchdog to release rather than collecting them.
+        miss: bool,
+        waiting: usize,
+        free_polls: usize,
+        status_reads: usize,
+    }
+
+    impl Reader {
+        /// Act on the firmware's access to the mailbox. The reader only changes the mailbox between polls of the status.
+        fn on_access(&mut self, write: &[u8], read: usize, memory: &mut [u8]) {
+            let addr = u16::from_be_bytes([write[0], write[1]]) as usize;
+            match addr {
+                a if a == MAILBOX_ADDR as usize && write.len() > 2 => {
+                    memory[MB_CTRL] |= HOST_PUT_MSG;
+                    memory[MB_LEN] = (write.len() - 3) as u8;
+                    self.waiting = self.collect_after;
+                }
+                a if a == MAILBOX_ADDR as usize && read > 0 => memory[MB_CTRL] &= !RF_PUT_MSG,
+                MB_CTRL if read > 0 => {
+                    self.status_reads += 1;
+                    let free = memory[MB_CTRL] & (HOST_PUT_MSG | RF_PUT_MSG) == 0;
+                    self.free_polls = if free { self.free_polls + 1 } else { 0 };
+                    if memory[MB_CTRL] & HOST_PUT_MSG != 0 {
+                        if self.waiting > 0 {
+                            self.waiting -= 1;
+                        } else if self.miss {
+                            memory[MB_CTRL] = memory[MB_CTRL] & !HOST_PUT_MSG | HOST_MISS_MSG;
+                        } else {
+                            let len = memory[MB_LEN] as usize + 1;
+                            let start = MAILBOX_ADDR as usize;
+                            self.responses.push(memory[start..start + len].to_vec());
+                            memory[MB_CTRL] &= !HOST_PUT_MSG;
+                        }
+                    } else if self.free_polls >= 2 {
+                        match self.requests.pop_front() {
+                            Some(request) => {
+                                let start = MAILBOX_ADDR as usize;
+                                memory[start..start + request.len()].copy_from_slice(&request);
+                                memory[MB_LEN] = (request.len() - 1) as u8;
+                                memory[MB_CTRL] |= RF_PUT_MSG;
+                            }
+                            None => memory[MB_CTRL] &= !MB_EN,
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn eeprom(reader: &Rc<RefCell<Reader>>, ctrl: u8) -> St25dv<MockI2c, NoopRawMutex> {
+        let mut i2c = MockI2c::new(&[DYNAMIC]);
+        i2c.set(DYNAMIC, MB_CTRL as u16, &[ctrl]);
+        let reader = reader.clone();
+        i2c.hook = Some(Box::new(move |t, memory| {
+            if t.address == DYNAMIC && t.write.len() >= 2 {
+                let memory = memory.get_mut(&DYNAMIC).unwrap();
+                reader.borrow_mut().on_access(&t.write, t.read, memory);
+            }
+        }));
+        St25dv::new(i2c)
+    }
+
+    fn reader(requests: &[&[u8]]) -> Rc<RefCell<Reader>> {
+        Rc::new(RefCell::new(Reader {
+            requests: requests.iter().map(|r| r.to_vec()).collect(),
+            collect_after: 2,
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn receive_waits_for_a_request() {
+        let reader = reader(&[b"PING"]);
+        let eeprom = eeprom(&reader, MB_EN);
+        let channel = MailboxChannel::new(&eeprom, Duration::from_millis(1));
+        let mut buf = [0; 16];
+        assert_eq!(block_on(channel.receive(&mut buf)).unwrap(), 4);
+        assert_eq!(&buf[..4], b"PING");
+        // Two polls found the mailbox empty, then one found the request and one more checked it before reading.
+        assert_eq!(reader.borrow().status_reads, 4);
+        // Reading the message frees the mailbox.
+        assert_eq!(eeprom.bus().mem(DYNAMIC, MB_CTRL as u16, 1), [MB_EN]);
+    }
+
+    #[test]
+    fn receive_needs_an_enabled_mailbox() {
+        let reader = reader(&[b"PING"]);
+        let eeprom = eeprom(&reader, 0);
+        let channel = MailboxChannel::new(&eeprom, Duration::from_millis(1));
+        let mut buf = [0; 16];
+        assert!(matches!(
+            block_on(channel.receive(&mut buf)),
+            Err(St25dvError::MailboxDisabled)
+        ));
+    }
+
+    #[test]
+    fn requests_must_fit_the_buffer() {
+        let reader = reader(&[b"A LONG REQUEST"]);
+        let eeprom = eeprom(&reader, MB_EN);
+        let channel = MailboxChannel::new(&eeprom, Duration::from_millis(1));
+        let mut buf = [0; 4];
+        assert!(matches!(
+            block_on(channel.receive(&mut buf)),
+            Err(St25dvError::MailboxMessageSize {
+                max: 4,
+                received: 14
+            })
+        ));
+    }
+
+    #[test]
+    fn respond_waits_for_the_reader_to_collect() {
+        let reader = reader(&[]);
+        let eeprom = eeprom(&reader, MB_EN);
+        let channel = MailboxChannel::new(&eeprom, Duration::from_millis(1));
+        block_on(channel.respond(b"PONG")).unwrap();
+        let reader = reader.borrow();
+        assert_eq!(reader.responses, [b"PONG"]);
+        // One check before writing, two polls while the reader waits, one as it collects and one seeing it gone.
+        assert_eq!(reader.status_reads, 5);
+    }
+
+    #[test]
+    fn respond_reports_a_missed_message() {
+        let reader = reader(&[]);
+        reader.borrow_mut().miss = true;
+        let eeprom = eeprom(&reader, MB_EN);
+        let channel = MailboxChannel::new(&eeprom, Duration::from_millis(1));
+        assert!(matches!(
+            block_on(channel.respond(b"PONG")),
+            Err(St25dvError::MailboxMessageMissed)
+        ));
+    }
+
+    #[test]
+    fn write_needs_a_free_mailbox() {
+        let reader = reader(&[]);
+        let busy = eeprom(&reader, MB_EN | RF_PUT_MSG);
+        assert!(matches!(
+            block_on(busy.mailbox_write(b"PONG")),
+            Err(St25dvError::MailboxBusy)
+        ));
+        let disabled = eeprom(&reader, 0);
+        assert!(matches!(
+            block_on(disabled.mailbox_write(b"PONG")),
+            Err(St25dvError::MailboxDisabled)
+        ));
+        assert!(matches!(
+            block_on(disabled.mailbox_write(&[0; MAILBOX_SIZE + 1])),
+            Err(St25dvError::MailboxMessageSize { .. })
+        ));
+        // None of them touched the mailbox.
+        assert!(reader.borrow().responses.is_empty());
+    }
+
+    #[test]
+    fn serve_answers_each_request() {
+        let reader = reader(&[b"g28", b"skip", b"m115"]);
+        let eeprom = eeprom(&reader, MB_EN);
+        let channel = MailboxChannel::new(&eeprom, Duration::from_millis(1));
+        let result = block_on(channel.serve(|request, response| {
+            if request == b"skip" {
+                return 0;
+            }
+            response[..request.len()].copy_from_slice(request);
+            response[..request.len()].make_ascii_uppercase();
+            request.len()
+        }));
+        // The reader switches the mailbox off once it runs out of requests.
+        assert!(matches!(result, Err(St25dvError::MailboxDisabled)));
+        assert_eq!(reader.borrow().responses, [&b"G28"[..], b"M115"]);
+    }
+}
@@ -0,0 +1,14 @@
+#![doc = include_str!("../../../docs/eeprom.md")]
+mod config;
+mod error;
+mod mailbox;
+mod ndef;
+mod registers;
+mod st25dv;
+
+pub use config::*;
+pub use error::*;
+pub use mailbox::*;
+pub use ndef::*;
+pub use registers::*;
+pub use st25dv::*;
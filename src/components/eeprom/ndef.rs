@@ -0,0 +1,682 @@
+use core::str::from_utf8;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal_async::i2c::I2c;
+
+use crate::components::eeprom::{
+    error::{NdefError, St25dvError},
+    st25dv::{Memory, St25dv},
+};
+
+/// The size of the area at the start of the user memory given to NDEF messages. The remainder holds the board configuration.
+pub const NDEF_AREA_SIZE: u16 = 384;
+
+/// The magic number of a capability container describing a memory of up to 2040 bytes.
+const CC_MAGIC: u8 = 0xE1;
+
+/// Mapping version 1.0 with read and write access always allowed.
+const CC_VERSION_ACCESS: u8 = 0x40;
+
+/// The tag supports the read multiple blocks command.
+const CC_FEATURES: u8 = 0x01;
+
+/// The length of the capability container.
+pub const CC_LEN: usize = 4;
+
+const TLV_NULL: u8 = 0x00;
+const TLV_NDEF: u8 = 0x03;
+const TLV_TERMINATOR: u8 = 0xFE;
+
+const FLAG_MB: u8 = 0x80;
+const FLAG_ME: u8 = 0x40;
+const FLAG_CF: u8 = 0x20;
+const FLAG_SR: u8 = 0x10;
+const FLAG_IL: u8 = 0x08;
+
+/// The type name format of well-known records (URI, text, etc.).
+pub const TNF_WELL_KNOWN: u8 = 0x01;
+
+/// The type name format of records typed by a MIME type.
+pub const TNF_MIME: u8 = 0x02;
+
+/// The abbreviations applied to the start of a URI record, indexed by their identifier code.
+const URI_PREFIXES: [&str; 36] = [
+    "",
+    "http://www.",
+    "https://www.",
+    "http://",
+    "https://",
+    "tel:",
+    "mailto:",
+    "ftp://anonymous:anonymous@",
+    "ftp://ftp.",
+    "ftps://",
+    "sftp://",
+    "smb://",
+    "nfs://",
+    "ftp://",
+    "dav://",
+    "news:",
+    "telnet://",
+    "imap:",
+    "rtsp://",
+    "urn:",
+    "pop:",
+    "sip:",
+    "sips:",
+    "tftp:",
+    "btspp://",
+    "btl2cap://",
+    "btgoep://",
+    "tcpobex://",
+    "irdaobex://",
+    "file://",
+    "urn:epc:id:",
+    "urn:epc:tag:",
+    "urn:epc:pat:",
+    "urn:epc:raw:",
+    "urn:epc:",
+    "urn:nfc:",
+];
+
+/// Return the URI prefix represented by an identifier code.
+pub fn uri_prefix(code: u8) -> &'static str {
+    URI_PREFIXES.get(code as usize).copied().unwrap_or("")
+}
+
+/// A record within an NDEF message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Record<'a> {
+    /// A well-known URI record. The full URI is the [`uri_prefix`] of the code followed by `uri`.
+    Uri { prefix: u8, uri: &'a str },
+    /// A well-known UTF-8 text record.
+    Text { language: &'a str, text: &'a str },
+    /// A record carrying a payload described by a MIME type, e.g. `application/vnd.wfa.wsc` for Wi-Fi credentials.
+    Mime {
+        mime_type: &'a str,
+        payload: &'a [u8],
+    },
+    /// Any other record.
+    Other {
+        tnf: u8,
+        record_type: &'a [u8],
+        payload: &'a [u8],
+    },
+}
+
+impl<'a> Record<'a> {
+    /// Create a URI record, abbreviating the longest matching prefix.
+    pub fn uri(uri: &'a str) -> Self {
+        let mut prefix = 0;
+        for (code, p) in URI_PREFIXES.iter().enumerate().skip(1) {
+            if uri.starts_with(p) && p.len() > URI_PREFIXES[prefix].len() {
+                prefix = code;
+            }
+        }
+        Self::Uri {
+            prefix: prefix as u8,
+            uri: &uri[URI_PREFIXES[prefix].len()..],
+        }
+    }
+
+    /// Create a text record.
+    pub fn text(language: &'a str, text: &'a str) -> Self {
+        Self::Text { language, text }
+    }
+
+    fn tnf(&self) -> u8 {
+        match self {
+            Self::Uri { .. } | Self::Text { .. } => TNF_WELL_KNOWN,
+            Self::Mime { .. } => TNF_MIME,
+            Self::Other { tnf, .. } => *tnf,
+        }
+    }
+
+    fn record_type(&self) -> &'a [u8] {
+        match self {
+            Self::Uri { .. } => b"U",
+            Self::Text { .. } => b"T",
+            Self::Mime { mime_type, .. } => mime_type.as_bytes(),
+            Self::Other { record_type, .. } => record_type,
+        }
+    }
+
+    fn payload_len(&self) -> usize {
+        match self {
+            Self::Uri { uri, .. } => 1 + uri.len(),
+            Self::Text { language, text } => 1 + language.len() + text.len(),
+            Self::Mime { payload, .. } | Self::Other { payload, .. } => payload.len(),
+        }
+    }
+
+    fn encode(&self, w: &mut Writer, first: bool, last: bool) -> Result<(), NdefError> {
+        let record_type = self.record_type();
+        if record_type.len() > u8::MAX as usize {
+            return Err(NdefError::Malformed);
+        }
+        let payload_len = self.payload_len();
+        let short = payload_len <= u8::MAX as usize;
+        let mut header = self.tnf() & 0x07;
+        if first {
+            header |= FLAG_MB;
+        }
+        if last {
+            header |= FLAG_ME;
+        }
+        if short {
+            header |= FLAG_SR;
+        }
+        w.put(&[header, record_type.len() as u8])?;
+        if short {
+            w.put(&[payload_len as u8])?;
+        } else {
+            w.put(&(payload_len as u32).to_be_bytes())?;
+        }
+        w.put(record_type)?;
+        match self {
+            Self::Uri { prefix, uri } => {
+                w.put(&[*prefix])?;
+                w.put(uri.as_bytes())
+            }
+            Self::Text { language, text } => {
+                if language.len() > 0x3F {
+                    return Err(NdefError::Malformed);
+                }
+                w.put(&[language.len() as u8])?;
+                w.put(language.as_bytes())?;
+                w.put(text.as_bytes())
+            }
+            Self::Mime { payload, .. } | Self::Other { payload, .. } => w.put(payload),
+        }
+    }
+
+    fn decode(tnf: u8, record_type: &'a [u8], payload: &'a [u8]) -> Result<Self, NdefError> {
+        match (tnf, record_type) {
+            (TNF_WELL_KNOWN, b"U") => {
+                let (prefix, uri) = payload.split_first().ok_or(NdefError::Malformed)?;
+                Ok(Self::Uri {
+                    prefix: *prefix,
+                    uri: from_utf8(uri).map_err(|_| NdefError::InvalidUtf8)?,
+                })
+            }
+            (TNF_WELL_KNOWN, b"T") => {
+                let (status, rest) = payload.split_first().ok_or(NdefError::Malformed)?;
+                if status & 0x80 != 0 {
+                    return Err(NdefError::Utf16Text);
+                }
+                let lang_len = (status & 0x3F) as usize;
+                if rest.len() < lang_len {
+                    return Err(NdefError::Malformed);
+                }
+                let (language, text) = rest.split_at(lang_len);
+                Ok(Self::Text {
+                    language: from_utf8(language).map_err(|_| NdefError::InvalidUtf8)?,
+                    text: from_utf8(text).map_err(|_| NdefError::InvalidUtf8)?,
+                })
+            }
+            (TNF_MIME, _) => Ok(Self::Mime {
+                mime_type: from_utf8(record_type).map_err(|_| NdefError::InvalidUtf8)?,
+                payload,
+            }),
+            _ => Ok(Self::Other {
+                tnf,
+                record_type,
+                payload,
+            }),
+        }
+    }
+}
+
+/// An NDEF message read from the tag.
+pub struct Message<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Message<'a> {
+    /// Wrap the bytes of an NDEF message.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Return the raw bytes of the message.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Iterate over the records in the message.
+    pub fn records(&self) -> Records<'a> {
+        Records {
+            bytes: self.bytes,
+            pos: 0,
+            done: self.bytes.is_empty(),
+        }
+    }
+}
+
+/// An iterator over the records of a [`Message`].
+pub struct Records<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    done: bool,
+}
+
+impl<'a> Records<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], NdefError> {
+        let end = self.pos.checked_add(n).ok_or(NdefError::Malformed)?;
+        let bytes = self.bytes.get(self.pos..end).ok_or(NdefError::Malformed)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn next_record(&mut self) -> Result<Record<'a>, NdefError> {
+        let header = self.take(2)?;
+        let (flags, type_len) = (header[0], header[1] as usize);
+        if flags & FLAG_CF != 0 {
+            return Err(NdefError::Chunked);
+        }
+        if flags & FLAG_ME != 0 {
+            self.done = true;
+        }
+        let payload_len = if flags & FLAG_SR != 0 {
+            self.take(1)?[0] as usize
+        } else {
+            let len = self.take(4)?;
+            u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize
+        };
+        let id_len = if flags & FLAG_IL != 0 {
+            self.take(1)?[0] as usize
+        } else {
+            0
+        };
+        let record_type = self.take(type_len)?;
+        self.take(id_len)?;
+        let payload = self.take(payload_len)?;
+        Record::decode(flags & 0x07, record_type, payload)
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>, NdefError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.pos >= self.bytes.len() {
+            return None;
+        }
+        let record = self.next_record();
+        if record.is_err() {
+            self.done = true;
+        }
+        Some(record)
+    }
+}
+
+/// Encode the records into an NDEF message, returning the number of bytes written.
+pub fn encode_message(records: &[Record], buf: &mut [u8]) -> Result<usize, NdefError> {
+    let mut w = Writer { buf, pos: 0 };
+    for (n, record) in records.iter().enumerate() {
+        record.encode(&mut w, n == 0, n == records.len() - 1)?;
+    }
+    Ok(w.pos)
+}
+
+/// Encode the records as the contents of a type 5 tag memory of `memory_len` bytes: a capability container followed by the NDEF message TLV and a terminator TLV. Returns the number of bytes written.
+pub fn encode_tag(
+    records: &[Record],
+    memory_len: usize,
+    buf: &mut [u8],
+) -> Result<usize, NdefError> {
+    if buf.len() < CC_LEN + 4 || memory_len < CC_LEN + 4 {
+        return Err(NdefError::BufferTooSmall);
+    }
+    buf[..CC_LEN].copy_from_slice(&[
+        CC_MAGIC,
+        CC_VERSION_ACCESS,
+        ((memory_len - CC_LEN) / 8) as u8,
+        CC_FEATURES,
+    ]);
+    // Encode the message after the largest TLV header then shift it down if the short form fits.
+    let len = encode_message(records, &mut buf[CC_LEN + 4..])?;
+    let header_len = if len < 0xFF {
+        buf[CC_LEN] = TLV_NDEF;
+        buf[CC_LEN + 1] = len as u8;
+        buf.copy_within(CC_LEN + 4..CC_LEN + 4 + len, CC_LEN + 2);
+        2
+    } else {
+        buf[CC_LEN] = TLV_NDEF;
+        buf[CC_LEN + 1] = 0xFF;
+        buf[CC_LEN + 2..CC_LEN + 4].copy_from_slice(&(len as u16).to_be_bytes());
+        4
+    };
+    let end = CC_LEN + header_len + len;
+    if end >= buf.len() || end >= memory_len {
+        return Err(NdefError::BufferTooSmall);
+    }
+    buf[end] = TLV_TERMINATOR;
+    Ok(end + 1)
+}
+
+/// The location of the NDEF message within the tag memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageLocation {
+    /// The offset of the message from the start of the memory.
+    pub offset: usize,
+    /// The length of the message.
+    pub len: usize,
+}
+
+/// Find the NDEF message in the start of a type 5 tag memory. `buf` must hold the capability container and at least the TLV headers up to the message.
+pub fn locate_message(buf: &[u8]) -> Result<MessageLocation, NdefError> {
+    if buf.len() < CC_LEN {
+        return Err(NdefError::BufferTooSmall);
+    }
+    if buf[0] != CC_MAGIC || buf[1] & 0xF0 != CC_VERSION_ACCESS {
+        return Err(NdefError::InvalidCapabilityContainer);
+    }
+    let mut pos = CC_LEN;
+    loop {
+        let tlv_type = *buf.get(pos).ok_or(NdefError::BufferTooSmall)?;
+        match tlv_type {
+            TLV_NULL => {
+                pos += 1;
+                continue;
+            }
+            TLV_TERMINATOR => return Err(NdefError::NoMessage),
+            _ => {}
+        }
+        let (len, header_len) = match *buf.get(pos + 1).ok_or(NdefError::BufferTooSmall)? {
+            0xFF => {
+                let len = buf.get(pos + 2..pos + 4).ok_or(NdefError::BufferTooSmall)?;
+                (u16::from_be_bytes([len[0], len[1]]) as usize, 4)
+            }
+            len => (len as usize, 2),
+        };
+        if tlv_type == TLV_NDEF {
+            return Ok(MessageLocation {
+                offset: pos + header_len,
+                len,
+            });
+        }
+        pos += header_len + len;
+    }
+}
+
+impl<T: I2c, M: RawMutex> St25dv<T, M> {
+    /// Publish an NDEF message to NFC readers, e.g. a URL to the printer's web interface.
+    pub async fn publish_ndef(&self, records: &[Record<'_>]) -> Result<(), St25dvError> {
+        let mut buf: [u8; NDEF_AREA_SIZE as usize] = [0; NDEF_AREA_SIZE as usize];
+        let len = encode_tag(records, NDEF_AREA_SIZE as usize, &mut buf)?;
+        self.write(Memory::User, 0, &buf[..len]).await
+    }
+
+    /// Read the NDEF message currently on the tag, e.g. one written from a phone. `buf` holds the contents of the NDEF area so should be [`NDEF_AREA_SIZE`] bytes to read the largest message.
+    pub async fn read_ndef<'b>(&self, buf: &'b mut [u8]) -> Result<Message<'b>, St25dvError> {
+        let len = buf.len().min(NDEF_AREA_SIZE as usize);
+        self.read(Memory::User, 0, &mut buf[..len]).await?;
+        let location = locate_message(&buf[..len])?;
+        let end = location.offset + location.len;
+        if end > len {
+            return Err(NdefError::BufferTooSmall.into());
+        }
+        Ok(Message::new(&buf[location.offset..end]))
+    }
+}
+
+/// Writes bytes sequentially into a buffer.
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn put(&mut self, bytes: &[u8]) -> Result<(), NdefError> {
+        let end = self.pos + bytes.len();
+        if end > self.buf.len() {
+            return Err(NdefError::BufferTooSmall);
+        }
+        self.buf[self.pos..end].copy_from_slice(bytes);
+        self.pos = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::mock::MockI2c;
+
+    /// `https://www.prusa3d.com` as a single URI record.
+    const URI_MESSAGE: [u8; 16] = [
+        0xD1, 0x01, 0x0C, b'U', 0x02, b'p', b'r', b'u', b's', b'a', b'3', b'd', b'.', b'c', b'o',
+        b'm',
+    ];
+
+    /// A URI record followed by an English text record.
+    const URI_TEXT_MESSAGE: [u8; 24] = [
+        0x91, 0x01, 0x06, b'U', 0x03, b'b', b'u', b'd', b'd', b'y', //
+        0x51, 0x01, 0x08, b'T', 0x02, b'e', b'n', b'H', b'e', b'l', b'l', b'o', //
+        0x00, 0x00,
+    ];
+
+    fn encode(records: &[Record]) -> Vec<u8> {
+        let mut buf = [0; 512];
+        let len = encode_message(records, &mut buf).unwrap();
+        buf[..len].to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Vec<Record<'_>>, NdefError> {
+        Message::new(bytes).records().collect()
+    }
+
+    #[test]
+    fn uri_prefixes_are_abbreviated() {
+        let cases = [
+            ("https://www.prusa3d.com", 0x02, "prusa3d.com"),
+            ("http://www.prusa3d.com", 0x01, "prusa3d.com"),
+            ("https://192.168.1.20/", 0x04, "192.168.1.20/"),
+            ("http://buddy.local", 0x03, "buddy.local"),
+            ("tel:+441234", 0x05, "+441234"),
+            ("mailto:print@example.com", 0x06, "print@example.com"),
+            ("urn:nfc:sn:1", 0x23, "sn:1"),
+            ("octoprint:job", 0x00, "octoprint:job"),
+        ];
+        for (uri, prefix, rest) in cases {
+            assert_eq!(Record::uri(uri), Record::Uri { prefix, uri: rest }, "{uri}");
+            assert_eq!(format!("{}{rest}", uri_prefix(prefix)), uri);
+        }
+        assert_eq!(uri_prefix(0x24), "");
+    }
+
+    #[test]
+    fn encodes_short_records() {
+        assert_eq!(
+            encode(&[Record::uri("https://www.prusa3d.com")]),
+            URI_MESSAGE
+        );
+        let records = [Record::uri("http://buddy"), Record::text("en", "Hello")];
+        assert_eq!(encode(&records), URI_TEXT_MESSAGE[..22]);
+    }
+
+    #[test]
+    fn decodes_short_records() {
+        assert_eq!(
+            decode(&URI_MESSAGE).unwrap(),
+            [Record::Uri {
+                prefix: 0x02,
+                uri: "prusa3d.com"
+            }]
+        );
+        // Decoding stops at the message end flag, ignoring the padding after it.
+        assert_eq!(
+            decode(&URI_TEXT_MESSAGE).unwrap(),
+            [
+                (Record::Uri {
+                    prefix: 0x03,
+                    uri: "buddy"
+                }),
+                Record::Text {
+                    language: "en",
+                    text: "Hello"
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn long_records_use_a_4_byte_length() {
+        let payload = [0x5A; 300];
+        let record = Record::Mime {
+            mime_type: "a/b",
+            payload: &payload,
+        };
+        let bytes = encode(&[record]);
+        assert_eq!(
+            bytes[..9],
+            [0xC2, 0x03, 0x00, 0x00, 0x01, 0x2C, b'a', b'/', b'b']
+        );
+        assert_eq!(bytes.len(), 9 + 300);
+        assert_eq!(decode(&bytes).unwrap(), [record]);
+    }
+
+    #[test]
+    fn decodes_ids_and_unknown_types() {
+        // An external type record with an ID field.
+        let bytes = [0xDC, 0x03, 0x02, 0x01, b'x', b'y', b'z', 0x07, 0xAB, 0xCD];
+        assert_eq!(
+            decode(&bytes).unwrap(),
+            [Record::Other {
+                tnf: 0x04,
+                record_type: b"xyz",
+                payload: &[0xAB, 0xCD]
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_records() {
+        // Chunked.
+        assert!(matches!(
+            decode(&[0xB1, 0x01, 0x01, b'U', 0x00]),
+            Err(NdefError::Chunked)
+        ));
+        // UTF-16 text.
+        assert!(matches!(
+            decode(&[0xD1, 0x01, 0x03, b'T', 0x82, b'e', b'n']),
+            Err(NdefError::Utf16Text)
+        ));
+        // Payload runs past the end.
+        assert!(matches!(
+            decode(&URI_MESSAGE[..10]),
+            Err(NdefError::Malformed)
+        ));
+        // Language longer than the payload.
+        assert!(matches!(
+            decode(&[0xD1, 0x01, 0x02, b'T', 0x05, b'e']),
+            Err(NdefError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn encodes_the_tag_memory() {
+        let mut buf = [0; NDEF_AREA_SIZE as usize];
+        let len = encode_tag(
+            &[Record::uri("https://www.prusa3d.com")],
+            NDEF_AREA_SIZE as usize,
+            &mut buf,
+        )
+        .unwrap();
+        let mut expected = vec![0xE1, 0x40, 0x2F, 0x01, 0x03, 0x10];
+        expected.extend_from_slice(&URI_MESSAGE);
+        expected.push(0xFE);
+        assert_eq!(buf[..len], expected[..]);
+        assert_eq!(
+            locate_message(&buf).unwrap(),
+            MessageLocation { offset: 6, len: 16 }
+        );
+    }
+
+    #[test]
+    fn long_messages_use_the_3_byte_tlv_length() {
+        let payload = [0x5A; 300];
+        let mut buf = [0; NDEF_AREA_SIZE as usize];
+        let len = encode_tag(
+            &[Record::Mime {
+                mime_type: "a/b",
+                payload: &payload,
+            }],
+            NDEF_AREA_SIZE as usize,
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(buf[4..8], [0x03, 0xFF, 0x01, 0x35]);
+        assert_eq!(len, 8 + 309 + 1);
+        assert_eq!(buf[len - 1], 0xFE);
+        assert_eq!(
+            locate_message(&buf).unwrap(),
+            MessageLocation {
+                offset: 8,
+                len: 309
+            }
+        );
+    }
+
+    #[test]
+    fn messages_must_fit_the_ndef_area() {
+        let payload = [0x5A; 367];
+        let mut buf = [0; NDEF_AREA_SIZE as usize];
+        // CC (4) + TLV header (4) + record header (9) + payload + terminator (1) fills the area exactly.
+        let fits = Record::Mime {
+            mime_type: "a/b",
+            payload: &payload[..366],
+        };
+        let len = encode_tag(&[fits], NDEF_AREA_SIZE as usize, &mut buf).unwrap();
+        assert_eq!(len, NDEF_AREA_SIZE as usize);
+        let too_big = Record::Mime {
+            mime_type: "a/b",
+            payload: &payload,
+        };
+        assert!(matches!(
+            encode_tag(&[too_big], NDEF_AREA_SIZE as usize, &mut buf),
+            Err(NdefError::BufferTooSmall)
+        ));
+    }
+
+    #[test]
+    fn locates_the_message_after_other_tlvs() {
+        let mut memory = vec![
+            0xE1, 0x40, 0x2F, 0x01, 0x00, 0x00, 0xFD, 0x02, 0xAA, 0xBB, 0x03,
+        ];
+        memory.push(URI_MESSAGE.len() as u8);
+        memory.extend_from_slice(&URI_MESSAGE);
+        assert_eq!(
+            locate_message(&memory).unwrap(),
+            MessageLocation {
+                offset: 12,
+                len: 16
+            }
+        );
+        assert!(matches!(
+            locate_message(&[0xE1, 0x40, 0x2F, 0x01, 0xFE]),
+            Err(NdefError::NoMessage)
+        ));
+        assert!(matches!(
+            locate_message(&[0xE2, 0x40, 0x2F, 0x01, 0x03, 0x00]),
+            Err(NdefError::InvalidCapabilityContainer)
+        ));
+    }
+
+    #[test]
+    fn publishes_and_reads_back_over_i2c() {
+        let eeprom: St25dv<_, NoopRawMutex> =
+            St25dv::new(MockI2c::new(&[Memory::User.device_select_code()]));
+        let records = [Record::uri("http://buddy"), Record::text("en", "Hello")];
+        block_on(eeprom.publish_ndef(&records)).unwrap();
+        let mut buf = [0; NDEF_AREA_SIZE as usize];
+        let message = block_on(eeprom.read_ndef(&mut buf)).unwrap();
+        assert_eq!(message.as_bytes(), &URI_TEXT_MESSAGE[..22]);
+        let decoded: Vec<_> = message.records().map(Result::unwrap).collect();
+        assert_eq!(decoded, records);
+    }
+}
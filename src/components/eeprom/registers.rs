@@ -0,0 +1,485 @@
+use defmt::Format;
+use packed_struct::{
+    PackedStructSlice,
+    derive::PackedStruct,
+    types::{Integer, bits::Bits},
+};
+
+use crate::components::eeprom::st25dv::Memory;
+
+/// The address of the I2C password in the system memory.
+pub const I2C_PWD_ADDR: u16 = 0x0900;
+
+/// A trait representing the system configuration and dynamic registers of the ST25DV.
+pub trait Register: PackedStructSlice + Default {
+    /// Return the address of the register.
+    fn addr() -> u16;
+
+    /// Return the memory the register is accessed through.
+    fn memory() -> Memory;
+}
+
+/// The protection applied to a memory area. Area 1 always remains readable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum AreaProtection {
+    /// Read and write freely.
+    None = 0b00,
+    /// Read freely but only write within an open security session.
+    WriteProtected = 0b01,
+    /// Only read and write within an open security session.
+    ReadWriteProtected = 0b10,
+    /// Only read within an open security session and never write.
+    WriteForbidden = 0b11,
+}
+
+impl AreaProtection {
+    /// Return the protection represented by the lower two bits.
+    pub const fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => Self::None,
+            0b01 => Self::WriteProtected,
+            0b10 => Self::ReadWriteProtected,
+            _ => Self::WriteForbidden,
+        }
+    }
+}
+
+/// A struct representing the GPO register, enabling the events that drive the GPO pin.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct Gpo {
+    #[packed_field(bits = "0")]
+    pub rf_user_en: bool,
+    #[packed_field(bits = "1")]
+    pub rf_activity_en: bool,
+    #[packed_field(bits = "2")]
+    pub rf_interrupt_en: bool,
+    #[packed_field(bits = "3")]
+    pub field_change_en: bool,
+    #[packed_field(bits = "4")]
+    pub rf_put_msg_en: bool,
+    #[packed_field(bits = "5")]
+    pub rf_get_msg_en: bool,
+    #[packed_field(bits = "6")]
+    pub rf_write_en: bool,
+    #[packed_field(bits = "7")]
+    pub gpo_en: bool,
+}
+
+impl Register for Gpo {
+    fn addr() -> u16 {
+        0x0000
+    }
+
+    fn memory() -> Memory {
+        Memory::System
+    }
+}
+
+/// A struct representing the IT_TIME register, the duration of the GPO interrupt pulse.
+#[derive(PackedStruct, Default)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct ItTime {
+    /// The pulse duration is 301us - it_time * 37.65us.
+    #[packed_field(bits = "0..=2")]
+    pub it_time: Integer<u8, Bits<3>>,
+}
+
+impl Register for ItTime {
+    fn addr() -> u16 {
+        0x0001
+    }
+
+    fn memory() -> Memory {
+        Memory::System
+    }
+}
+
+/// A struct representing the EH_MODE register.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct EhMode {
+    /// When clear, energy harvesting is enabled at boot. When set, it is enabled on demand.
+    #[packed_field(bits = "0")]
+    pub eh_mode: bool,
+}
+
+impl Register for EhMode {
+    fn addr() -> u16 {
+        0x0002
+    }
+
+    fn memory() -> Memory {
+        Memory::System
+    }
+}
+
+/// A struct representing the RF_MNGT register, the RF state at boot.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct RfMngt {
+    #[packed_field(bits = "0")]
+    pub rf_disable: bool,
+    #[packed_field(bits = "1")]
+    pub rf_sleep: bool,
+}
+
+impl Register for RfMngt {
+    fn addr() -> u16 {
+        0x0003
+    }
+
+    fn memory() -> Memory {
+        Memory::System
+    }
+}
+
+/// A struct representing the RFA1SS register, the RF access protection of area 1.
+#[derive(PackedStruct, Default)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct Rfa1ss {
+    /// The RF password (0 for none, 1 to 3) that opens the security session for the area.
+    #[packed_field(bits = "0..=1")]
+    pub pwd_ctrl: Integer<u8, Bits<2>>,
+    /// The [`AreaProtection`] bits.
+    #[packed_field(bits = "2..=3")]
+    pub rw_protection: Integer<u8, Bits<2>>,
+}
+
+impl Register for Rfa1ss {
+    fn addr() -> u16 {
+        0x0004
+    }
+
+    fn memory() -> Memory {
+        Memory::System
+    }
+}
+
+/// A struct representing the ENDA1 register, the end of area 1.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct Enda1 {
+    /// Area 1 ends at byte 32 * enda1 + 31.
+    #[packed_field(bytes = "0")]
+    pub enda1: u8,
+}
+
+impl Register for Enda1 {
+    fn addr() -> u16 {
+        0x0005
+    }
+
+    fn memory() -> Memory {
+        Memory::System
+    }
+}
+
+/// A struct representing the I2CSS register, the I2C access protection of each area.
+#[derive(PackedStruct, Default)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct I2css {
+    #[packed_field(bits = "0..=1")]
+    pub rw_protection_a1: Integer<u8, Bits<2>>,
+    #[packed_field(bits = "2..=3")]
+    pub rw_protection_a2: Integer<u8, Bits<2>>,
+    #[packed_field(bits = "4..=5")]
+    pub rw_protection_a3: Integer<u8, Bits<2>>,
+    #[packed_field(bits = "6..=7")]
+    pub rw_protection_a4: Integer<u8, Bits<2>>,
+}
+
+impl Register for I2css {
+    fn addr() -> u16 {
+        0x000B
+    }
+
+    fn memory() -> Memory {
+        Memory::System
+    }
+}
+
+/// A struct representing the LOCK_CCFILE register, locking the blocks holding the capability container.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct LockCcFile {
+    #[packed_field(bits = "0")]
+    pub lck_bck0: bool,
+    #[packed_field(bits = "1")]
+    pub lck_bck1: bool,
+}
+
+impl Register for LockCcFile {
+    fn addr() -> u16 {
+        0x000C
+    }
+
+    fn memory() -> Memory {
+        Memory::System
+    }
+}
+
+/// A struct representing the MB_MODE register.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct MbMode {
+    /// Allow the fast transfer mailbox to be enabled.
+    #[packed_field(bits = "0")]
+    pub mb_mode: bool,
+}
+
+impl Register for MbMode {
+    fn addr() -> u16 {
+        0x000D
+    }
+
+    fn memory() -> Memory {
+        Memory::System
+    }
+}
+
+/// A struct representing the MB_WDG register, how long a message is held for the reader before it is released.
+#[derive(PackedStruct, Default)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct MbWdg {
+    /// The watchdog duration is 2^(mb_wdg - 1) * 30ms. Zero disables the watchdog.
+    #[packed_field(bits = "0..=2")]
+    pub mb_wdg: Integer<u8, Bits<3>>,
+}
+
+impl Register for MbWdg {
+    fn addr() -> u16 {
+        0x000E
+    }
+
+    fn memory() -> Memory {
+        Memory::System
+    }
+}
+
+/// A struct representing the MEM_SIZE and BLK_SIZE registers.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "3", bit_numbering = "msb0")]
+pub struct MemSize {
+    /// The number of 4-byte blocks in the user memory minus one.
+    #[packed_field(bytes = "0..=1", endian = "lsb")]
+    pub mem_size: u16,
+    /// The number of bytes in a block minus one.
+    #[packed_field(bytes = "2")]
+    pub blk_size: u8,
+}
+
+impl Register for MemSize {
+    fn addr() -> u16 {
+        0x0014
+    }
+
+    fn memory() -> Memory {
+        Memory::System
+    }
+}
+
+/// A struct representing the IC_REF register.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct IcRef {
+    #[packed_field(bytes = "0")]
+    pub ic_ref: u8,
+}
+
+impl Register for IcRef {
+    fn addr() -> u16 {
+        0x0017
+    }
+
+    fn memory() -> Memory {
+        Memory::System
+    }
+}
+
+/// A struct representing the UID register.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "8", bit_numbering = "msb0")]
+pub struct Uid {
+    #[packed_field(bytes = "0..=7", endian = "lsb")]
+    pub uid: u64,
+}
+
+impl Register for Uid {
+    fn addr() -> u16 {
+        0x0018
+    }
+
+    fn memory() -> Memory {
+        Memory::System
+    }
+}
+
+/// A struct representing the GPO_CTRL_Dyn register.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct GpoDyn {
+    #[packed_field(bits = "0")]
+    pub gpo_en: bool,
+}
+
+impl Register for GpoDyn {
+    fn addr() -> u16 {
+        0x2000
+    }
+
+    fn memory() -> Memory {
+        Memory::Dynamic
+    }
+}
+
+/// A struct representing the EH_CTRL_Dyn register.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct EhCtrlDyn {
+    #[packed_field(bits = "0")]
+    pub eh_en: bool,
+    /// Read only.
+    #[packed_field(bits = "1")]
+    pub eh_on: bool,
+    /// Read only.
+    #[packed_field(bits = "2")]
+    pub field_on: bool,
+    /// Read only.
+    #[packed_field(bits = "3")]
+    pub vcc_on: bool,
+}
+
+impl Register for EhCtrlDyn {
+    fn addr() -> u16 {
+        0x2002
+    }
+
+    fn memory() -> Memory {
+        Memory::Dynamic
+    }
+}
+
+/// A struct representing the RF_MNGT_Dyn register, the current RF state.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct RfMngtDyn {
+    #[packed_field(bits = "0")]
+    pub rf_disable: bool,
+    #[packed_field(bits = "1")]
+    pub rf_sleep: bool,
+}
+
+impl Register for RfMngtDyn {
+    fn addr() -> u16 {
+        0x2003
+    }
+
+    fn memory() -> Memory {
+        Memory::Dynamic
+    }
+}
+
+/// A struct representing the I2C_SSO_Dyn register.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct I2cSsoDyn {
+    /// Set while an I2C security session is open.
+    #[packed_field(bits = "0")]
+    pub i2c_sso: bool,
+}
+
+impl Register for I2cSsoDyn {
+    fn addr() -> u16 {
+        0x2004
+    }
+
+    fn memory() -> Memory {
+        Memory::Dynamic
+    }
+}
+
+/// A struct representing the IT_STS_Dyn register. The register is cleared when read.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct ItStsDyn {
+    #[packed_field(bits = "0")]
+    pub rf_user: bool,
+    #[packed_field(bits = "1")]
+    pub rf_activity: bool,
+    #[packed_field(bits = "2")]
+    pub rf_interrupt: bool,
+    #[packed_field(bits = "3")]
+    pub field_falling: bool,
+    #[packed_field(bits = "4")]
+    pub field_rising: bool,
+    #[packed_field(bits = "5")]
+    pub rf_put_msg: bool,
+    #[packed_field(bits = "6")]
+    pub rf_get_msg: bool,
+    #[packed_field(bits = "7")]
+    pub rf_write: bool,
+}
+
+impl Register for ItStsDyn {
+    fn addr() -> u16 {
+        0x2005
+    }
+
+    fn memory() -> Memory {
+        Memory::Dynamic
+    }
+}
+
+/// A struct representing the MB_CTRL_Dyn register. Only `mb_en` is writable.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct MbCtrlDyn {
+    #[packed_field(bits = "0")]
+    pub mb_en: bool,
+    /// The mailbox holds a message written over I2C.
+    #[packed_field(bits = "1")]
+    pub host_put_msg: bool,
+    /// The mailbox holds a message written over RF.
+    #[packed_field(bits = "2")]
+    pub rf_put_msg: bool,
+    /// The I2C message was not read by the reader before the watchdog released it.
+    #[packed_field(bits = "4")]
+    pub host_miss_msg: bool,
+    /// The RF message was not read by the host before the watchdog released it.
+    #[packed_field(bits = "5")]
+    pub rf_miss_msg: bool,
+    #[packed_field(bits = "6")]
+    pub host_current_msg: bool,
+    #[packed_field(bits = "7")]
+    pub rf_current_msg: bool,
+}
+
+impl Register for MbCtrlDyn {
+    fn addr() -> u16 {
+        0x2006
+    }
+
+    fn memory() -> Memory {
+        Memory::Dynamic
+    }
+}
+
+/// A struct representing the MB_LEN_Dyn register.
+#[derive(PackedStruct, Default, Format)]
+#[packed_struct(size_bytes = "1", bit_numbering = "lsb0")]
+pub struct MbLenDyn {
+    /// The length of the message in the mailbox minus one.
+    #[packed_field(bytes = "0")]
+    pub mb_len: u8,
+}
+
+impl Register for MbLenDyn {
+    fn addr() -> u16 {
+        0x2007
+    }
+
+    fn memory() -> Memory {
+        Memory::Dynamic
+    }
+}
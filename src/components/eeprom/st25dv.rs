@@ -0,0 +1,515 @@
+use embassy_stm32::{
+    bind_interrupts,
+    i2c::I2c as Stm32I2c,
+    peripherals::{DMA1_CH5, DMA1_CH6, I2C1, PB8, PB9},
+    time::Hertz,
+};
+use embassy_sync::{
+    blocking_mutex::raw::{RawMutex, ThreadModeRawMutex},
+    mutex::Mutex,
+};
+use embassy_time::Timer;
+use embedded_hal_async::i2c::I2c;
+
+use crate::components::eeprom::{
+    error::St25dvError,
+    registers::{
+        AreaProtection, Enda1, I2C_PWD_ADDR, I2cSsoDyn, I2css, Register, RfMngtDyn, Rfa1ss, Uid,
+    },
+};
+
+/// The size of the user memory area on the ST25DV04KC.
+pub const USER_MEMORY_SIZE: u16 = 512;
+
+/// The start of the dynamic registers.
+pub const DYNAMIC_START: u16 = 0x2000;
+
+/// The end of the mailbox that follows the dynamic registers.
+pub const DYNAMIC_END: u16 = 0x2108;
+
+/// The largest number of bytes sent in a single write by [`St25dv::write`]. Over I2C the ST25DV programs its memory in 16-byte rows, one write cycle per row, so writes are aligned to this size to keep each within one row. The 4-byte blocks in the datasheet are the RF block size and do not apply here.
+pub const MAX_WRITE_LEN: u16 = 16;
+
+/// The validation code that presents the password.
+const PRESENT_PASSWORD: u8 = 0x09;
+
+/// The validation code that writes a new password.
+const WRITE_PASSWORD: u8 = 0x07;
+
+/// How many times to poll the device while it completes a write (each poll is 1ms apart). A row takes 5ms to program.
+const ACK_POLL_ATTEMPTS: u8 = 50;
+
+bind_interrupts!(struct Irqs {
+    I2C1_EV => embassy_stm32::i2c::EventInterruptHandler<I2C1>;
+    I2C1_ER => embassy_stm32::i2c::ErrorInterruptHandler<I2C1>;
+});
+
+/// A convenience type to represent the EEPROM type.
+pub type BuddyEeprom<'a> = St25dv<Stm32I2c<'a, embassy_stm32::mode::Async>, ThreadModeRawMutex>;
+
+/// A convenience function to initialise the eeprom on the buddy board.
+pub fn build_eeprom<'a>(
+    peri: I2C1,
+    scl: PB8,
+    sda: PB9,
+    tx_dma: DMA1_CH6,
+    rx_dma: DMA1_CH5,
+) -> BuddyEeprom<'a> {
+    let i2c = Stm32I2c::new(
+        peri,
+        scl,
+        sda,
+        Irqs,
+        tx_dma,
+        rx_dma,
+        Hertz(100_000),
+        Default::default(),
+    );
+    let eeprom: BuddyEeprom<'_> = St25dv::new(i2c);
+    eeprom
+}
+
+/// The buddy board features a [ST25DV04KC](https://www.st.com/resource/en/datasheet/st25dv04kc.pdf) eeprom chip connected via I2C using PB8 (SCL) and PB9 (SDA) pins. This struct is an interface/driver for the EEPROM and should work with any of the ST25DVxx series of EEPROMs
+pub struct St25dv<T: I2c, M: RawMutex> {
+    /// Holds a Mutex for the I2C connection.
+    i2c: Mutex<M, T>,
+}
+
+impl<T: I2c, M: RawMutex> St25dv<T, M> {
+    /// Create a new instance of the ST25DV interface.
+    pub fn new(i2c: T) -> Self {
+        Self {
+            i2c: Mutex::new(i2c),
+        }
+    }
+
+    /// Lock the bus so the tests can look at the devices on it.
+    #[cfg(test)]
+    pub(crate) fn bus(&self) -> embassy_sync::mutex::MutexGuard<'_, M, T> {
+        self.i2c.try_lock().unwrap()
+    }
+
+    /// Turn on RF by clearing the sleep and disable bits in the RF_MNGT_Dyn register.
+    pub async fn rf_on(&self) -> Result<(), St25dvError> {
+        self.write_register(&RfMngtDyn::default()).await
+    }
+
+    /// Turn off RF by putting it to sleep through the RF_MNGT_Dyn register. The tag will not respond to readers until [`St25dv::rf_on`] is called.
+    pub async fn rf_off(&self) -> Result<(), St25dvError> {
+        let register = RfMngtDyn {
+            rf_disable: false,
+            rf_sleep: true,
+        };
+        self.write_register(&register).await
+    }
+
+    /// Read a register from the device into the provided register struct.
+    pub async fn read_register<R: Register>(&self, register: &mut R) -> Result<(), St25dvError> {
+        let mut buf: [u8; 8] = [0; 8];
+        let len = register_len::<R>()?;
+        self.read(R::memory(), R::addr(), &mut buf[..len]).await?;
+        match R::unpack_from_slice(&buf[..len]) {
+            Ok(r) => *register = r,
+            Err(_) => return Err(St25dvError::UnpackingError),
+        }
+        Ok(())
+    }
+
+    /// Write a register to the device. System configuration registers require an open security session, see [`St25dv::present_password`].
+    pub async fn write_register<R: Register>(&self, register: &R) -> Result<(), St25dvError> {
+        let mut buf: [u8; 8] = [0; 8];
+        let len = register_len::<R>()?;
+        if register.pack_to_slice(&mut buf[..len]).is_err() {
+            return Err(St25dvError::PackingError);
+        }
+        self.write(R::memory(), R::addr(), &buf[..len]).await
+    }
+
+    /// Present the I2C password to open a security session, allowing the system configuration and protected areas to be written.
+    pub async fn present_password(&self, password: u64) -> Result<(), St25dvError> {
+        self.password_command(password, PRESENT_PASSWORD).await?;
+        let mut sso = I2cSsoDyn::default();
+        self.read_register(&mut sso).await?;
+        if !sso.i2c_sso {
+            return Err(St25dvError::InvalidPassword);
+        }
+        Ok(())
+    }
+
+    /// Change the I2C password. Requires an open security session.
+    pub async fn write_password(&self, password: u64) -> Result<(), St25dvError> {
+        self.password_command(password, WRITE_PASSWORD).await?;
+        self.ack_poll(Memory::System).await
+    }
+
+    /// The password is sent twice around a validation code that selects the command.
+    async fn password_command(&self, password: u64, code: u8) -> Result<(), St25dvError> {
+        let password = password.to_be_bytes();
+        let mut payload: [u8; 19] = [0; 19];
+        payload[..2].copy_from_slice(&I2C_PWD_ADDR.to_be_bytes());
+        payload[2..10].copy_from_slice(&password);
+        payload[10] = code;
+        payload[11..].copy_from_slice(&password);
+        let mut i2c = self.i2c.lock().await;
+        if i2c
+            .write(Memory::System.device_select_code(), &payload)
+            .await
+            .is_err()
+        {
+            return Err(St25dvError::WriteError);
+        }
+        Ok(())
+    }
+
+    /// Read the 64-bit unique identifier of the tag.
+    pub async fn uid(&self) -> Result<u64, St25dvError> {
+        let mut uid = Uid::default();
+        self.read_register(&mut uid).await?;
+        Ok(uid.uid)
+    }
+
+    /// Set the end of area 1 in 32-byte blocks. Area 2 starts from the next byte. Requires an open security session.
+    pub async fn set_area1_end(&self, end: u8) -> Result<(), St25dvError> {
+        self.write_register(&Enda1 { enda1: end }).await
+    }
+
+    /// Set the protection of an area (1 to 4) when accessed over I2C. Requires an open security session.
+    pub async fn set_i2c_area_protection(
+        &self,
+        area: u8,
+        protection: AreaProtection,
+    ) -> Result<(), St25dvError> {
+        let mut i2css = I2css::default();
+        self.read_register(&mut i2css).await?;
+        let bits = (protection as u8).into();
+        match area {
+            1 => i2css.rw_protection_a1 = bits,
+            2 => i2css.rw_protection_a2 = bits,
+            3 => i2css.rw_protection_a3 = bits,
+            4 => i2css.rw_protection_a4 = bits,
+            _ => return Err(St25dvError::InvalidArea(area)),
+        }
+        self.write_register(&i2css).await
+    }
+
+    /// Set the protection of area 1 when accessed over RF along with the RF password (1 to 3) that unlocks it. Requires an open security session.
+    pub async fn set_rf_area1_protection(
+        &self,
+        protection: AreaProtection,
+        password: u8,
+    ) -> Result<(), St25dvError> {
+        let register = Rfa1ss {
+            pwd_ctrl: (password & 0b11).into(),
+            rw_protection: (protection as u8).into(),
+        };
+        self.write_register(&register).await
+    }
+
+    /// Read some memory out of the device.
+    pub async fn sequential_current_read(
+        &self,
+        memory: Memory,
+        data_out: &mut [u8],
+    ) -> Result<(), St25dvError> {
+        let ds = memory.device_select_code();
+        let mut i2c = self.i2c.lock().await;
+        if i2c.read(ds, data_out).await.is_err() {
+            return Err(St25dvError::ReadError);
+        }
+        Ok(())
+    }
+
+    /// Read the current address out of the device.
+    pub async fn current_address_read(&self, memory: Memory) -> Result<u8, St25dvError> {
+        let mut data_out: [u8; 1] = [0; 1];
+        self.sequential_current_read(memory, &mut data_out).await?;
+        Ok(data_out[0])
+    }
+
+    /// Read the memory from a specified starting point out of the device.
+    pub async fn sequential_random_read(
+        &self,
+        memory: Memory,
+        byte_addr: u16,
+        data_out: &mut [u8],
+    ) -> Result<(), St25dvError> {
+        let ds = memory.device_select_code();
+        let mut i2c = self.i2c.lock().await;
+        if i2c
+            .write_read(ds, &byte_addr.to_be_bytes(), data_out)
+            .await
+            .is_err()
+        {
+            return Err(St25dvError::ReadError);
+        }
+        Ok(())
+    }
+
+    /// Read a single address.
+    pub async fn random_address_read(
+        &self,
+        memory: Memory,
+        byte_addr: u16,
+    ) -> Result<u8, St25dvError> {
+        let mut data_out: [u8; 1] = [0; 1];
+        self.sequential_random_read(memory, byte_addr, &mut data_out)
+            .await?;
+        Ok(data_out[0])
+    }
+
+    /// Read the memory from a specified starting point, checking that the range lies within the memory.
+    pub async fn read(
+        &self,
+        memory: Memory,
+        addr: u16,
+        data_out: &mut [u8],
+    ) -> Result<(), St25dvError> {
+        memory.check_bounds(addr, data_out.len())?;
+        self.sequential_random_read(memory, addr, data_out).await
+    }
+
+    /// Write any amount of data from a specified starting point. The data is split into writes that never straddle a 16-byte row and the device is polled after each one until it has finished programming.
+    pub async fn write(&self, memory: Memory, addr: u16, data: &[u8]) -> Result<(), St25dvError> {
+        memory.check_bounds(addr, data.len())?;
+        let mut addr = addr;
+        let mut data = data;
+        while !data.is_empty() {
+            let len = ((MAX_WRITE_LEN - addr % MAX_WRITE_LEN) as usize).min(data.len());
+            let (chunk, rest) = data.split_at(len);
+            self.sequential_write::<{ MAX_WRITE_LEN as usize + 2 }>(memory, addr, chunk)
+                .await?;
+            self.ack_poll(memory).await?;
+            addr += len as u16;
+            data = rest;
+        }
+        Ok(())
+    }
+
+    /// Wait for the device to acknowledge its address again, signalling the internal write cycle has completed.
+    async fn ack_poll(&self, memory: Memory) -> Result<(), St25dvError> {
+        let ds = memory.device_select_code();
+        for _ in 0..ACK_POLL_ATTEMPTS {
+            Timer::after_millis(1).await;
+            let mut i2c = self.i2c.lock().await;
+            if i2c.write(ds, &[]).await.is_ok() {
+                return Ok(());
+            }
+        }
+        Err(St25dvError::WriteTimeout)
+    }
+
+    /// N must be at least the len of the data + 2 to include the memory address. The function will shorten the slice based on the data slice so it won't write null bytes beyond the length of the data. This does not wait for the device to finish programming, see [`St25dv::write`].
+    pub async fn sequential_write<const N: usize>(
+        &self,
+        memory: Memory,
+        addr: u16,
+        data: &[u8],
+    ) -> Result<(), St25dvError> {
+        // TODO: Concatenate
+        let ds = memory.device_select_code();
+        let mut payload: [u8; N] = [0; N];
+
+        if payload.len() < data.len() + 2 {
+            return Err(St25dvError::SequentialWritePayloadTooSmall);
+        }
+
+        payload[0] = addr.to_be_bytes()[0];
+        payload[1] = addr.to_be_bytes()[1];
+
+        let mut n = 1;
+        for d in data {
+            n += 1;
+            payload[n] = *d;
+        }
+
+        let mut i2c = self.i2c.lock().await;
+        if i2c.write(ds, &payload[..data.len() + 2]).await.is_err() {
+            return Err(St25dvError::WriteError);
+        }
+        Ok(())
+    }
+
+    /// Write a byte to a specific address.
+    pub async fn byte_write(&self, memory: Memory, addr: u16, data: u8) -> Result<(), St25dvError> {
+        let ds = memory.device_select_code();
+        let data = [addr.to_be_bytes()[0], addr.to_be_bytes()[1], data];
+        let mut i2c = self.i2c.lock().await;
+        if i2c.write(ds, &data).await.is_err() {
+            return Err(St25dvError::WriteError);
+        }
+        Ok(())
+    }
+}
+
+/// Return the packed size of a register.
+fn register_len<R: Register>() -> Result<usize, St25dvError> {
+    match R::packed_bytes_size(None) {
+        Ok(len) if len <= 8 => Ok(len),
+        _ => Err(St25dvError::PackingError),
+    }
+}
+
+/// A representation of the different memory sections in the EEPROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Memory {
+    User,
+    System,
+    /// The dynamic registers and mailbox, accessed through the user memory device select code.
+    Dynamic,
+}
+
+impl Memory {
+    /// Return the 7-bit device select code for accessing the respective memory. This is bit-shifted and the R/W bit appended by the I2C protocol.
+    pub const fn device_select_code(&self) -> u8 {
+        match &self {
+            Self::User | Self::Dynamic => 0b0101_0011,
+            Self::System => 0b0101_0111,
+        }
+    }
+
+    /// Check an access of `len` bytes from `addr` lies within the memory. The system memory is not checked.
+    pub const fn check_bounds(&self, addr: u16, len: usize) -> Result<(), St25dvError> {
+        match self {
+            Self::User if addr as usize + len > USER_MEMORY_SIZE as usize => {
+                Err(St25dvError::OutOfBounds)
+            }
+            Self::Dynamic if addr < DYNAMIC_START || addr as usize + len > DYNAMIC_END as usize => {
+                Err(St25dvError::OutOfBounds)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::mock::MockI2c;
+
+    const USER: u8 = Memory::User.device_select_code();
+    const SYSTEM: u8 = Memory::System.device_select_code();
+
+    fn eeprom(busy_polls: usize) -> St25dv<MockI2c, NoopRawMutex> {
+        let mut i2c = MockI2c::new(&[USER, SYSTEM]);
+        i2c.busy_polls = busy_polls;
+        St25dv::new(i2c)
+    }
+
+    fn polls(i2c: &MockI2c) -> usize {
+        i2c.transactions
+            .iter()
+            .filter(|t| t.write.is_empty())
+            .count()
+    }
+
+    #[test]
+    fn writes_are_split_at_max_write_len() {
+        let eeprom = eeprom(0);
+        let data: Vec<u8> = (0..40).collect();
+        block_on(eeprom.write(Memory::User, 10, &data)).unwrap();
+
+        let i2c = eeprom.bus();
+        let chunks: Vec<(u16, usize)> = i2c
+            .accesses()
+            .map(|t| {
+                (
+                    u16::from_be_bytes([t.write[0], t.write[1]]),
+                    t.write.len() - 2,
+                )
+            })
+            .collect();
+        assert_eq!(chunks, [(10, 6), (16, 16), (32, 16), (48, 2)]);
+        // Every chunk is followed by one poll that is acknowledged straight away.
+        assert_eq!(polls(&i2c), 4);
+        assert_eq!(i2c.mem(USER, 10, 40), &data[..]);
+        assert_eq!(i2c.mem(USER, 9, 1), [0]);
+        assert_eq!(i2c.mem(USER, 50, 1), [0]);
+    }
+
+    #[test]
+    fn aligned_writes_fill_whole_chunks() {
+        let eeprom = eeprom(0);
+        block_on(eeprom.write(Memory::User, 32, &[0xAA; 32])).unwrap();
+        let i2c = eeprom.bus();
+        let writes: Vec<&[u8]> = i2c.accesses().map(|t| &t.write[..2]).collect();
+        assert_eq!(writes, [[0x00, 0x20], [0x00, 0x30]]);
+    }
+
+    #[test]
+    fn write_waits_for_the_write_cycle() {
+        let eeprom = eeprom(3);
+        block_on(eeprom.write(Memory::User, 0, &[1, 2, 3, 4])).unwrap();
+        let i2c = eeprom.bus();
+        let acks: Vec<bool> = i2c.transactions.iter().map(|t| t.acked).collect();
+        assert_eq!(acks, [true, false, false, false, true]);
+        assert_eq!(i2c.mem(USER, 0, 4), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn write_gives_up_when_the_device_stays_busy() {
+        let eeprom = eeprom(usize::MAX);
+        let result = block_on(eeprom.write(Memory::User, 0, &[0x55; 20]));
+        assert!(matches!(result, Err(St25dvError::WriteTimeout)));
+        let i2c = eeprom.bus();
+        assert_eq!(polls(&i2c), ACK_POLL_ATTEMPTS as usize);
+        // The second chunk is never sent.
+        assert_eq!(i2c.accesses().count(), 1);
+    }
+
+    #[test]
+    fn addresses_are_sent_big_endian() {
+        let eeprom = eeprom(0);
+        eeprom.bus().set(SYSTEM, 0x0102, &[0x42]);
+        assert_eq!(
+            block_on(eeprom.random_address_read(Memory::System, 0x0102)).unwrap(),
+            0x42
+        );
+        block_on(eeprom.byte_write(Memory::User, 0x01FF, 0x24)).unwrap();
+        let i2c = eeprom.bus();
+        let writes: Vec<(u8, &[u8])> = i2c.accesses().map(|t| (t.address, &t.write[..])).collect();
+        assert_eq!(
+            writes,
+            [(SYSTEM, &[0x01, 0x02][..]), (USER, &[0x01, 0xFF, 0x24][..])]
+        );
+        assert_eq!(i2c.mem(USER, 0x01FF, 1), [0x24]);
+    }
+
+    #[test]
+    fn bounds_are_checked() {
+        assert!(
+            Memory::User
+                .check_bounds(0, USER_MEMORY_SIZE as usize)
+                .is_ok()
+        );
+        assert!(Memory::User.check_bounds(511, 1).is_ok());
+        assert!(Memory::User.check_bounds(511, 2).is_err());
+        assert!(Memory::User.check_bounds(512, 0).is_ok());
+        assert!(Memory::User.check_bounds(u16::MAX, 1).is_err());
+        assert!(Memory::Dynamic.check_bounds(DYNAMIC_START - 1, 1).is_err());
+        assert!(
+            Memory::Dynamic
+                .check_bounds(DYNAMIC_START, (DYNAMIC_END - DYNAMIC_START) as usize)
+                .is_ok()
+        );
+        assert!(Memory::Dynamic.check_bounds(DYNAMIC_END - 1, 2).is_err());
+        assert!(Memory::System.check_bounds(0x0900, 8).is_ok());
+    }
+
+    #[test]
+    fn out_of_bounds_accesses_never_reach_the_bus() {
+        let eeprom = eeprom(0);
+        let mut buf = [0; 4];
+        assert!(matches!(
+            block_on(eeprom.read(Memory::User, 510, &mut buf)),
+            Err(St25dvError::OutOfBounds)
+        ));
+        assert!(matches!(
+            block_on(eeprom.write(Memory::User, 500, &[0; 13])),
+            Err(St25dvError::OutOfBounds)
+        ));
+        assert!(eeprom.bus().transactions.is_empty());
+    }
+}
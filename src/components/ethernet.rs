@@ -1,15 +1,18 @@
 #![doc = include_str!("../../docs/ethernet.md")]
 #![allow(clippy::too_many_arguments)]
 use embassy_executor::Spawner;
-use embassy_net::{Stack, StackResources};
+use embassy_net::{Ipv4Address, Ipv4Cidr, Runner, Stack, StackResources, StaticConfigV4};
 use embassy_stm32::{
     bind_interrupts,
     eth::{Ethernet, PacketQueue, generic_smi::GenericSMI},
     peripherals::{ETH, PA1, PA2, PA7, PB11, PB12, PB13, PC1, PC4, PC5, RNG},
     rng::Rng,
 };
+use heapless::{String, Vec};
 use static_cell::StaticCell;
 
+use crate::fmt::error;
+
 bind_interrupts!(struct Irqs {
     ETH => embassy_stm32::eth::InterruptHandler;
     RNG => embassy_stm32::rng::InterruptHandler<embassy_stm32::peripherals::RNG>;
@@ -17,8 +20,257 @@ bind_interrupts!(struct Irqs {
 
 type Device = Ethernet<'static, ETH, GenericSMI>;
 
+/// The longest hostname, limited by the space for the [`BoardConfig`](crate::BoardConfig) in the EEPROM.
+pub const MAX_HOSTNAME_LEN: usize = 20;
+
+/// The most DNS servers a static configuration holds.
+pub const MAX_DNS_SERVERS: usize = 2;
+
+/// How the board gets its IPv4 address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkConfig {
+    /// Ask a DHCP server, sending the hostname if there is one.
+    Dhcp {
+        hostname: Option<String<MAX_HOSTNAME_LEN>>,
+    },
+    /// A fixed address, for networks without a DHCP server. [`NetworkConfig::fixed`] checks the prefix length.
+    Static {
+        address: Ipv4Address,
+        /// The length of the subnet mask, e.g. 24 for 255.255.255.0.
+        prefix_len: u8,
+        gateway: Option<Ipv4Address>,
+        dns_servers: Vec<Ipv4Address, MAX_DNS_SERVERS>,
+    },
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self::Dhcp { hostname: None }
+    }
+}
+
+impl NetworkConfig {
+    /// Layout: kind (1), hostname length (1), hostname (20), address (4), prefix length (1), gateway (4), DNS
+    /// servers (8). Unused addresses are stored as 0.0.0.0.
+    pub(crate) const SIZE: usize = 2 + MAX_HOSTNAME_LEN + 4 + 1 + 4 + 4 * MAX_DNS_SERVERS;
+
+    /// DHCP, sending `hostname` to the server. Returns `None` if the hostname is too long.
+    pub fn dhcp(hostname: &str) -> Option<Self> {
+        Some(Self::Dhcp {
+            hostname: Some(String::try_from(hostname).ok()?),
+        })
+    }
+
+    /// A fixed address. Returns `None` if the prefix length is over 32 or there are more than [`MAX_DNS_SERVERS`].
+    pub fn fixed(
+        address: Ipv4Address,
+        prefix_len: u8,
+        gateway: Option<Ipv4Address>,
+        dns_servers: &[Ipv4Address],
+    ) -> Option<Self> {
+        if prefix_len > 32 {
+            return None;
+        }
+        Some(Self::Static {
+            address,
+            prefix_len,
+            gateway,
+            dns_servers: Vec::from_slice(dns_servers).ok()?,
+        })
+    }
+
+    pub(crate) fn encode(&self, buf: &mut [u8]) {
+        buf[..Self::SIZE].fill(0);
+        match self {
+            Self::Dhcp { hostname } => {
+                if let Some(hostname) = hostname {
+                    buf[1] = hostname.len() as u8;
+                    buf[2..2 + hostname.len()].copy_from_slice(hostname.as_bytes());
+                }
+            }
+            Self::Static {
+                address,
+                prefix_len,
+                gateway,
+                dns_servers,
+            } => {
+                buf[0] = 1;
+                let addrs = &mut buf[2 + MAX_HOSTNAME_LEN..];
+                addrs[..4].copy_from_slice(&address.octets());
+                addrs[4] = *prefix_len;
+                if let Some(gateway) = gateway {
+                    addrs[5..9].copy_from_slice(&gateway.octets());
+                }
+                for (i, dns) in dns_servers.iter().enumerate() {
+                    addrs[9 + 4 * i..13 + 4 * i].copy_from_slice(&dns.octets());
+                }
+            }
+        }
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Option<Self> {
+        let addr = |i: usize| {
+            let start = 2 + MAX_HOSTNAME_LEN + i;
+            let octets: [u8; 4] = buf[start..start + 4].try_into().unwrap();
+            Some(Ipv4Address::from(octets)).filter(|a| !a.is_unspecified())
+        };
+        match buf[0] {
+            0 => {
+                let len = buf[1] as usize;
+                if len > MAX_HOSTNAME_LEN {
+                    return None;
+                }
+                let hostname = match len {
+                    0 => None,
+                    _ => Some(String::try_from(core::str::from_utf8(&buf[2..2 + len]).ok()?).ok()?),
+                };
+                Some(Self::Dhcp { hostname })
+            }
+            1 => {
+                let prefix_len = buf[2 + MAX_HOSTNAME_LEN + 4];
+                if prefix_len > 32 {
+                    return None;
+                }
+                Some(Self::Static {
+                    address: addr(0)?,
+                    prefix_len,
+                    gateway: addr(5),
+                    dns_servers: (0..MAX_DNS_SERVERS)
+                        .filter_map(|i| addr(9 + 4 * i))
+                        .collect(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<&NetworkConfig> for embassy_net::Config {
+    fn from(config: &NetworkConfig) -> Self {
+        match config {
+            NetworkConfig::Dhcp { hostname } => {
+                let mut dhcp = embassy_net::DhcpConfig::default();
+                // The embassy-net hostname is longer than ours so it always fits.
+                dhcp.hostname = hostname
+                    .as_ref()
+                    .and_then(|h| String::try_from(h.as_str()).ok());
+                Self::dhcpv4(dhcp)
+            }
+            NetworkConfig::Static {
+                address,
+                prefix_len,
+                gateway,
+                dns_servers,
+            } => {
+                // `Ipv4Cidr::new` panics on a prefix longer than the address.
+                if *prefix_len > 32 {
+                    error!(
+                        "[Ethernet] Prefix length {} is over 32, using 32",
+                        prefix_len
+                    );
+                }
+                Self::ipv4_static(StaticConfigV4 {
+                    address: Ipv4Cidr::new(*address, (*prefix_len).min(32)),
+                    gateway: *gateway,
+                    dns_servers: dns_servers.iter().copied().collect(),
+                })
+            }
+        }
+    }
+}
+
+/// The Ethernet peripheral and its pins, according to the Buddy Board pinout.
+///  https://github.com/prusa3d/Buddy-board-MINI-PCB/blob/master/rev.1.0.0/BUDDY_v1.0.0.pdf
+pub struct EthernetPins {
+    eth: ETH,
+    ref_clk: PA1,
+    mdio: PA2,
+    mdc: PC1,
+    crs: PA7,
+    rx_d0: PC4,
+    rx_d1: PC5,
+    tx_d0: PB12,
+    tx_d1: PB13,
+    tx_en: PB11,
+}
+
+/// Memory for the network stack, which the [`BoardBuilder`](crate::BoardBuilder) takes whatever its size.
+pub trait EthernetResources {
+    /// Create the device and the network stack in this memory.
+    fn init(
+        &'static mut self,
+        pins: EthernetPins,
+        mac_addr: [u8; 6],
+        config: embassy_net::Config,
+        seed: u64,
+    ) -> (Stack<'static>, Runner<'static, Device>);
+}
+
+/// Room for `SOCKETS` sockets and queues of `RX` and `TX` packets. The default is 3 sockets and 4 packets each way,
+/// and each packet takes about 1.5 kB.
+///
+/// ```rust,ignore
+/// static RESOURCES: ConstStaticCell<NetworkResources<8, 8, 8>> = ConstStaticCell::new(NetworkResources::new());
+/// let board = BoardBuilder::new()
+///     .ethernet(&spawner, mac_addr, NetworkConfig::default())
+///     .ethernet_resources(RESOURCES.take())
+///     .build()
+///     .await;
+/// ```
+pub struct NetworkResources<const SOCKETS: usize, const RX: usize, const TX: usize> {
+    stack: StackResources<SOCKETS>,
+    packets: PacketQueue<TX, RX>,
+}
+
+impl<const SOCKETS: usize, const RX: usize, const TX: usize> NetworkResources<SOCKETS, RX, TX> {
+    pub const fn new() -> Self {
+        Self {
+            stack: StackResources::new(),
+            packets: PacketQueue::new(),
+        }
+    }
+}
+
+impl<const SOCKETS: usize, const RX: usize, const TX: usize> Default
+    for NetworkResources<SOCKETS, RX, TX>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SOCKETS: usize, const RX: usize, const TX: usize> EthernetResources
+    for NetworkResources<SOCKETS, RX, TX>
+{
+    fn init(
+        &'static mut self,
+        pins: EthernetPins,
+        mac_addr: [u8; 6],
+        config: embassy_net::Config,
+        seed: u64,
+    ) -> (Stack<'static>, Runner<'static, Device>) {
+        let device = Ethernet::new(
+            &mut self.packets,
+            pins.eth,
+            Irqs,
+            pins.ref_clk,
+            pins.mdio,
+            pins.mdc,
+            pins.crs,
+            pins.rx_d0,
+            pins.rx_d1,
+            pins.tx_d0,
+            pins.tx_d1,
+            pins.tx_en,
+            GenericSMI::new(0),
+            mac_addr,
+        );
+        embassy_net::new(device, config, &mut self.stack, seed)
+    }
+}
+
 #[embassy_executor::task]
-async fn net_task(mut runner: embassy_net::Runner<'static, Device>) -> ! {
+async fn net_task(mut runner: Runner<'static, Device>) -> ! {
     runner.run().await
 }
 
@@ -36,20 +288,17 @@ pub async fn build_ethernet(
     tx_d1: PB13,
     tx_en: PB11,
     mac_addr: [u8; 6],
-) -> Stack<'_> {
+    network: &NetworkConfig,
+    resources: Option<&'static mut dyn EthernetResources>,
+) -> Stack<'static> {
     // Generate random seed.
     let mut rng = Rng::new(rng, Irqs);
     let mut seed = [0; 8];
     let _ = rng.async_fill_bytes(&mut seed).await;
     let seed = u64::from_le_bytes(seed);
 
-    static PACKETS: StaticCell<PacketQueue<4, 4>> = StaticCell::new();
-    // Creating the device according to the Buddy Board pinout.
-    //  https://github.com/prusa3d/Buddy-board-MINI-PCB/blob/master/rev.1.0.0/BUDDY_v1.0.0.pdf
-    let device = Ethernet::new(
-        PACKETS.init(PacketQueue::<4, 4>::new()),
+    let pins = EthernetPins {
         eth,
-        Irqs,
         ref_clk,
         mdio,
         mdc,
@@ -59,17 +308,91 @@ pub async fn build_ethernet(
         tx_d0,
         tx_d1,
         tx_en,
-        GenericSMI::new(0),
-        mac_addr,
-    );
-
-    let config = embassy_net::Config::dhcpv4(Default::default());
-
-    static RESOURCES: StaticCell<StackResources<3>> = StaticCell::new();
-    let (stack, runner) =
-        embassy_net::new(device, config, RESOURCES.init(StackResources::new()), seed);
+    };
+    let resources = resources.unwrap_or_else(|| {
+        static RESOURCES: StaticCell<NetworkResources<3, 4, 4>> = StaticCell::new();
+        RESOURCES.init(NetworkResources::new())
+    });
+    let (stack, runner) = resources.init(pins, mac_addr, network.into(), seed);
 
     spawner.spawn(net_task(runner)).unwrap();
 
     stack
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: Ipv4Address = Ipv4Address::new(192, 168, 0, 50);
+    const GATEWAY: Ipv4Address = Ipv4Address::new(192, 168, 0, 1);
+    const DNS: [Ipv4Address; 2] = [Ipv4Address::new(1, 1, 1, 1), Ipv4Address::new(8, 8, 8, 8)];
+
+    fn round_trip(config: &NetworkConfig) -> Option<NetworkConfig> {
+        let mut buf = [0xAA; NetworkConfig::SIZE];
+        config.encode(&mut buf);
+        NetworkConfig::decode(&buf)
+    }
+
+    #[test]
+    fn round_trips_static_configs() {
+        for dns in 0..=MAX_DNS_SERVERS {
+            let config = NetworkConfig::fixed(ADDRESS, 24, Some(GATEWAY), &DNS[..dns]).unwrap();
+            assert_eq!(
+                round_trip(&config).as_ref(),
+                Some(&config),
+                "{dns} DNS servers"
+            );
+        }
+        let config = NetworkConfig::fixed(ADDRESS, 32, None, &[]).unwrap();
+        assert_eq!(round_trip(&config), Some(config));
+    }
+
+    #[test]
+    fn round_trips_dhcp_configs() {
+        let config = NetworkConfig::dhcp("buddy-mini-workshop1").unwrap();
+        assert_eq!(MAX_HOSTNAME_LEN, 20);
+        assert_eq!(round_trip(&config).as_ref(), Some(&config));
+        assert_eq!(
+            round_trip(&NetworkConfig::default()),
+            Some(NetworkConfig::default())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        assert_eq!(NetworkConfig::fixed(ADDRESS, 33, None, &[]), None);
+        assert_eq!(NetworkConfig::fixed(ADDRESS, 24, None, &[GATEWAY; 3]), None);
+        assert_eq!(NetworkConfig::dhcp("buddy-mini-workshop12"), None);
+
+        let mut buf = [0; NetworkConfig::SIZE];
+        NetworkConfig::fixed(ADDRESS, 24, Some(GATEWAY), &DNS)
+            .unwrap()
+            .encode(&mut buf);
+        buf[2 + MAX_HOSTNAME_LEN + 4] = 33;
+        assert_eq!(NetworkConfig::decode(&buf), None);
+
+        let mut buf = [0; NetworkConfig::SIZE];
+        buf[0] = 2;
+        assert_eq!(NetworkConfig::decode(&buf), None);
+
+        let mut buf = [0; NetworkConfig::SIZE];
+        buf[1] = MAX_HOSTNAME_LEN as u8 + 1;
+        buf[2..2 + MAX_HOSTNAME_LEN].fill(b'a');
+        assert_eq!(NetworkConfig::decode(&buf), None);
+    }
+
+    #[test]
+    fn clamps_an_overlong_prefix() {
+        let config = NetworkConfig::Static {
+            address: ADDRESS,
+            prefix_len: 40,
+            gateway: None,
+            dns_servers: Vec::new(),
+        };
+        let embassy_net::ConfigV4::Static(config) = embassy_net::Config::from(&config).ipv4 else {
+            panic!("not a static config");
+        };
+        assert_eq!(config.address, Ipv4Cidr::new(ADDRESS, 32));
+    }
+}
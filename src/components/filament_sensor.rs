@@ -9,11 +9,14 @@ use embassy_stm32::{
 };
 use embassy_sync::{
     blocking_mutex::raw::{RawMutex, ThreadModeRawMutex},
-    mutex::{Mutex, TryLockError},
+    mutex::{Mutex, MutexGuard, TryLockError},
 };
+use embassy_time::{Duration, Instant};
 use embedded_hal::digital::InputPin;
 use embedded_hal_async::digital::Wait;
 
+use crate::components::debounce::{Debounced, DebouncedInput, wait_debounced};
+
 pub type BuddyFilamentSensor<'a> = FilamentSensor<ThreadModeRawMutex, ExtiInput<'a>>;
 
 pub(crate) fn build_filament_sensor<'a>(pin: PB4, ch: EXTI4) -> BuddyFilamentSensor<'a> {
@@ -21,23 +24,197 @@ pub(crate) fn build_filament_sensor<'a>(pin: PB4, ch: EXTI4) -> BuddyFilamentSen
     FilamentSensor::new(exti)
 }
 
-#[derive(Debug, Format)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
 pub enum FilamentChanged {
     Added,
     Removed,
 }
 
+/// The settings used to detect a runout.
+#[derive(Debug, Clone, Copy)]
+pub struct RunoutConfig {
+    /// How long the sensor must read the same level before a change is accepted.
+    pub debounce: Duration,
+    /// How far the extruder keeps feeding after the filament leaves the sensor before it has run out (mm). This uses up
+    /// the filament still in the bowden tube.
+    pub extra_distance: f32,
+}
+
+impl Default for RunoutConfig {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(50),
+            extra_distance: 20.0,
+        }
+    }
+}
+
+/// A state machine turning the raw level of the sensor into debounced changes. It is fed with each level change and
+/// polled for changes.
+#[derive(Debug, Clone)]
+pub struct FilamentDebounce {
+    present: Debounced,
+}
+
+impl FilamentDebounce {
+    /// Create a state machine starting from a known level.
+    pub fn new(debounce: Duration, present: bool) -> Self {
+        Self {
+            present: Debounced::new(debounce, present),
+        }
+    }
+
+    /// Return the debounced level.
+    pub fn present(&self) -> bool {
+        self.present.level()
+    }
+
+    /// Record the raw level of the sensor at a point in time.
+    pub fn on_level(&mut self, present: bool, now: Instant) {
+        self.present.on_level(present, now);
+    }
+
+    /// Return the change that has settled by `now`, if any.
+    pub fn poll(&mut self, now: Instant) -> Option<FilamentChanged> {
+        self.present.poll(now).map(|present| {
+            if present {
+                FilamentChanged::Added
+            } else {
+                FilamentChanged::Removed
+            }
+        })
+    }
+
+    /// Return when the state machine next needs polling if the level does not change.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.present.next_deadline()
+    }
+}
+
+impl DebouncedInput for FilamentDebounce {
+    type Event = FilamentChanged;
+
+    fn on_level(&mut self, present: bool, now: Instant) {
+        FilamentDebounce::on_level(self, present, now);
+    }
+
+    fn poll(&mut self, now: Instant) -> Option<FilamentChanged> {
+        FilamentDebounce::poll(self, now)
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        FilamentDebounce::next_deadline(self)
+    }
+}
+
+/// The state of the filament as seen by the [`RunoutDetector`].
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub enum RunoutState {
+    Loaded,
+    /// The filament has left the sensor and the remaining distance is being fed (mm).
+    Draining(f32),
+    RunOut,
+}
+
+/// Decides when the filament has run out from the debounced sensor changes and the distance extruded.
+#[derive(Debug, Clone)]
+pub struct RunoutDetector {
+    extra_distance: f32,
+    state: RunoutState,
+}
+
+impl RunoutDetector {
+    pub fn new(extra_distance: f32, present: bool) -> Self {
+        Self {
+            extra_distance,
+            state: if present {
+                RunoutState::Loaded
+            } else {
+                RunoutState::RunOut
+            },
+        }
+    }
+
+    pub fn state(&self) -> RunoutState {
+        self.state
+    }
+
+    /// Record a debounced change of the sensor.
+    pub fn on_change(&mut self, change: FilamentChanged) {
+        self.state = match (change, self.state) {
+            (FilamentChanged::Added, _) => RunoutState::Loaded,
+            (FilamentChanged::Removed, RunoutState::Loaded) => {
+                RunoutState::Draining(self.extra_distance)
+            }
+            (FilamentChanged::Removed, state) => state,
+        };
+    }
+
+    /// Record filament fed by the extruder (mm). Returns `true` once, when the filament has run out.
+    pub fn on_extruded(&mut self, distance: f32) -> bool {
+        if let RunoutState::Draining(remaining) = self.state {
+            let remaining = remaining - distance.max(0.0);
+            if remaining <= 0.0 {
+                self.state = RunoutState::RunOut;
+                return true;
+            }
+            self.state = RunoutState::Draining(remaining);
+        }
+        false
+    }
+}
+
+/// The steps a print controller takes when the filament runs out. Each step is run in order by
+/// [`FilamentSensor::handle_runout`].
+#[allow(async_fn_in_trait)]
+pub trait RunoutPolicy {
+    /// Stop the print after the current move.
+    async fn pause(&mut self);
+
+    /// Move the head away from the print.
+    async fn park(&mut self);
+
+    /// Let the user know, e.g. by beeping the buzzer.
+    async fn alert(&mut self) {}
+
+    /// Called once new filament has been inserted, e.g. to wait for the user to confirm it has been loaded.
+    async fn inserted(&mut self) {}
+
+    /// Extrude until the new filament comes through the nozzle.
+    async fn purge(&mut self);
+
+    /// Return the head and continue the print.
+    async fn resume(&mut self);
+}
+
 pub struct FilamentSensor<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> {
     exti: Mutex<M, T>,
+    debounce: Mutex<M, Option<FilamentDebounce>>,
+    config: RunoutConfig,
 }
 
 impl<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> FilamentSensor<M, T> {
     pub fn new(exti: T) -> Self {
+        Self::new_with_config(exti, RunoutConfig::default())
+    }
+
+    pub fn new_with_config(exti: T, config: RunoutConfig) -> Self {
         Self {
             exti: Mutex::new(exti),
+            debounce: Mutex::new(None),
+            config,
         }
     }
 
+    pub fn config(&self) -> &RunoutConfig {
+        &self.config
+    }
+
+    /// Create a runout detector using the configured extra distance, starting from the current level.
+    pub async fn runout_detector(&self) -> RunoutDetector {
+        RunoutDetector::new(self.config.extra_distance, self.available().await)
+    }
+
     pub async fn available(&self) -> bool {
         let mut exti = self.exti.lock().await;
         exti.is_high().unwrap()
@@ -59,12 +236,12 @@ impl<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> Fi
         Ok(())
     }
 
-    pub async fn on_removed(self) {
+    pub async fn on_removed(&self) {
         let mut exti = self.exti.lock().await;
         exti.wait_for_falling_edge().await.unwrap();
     }
 
-    pub async fn try_on_removed(self) -> Result<(), TryLockError> {
+    pub async fn try_on_removed(&self) -> Result<(), TryLockError> {
         let mut exti = self.exti.try_lock()?;
         exti.wait_for_falling_edge().await.unwrap();
         Ok(())
@@ -89,4 +266,196 @@ impl<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> Fi
             FilamentChanged::Removed
         }
     }
+
+    /// Wait for the next debounced change. Changes are tracked from the first call.
+    pub async fn next_change(&self) -> FilamentChanged {
+        let mut exti = self.exti.lock().await;
+        let mut debounce = self.debounce.lock().await;
+        Self::wait_for_change(&mut exti, &mut debounce, self.config.debounce).await
+    }
+
+    /// Wait for the next debounced change. Changes are tracked from the first call.
+    pub async fn try_next_change(&self) -> Result<FilamentChanged, TryLockError> {
+        let mut exti = self.exti.try_lock()?;
+        let mut debounce = self.debounce.try_lock()?;
+        Ok(Self::wait_for_change(&mut exti, &mut debounce, self.config.debounce).await)
+    }
+
+    /// Wait until filament has been inserted and has settled.
+    pub async fn wait_for_insertion(&self) {
+        let mut exti = self.exti.lock().await;
+        let mut debounce = self.debounce.lock().await;
+        let period = self.config.debounce;
+        let present = debounce
+            .get_or_insert_with(|| FilamentDebounce::new(period, exti.is_high().unwrap()))
+            .present();
+        if present {
+            return;
+        }
+        while Self::wait_for_change(&mut exti, &mut debounce, self.config.debounce).await
+            != FilamentChanged::Added
+        {}
+    }
+
+    /// Run the policy through a runout: pause, park, alert, wait for new filament, purge and resume.
+    pub async fn handle_runout<P: RunoutPolicy>(&self, policy: &mut P) {
+        policy.pause().await;
+        policy.park().await;
+        policy.alert().await;
+        self.wait_for_insertion().await;
+        policy.inserted().await;
+        policy.purge().await;
+        policy.resume().await;
+    }
+
+    async fn wait_for_change(
+        exti: &mut MutexGuard<'_, M, T>,
+        debounce: &mut MutexGuard<'_, M, Option<FilamentDebounce>>,
+        period: Duration,
+    ) -> FilamentChanged {
+        let debounce =
+            debounce.get_or_insert_with(|| FilamentDebounce::new(period, exti.is_high().unwrap()));
+        wait_debounced(&mut **exti, false, debounce).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::mock::MockPin;
+
+    fn at(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    fn debounce() -> FilamentDebounce {
+        FilamentDebounce::new(Duration::from_millis(50), true)
+    }
+
+    #[test]
+    fn rejects_glitches_shorter_than_the_debounce() {
+        let mut d = debounce();
+        d.on_level(false, at(100));
+        assert_eq!(d.poll(at(120)), None);
+        d.on_level(true, at(130));
+        assert_eq!(d.next_deadline(), None);
+        assert_eq!(d.poll(at(200)), None);
+        assert!(d.present());
+    }
+
+    #[test]
+    fn accepts_a_level_held_for_the_debounce() {
+        let mut d = debounce();
+        d.on_level(false, at(100));
+        assert_eq!(d.next_deadline(), Some(at(150)));
+        assert_eq!(d.poll(at(149)), None);
+        assert_eq!(d.poll(at(150)), Some(FilamentChanged::Removed));
+        assert!(!d.present());
+        assert_eq!(d.poll(at(500)), None);
+
+        // Repeating the settled level changes nothing.
+        d.on_level(false, at(600));
+        assert_eq!(d.next_deadline(), None);
+        d.on_level(true, at(700));
+        assert_eq!(d.poll(at(750)), Some(FilamentChanged::Added));
+    }
+
+    #[test]
+    fn bouncing_restarts_the_hold_time() {
+        let mut d = debounce();
+        d.on_level(false, at(100));
+        d.on_level(true, at(110));
+        d.on_level(false, at(120));
+        assert_eq!(d.next_deadline(), Some(at(170)));
+        assert_eq!(d.poll(at(150)), None);
+        assert_eq!(d.poll(at(170)), Some(FilamentChanged::Removed));
+    }
+
+    #[test]
+    fn runs_out_once_after_the_extra_distance() {
+        let mut runout = RunoutDetector::new(20.0, true);
+        assert!(!runout.on_extruded(100.0));
+        runout.on_change(FilamentChanged::Removed);
+        assert_eq!(runout.state(), RunoutState::Draining(20.0));
+        assert!(!runout.on_extruded(15.0));
+        // Retractions do not put filament back.
+        assert!(!runout.on_extruded(-5.0));
+        assert_eq!(runout.state(), RunoutState::Draining(5.0));
+        assert!(runout.on_extruded(5.0));
+        assert_eq!(runout.state(), RunoutState::RunOut);
+        assert!(!runout.on_extruded(5.0));
+
+        // A second removal does not restart the countdown.
+        runout.on_change(FilamentChanged::Removed);
+        assert_eq!(runout.state(), RunoutState::RunOut);
+        runout.on_change(FilamentChanged::Added);
+        assert_eq!(runout.state(), RunoutState::Loaded);
+    }
+
+    #[test]
+    fn reinserting_while_draining_cancels_the_runout() {
+        let mut runout = RunoutDetector::new(20.0, true);
+        runout.on_change(FilamentChanged::Removed);
+        assert!(!runout.on_extruded(10.0));
+        runout.on_change(FilamentChanged::Added);
+        assert!(!runout.on_extruded(50.0));
+        assert_eq!(runout.state(), RunoutState::Loaded);
+    }
+
+    #[test]
+    fn starts_from_the_sensor_level() {
+        assert_eq!(
+            RunoutDetector::new(20.0, false).state(),
+            RunoutState::RunOut
+        );
+        let sensor = FilamentSensor::<NoopRawMutex, _>::new(MockPin { high: true });
+        assert_eq!(
+            block_on(sensor.runout_detector()).state(),
+            RunoutState::Loaded
+        );
+    }
+
+    #[derive(Default)]
+    struct Steps(Vec<&'static str>);
+
+    impl RunoutPolicy for Steps {
+        async fn pause(&mut self) {
+            self.0.push("pause");
+        }
+
+        async fn park(&mut self) {
+            self.0.push("park");
+        }
+
+        async fn alert(&mut self) {
+            self.0.push("alert");
+        }
+
+        async fn inserted(&mut self) {
+            self.0.push("inserted");
+        }
+
+        async fn purge(&mut self) {
+            self.0.push("purge");
+        }
+
+        async fn resume(&mut self) {
+            self.0.push("resume");
+        }
+    }
+
+    #[test]
+    fn handles_a_runout_in_order() {
+        // The filament is already back so the wait for insertion returns straight away.
+        let sensor = FilamentSensor::<NoopRawMutex, _>::new(MockPin { high: true });
+        let mut steps = Steps::default();
+        block_on(sensor.handle_runout(&mut steps));
+        assert_eq!(
+            steps.0,
+            ["pause", "park", "alert", "inserted", "purge", "resume"]
+        );
+    }
 }
@@ -0,0 +1,496 @@
+use core::{cell::RefCell, cmp::Reverse};
+
+use embassy_futures::block_on;
+use embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx, TimeSource, Timestamp};
+use embedded_storage_async::nor_flash::NorFlash;
+
+use crate::components::flash::{error::BlockDeviceError, w25q64jv::SECTOR_SIZE};
+
+/// The number of 512-byte blocks held in one erase sector.
+const BLOCKS_PER_SECTOR: u32 = SECTOR_SIZE / Block::LEN_U32;
+
+/// The first block of the FAT partition. Starting the volume on an erase sector boundary means its reserved area does not share a sector with the partition table.
+const PARTITION_START: u32 = BLOCKS_PER_SECTOR;
+
+/// The number of entries in the FAT16 root directory.
+const ROOT_ENTRIES: u32 = 512;
+
+/// FAT16 volumes hold between 4085 and 65524 clusters, fewer is FAT12 and more is FAT32.
+const FAT16_CLUSTERS: core::ops::Range<u32> = 4_085..65_525;
+
+/// The smallest FAT16 volume: the fewest clusters at one block each, the boot sector, two FATs and the root directory.
+const MIN_VOLUME_BLOCKS: u32 = FAT16_CLUSTERS.start
+    + 1
+    + 2 * ((FAT16_CLUSTERS.start + 2) * 2).div_ceil(Block::LEN_U32)
+    + ROOT_ENTRIES * 32 / Block::LEN_U32;
+
+/// The smallest region, in bytes, that [`FlashBlockDevice::format`] can hold a FAT16 volume in (520 erase sectors, a little over 2MB).
+pub const MIN_FILESYSTEM_SIZE: u32 =
+    (PARTITION_START + MIN_VOLUME_BLOCKS).div_ceil(BLOCKS_PER_SECTOR) * SECTOR_SIZE;
+
+/// The partition type for FAT16 with LBA addressing.
+const PARTITION_ID_FAT16_LBA: u8 = 0x0E;
+
+/// A write-back cache of one erase sector.
+struct CachedSector {
+    sector: Option<u32>,
+    dirty: bool,
+    last_used: u32,
+    data: [u8; SECTOR_SIZE as usize],
+}
+
+impl CachedSector {
+    const fn new() -> Self {
+        Self {
+            sector: None,
+            dirty: false,
+            last_used: 0,
+            data: [0xFF; SECTOR_SIZE as usize],
+        }
+    }
+}
+
+struct Cache<const N: usize> {
+    sectors: [CachedSector; N],
+    clock: u32,
+}
+
+/// Presents a region of the flash as a block device of 512-byte blocks so it can hold a FAT filesystem.
+///
+/// A 512-byte block cannot be rewritten in place on NOR flash, so writes go into a cache of `N` 4KB erase sectors. A modified sector is erased and programmed back when it is evicted or when [`FlashBlockDevice::flush`] is called. The default of three sectors keeps the data sector and both copies of the FAT cached while a file is streamed in. Blocks that are not cached are read straight from the flash.
+///
+/// [`BlockDevice`] is a blocking trait so the flash operations are driven to completion with `block_on`, which holds the executor until they finish. Writing back a sector erases it first, which takes 45ms and up to 400ms, so a write or flush can stall every other task on the same executor for that long. Do not use the device from an executor that runs the heater or motion tasks; run those on an `InterruptExecutor` so they pre-empt the filesystem, or give the filesystem an executor of its own.
+pub struct FlashBlockDevice<F: NorFlash, const N: usize = 3> {
+    flash: RefCell<F>,
+    /// The flash address of the first block.
+    start: u32,
+    /// The number of blocks on the device.
+    blocks: u32,
+    cache: RefCell<Cache<N>>,
+}
+
+impl<F: NorFlash, const N: usize> FlashBlockDevice<F, N> {
+    /// Create a block device over `len` bytes of flash starting at `start`. Both must be aligned to the erase sector size.
+    pub fn new(flash: F, start: u32, len: u32) -> Result<Self, BlockDeviceError> {
+        let end = start as u64 + len as u64;
+        if F::ERASE_SIZE as u32 != SECTOR_SIZE
+            || !start.is_multiple_of(SECTOR_SIZE)
+            || !len.is_multiple_of(SECTOR_SIZE)
+            || end > flash.capacity() as u64
+            || N == 0
+        {
+            return Err(BlockDeviceError::InvalidRegion);
+        }
+        Ok(Self {
+            flash: RefCell::new(flash),
+            start,
+            blocks: len / Block::LEN_U32,
+            cache: RefCell::new(Cache {
+                sectors: [const { CachedSector::new() }; N],
+                clock: 0,
+            }),
+        })
+    }
+
+    /// Write any modified sectors back to the flash. Call this once files have been closed and before the board powers down.
+    pub fn flush(&self) -> Result<(), BlockDeviceError> {
+        let mut cache = self.cache.borrow_mut();
+        for slot in cache.sectors.iter_mut() {
+            self.write_back(slot)?;
+        }
+        Ok(())
+    }
+
+    /// Flush the cache and release the underlying flash.
+    pub fn release(self) -> Result<F, BlockDeviceError> {
+        self.flush()?;
+        Ok(self.flash.into_inner())
+    }
+
+    /// Write a blank FAT16 filesystem to the device, destroying any files on it. The device must be at least [`MIN_FILESYSTEM_SIZE`] bytes. The device holds a partition table in its first sector followed by a single FAT16 volume, which is the layout `embedded_sdmmc::VolumeManager` expects.
+    pub fn format(&self) -> Result<(), BlockDeviceError> {
+        let volume_blocks = self.blocks.saturating_sub(PARTITION_START);
+        let layout = FatLayout::new(volume_blocks).ok_or(BlockDeviceError::TooSmall)?;
+
+        let mut block = Block::new();
+        encode_mbr(&mut block, volume_blocks);
+        self.write(&[block], BlockIdx(0))?;
+
+        let mut block = Block::new();
+        layout.encode_boot_sector(&mut block, volume_blocks);
+        self.write(&[block], BlockIdx(PARTITION_START))?;
+
+        // Empty both FATs apart from the two reserved entries and clear the root directory.
+        let fat_start = PARTITION_START + layout.reserved_blocks;
+        for fat in 0..layout.fats {
+            let first = fat_start + fat * layout.fat_blocks;
+            for n in 0..layout.fat_blocks {
+                let mut block = Block::new();
+                if n == 0 {
+                    block[..4].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0xFF]);
+                }
+                self.write(&[block], BlockIdx(first + n))?;
+            }
+        }
+        let root_start = fat_start + layout.fats * layout.fat_blocks;
+        for n in 0..layout.root_blocks() {
+            self.write(&[Block::new()], BlockIdx(root_start + n))?;
+        }
+        self.flush()
+    }
+
+    fn check_range(&self, start: BlockIdx, count: usize) -> Result<(), BlockDeviceError> {
+        match start.0.checked_add(count as u32) {
+            Some(end) if end <= self.blocks => Ok(()),
+            _ => Err(BlockDeviceError::OutOfBounds),
+        }
+    }
+
+    /// Return the cache slot holding `sector`, loading it from flash (and evicting the least recently used slot) if needed.
+    fn load<'c>(
+        &self,
+        cache: &'c mut Cache<N>,
+        sector: u32,
+    ) -> Result<&'c mut CachedSector, BlockDeviceError> {
+        cache.clock = cache.clock.wrapping_add(1);
+        let clock = cache.clock;
+        let idx = match cache.sectors.iter().position(|s| s.sector == Some(sector)) {
+            Some(idx) => idx,
+            None => {
+                // Prefer an empty slot, then the least recently used.
+                let idx = cache
+                    .sectors
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, s)| {
+                        (s.sector.is_some(), Reverse(clock.wrapping_sub(s.last_used)))
+                    })
+                    .map(|(idx, _)| idx)
+                    .unwrap();
+                let slot = &mut cache.sectors[idx];
+                self.write_back(slot)?;
+                slot.sector = None;
+                let addr = self.start + sector * SECTOR_SIZE;
+                block_on(self.flash.borrow_mut().read(addr, &mut slot.data))?;
+                slot.sector = Some(sector);
+                idx
+            }
+        };
+        let slot = &mut cache.sectors[idx];
+        slot.last_used = clock;
+        Ok(slot)
+    }
+
+    /// Erase and reprogram a cached sector if it has been modified.
+    fn write_back(&self, slot: &mut CachedSector) -> Result<(), BlockDeviceError> {
+        if let (Some(sector), true) = (slot.sector, slot.dirty) {
+            let addr = self.start + sector * SECTOR_SIZE;
+            let mut flash = self.flash.borrow_mut();
+            block_on(flash.erase(addr, addr + SECTOR_SIZE))?;
+            block_on(flash.write(addr, &slot.data))?;
+            slot.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+impl<F: NorFlash, const N: usize> BlockDevice for FlashBlockDevice<F, N> {
+    type Error = BlockDeviceError;
+
+    fn read(
+        &self,
+        blocks: &mut [Block],
+        start_block_idx: BlockIdx,
+        _reason: &str,
+    ) -> Result<(), Self::Error> {
+        self.check_range(start_block_idx, blocks.len())?;
+        let cache = self.cache.borrow();
+        for (n, block) in blocks.iter_mut().enumerate() {
+            let idx = start_block_idx.0 + n as u32;
+            let sector = idx / BLOCKS_PER_SECTOR;
+            let offset = ((idx % BLOCKS_PER_SECTOR) * Block::LEN_U32) as usize;
+            match cache.sectors.iter().find(|s| s.sector == Some(sector)) {
+                Some(slot) => block.copy_from_slice(&slot.data[offset..offset + Block::LEN]),
+                None => {
+                    let addr = self.start + idx * Block::LEN_U32;
+                    block_on(self.flash.borrow_mut().read(addr, &mut block.contents))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        self.check_range(start_block_idx, blocks.len())?;
+        let mut cache = self.cache.borrow_mut();
+        for (n, block) in blocks.iter().enumerate() {
+            let idx = start_block_idx.0 + n as u32;
+            let offset = ((idx % BLOCKS_PER_SECTOR) * Block::LEN_U32) as usize;
+            let slot = self.load(&mut cache, idx / BLOCKS_PER_SECTOR)?;
+            let data = &mut slot.data[offset..offset + Block::LEN];
+            if *data != block.contents[..] {
+                data.copy_from_slice(&block.contents);
+                slot.dirty = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+        Ok(BlockCount(self.blocks))
+    }
+}
+
+/// The geometry of a FAT16 volume.
+struct FatLayout {
+    blocks_per_cluster: u32,
+    reserved_blocks: u32,
+    fats: u32,
+    fat_blocks: u32,
+}
+
+impl FatLayout {
+    /// Find the largest cluster size that still gives a valid FAT16 cluster count. Even with one block per cluster the volume needs [`MIN_VOLUME_BLOCKS`] blocks.
+    fn new(volume_blocks: u32) -> Option<Self> {
+        let mut blocks_per_cluster = BLOCKS_PER_SECTOR;
+        while blocks_per_cluster > 0 {
+            let mut layout = Self {
+                blocks_per_cluster,
+                reserved_blocks: 1,
+                fats: 2,
+                fat_blocks: 1,
+            };
+            // The FAT size depends on the cluster count which depends on the FAT size, so grow the FAT until it covers every cluster. Starting small finds the smallest FAT and leaves the most room for data.
+            while (layout.clusters(volume_blocks)? + 2) * 2 > layout.fat_blocks * Block::LEN_U32 {
+                layout.fat_blocks += 1;
+            }
+            let clusters = layout.clusters(volume_blocks)?;
+            if FAT16_CLUSTERS.contains(&clusters) {
+                return Some(layout);
+            }
+            if clusters >= FAT16_CLUSTERS.end {
+                return None;
+            }
+            blocks_per_cluster /= 2;
+        }
+        None
+    }
+
+    fn root_blocks(&self) -> u32 {
+        (ROOT_ENTRIES * 32).div_ceil(Block::LEN_U32)
+    }
+
+    fn clusters(&self, volume_blocks: u32) -> Option<u32> {
+        let data_blocks = volume_blocks
+            .checked_sub(self.reserved_blocks + self.fats * self.fat_blocks + self.root_blocks())?;
+        Some(data_blocks / self.blocks_per_cluster)
+    }
+
+    fn encode_boot_sector(&self, block: &mut Block, volume_blocks: u32) {
+        block[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+        block[3..11].copy_from_slice(b"EMBBUDDY");
+        block[11..13].copy_from_slice(&(Block::LEN as u16).to_le_bytes());
+        block[13] = self.blocks_per_cluster as u8;
+        block[14..16].copy_from_slice(&(self.reserved_blocks as u16).to_le_bytes());
+        block[16] = self.fats as u8;
+        block[17..19].copy_from_slice(&(ROOT_ENTRIES as u16).to_le_bytes());
+        if volume_blocks <= u16::MAX as u32 {
+            block[19..21].copy_from_slice(&(volume_blocks as u16).to_le_bytes());
+        } else {
+            block[32..36].copy_from_slice(&volume_blocks.to_le_bytes());
+        }
+        // Fixed disk.
+        block[21] = 0xF8;
+        block[22..24].copy_from_slice(&(self.fat_blocks as u16).to_le_bytes());
+        // Nominal geometry, only used by BIOS CHS addressing.
+        block[24..26].copy_from_slice(&32u16.to_le_bytes());
+        block[26..28].copy_from_slice(&64u16.to_le_bytes());
+        block[28..32].copy_from_slice(&PARTITION_START.to_le_bytes());
+        // Extended boot record.
+        block[36] = 0x80;
+        block[38] = 0x29;
+        block[39..43].copy_from_slice(&0x4255_4459u32.to_le_bytes());
+        block[43..54].copy_from_slice(b"BUDDY      ");
+        block[54..62].copy_from_slice(b"FAT16   ");
+        block[510..512].copy_from_slice(&[0x55, 0xAA]);
+    }
+}
+
+/// Write a master boot record with a single FAT16 partition.
+fn encode_mbr(block: &mut Block, volume_blocks: u32) {
+    let entry = &mut block[446..462];
+    entry[0] = 0x00;
+    // CHS addresses are unused with LBA so mark them as out of range.
+    entry[1..4].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+    entry[4] = PARTITION_ID_FAT16_LBA;
+    entry[5..8].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+    entry[8..12].copy_from_slice(&PARTITION_START.to_le_bytes());
+    entry[12..16].copy_from_slice(&volume_blocks.to_le_bytes());
+    block[510..512].copy_from_slice(&[0x55, 0xAA]);
+}
+
+/// A [`TimeSource`] that stamps every file with the same time, for when the board has no wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixedTimeSource;
+
+impl TimeSource for FixedTimeSource {
+    fn get_timestamp(&self) -> Timestamp {
+        // 2025-01-01 00:00:00
+        Timestamp {
+            year_since_1970: 55,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_sdmmc::{Mode, VolumeIdx, VolumeManager};
+
+    use super::*;
+    use crate::{
+        components::flash::w25q64jv::{FILESYSTEM_START, FLASH_CAPACITY},
+        mock::RamFlash,
+    };
+
+    const SECTOR: usize = SECTOR_SIZE as usize;
+    type Flash = RamFlash<{ 1024 * SECTOR }, SECTOR>;
+
+    fn job(len: usize) -> Vec<u8> {
+        (0..len).map(|n| b"G1 X10 Y20 E0.5\n"[n % 16]).collect()
+    }
+
+    fn write_file<D: BlockDevice>(
+        volume_mgr: &mut VolumeManager<D, FixedTimeSource>,
+        name: &str,
+        data: &[u8],
+    ) where
+        D::Error: core::fmt::Debug,
+    {
+        let mut volume = volume_mgr.open_volume(VolumeIdx(0)).unwrap();
+        let mut root = volume.open_root_dir().unwrap();
+        let mut file = root
+            .open_file_in_dir(name, Mode::ReadWriteCreateOrTruncate)
+            .unwrap();
+        file.write(data).unwrap();
+        file.close().unwrap();
+    }
+
+    fn read_file<D: BlockDevice>(
+        volume_mgr: &mut VolumeManager<D, FixedTimeSource>,
+        name: &str,
+    ) -> Vec<u8>
+    where
+        D::Error: core::fmt::Debug,
+    {
+        let mut volume = volume_mgr.open_volume(VolumeIdx(0)).unwrap();
+        let mut root = volume.open_root_dir().unwrap();
+        let mut file = root.open_file_in_dir(name, Mode::ReadOnly).unwrap();
+        let mut data = Vec::new();
+        let mut buf = [0; 100];
+        while !file.is_eof() {
+            let n = file.read(&mut buf).unwrap();
+            data.extend_from_slice(&buf[..n]);
+        }
+        file.close().unwrap();
+        data
+    }
+
+    #[test]
+    fn layout_uses_smaller_clusters_for_smaller_volumes() {
+        assert!(FatLayout::new(MIN_VOLUME_BLOCKS - 1).is_none());
+        let layout = FatLayout::new(MIN_VOLUME_BLOCKS).unwrap();
+        assert_eq!(layout.blocks_per_cluster, 1);
+        assert_eq!(
+            layout.clusters(MIN_VOLUME_BLOCKS),
+            Some(FAT16_CLUSTERS.start)
+        );
+        assert_eq!(MIN_FILESYSTEM_SIZE, 520 * SECTOR_SIZE);
+
+        // Too few clusters at 8 or 4 blocks each.
+        assert_eq!(FatLayout::new(12_000).unwrap().blocks_per_cluster, 2);
+        // The rest of the 8MB flash.
+        let layout = FatLayout::new((FLASH_CAPACITY - FILESYSTEM_START) / Block::LEN_U32).unwrap();
+        assert_eq!(layout.blocks_per_cluster, 2);
+        for volume_blocks in MIN_VOLUME_BLOCKS..70_000 {
+            let layout = FatLayout::new(volume_blocks).unwrap();
+            let clusters = layout.clusters(volume_blocks).unwrap();
+            assert!(FAT16_CLUSTERS.contains(&clusters));
+            assert!((clusters + 2) * 2 <= layout.fat_blocks * Block::LEN_U32);
+        }
+    }
+
+    #[test]
+    fn format_needs_the_minimum_size() {
+        let mut flash = Flash::new();
+        let device: FlashBlockDevice<_> =
+            FlashBlockDevice::new(&mut flash, 0, MIN_FILESYSTEM_SIZE - SECTOR_SIZE).unwrap();
+        assert!(matches!(device.format(), Err(BlockDeviceError::TooSmall)));
+
+        let device: FlashBlockDevice<_> =
+            FlashBlockDevice::new(&mut flash, 0, MIN_FILESYSTEM_SIZE).unwrap();
+        device.format().unwrap();
+        let mut volume_mgr = VolumeManager::new(device, FixedTimeSource);
+        write_file(&mut volume_mgr, "JOB.GCO", &job(100));
+        assert_eq!(read_file(&mut volume_mgr, "JOB.GCO"), job(100));
+    }
+
+    #[test]
+    fn files_survive_a_flush_and_remount() {
+        let mut flash = Flash::new();
+        let device: FlashBlockDevice<_> =
+            FlashBlockDevice::new(&mut flash, SECTOR_SIZE, 1023 * SECTOR_SIZE).unwrap();
+        device.format().unwrap();
+        let mut volume_mgr = VolumeManager::new(device, FixedTimeSource);
+        // Spans several erase sectors so some are evicted from the cache part way through.
+        write_file(&mut volume_mgr, "BIG.GCO", &job(40_000));
+        write_file(&mut volume_mgr, "SMALL.GCO", &job(10));
+        // Read back through the cache.
+        assert_eq!(read_file(&mut volume_mgr, "BIG.GCO"), job(40_000));
+        assert_eq!(read_file(&mut volume_mgr, "SMALL.GCO"), job(10));
+        let (device, _) = volume_mgr.free();
+        device.release().unwrap();
+
+        // Power up a fresh device on what reached the flash.
+        let mut flash = Flash::from_image(flash.data.clone());
+        let device: FlashBlockDevice<_> =
+            FlashBlockDevice::new(&mut flash, SECTOR_SIZE, 1023 * SECTOR_SIZE).unwrap();
+        let mut volume_mgr = VolumeManager::new(device, FixedTimeSource);
+        assert_eq!(read_file(&mut volume_mgr, "BIG.GCO"), job(40_000));
+        assert_eq!(read_file(&mut volume_mgr, "SMALL.GCO"), job(10));
+
+        // Truncating and rewriting a file reuses its clusters.
+        write_file(&mut volume_mgr, "BIG.GCO", &job(600));
+        volume_mgr.device().flush().unwrap();
+        assert_eq!(read_file(&mut volume_mgr, "BIG.GCO"), job(600));
+    }
+
+    #[test]
+    fn writes_stay_cached_until_flushed() {
+        let mut flash = Flash::new();
+        let device: FlashBlockDevice<_> =
+            FlashBlockDevice::new(&mut flash, 0, 4 * SECTOR_SIZE).unwrap();
+        let mut block = Block::new();
+        block.contents.fill(0xA5);
+        device.write(&[block.clone()], BlockIdx(9)).unwrap();
+
+        let mut read = [Block::new()];
+        device.read(&mut read, BlockIdx(9), "").unwrap();
+        assert_eq!(read[0].contents, block.contents);
+        let flash = device.release().unwrap();
+        assert!(
+            flash.data[9 * Block::LEN..10 * Block::LEN]
+                .iter()
+                .all(|b| *b == 0xA5)
+        );
+        assert!(
+            flash.data[8 * Block::LEN..9 * Block::LEN]
+                .iter()
+                .all(|b| *b == 0xFF)
+        );
+    }
+}
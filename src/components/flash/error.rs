@@ -0,0 +1,69 @@
+use embedded_storage_async::nor_flash::{NorFlashError, NorFlashErrorKind};
+use thiserror::Error;
+
+/// The set of errors that may occur when interacting with the flash chip.
+#[derive(Debug, Error)]
+pub enum FlashError {
+    #[error("SPI Error")]
+    Spi,
+    #[error("The address range is outside the flash memory.")]
+    OutOfBounds,
+    #[error("The address is not aligned to an erase sector.")]
+    NotAligned,
+}
+
+impl NorFlashError for FlashError {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Self::Spi => NorFlashErrorKind::Other,
+            Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            Self::NotAligned => NorFlashErrorKind::NotAligned,
+        }
+    }
+}
+
+/// The set of errors that may occur when interacting with the key-value store.
+#[derive(Debug, Error)]
+pub enum KvStoreError {
+    #[error("Flash Error: {0:?}")]
+    Flash(NorFlashErrorKind),
+    #[error(
+        "The store region must be sector aligned and span at least two sectors of at most 64 KiB."
+    )]
+    InvalidRegion,
+    #[error("Key {0} is reserved.")]
+    InvalidKey(u16),
+    #[error("The value is too large to store. Max: {max}, Received: {received}")]
+    ValueTooLarge { max: usize, received: usize },
+    #[error("The buffer is too small to hold the value. Required: {0}")]
+    BufferTooSmall(usize),
+    #[error("The stored value has a different type to the one requested.")]
+    TypeMismatch,
+    #[error("The store is full of live values.")]
+    Full,
+}
+
+impl<E: NorFlashError> From<E> for KvStoreError {
+    fn from(e: E) -> Self {
+        Self::Flash(e.kind())
+    }
+}
+
+/// The set of errors that may occur when using the flash as a block device.
+#[derive(Debug, Error)]
+pub enum BlockDeviceError {
+    #[error("Flash Error: {0:?}")]
+    Flash(NorFlashErrorKind),
+    #[error("The region must be aligned to and a multiple of the erase sector size.")]
+    InvalidRegion,
+    #[error("The block range is outside the device.")]
+    OutOfBounds,
+    #[error("The device is too small to hold a FAT16 volume.")]
+    TooSmall,
+}
+
+impl<E: NorFlashError> From<E> for BlockDeviceError {
+    fn from(e: E) -> Self {
+        Self::Flash(e.kind())
+    }
+}
@@ -0,0 +1,746 @@
+use embedded_storage_async::nor_flash::NorFlash;
+use heapless::Vec;
+
+use crate::{components::flash::error::KvStoreError, crc::crc32};
+
+/// Identifies a sector that belongs to the store ("KVS1").
+const MAGIC: u32 = 0x4B56_5331;
+
+/// Sector header layout: magic (4), sequence (4), crc of the previous 8 bytes (4), state (1), reserved (3).
+const HEADER_LEN: u32 = 16;
+
+/// Offset of the state byte within the sector header.
+const STATE_OFFSET: u32 = 12;
+
+/// The state byte is left erased (`0xFF`) while a sector is being compacted into and programmed to `0x00` once it is complete.
+const STATE_DONE: u8 = 0x00;
+
+/// Record layout: key (2), kind (1), value length (1), value (n), crc of the previous bytes (4).
+const RECORD_OVERHEAD: usize = 8;
+
+/// The largest value that can be stored against a key.
+pub const MAX_VALUE_LEN: usize = u8::MAX as usize;
+
+const MAX_RECORD_LEN: usize = RECORD_OVERHEAD + MAX_VALUE_LEN;
+
+/// An erased key marks the end of the log within a sector.
+const BLANK_KEY: u16 = 0xFFFF;
+
+/// The most keys indexed when compacting a sector, enough for a 4 KiB sector of empty values.
+const INDEX_LEN: usize = 512;
+
+/// Marks an indexed key that has a newer record in a later sector. Records never start at the end of a sector.
+const SUPERSEDED: u16 = u16::MAX;
+
+/// The keys in a sector and the offset of the newest record for each, sorted by key.
+type Index = Vec<(u16, u16), INDEX_LEN>;
+
+/// The type of value held by a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    U32,
+    F32,
+    Bytes,
+    Tombstone,
+}
+
+impl Kind {
+    const fn to_byte(self) -> u8 {
+        match self {
+            Self::U32 => 0x01,
+            Self::F32 => 0x02,
+            Self::Bytes => 0x03,
+            Self::Tombstone => 0x04,
+        }
+    }
+
+    const fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x01 => Some(Self::U32),
+            0x02 => Some(Self::F32),
+            0x03 => Some(Self::Bytes),
+            0x04 => Some(Self::Tombstone),
+            _ => None,
+        }
+    }
+}
+
+/// A record that passed its CRC check.
+#[derive(Debug, Clone, Copy)]
+struct Record {
+    key: u16,
+    kind: Kind,
+    len: usize,
+}
+
+impl Record {
+    const fn total_len(&self) -> u32 {
+        (RECORD_OVERHEAD + self.len) as u32
+    }
+}
+
+/// Where the latest record for a key lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Location {
+    sector: u32,
+    offset: u32,
+}
+
+/// A log-structured key-value store spread over a ring of flash sectors.
+///
+/// Values are appended as CRC protected records to the active sector and the newest record for a key wins. When the active sector fills up the next (always erased) sector becomes active, the live records of the oldest sector are copied into it and the oldest sector is erased to become the new spare. Sectors are therefore used in turn, spreading the erase cycles across the region.
+///
+/// A write interrupted by power loss only ever affects the record being written. Torn records fail their CRC and are ignored on the next mount, and an interrupted compaction is detected from the sector state byte and redone from the untouched oldest sector.
+pub struct KvStore<F: NorFlash> {
+    flash: F,
+    /// The flash address of the first sector in the store.
+    start: u32,
+    /// The number of sectors in the ring.
+    sectors: u32,
+    /// The sector that new records are appended to.
+    active: u32,
+    /// The sequence number of the active sector.
+    seq: u32,
+    /// The offset within the active sector where the next record will be written.
+    write_offset: u32,
+}
+
+impl<F: NorFlash> KvStore<F> {
+    /// Mount the store on `sectors` erase sectors starting at the flash address `start`. A region without a valid store is formatted and a store that lost power part way through a write or compaction is repaired.
+    pub async fn mount(flash: F, start: u32, sectors: u32) -> Result<Self, KvStoreError> {
+        let sector_size = F::ERASE_SIZE as u32;
+        let end = (sectors as u64 * sector_size as u64) + start as u64;
+        if sectors < 2
+            || !start.is_multiple_of(sector_size)
+            || end > flash.capacity() as u64
+            || F::WRITE_SIZE != 1
+            || sector_size > 1 << 16
+        {
+            return Err(KvStoreError::InvalidRegion);
+        }
+
+        let mut store = Self {
+            flash,
+            start,
+            sectors,
+            active: 0,
+            seq: 0,
+            write_offset: HEADER_LEN,
+        };
+
+        // Find the newest sector in the ring.
+        let mut newest: Option<(u32, u32, bool)> = None;
+        for sector in 0..sectors {
+            if let Some((seq, done)) = store.read_header(sector).await?
+                && newest.is_none_or(|(_, s, _)| seq > s)
+            {
+                newest = Some((sector, seq, done));
+            }
+        }
+
+        match newest {
+            Some((sector, seq, true)) => {
+                store.active = sector;
+                store.seq = seq;
+                // Finish an erase of the oldest sector that was interrupted.
+                store.ensure_blank(store.next(sector)).await?;
+                store.write_offset = store.find_write_offset().await?;
+            }
+            Some((sector, seq, false)) if seq > 1 => {
+                // A compaction into this sector was interrupted. The oldest
+                // sector has not been touched yet, so start again.
+                store.erase(sector).await?;
+                store.active = store.prev(sector);
+                store.seq = seq - 1;
+                store.rotate().await?;
+            }
+            _ => store.format().await?,
+        }
+        Ok(store)
+    }
+
+    /// Release the underlying flash.
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    /// Read a `u32` value.
+    pub async fn get_u32(&mut self, key: u16) -> Result<Option<u32>, KvStoreError> {
+        let mut buf: [u8; 4] = [0; 4];
+        match self.get(key, Kind::U32, &mut buf).await? {
+            Some(_) => Ok(Some(u32::from_le_bytes(buf))),
+            None => Ok(None),
+        }
+    }
+
+    /// Store a `u32` value.
+    pub async fn set_u32(&mut self, key: u16, value: u32) -> Result<(), KvStoreError> {
+        self.set(key, Kind::U32, &value.to_le_bytes()).await
+    }
+
+    /// Read a `f32` value.
+    pub async fn get_f32(&mut self, key: u16) -> Result<Option<f32>, KvStoreError> {
+        let mut buf: [u8; 4] = [0; 4];
+        match self.get(key, Kind::F32, &mut buf).await? {
+            Some(_) => Ok(Some(f32::from_le_bytes(buf))),
+            None => Ok(None),
+        }
+    }
+
+    /// Store a `f32` value.
+    pub async fn set_f32(&mut self, key: u16, value: f32) -> Result<(), KvStoreError> {
+        self.set(key, Kind::F32, &value.to_le_bytes()).await
+    }
+
+    /// Read a byte value into the buffer, returning the number of bytes read.
+    pub async fn get_bytes(
+        &mut self,
+        key: u16,
+        data_out: &mut [u8],
+    ) -> Result<Option<usize>, KvStoreError> {
+        self.get(key, Kind::Bytes, data_out).await
+    }
+
+    /// Store a byte value of up to [`MAX_VALUE_LEN`] bytes.
+    pub async fn set_bytes(&mut self, key: u16, value: &[u8]) -> Result<(), KvStoreError> {
+        self.set(key, Kind::Bytes, value).await
+    }
+
+    /// Remove a key from the store.
+    pub async fn remove(&mut self, key: u16) -> Result<(), KvStoreError> {
+        match self.lookup(key).await? {
+            Some((record, _)) if record.kind != Kind::Tombstone => {
+                self.append(key, Kind::Tombstone, &[]).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Move on to the next sector, reclaiming the space used by superseded values in the oldest sector.
+    pub async fn compact(&mut self) -> Result<(), KvStoreError> {
+        self.rotate().await
+    }
+
+    async fn get(
+        &mut self,
+        key: u16,
+        kind: Kind,
+        data_out: &mut [u8],
+    ) -> Result<Option<usize>, KvStoreError> {
+        let Some((record, location)) = self.lookup(key).await? else {
+            return Ok(None);
+        };
+        if record.kind == Kind::Tombstone {
+            return Ok(None);
+        }
+        if record.kind != kind {
+            return Err(KvStoreError::TypeMismatch);
+        }
+        if data_out.len() < record.len {
+            return Err(KvStoreError::BufferTooSmall(record.len));
+        }
+        let addr = self.sector_addr(location.sector) + location.offset + 4;
+        self.flash.read(addr, &mut data_out[..record.len]).await?;
+        Ok(Some(record.len))
+    }
+
+    async fn set(&mut self, key: u16, kind: Kind, value: &[u8]) -> Result<(), KvStoreError> {
+        if key == BLANK_KEY {
+            return Err(KvStoreError::InvalidKey(key));
+        }
+        let max =
+            MAX_VALUE_LEN.min(self.sector_size() as usize - HEADER_LEN as usize - RECORD_OVERHEAD);
+        if value.len() > max {
+            return Err(KvStoreError::ValueTooLarge {
+                max,
+                received: value.len(),
+            });
+        }
+
+        // Avoid wearing the flash when the value has not changed.
+        if let Some((record, location)) = self.lookup(key).await?
+            && record.kind == kind
+            && record.len == value.len()
+        {
+            let mut buf: [u8; MAX_VALUE_LEN] = [0; MAX_VALUE_LEN];
+            let addr = self.sector_addr(location.sector) + location.offset + 4;
+            self.flash.read(addr, &mut buf[..record.len]).await?;
+            if buf[..record.len] == *value {
+                return Ok(());
+            }
+        }
+
+        self.append(key, kind, value).await
+    }
+
+    /// Append a record to the active sector, rotating sectors until there is room.
+    async fn append(&mut self, key: u16, kind: Kind, value: &[u8]) -> Result<(), KvStoreError> {
+        let mut buf: [u8; MAX_RECORD_LEN] = [0; MAX_RECORD_LEN];
+        let len = encode_record(&mut buf, key, kind, value);
+        for _ in 0..self.sectors {
+            if self.write_offset + len as u32 <= self.sector_size() {
+                return self.write_raw(&buf[..len]).await;
+            }
+            self.rotate().await?;
+        }
+        Err(KvStoreError::Full)
+    }
+
+    /// Program raw record bytes at the write offset of the active sector.
+    async fn write_raw(&mut self, bytes: &[u8]) -> Result<(), KvStoreError> {
+        let addr = self.sector_addr(self.active) + self.write_offset;
+        if let Err(e) = self.flash.write(addr, bytes).await {
+            // The tail of the sector is now unknown so close it off.
+            self.write_offset = self.sector_size();
+            return Err(e.into());
+        }
+        self.write_offset += bytes.len() as u32;
+        Ok(())
+    }
+
+    /// Open the spare sector, copy the live records of the oldest sector into it and then erase the oldest sector to become the new spare.
+    async fn rotate(&mut self) -> Result<(), KvStoreError> {
+        let new = self.next(self.active);
+        let oldest = self.next(new);
+        let seq = self.seq + 1;
+
+        self.ensure_blank(new).await?;
+        let mut header: [u8; 12] = [0; 12];
+        header[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&seq.to_le_bytes());
+        let crc = crc32(&header[..8]);
+        header[8..].copy_from_slice(&crc.to_le_bytes());
+        self.flash.write(self.sector_addr(new), &header).await?;
+
+        self.active = new;
+        self.seq = seq;
+        self.write_offset = HEADER_LEN;
+
+        // The oldest sector sits in the spare slot of the ring until it is erased.
+        let oldest_seq = (seq + 1).wrapping_sub(self.sectors);
+        if matches!(self.read_header(oldest).await?, Some((s, _)) if s == oldest_seq) {
+            let index = self.live_index(oldest).await?;
+            let mut buf: [u8; MAX_RECORD_LEN] = [0; MAX_RECORD_LEN];
+            let mut offset = HEADER_LEN;
+            while let Some(record) = self.read_record(oldest, offset, &mut buf).await? {
+                // Tombstones in the oldest sector have nothing left to hide.
+                let live = record.kind != Kind::Tombstone
+                    && match index.binary_search_by_key(&record.key, |(key, _)| *key) {
+                        Ok(i) => index[i].1 == offset as u16,
+                        // The index overflowed, so fall back to searching for the key.
+                        Err(_) => {
+                            let location = Location {
+                                sector: oldest,
+                                offset,
+                            };
+                            self.lookup(record.key).await?.map(|(_, l)| l) == Some(location)
+                        }
+                    };
+                if live {
+                    self.write_raw(&buf[..record.total_len() as usize]).await?;
+                }
+                offset += record.total_len();
+            }
+        }
+
+        let addr = self.sector_addr(new) + STATE_OFFSET;
+        self.flash.write(addr, &[STATE_DONE]).await?;
+        self.ensure_blank(oldest).await
+    }
+
+    /// Start a fresh store in the first sector.
+    async fn format(&mut self) -> Result<(), KvStoreError> {
+        // Rotating from the last sector opens sector 0 with sequence 1.
+        self.active = self.sectors - 1;
+        self.seq = 0;
+        self.rotate().await
+    }
+
+    /// Find the newest record for a key by searching the sectors from newest to oldest.
+    async fn lookup(&mut self, key: u16) -> Result<Option<(Record, Location)>, KvStoreError> {
+        let mut buf: [u8; MAX_RECORD_LEN] = [0; MAX_RECORD_LEN];
+        for age in 0..self.sectors {
+            let Some(sector) = self.ring_sector(age).await? else {
+                continue;
+            };
+            let mut found = None;
+            let mut offset = HEADER_LEN;
+            while let Some(record) = self.read_record(sector, offset, &mut buf).await? {
+                if record.key == key {
+                    found = Some((record, Location { sector, offset }));
+                }
+                offset += record.total_len();
+            }
+            if found.is_some() {
+                return Ok(found);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Index the newest record of each key in the oldest sector that is not superseded by a newer sector, in one
+    /// pass over the ring. Keys that do not fit in the index are left out.
+    async fn live_index(&mut self, oldest: u32) -> Result<Index, KvStoreError> {
+        let mut buf: [u8; MAX_RECORD_LEN] = [0; MAX_RECORD_LEN];
+        let mut index = Index::new();
+        let mut offset = HEADER_LEN;
+        while let Some(record) = self.read_record(oldest, offset, &mut buf).await? {
+            match index.binary_search_by_key(&record.key, |(key, _)| *key) {
+                Ok(i) => index[i].1 = offset as u16,
+                Err(i) => {
+                    let _ = index.insert(i, (record.key, offset as u16));
+                }
+            }
+            offset += record.total_len();
+        }
+        // The active sector is empty and the oldest is the last in the ring.
+        for age in 1..self.sectors - 1 {
+            let Some(sector) = self.ring_sector(age).await? else {
+                continue;
+            };
+            let mut offset = HEADER_LEN;
+            while let Some(record) = self.read_record(sector, offset, &mut buf).await? {
+                if let Ok(i) = index.binary_search_by_key(&record.key, |(key, _)| *key) {
+                    index[i].1 = SUPERSEDED;
+                }
+                offset += record.total_len();
+            }
+        }
+        Ok(index)
+    }
+
+    /// The sector `age` sectors older than the active one, or `None` if it is not part of the ring (i.e. the spare).
+    async fn ring_sector(&mut self, age: u32) -> Result<Option<u32>, KvStoreError> {
+        let sector = (self.active + self.sectors - age) % self.sectors;
+        match self.read_header(sector).await? {
+            Some((seq, _)) if seq == self.seq.wrapping_sub(age) => Ok(Some(sector)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Find the end of the log in the active sector. A sector with a torn record at the end is treated as full.
+    async fn find_write_offset(&mut self) -> Result<u32, KvStoreError> {
+        let mut buf: [u8; MAX_RECORD_LEN] = [0; MAX_RECORD_LEN];
+        let mut offset = HEADER_LEN;
+        while let Some(record) = self.read_record(self.active, offset, &mut buf).await? {
+            offset += record.total_len();
+        }
+        if self.is_blank(self.active, offset).await? {
+            Ok(offset)
+        } else {
+            Ok(self.sector_size())
+        }
+    }
+
+    /// Read a sector header, returning the sequence number and whether the sector is complete.
+    async fn read_header(&mut self, sector: u32) -> Result<Option<(u32, bool)>, KvStoreError> {
+        let mut buf: [u8; HEADER_LEN as usize] = [0; HEADER_LEN as usize];
+        self.flash.read(self.sector_addr(sector), &mut buf).await?;
+        let magic = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let seq = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+        let crc = u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]);
+        if magic != MAGIC || crc != crc32(&buf[..8]) {
+            return Ok(None);
+        }
+        Ok(Some((seq, buf[STATE_OFFSET as usize] == STATE_DONE)))
+    }
+
+    /// Read and validate the record at `offset`. Returns `None` at the end of the log or if the record is torn.
+    async fn read_record(
+        &mut self,
+        sector: u32,
+        offset: u32,
+        buf: &mut [u8; MAX_RECORD_LEN],
+    ) -> Result<Option<Record>, KvStoreError> {
+        let size = self.sector_size();
+        if offset + RECORD_OVERHEAD as u32 > size {
+            return Ok(None);
+        }
+        let addr = self.sector_addr(sector) + offset;
+        self.flash.read(addr, &mut buf[..4]).await?;
+        let key = u16::from_le_bytes([buf[0], buf[1]]);
+        if key == BLANK_KEY {
+            return Ok(None);
+        }
+        let Some(kind) = Kind::from_byte(buf[2]) else {
+            return Ok(None);
+        };
+        let record = Record {
+            key,
+            kind,
+            len: buf[3] as usize,
+        };
+        let total = record.total_len() as usize;
+        if offset + total as u32 > size {
+            return Ok(None);
+        }
+        self.flash.read(addr + 4, &mut buf[4..total]).await?;
+        let crc = u32::from_le_bytes([
+            buf[total - 4],
+            buf[total - 3],
+            buf[total - 2],
+            buf[total - 1],
+        ]);
+        if crc != crc32(&buf[..total - 4]) {
+            return Ok(None);
+        }
+        Ok(Some(record))
+    }
+
+    /// Check that a sector reads back as erased from `offset` onwards.
+    async fn is_blank(&mut self, sector: u32, offset: u32) -> Result<bool, KvStoreError> {
+        let mut buf: [u8; 64] = [0; 64];
+        let base = self.sector_addr(sector);
+        let mut offset = offset;
+        while offset < self.sector_size() {
+            let n = (self.sector_size() - offset).min(buf.len() as u32) as usize;
+            self.flash.read(base + offset, &mut buf[..n]).await?;
+            if buf[..n].iter().any(|b| *b != 0xFF) {
+                return Ok(false);
+            }
+            offset += n as u32;
+        }
+        Ok(true)
+    }
+
+    /// Erase a sector unless it is already blank.
+    async fn ensure_blank(&mut self, sector: u32) -> Result<(), KvStoreError> {
+        if !self.is_blank(sector, 0).await? {
+            self.erase(sector).await?;
+        }
+        Ok(())
+    }
+
+    async fn erase(&mut self, sector: u32) -> Result<(), KvStoreError> {
+        let addr = self.sector_addr(sector);
+        self.flash.erase(addr, addr + self.sector_size()).await?;
+        Ok(())
+    }
+
+    const fn sector_size(&self) -> u32 {
+        F::ERASE_SIZE as u32
+    }
+
+    const fn sector_addr(&self, sector: u32) -> u32 {
+        self.start + sector * self.sector_size()
+    }
+
+    const fn next(&self, sector: u32) -> u32 {
+        (sector + 1) % self.sectors
+    }
+
+    const fn prev(&self, sector: u32) -> u32 {
+        (sector + self.sectors - 1) % self.sectors
+    }
+}
+
+/// Encode a record into the buffer, returning its length.
+fn encode_record(buf: &mut [u8; MAX_RECORD_LEN], key: u16, kind: Kind, value: &[u8]) -> usize {
+    let len = RECORD_OVERHEAD + value.len();
+    buf[..2].copy_from_slice(&key.to_le_bytes());
+    buf[2] = kind.to_byte();
+    buf[3] = value.len() as u8;
+    buf[4..4 + value.len()].copy_from_slice(value);
+    let crc = crc32(&buf[..len - 4]);
+    buf[len - 4..len].copy_from_slice(&crc.to_le_bytes());
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::RamFlash;
+
+    const SECTOR: usize = 128;
+    const SECTORS: u32 = 3;
+    type Flash = RamFlash<{ SECTORS as usize * SECTOR }, SECTOR>;
+
+    /// The key holding a byte value, the others hold `u32`s.
+    const BYTES_KEY: u16 = 10;
+    const KEYS: [u16; 6] = [0, 1, 2, 3, 4, BYTES_KEY];
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Value {
+        U32(u32),
+        Bytes(u8),
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum Op {
+        Set(u16, u32),
+        SetBytes(u8),
+        Remove(u16),
+    }
+
+    /// Enough writes to go round the ring several times.
+    fn ops() -> impl Iterator<Item = Op> {
+        (0..60u32).map(|i| match i % 4 {
+            0 | 1 => Op::Set((i % 5) as u16, i),
+            2 => Op::SetBytes(i as u8),
+            _ => Op::Remove((i % 5) as u16),
+        })
+    }
+
+    fn commit(model: &mut BTreeMap<u16, Value>, op: Op) {
+        match op {
+            Op::Set(key, value) => {
+                model.insert(key, Value::U32(value));
+            }
+            Op::SetBytes(value) => {
+                model.insert(BYTES_KEY, Value::Bytes(value));
+            }
+            Op::Remove(key) => {
+                model.remove(&key);
+            }
+        }
+    }
+
+    async fn apply(store: &mut KvStore<&mut Flash>, op: Op) -> Result<(), KvStoreError> {
+        match op {
+            Op::Set(key, value) => store.set_u32(key, value).await,
+            Op::SetBytes(value) => store.set_bytes(BYTES_KEY, &[value; 20]).await,
+            Op::Remove(key) => store.remove(key).await,
+        }
+    }
+
+    async fn read(store: &mut KvStore<&mut Flash>, key: u16) -> Option<Value> {
+        if key == BYTES_KEY {
+            let mut buf = [0; 32];
+            let len = store.get_bytes(key, &mut buf).await.unwrap()?;
+            assert_eq!(len, 20);
+            assert!(buf[..len].iter().all(|b| *b == buf[0]));
+            Some(Value::Bytes(buf[0]))
+        } else {
+            store.get_u32(key).await.unwrap().map(Value::U32)
+        }
+    }
+
+    #[test]
+    fn values_survive_a_remount() {
+        let mut flash = Flash::new();
+        block_on(async {
+            let mut store = KvStore::mount(&mut flash, 0, SECTORS).await.unwrap();
+            store.set_u32(1, 42).await.unwrap();
+            store.set_f32(2, 1.5).await.unwrap();
+            store.set_bytes(3, b"buddy").await.unwrap();
+            store.set_u32(4, 7).await.unwrap();
+            store.remove(4).await.unwrap();
+        });
+        let mut flash = Flash::from_image(flash.data);
+        block_on(async {
+            let mut store = KvStore::mount(&mut flash, 0, SECTORS).await.unwrap();
+            assert_eq!(store.get_u32(1).await.unwrap(), Some(42));
+            assert_eq!(store.get_f32(2).await.unwrap(), Some(1.5));
+            let mut buf = [0; 8];
+            assert_eq!(store.get_bytes(3, &mut buf).await.unwrap(), Some(5));
+            assert_eq!(&buf[..5], b"buddy");
+            assert_eq!(store.get_u32(4).await.unwrap(), None);
+            assert_eq!(store.get_u32(5).await.unwrap(), None);
+            assert!(matches!(
+                store.get_u32(2).await,
+                Err(KvStoreError::TypeMismatch)
+            ));
+        });
+    }
+
+    #[test]
+    fn unchanged_values_are_not_rewritten() {
+        let mut flash = Flash::new();
+        block_on(async {
+            let mut store = KvStore::mount(&mut flash, 0, SECTORS).await.unwrap();
+            store.set_u32(1, 42).await.unwrap();
+            store.set_u32(1, 42).await.unwrap();
+        });
+        // The sector header, its state byte and one record.
+        assert_eq!(flash.writes, 3);
+    }
+
+    #[test]
+    fn compaction_keeps_the_newest_values() {
+        let mut flash = Flash::new();
+        block_on(async {
+            let mut store = KvStore::mount(&mut flash, 0, SECTORS).await.unwrap();
+            let mut model = BTreeMap::new();
+            for op in ops() {
+                apply(&mut store, op).await.unwrap();
+                commit(&mut model, op);
+                for key in KEYS {
+                    assert_eq!(read(&mut store, key).await, model.get(&key).copied());
+                }
+            }
+        });
+        // Every sector took a turn at being erased.
+        assert!(flash.erases > 2 * SECTORS as usize);
+    }
+
+    #[test]
+    fn compaction_without_room_to_index_every_key() {
+        // More distinct keys fit in a sector than in the index, so the rest are looked up.
+        const SECTOR: usize = 8192;
+        let mut flash = RamFlash::<{ 3 * SECTOR }, SECTOR>::new();
+        block_on(async {
+            let mut store = KvStore::mount(&mut flash, 0, 3).await.unwrap();
+            for key in 0..600 {
+                store.set_u32(key, key as u32).await.unwrap();
+            }
+            store.compact().await.unwrap();
+            store.compact().await.unwrap();
+            for key in 0..600 {
+                assert_eq!(store.get_u32(key).await.unwrap(), Some(key as u32));
+            }
+        });
+    }
+
+    #[test]
+    fn survives_power_loss_at_every_step() {
+        for budget in 0.. {
+            let mut flash = Flash::new();
+            flash.budget = Some(budget);
+            let mut committed = BTreeMap::new();
+            let mut in_flight = None;
+            block_on(async {
+                let Ok(mut store) = KvStore::mount(&mut flash, 0, SECTORS).await else {
+                    return;
+                };
+                for op in ops() {
+                    in_flight = Some(op);
+                    if apply(&mut store, op).await.is_err() {
+                        return;
+                    }
+                    commit(&mut committed, op);
+                    in_flight = None;
+                }
+            });
+            let finished = !flash.lost_power();
+
+            // The operation that lost power may or may not have taken effect.
+            let mut attempted = committed.clone();
+            if let Some(op) = in_flight {
+                commit(&mut attempted, op);
+            }
+            let mut flash = Flash::from_image(flash.data);
+            block_on(async {
+                let mut store = KvStore::mount(&mut flash, 0, SECTORS).await.unwrap();
+                for key in KEYS {
+                    let value = read(&mut store, key).await;
+                    assert!(
+                        value == committed.get(&key).copied()
+                            || value == attempted.get(&key).copied(),
+                        "power lost after {budget} operations: key {key} is {value:?}, expected {:?}",
+                        committed.get(&key)
+                    );
+                }
+                // The repaired store can still be written to.
+                store.set_u32(0, 1234).await.unwrap();
+                assert_eq!(store.get_u32(0).await.unwrap(), Some(1234));
+            });
+            if finished {
+                break;
+            }
+        }
+    }
+}
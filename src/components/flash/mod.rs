@@ -0,0 +1,12 @@
+#![doc = include_str!("../../../docs/flash.md")]
+mod block_device;
+mod error;
+mod kv_store;
+mod w25q64jv;
+
+pub use block_device::*;
+pub use error::*;
+pub use kv_store::*;
+pub use w25q64jv::*;
+
+pub use embedded_sdmmc;
@@ -0,0 +1,332 @@
+use embassy_stm32::{
+    gpio::{Level, Output, Speed},
+    mode::Async,
+    peripherals::{DMA1_CH0, DMA1_CH7, PC10, PC11, PC12, PD7, SPI3},
+    spi::{Config, Spi},
+};
+use embassy_sync::{
+    blocking_mutex::raw::{RawMutex, ThreadModeRawMutex},
+    mutex::Mutex,
+};
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::spi::SpiBus;
+use embedded_storage_async::nor_flash::{ErrorType, NorFlash, ReadNorFlash};
+use packed_struct::{PackedStructSlice, derive::PackedStruct};
+
+use crate::components::flash::error::FlashError;
+
+/// The capacity of the flash chip (64M-bit).
+pub const FLASH_CAPACITY: u32 = 8 * 1024 * 1024;
+
+/// The size of the smallest erasable region (4KB sector).
+pub const SECTOR_SIZE: u32 = 4 * 1024;
+
+/// The maximum number of bytes that can be programmed in one instruction.
+pub const PAGE_SIZE: u32 = 256;
+
+/// The number of sectors at the start of the flash set aside for the [`KvStore`](crate::components::flash::KvStore).
+pub const KV_STORE_SECTORS: u32 = 4;
+
+/// The filesystem takes the rest of the flash after the key-value store.
+pub const FILESYSTEM_START: u32 = KV_STORE_SECTORS * SECTOR_SIZE;
+
+pub type BuddyFlash<'a> = W25q64jv<ThreadModeRawMutex, Spi<'a, Async>, Output<'a>>;
+
+pub fn build_flash<'a>(
+    peri: SPI3,
+    sck: PC10,
+    mosi: PC12,
+    miso: PC11,
+    tx_dma: DMA1_CH7,
+    rx_dma: DMA1_CH0,
+    cs: PD7,
+) -> BuddyFlash<'a> {
+    let config = Config::default();
+    let spi = Spi::new(peri, sck, mosi, miso, tx_dma, rx_dma, config);
+    let cs = Output::new(cs, Level::High, Speed::VeryHigh);
+    W25q64jv::new(spi, cs)
+}
+
+pub struct W25q64jv<M: RawMutex, T: SpiBus, O: OutputPin> {
+    spi: Mutex<M, T>,
+    cs: Mutex<M, O>,
+}
+
+impl<M: RawMutex, T: SpiBus, O: OutputPin> W25q64jv<M, T, O> {
+    pub fn new(spi: T, cs: O) -> Self {
+        Self {
+            spi: Mutex::new(spi),
+            cs: Mutex::new(cs),
+        }
+    }
+
+    pub async fn send_instruction(&self, ins: Instruction) -> [u8; 6] {
+        let mut data: [u8; 6] = [0; 6];
+        let mut spi = self.spi.lock().await;
+        let mut cs = self.cs.lock().await;
+        let ins = &[ins.to_byte()];
+        cs.set_low().unwrap();
+        spi.write(ins).await.unwrap();
+        spi.read(&mut data).await.unwrap();
+        cs.set_high().unwrap();
+        data
+    }
+
+    pub async fn read_status_register_one(&self) -> RegisterOne {
+        let data = self
+            .send_instruction(Instruction::ReadStatusRegister1)
+            .await;
+        let data = data[0];
+        RegisterOne::unpack_from_slice(&[data]).unwrap()
+    }
+
+    pub async fn read_status_register_two(&self) -> RegisterTwo {
+        let data = self
+            .send_instruction(Instruction::ReadStatusRegister2)
+            .await;
+        let data = data[0];
+        RegisterTwo::unpack_from_slice(&[data]).unwrap()
+    }
+
+    pub async fn read_status_register_three(&self) -> RegisterThree {
+        let data = self
+            .send_instruction(Instruction::ReadStatusRegister3)
+            .await;
+        let data = data[0];
+        RegisterThree::unpack_from_slice(&[data]).unwrap()
+    }
+
+    /// Read data starting at `addr` into the buffer.
+    pub async fn read_data(&self, addr: u32, data_out: &mut [u8]) -> Result<(), FlashError> {
+        check_bounds(addr, data_out.len())?;
+        let mut spi = self.spi.lock().await;
+        let mut cs = self.cs.lock().await;
+        cs.set_low().unwrap();
+        let res = match spi.write(&address_frame(Instruction::ReadData, addr)).await {
+            Ok(_) => spi.read(data_out).await,
+            Err(e) => Err(e),
+        };
+        cs.set_high().unwrap();
+        res.map_err(|_| FlashError::Spi)
+    }
+
+    /// Program the data starting at `addr`. Writes are split on page boundaries and the function waits for each page to finish programming. The target bytes need to have been erased beforehand as programming can only clear bits.
+    pub async fn program(&self, addr: u32, data: &[u8]) -> Result<(), FlashError> {
+        check_bounds(addr, data.len())?;
+        let mut addr = addr;
+        let mut data = data;
+        while !data.is_empty() {
+            let page_remaining = (PAGE_SIZE - (addr % PAGE_SIZE)) as usize;
+            let n = page_remaining.min(data.len());
+            self.write_enable().await?;
+            self.transaction(Instruction::PageProgram, addr, &data[..n])
+                .await?;
+            self.wait_until_ready().await?;
+            addr += n as u32;
+            data = &data[n..];
+        }
+        Ok(())
+    }
+
+    /// Erase the 4KB sector containing `addr` so all its bytes read back as `0xFF`.
+    pub async fn erase_sector(&self, addr: u32) -> Result<(), FlashError> {
+        if !addr.is_multiple_of(SECTOR_SIZE) {
+            return Err(FlashError::NotAligned);
+        }
+        check_bounds(addr, SECTOR_SIZE as usize)?;
+        self.write_enable().await?;
+        self.transaction(Instruction::SectorErase, addr, &[])
+            .await?;
+        self.wait_until_ready().await
+    }
+
+    /// Set the write enable latch. Required before every program or erase.
+    pub async fn write_enable(&self) -> Result<(), FlashError> {
+        let mut spi = self.spi.lock().await;
+        let mut cs = self.cs.lock().await;
+        cs.set_low().unwrap();
+        let res = spi.write(&[Instruction::WriteEnable.to_byte()]).await;
+        cs.set_high().unwrap();
+        res.map_err(|_| FlashError::Spi)
+    }
+
+    /// Poll the busy bit in status register one until the chip finishes its internal program or erase cycle.
+    pub async fn wait_until_ready(&self) -> Result<(), FlashError> {
+        loop {
+            let mut status: [u8; 1] = [0; 1];
+            {
+                let mut spi = self.spi.lock().await;
+                let mut cs = self.cs.lock().await;
+                cs.set_low().unwrap();
+                let res = match spi
+                    .write(&[Instruction::ReadStatusRegister1.to_byte()])
+                    .await
+                {
+                    Ok(_) => spi.read(&mut status).await,
+                    Err(e) => Err(e),
+                };
+                cs.set_high().unwrap();
+                res.map_err(|_| FlashError::Spi)?;
+            }
+            let register = RegisterOne::unpack_from_slice(&status).unwrap();
+            if !register.busy {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Send an instruction with a 24-bit address followed by an optional payload.
+    async fn transaction(
+        &self,
+        ins: Instruction,
+        addr: u32,
+        payload: &[u8],
+    ) -> Result<(), FlashError> {
+        let mut spi = self.spi.lock().await;
+        let mut cs = self.cs.lock().await;
+        cs.set_low().unwrap();
+        let mut res = spi.write(&address_frame(ins, addr)).await;
+        if res.is_ok() && !payload.is_empty() {
+            res = spi.write(payload).await;
+        }
+        cs.set_high().unwrap();
+        res.map_err(|_| FlashError::Spi)
+    }
+}
+
+fn address_frame(ins: Instruction, addr: u32) -> [u8; 4] {
+    let addr = addr.to_be_bytes();
+    [ins.to_byte(), addr[1], addr[2], addr[3]]
+}
+
+fn check_bounds(addr: u32, len: usize) -> Result<(), FlashError> {
+    match addr.checked_add(len as u32) {
+        Some(end) if end <= FLASH_CAPACITY => Ok(()),
+        _ => Err(FlashError::OutOfBounds),
+    }
+}
+
+impl<M: RawMutex, T: SpiBus, O: OutputPin> ErrorType for W25q64jv<M, T, O> {
+    type Error = FlashError;
+}
+
+impl<M: RawMutex, T: SpiBus, O: OutputPin> ReadNorFlash for W25q64jv<M, T, O> {
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.read_data(offset, bytes).await
+    }
+
+    fn capacity(&self) -> usize {
+        FLASH_CAPACITY as usize
+    }
+}
+
+impl<M: RawMutex, T: SpiBus, O: OutputPin> NorFlash for W25q64jv<M, T, O> {
+    const WRITE_SIZE: usize = 1;
+    const ERASE_SIZE: usize = SECTOR_SIZE as usize;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if to < from || to > FLASH_CAPACITY {
+            return Err(FlashError::OutOfBounds);
+        }
+        if !from.is_multiple_of(SECTOR_SIZE) || !to.is_multiple_of(SECTOR_SIZE) {
+            return Err(FlashError::NotAligned);
+        }
+        for addr in (from..to).step_by(SECTOR_SIZE as usize) {
+            self.erase_sector(addr).await?;
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.program(offset, bytes).await
+    }
+}
+
+pub enum Instruction {
+    ReadStatusRegister1,
+    ReadStatusRegister2,
+    ReadStatusRegister3,
+    WriteEnable,
+    ReadData,
+    PageProgram,
+    SectorErase,
+}
+
+impl Instruction {
+    pub const fn to_byte(&self) -> u8 {
+        match self {
+            Self::ReadStatusRegister1 => 0x05,
+            Self::ReadStatusRegister2 => 0x35,
+            Self::ReadStatusRegister3 => 0x15,
+            Self::WriteEnable => 0x06,
+            Self::ReadData => 0x03,
+            Self::PageProgram => 0x02,
+            Self::SectorErase => 0x20,
+        }
+    }
+}
+
+#[derive(Debug, PackedStruct)]
+#[packed_struct(bit_numbering = "lsb0", size_bytes = "1")]
+pub struct RegisterOne {
+    #[packed_field(bits = "0")]
+    pub busy: bool,
+    #[packed_field(bits = "1")]
+    pub write_enable: bool,
+    #[packed_field(bits = "2")]
+    pub block_protect_0: bool,
+    #[packed_field(bits = "3")]
+    pub block_protect_1: bool,
+    #[packed_field(bits = "4")]
+    pub block_protect_2: bool,
+    #[packed_field(bits = "5")]
+    pub top_bottom_protect: bool,
+    #[packed_field(bits = "6")]
+    pub sector_block_protect: bool,
+    #[packed_field(bits = "7")]
+    pub complement_protect: bool,
+}
+
+#[derive(Debug, PackedStruct)]
+#[packed_struct(bit_numbering = "lsb0", size_bytes = "1")]
+pub struct RegisterTwo {
+    #[packed_field(bits = "0")]
+    pub status_register_lock: bool,
+    #[packed_field(bits = "1")]
+    pub quad_enable: bool,
+    #[packed_field(bits = "2")]
+    pub reserved: bool,
+    #[packed_field(bits = "3")]
+    pub lock_bit_1: bool,
+    #[packed_field(bits = "4")]
+    pub lock_bit_2: bool,
+    #[packed_field(bits = "5")]
+    pub lock_bit_3: bool,
+    #[packed_field(bits = "6")]
+    pub complement_protect: bool,
+    #[packed_field(bits = "7")]
+    pub suspend_status: bool,
+}
+
+#[derive(Debug, PackedStruct)]
+#[packed_struct(bit_numbering = "lsb0", size_bytes = "1")]
+pub struct RegisterThree {
+    #[packed_field(bits = "0")]
+    pub reserved_0: bool,
+    #[packed_field(bits = "1")]
+    pub reserved_1: bool,
+    #[packed_field(bits = "2")]
+    pub write_protect_selection: bool,
+    #[packed_field(bits = "3")]
+    pub reserved_3: bool,
+    #[packed_field(bits = "4")]
+    pub reserved_4: bool,
+    #[packed_field(bits = "5")]
+    pub output_driver_strength_0: bool,
+    #[packed_field(bits = "6")]
+    pub output_driver_strength_1: bool,
+    #[packed_field(bits = "7")]
+    pub reserved_7: bool,
+}
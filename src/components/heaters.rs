@@ -1,4 +1,6 @@
 #![doc = include_str!("../../docs/heaters.md")]
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use embassy_stm32::{peripherals::TIM3, timer::simple_pwm::SimplePwmChannel};
 use embassy_sync::{
     blocking_mutex::raw::{RawMutex, ThreadModeRawMutex},
@@ -10,54 +12,76 @@ pub type BuddyHeater<'a> = Heater<ThreadModeRawMutex, SimplePwmChannel<'a, TIM3>
 
 pub struct Heater<M: RawMutex, T> {
     ch: Mutex<M, T>,
+    /// The last duty cycle set, as the bits of an `f32` from 0 to 1.
+    duty: AtomicU32,
 }
 
 impl<M: RawMutex, T: SetDutyCycle> Heater<M, T> {
     pub fn new(ch: T) -> Self {
-        Self { ch: Mutex::new(ch) }
+        Self {
+            ch: Mutex::new(ch),
+            duty: AtomicU32::new(0),
+        }
+    }
+
+    /// The last duty cycle set, from 0 (off) to 1 (fully on).
+    pub fn duty_cycle(&self) -> f32 {
+        f32::from_bits(self.duty.load(Ordering::Relaxed))
+    }
+
+    fn set_duty(&self, duty: f32) {
+        self.duty.store(duty.to_bits(), Ordering::Relaxed);
     }
 
     pub fn try_set_duty_cycle_fully_off(&self) -> Result<(), TryLockError> {
         let mut ch = self.ch.try_lock()?;
         ch.set_duty_cycle_fully_off().unwrap();
+        self.set_duty(0.0);
         Ok(())
     }
 
     pub async fn set_duty_cycle_fully_off(&self) {
         let mut ch = self.ch.lock().await;
         ch.set_duty_cycle_fully_off().unwrap();
+        self.set_duty(0.0);
     }
 
     pub fn try_set_duty_cycle_fully_on(&self) -> Result<(), TryLockError> {
         let mut ch = self.ch.try_lock()?;
         ch.set_duty_cycle_fully_on().unwrap();
+        self.set_duty(1.0);
         Ok(())
     }
 
     pub async fn set_duty_cycle_fully_on(&self) {
         let mut ch = self.ch.lock().await;
         ch.set_duty_cycle_fully_on().unwrap();
+        self.set_duty(1.0);
     }
 
     pub async fn set_duty_cycle_fraction(&self, num: u16, denom: u16) {
         let mut ch = self.ch.lock().await;
         ch.set_duty_cycle_fraction(num, denom).unwrap();
+        self.set_duty(num as f32 / denom as f32);
     }
 
     pub fn try_set_duty_cycle_fraction(&self, num: u16, denom: u16) -> Result<(), TryLockError> {
         let mut ch = self.ch.try_lock()?;
         ch.set_duty_cycle_fraction(num, denom).unwrap();
+        self.set_duty(num as f32 / denom as f32);
         Ok(())
     }
 
     pub async fn set_duty_cycle_percent(&self, percent: u8) {
         let mut ch = self.ch.lock().await;
         ch.set_duty_cycle_percent(percent).unwrap();
+        self.set_duty(percent as f32 / 100.0);
     }
 
     pub fn try_set_duty_cycle_percent(&self, percent: u8) -> Result<(), TryLockError> {
         let mut ch = self.ch.try_lock()?;
         ch.set_duty_cycle_percent(percent).unwrap();
+        self.set_duty(percent as f32 / 100.0);
         Ok(())
     }
 }
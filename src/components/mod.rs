@@ -2,6 +2,7 @@
 pub mod adc;
 pub mod bed_power_monitor;
 pub mod buzzer;
+pub mod debounce;
 pub mod display;
 pub mod eeprom;
 pub mod ethernet;
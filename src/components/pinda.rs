@@ -1,5 +1,11 @@
 #![doc = include_str!("../../docs/pinda.md")]
-use core::convert::Infallible;
+use core::{
+    cell::Cell,
+    convert::Infallible,
+    future::{Future, poll_fn},
+    pin::pin,
+    task::{Context, RawWaker, RawWakerVTable, Waker},
+};
 
 use defmt::Format;
 use embassy_stm32::{
@@ -8,33 +14,119 @@ use embassy_stm32::{
     peripherals::{EXTI8, PA8},
 };
 use embassy_sync::{
-    blocking_mutex::raw::{RawMutex, ThreadModeRawMutex},
+    blocking_mutex::{
+        Mutex as BlockingMutex,
+        raw::{CriticalSectionRawMutex, RawMutex, ThreadModeRawMutex},
+    },
     mutex::{Mutex, TryLockError},
+    waitqueue::AtomicWaker,
 };
+use embassy_time::Instant;
 use embedded_hal::digital::InputPin;
 use embedded_hal_async::digital::Wait;
 
 pub type BuddyPinda<'a> = Pinda<ThreadModeRawMutex, ExtiInput<'a>>;
 
 pub(crate) fn init_pinda<'a>(pin: PA8, ch: EXTI8) -> BuddyPinda<'a> {
+    static EDGES: EdgeStamp = EdgeStamp::new();
     let exti = ExtiInput::new(pin, ch, Pull::None);
-    Pinda::new(exti)
+    Pinda::new(exti, &EDGES)
 }
 
-#[derive(Debug, Format)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
 pub enum PindaStateChange {
     Contact,
     NoContact,
 }
 
+/// A change of the probe state and when it happened.
+#[derive(Debug, Clone, Copy, Format)]
+pub struct PindaEdge {
+    pub change: PindaStateChange,
+    /// Taken in the EXTI interrupt, so it does not include how long the executor took to resume the waiting task.
+    pub at: Instant,
+}
+
+/// Records when the task waiting on an EXTI line was woken.
+///
+/// The EXTI interrupt wakes the waiting task from inside the interrupt handler. The wait is polled with a waker that
+/// takes the time as it is woken and then wakes the task, so the time is that of the edge. The driver keeps hold of
+/// the last waker it was given, which is why the stamp has to be `'static`.
+pub struct EdgeStamp {
+    at: BlockingMutex<CriticalSectionRawMutex, Cell<Option<Instant>>>,
+    task: AtomicWaker,
+}
+
+impl EdgeStamp {
+    pub const fn new() -> Self {
+        Self {
+            at: BlockingMutex::new(Cell::new(None)),
+            task: AtomicWaker::new(),
+        }
+    }
+
+    /// Run the future, returning its output with when it was first woken. A future that completes without being
+    /// woken, e.g. waiting for a level that is already there, returns the time it completed.
+    pub async fn stamped<F: Future>(&'static self, future: F) -> (F::Output, Instant) {
+        self.at.lock(|at| at.set(None));
+        let mut future = pin!(future);
+        let output = poll_fn(|cx| {
+            self.task.register(cx.waker());
+            let waker = self.waker();
+            future.as_mut().poll(&mut Context::from_waker(&waker))
+        })
+        .await;
+        let at = self.at.lock(Cell::take).unwrap_or_else(Instant::now);
+        (output, at)
+    }
+
+    fn waker(&'static self) -> Waker {
+        // SAFETY: The data is a `&'static EdgeStamp` so it outlives every clone, and the vtable functions only use it
+        // through shared references to its `Sync` fields.
+        unsafe {
+            Waker::from_raw(RawWaker::new(
+                self as *const Self as *const (),
+                &STAMP_VTABLE,
+            ))
+        }
+    }
+
+    fn wake(&self) {
+        let now = Instant::now();
+        self.at.lock(|at| {
+            if at.get().is_none() {
+                at.set(Some(now));
+            }
+        });
+        self.task.wake();
+    }
+}
+
+impl Default for EdgeStamp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static STAMP_VTABLE: RawWakerVTable = RawWakerVTable::new(
+    |data| RawWaker::new(data, &STAMP_VTABLE),
+    // SAFETY: The data is always a `&'static EdgeStamp`, see `EdgeStamp::waker`.
+    |data| unsafe { (*(data as *const EdgeStamp)).wake() },
+    |data| unsafe { (*(data as *const EdgeStamp)).wake() },
+    |_| {},
+);
+
 pub struct Pinda<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> {
     exti: Mutex<M, T>,
+    edges: &'static EdgeStamp,
 }
 
 impl<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> Pinda<M, T> {
-    pub fn new(exti: T) -> Self {
+    /// Create the probe. `edges` timestamps its edges and must only be used by this probe.
+    pub fn new(exti: T, edges: &'static EdgeStamp) -> Self {
         Self {
             exti: Mutex::new(exti),
+            edges,
         }
     }
 
@@ -48,10 +140,20 @@ impl<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> Pi
         Ok(exti.is_high().unwrap())
     }
 
-    pub async fn try_wait_for_contact(self) -> Result<(), TryLockError> {
+    /// Wait until the probe is in contact, returning immediately if it already is. Returns when contact was made.
+    pub async fn wait_for_contact(&self) -> Instant {
+        let mut exti = self.exti.lock().await;
+        let (result, at) = self.edges.stamped(exti.wait_for_high()).await;
+        result.unwrap();
+        at
+    }
+
+    /// Wait for the probe to come into contact. Returns when contact was made.
+    pub async fn try_wait_for_contact(&self) -> Result<Instant, TryLockError> {
         let mut exti = self.exti.try_lock()?;
-        exti.wait_for_rising_edge().await.unwrap();
-        Ok(())
+        let (result, at) = self.edges.stamped(exti.wait_for_rising_edge()).await;
+        result.unwrap();
+        Ok(at)
     }
 
     pub async fn try_wait_for_no_contact(&self) -> Result<(), TryLockError> {
@@ -60,6 +162,36 @@ impl<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> Pi
         Ok(())
     }
 
+    /// Wait for the next change and return it with when it happened.
+    pub async fn on_edge(&self) -> PindaEdge {
+        let mut exti = self.exti.lock().await;
+        let (result, at) = self.edges.stamped(exti.wait_for_any_edge()).await;
+        result.unwrap();
+        PindaEdge {
+            change: Self::state(exti.is_high().unwrap()),
+            at,
+        }
+    }
+
+    /// Wait for the next change and return it with when it happened.
+    pub async fn try_on_edge(&self) -> Result<PindaEdge, TryLockError> {
+        let mut exti = self.exti.try_lock()?;
+        let (result, at) = self.edges.stamped(exti.wait_for_any_edge()).await;
+        result.unwrap();
+        Ok(PindaEdge {
+            change: Self::state(exti.is_high().unwrap()),
+            at,
+        })
+    }
+
+    fn state(high: bool) -> PindaStateChange {
+        if high {
+            PindaStateChange::Contact
+        } else {
+            PindaStateChange::NoContact
+        }
+    }
+
     pub async fn try_on_change(&self) -> Result<PindaStateChange, TryLockError> {
         let mut exti = self.exti.try_lock()?;
         exti.wait_for_any_edge().await.unwrap();
@@ -70,3 +202,61 @@ impl<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> Pi
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::{cell::RefCell, task::Poll};
+    use std::{thread, time::Duration};
+
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::mock::MockPin;
+
+    /// A wait that completes once `fired` is set, keeping the waker like the EXTI driver does.
+    async fn edge(fired: &Cell<bool>, waker: &RefCell<Option<Waker>>) {
+        poll_fn(|cx| {
+            *waker.borrow_mut() = Some(cx.waker().clone());
+            if fired.get() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    #[test]
+    fn stamps_the_time_of_the_wake_not_the_resume() {
+        let stamp: &'static EdgeStamp = Box::leak(Box::new(EdgeStamp::new()));
+        let (fired, waker) = (Cell::new(false), RefCell::new(None));
+        let mut future = pin!(stamp.stamped(edge(&fired, &waker)));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+
+        // The interrupt fires and wakes the task, which is only resumed some time later.
+        fired.set(true);
+        let before = Instant::now();
+        waker.borrow().as_ref().unwrap().wake_by_ref();
+        let after = Instant::now();
+        thread::sleep(Duration::from_millis(20));
+        // A second wake, e.g. from a later edge, does not move the stamp.
+        waker.borrow().as_ref().unwrap().wake_by_ref();
+
+        let Poll::Ready(((), at)) = future.as_mut().poll(&mut cx) else {
+            panic!("the edge should have completed the wait");
+        };
+        assert!(before <= at && at <= after);
+        assert!(Instant::now() - at >= embassy_time::Duration::from_millis(20));
+    }
+
+    #[test]
+    fn a_wait_that_is_already_satisfied_returns_the_current_time() {
+        let stamp: &'static EdgeStamp = Box::leak(Box::new(EdgeStamp::new()));
+        let pinda = Pinda::<NoopRawMutex, _>::new(MockPin { high: true }, stamp);
+        let before = Instant::now();
+        let at = block_on(pinda.wait_for_contact());
+        assert!(before <= at && at <= Instant::now());
+    }
+}
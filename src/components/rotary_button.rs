@@ -1,6 +1,7 @@
 #![doc = include_str!("../../docs/rotary_button.md")]
 use core::convert::Infallible;
 
+use defmt::Format;
 use embassy_stm32::{
     exti::ExtiInput,
     gpio::Pull,
@@ -8,10 +9,14 @@ use embassy_stm32::{
 };
 use embassy_sync::{
     blocking_mutex::raw::{RawMutex, ThreadModeRawMutex},
-    mutex::{Mutex, TryLockError},
+    mutex::{Mutex, MutexGuard, TryLockError},
 };
+use embassy_time::{Duration, Instant};
+use embedded_hal::digital::InputPin;
 use embedded_hal_async::digital::Wait;
 
+use crate::components::debounce::{Debounced, DebouncedInput, wait_debounced};
+
 pub type BuddyRotaryButton<'a> = RotaryButton<ThreadModeRawMutex, ExtiInput<'a>>;
 
 pub(crate) fn build_rotary_button<'a>(pin: PE12, ch: EXTI12) -> BuddyRotaryButton<'a> {
@@ -19,20 +24,312 @@ pub(crate) fn build_rotary_button<'a>(pin: PE12, ch: EXTI12) -> BuddyRotaryButto
     RotaryButton::new(exti)
 }
 
-pub struct RotaryButton<M: RawMutex, T: Wait<Error = Infallible>> {
+/// The gestures recognised by the button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum ButtonEvent {
+    /// The button has been pressed.
+    Pressed,
+    /// The button has been released.
+    Released,
+    /// The button was pressed and released once. Emitted once the double click window has passed.
+    Click,
+    /// The button was clicked twice within the double click window.
+    DoubleClick,
+    /// The button has been held down for the long press time.
+    LongPress,
+    /// The button is still held down after a long press. Repeats every hold interval.
+    Held,
+}
+
+/// The timings used to recognise gestures.
+#[derive(Debug, Clone, Copy)]
+pub struct ButtonConfig {
+    /// How long the level must be stable before a press or release is accepted.
+    pub debounce: Duration,
+    /// How long after a click a second click is treated as a double click.
+    pub double_click: Duration,
+    /// How long the button must be held for a long press.
+    pub long_press: Duration,
+    /// How often a held event repeats after a long press.
+    pub hold_repeat: Duration,
+}
+
+impl Default for ButtonConfig {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(20),
+            double_click: Duration::from_millis(300),
+            long_press: Duration::from_millis(800),
+            hold_repeat: Duration::from_millis(200),
+        }
+    }
+}
+
+/// A state machine turning the raw level of the button into debounced gestures. It is fed with each level change and polled for events.
+#[derive(Debug, Clone)]
+pub struct ButtonGestures {
+    config: ButtonConfig,
+    /// Whether the button is pressed.
+    level: Debounced,
+    pressed_at: Instant,
+    released_at: Instant,
+    long_pressed: bool,
+    next_held: Instant,
+    /// Clicks waiting to be reported as a click or double click.
+    clicks: u8,
+    pending: Option<ButtonEvent>,
+}
+
+impl ButtonGestures {
+    /// Create a state machine with the button released.
+    pub fn new(config: ButtonConfig) -> Self {
+        Self {
+            config,
+            level: Debounced::new(config.debounce, false),
+            pressed_at: Instant::from_ticks(0),
+            released_at: Instant::from_ticks(0),
+            long_pressed: false,
+            next_held: Instant::from_ticks(0),
+            clicks: 0,
+            pending: None,
+        }
+    }
+
+    /// Record the raw level of the button at a point in time.
+    pub fn on_level(&mut self, pressed: bool, now: Instant) {
+        self.level.on_level(pressed, now);
+    }
+
+    /// Return the next event that has occurred by `now`. Call until `None` is returned.
+    pub fn poll(&mut self, now: Instant) -> Option<ButtonEvent> {
+        if let Some(event) = self.pending.take() {
+            return Some(event);
+        }
+        if let Some(pressed) = self.level.poll(now) {
+            if pressed {
+                self.pressed_at = self.level.changed_at();
+                self.long_pressed = false;
+                return Some(ButtonEvent::Pressed);
+            }
+            self.released_at = self.level.changed_at();
+            if !self.long_pressed {
+                self.clicks += 1;
+                if self.clicks == 2 {
+                    self.clicks = 0;
+                    self.pending = Some(ButtonEvent::DoubleClick);
+                }
+            }
+            return Some(ButtonEvent::Released);
+        }
+        if self.level.level() {
+            if !self.long_pressed && now >= self.pressed_at + self.config.long_press {
+                self.long_pressed = true;
+                self.clicks = 0;
+                self.next_held = self.pressed_at + self.config.long_press + self.config.hold_repeat;
+                return Some(ButtonEvent::LongPress);
+            }
+            if self.long_pressed && now >= self.next_held {
+                self.next_held += self.config.hold_repeat;
+                return Some(ButtonEvent::Held);
+            }
+        } else if self.clicks == 1 && now >= self.released_at + self.config.double_click {
+            self.clicks = 0;
+            return Some(ButtonEvent::Click);
+        }
+        None
+    }
+
+    /// Return when the state machine next needs polling if the level does not change.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let pressed = self.level.level();
+        if self.pending.is_some() {
+            Some(Instant::from_ticks(0))
+        } else if let Some(deadline) = self.level.next_deadline() {
+            Some(deadline)
+        } else if pressed && !self.long_pressed {
+            Some(self.pressed_at + self.config.long_press)
+        } else if pressed {
+            Some(self.next_held)
+        } else if self.clicks == 1 {
+            Some(self.released_at + self.config.double_click)
+        } else {
+            None
+        }
+    }
+}
+
+impl DebouncedInput for ButtonGestures {
+    type Event = ButtonEvent;
+
+    fn on_level(&mut self, pressed: bool, now: Instant) {
+        ButtonGestures::on_level(self, pressed, now);
+    }
+
+    fn poll(&mut self, now: Instant) -> Option<ButtonEvent> {
+        ButtonGestures::poll(self, now)
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        ButtonGestures::next_deadline(self)
+    }
+}
+
+pub struct RotaryButton<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> {
     exti: Mutex<M, T>,
+    gestures: Mutex<M, ButtonGestures>,
 }
 
-impl<M: RawMutex, T: Wait<Error = Infallible>> RotaryButton<M, T> {
+impl<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> RotaryButton<M, T> {
     pub fn new(exti: T) -> Self {
+        Self::new_with_config(exti, ButtonConfig::default())
+    }
+
+    pub fn new_with_config(exti: T, config: ButtonConfig) -> Self {
         Self {
             exti: Mutex::new(exti),
+            gestures: Mutex::new(ButtonGestures::new(config)),
         }
     }
 
-    pub async fn try_on_click(&self) -> Result<(), TryLockError> {
+    /// Wait for the next gesture.
+    ///
+    /// The button is only watched while a call is waiting, so edges in between calls are not buffered. A level change is still seen by the next call but a press and release that both happen in between are missed. Keep a task waiting on the button to catch every gesture.
+    pub async fn next_event(&self) -> ButtonEvent {
+        let mut exti = self.exti.lock().await;
+        let mut gestures = self.gestures.lock().await;
+        Self::wait_for_event(&mut exti, &mut gestures).await
+    }
+
+    /// Wait for the next gesture.
+    pub async fn try_next_event(&self) -> Result<ButtonEvent, TryLockError> {
         let mut exti = self.exti.try_lock()?;
-        exti.wait_for_rising_edge().await.unwrap();
+        let mut gestures = self.gestures.try_lock()?;
+        Ok(Self::wait_for_event(&mut exti, &mut gestures).await)
+    }
+
+    /// Wait for a single click.
+    pub async fn on_click(&self) {
+        while self.next_event().await != ButtonEvent::Click {}
+    }
+
+    /// Wait for a single click.
+    pub async fn try_on_click(&self) -> Result<(), TryLockError> {
+        while self.try_next_event().await? != ButtonEvent::Click {}
         Ok(())
     }
+
+    async fn wait_for_event(
+        exti: &mut MutexGuard<'_, M, T>,
+        gestures: &mut MutexGuard<'_, M, ButtonGestures>,
+    ) -> ButtonEvent {
+        // The button pulls the pin low when pressed.
+        wait_debounced(&mut **exti, true, &mut **gestures).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ButtonEvent::*;
+
+    /// Play the raw level changes `(ms, pressed)` into the state machine, polling it every millisecond up to `until`, and return the events with when they occurred.
+    fn timeline(edges: &[(u64, bool)], until: u64) -> Vec<(u64, ButtonEvent)> {
+        let mut gestures = ButtonGestures::new(ButtonConfig::default());
+        let mut events = Vec::new();
+        for ms in 0..=until {
+            let now = Instant::from_millis(ms);
+            for (_, pressed) in edges.iter().filter(|(t, _)| *t == ms) {
+                gestures.on_level(*pressed, now);
+            }
+            while let Some(event) = gestures.poll(now) {
+                events.push((ms, event));
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn click() {
+        let events = timeline(&[(100, true), (200, false)], 1_000);
+        assert_eq!(events, [(120, Pressed), (220, Released), (500, Click)]);
+    }
+
+    #[test]
+    fn double_click() {
+        let edges = [(100, true), (200, false), (300, true), (400, false)];
+        let events = timeline(&edges, 1_000);
+        assert_eq!(
+            events,
+            [
+                (120, Pressed),
+                (220, Released),
+                (320, Pressed),
+                (420, Released),
+                (420, DoubleClick)
+            ]
+        );
+    }
+
+    #[test]
+    fn clicks_outside_the_window_are_separate() {
+        let edges = [(100, true), (200, false), (600, true), (700, false)];
+        let clicks: Vec<_> = timeline(&edges, 1_500)
+            .into_iter()
+            .filter(|(_, e)| matches!(e, Click | DoubleClick))
+            .collect();
+        assert_eq!(clicks, [(500, Click), (1_000, Click)]);
+    }
+
+    #[test]
+    fn long_press_repeats_until_released() {
+        let events = timeline(&[(100, true), (1_500, false)], 2_000);
+        assert_eq!(
+            events,
+            [
+                (120, Pressed),
+                (900, LongPress),
+                (1_100, Held),
+                (1_300, Held),
+                (1_500, Held),
+                (1_520, Released)
+            ]
+        );
+    }
+
+    #[test]
+    fn bounce_is_filtered() {
+        let edges = [
+            (100, true),
+            (103, false),
+            (105, true),
+            (108, false),
+            (110, true),
+            (250, false),
+            (252, true),
+            (255, false),
+        ];
+        let events = timeline(&edges, 1_000);
+        assert_eq!(events, [(130, Pressed), (275, Released), (555, Click)]);
+    }
+
+    #[test]
+    fn glitches_are_ignored() {
+        let edges = [(100, true), (105, false), (300, true), (319, false)];
+        assert!(timeline(&edges, 1_000).is_empty());
+    }
+
+    #[test]
+    fn deadlines_follow_the_state() {
+        let config = ButtonConfig::default();
+        let mut gestures = ButtonGestures::new(config);
+        assert_eq!(gestures.next_deadline(), None);
+        gestures.on_level(true, Instant::from_millis(100));
+        assert_eq!(gestures.next_deadline(), Some(Instant::from_millis(120)));
+        assert_eq!(gestures.poll(Instant::from_millis(120)), Some(Pressed));
+        assert_eq!(gestures.next_deadline(), Some(Instant::from_millis(900)));
+        gestures.on_level(false, Instant::from_millis(200));
+        assert_eq!(gestures.poll(Instant::from_millis(220)), Some(Released));
+        assert_eq!(gestures.next_deadline(), Some(Instant::from_millis(500)));
+    }
 }
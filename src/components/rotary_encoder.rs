@@ -1,7 +1,11 @@
 #![doc = include_str!("../../docs/rotary_encoder.md")]
-use core::convert::Infallible;
+use core::{
+    convert::Infallible,
+    sync::atomic::{AtomicI32, Ordering},
+};
 
 use defmt::Format;
+use embassy_futures::select::select;
 use embassy_stm32::{
     exti::ExtiInput,
     gpio::Pull,
@@ -9,8 +13,10 @@ use embassy_stm32::{
 };
 use embassy_sync::{
     blocking_mutex::raw::{RawMutex, ThreadModeRawMutex},
-    mutex::{Mutex, TryLockError},
+    mutex::{Mutex, MutexGuard, TryLockError},
+    signal::Signal,
 };
+use embassy_time::Instant;
 use embedded_hal::digital::InputPin;
 use embedded_hal_async::digital::Wait;
 
@@ -24,36 +30,393 @@ pub(crate) fn build_rotary_encoder<'a>(
 ) -> BuddyRotaryEncoder<'a> {
     let extia = ExtiInput::new(pin_a, ch_a, Pull::None);
     let extib = ExtiInput::new(pin_b, ch_b, Pull::None);
-    RotaryEncoder::new(extia, extib)
+    RotaryEncoder::new_with_config(extia, extib, 4, Acceleration::default())
 }
 
-pub struct RotaryEncoder<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> {
-    extia: Mutex<M, T>,
-    extib: Mutex<M, T>,
-}
+/// The change in position for each transition, indexed by the previous and current AB state (`prev << 2 | curr`). Transitions where both channels change at once are invalid (bounce or a missed edge) and are ignored.
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
 
-#[derive(Debug, Clone, Copy, Format)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
 pub enum Direction {
     CounterClockwise,
     Clockwise,
 }
 
+/// A quadrature decoder that turns the Gray-code sequence of the A and B channels into detents.
+#[derive(Debug, Clone, Copy)]
+pub struct QuadratureDecoder {
+    /// The current AB state.
+    state: u8,
+    /// The AB state the encoder rests in at a detent.
+    rest: u8,
+    /// The quarter steps moved since the last detent.
+    steps: i8,
+    steps_per_detent: i8,
+}
+
+impl QuadratureDecoder {
+    /// Create a decoder starting at rest in the current state of the channels. `steps_per_detent` is the number of quarter steps between detents (1, 2 or 4). Values below one are treated as one.
+    pub const fn new(a: bool, b: bool, steps_per_detent: i8) -> Self {
+        let state = Self::encode(a, b);
+        Self {
+            state,
+            rest: state,
+            steps: 0,
+            steps_per_detent: if steps_per_detent < 1 {
+                1
+            } else {
+                steps_per_detent
+            },
+        }
+    }
+
+    const fn encode(a: bool, b: bool) -> u8 {
+        ((a as u8) << 1) | b as u8
+    }
+
+    /// Update the decoder with the current state of the channels, returning the direction when a detent is reached.
+    pub fn update(&mut self, a: bool, b: bool) -> Option<Direction> {
+        let state = Self::encode(a, b);
+        let step = TRANSITIONS[((self.state << 2) | state) as usize];
+        self.state = state;
+        self.steps += step;
+        if self.steps >= self.steps_per_detent {
+            self.steps = 0;
+            Some(Direction::Clockwise)
+        } else if self.steps <= -self.steps_per_detent {
+            self.steps = 0;
+            Some(Direction::CounterClockwise)
+        } else {
+            // Returning to rest without reaching a detent means the knob was
+            // nudged and sprang back so forget the partial steps.
+            if state == self.rest {
+                self.steps = 0;
+            }
+            None
+        }
+    }
+}
+
+/// Scales each detent when the knob is spun quickly so long lists can be scrolled.
+#[derive(Debug, Clone, Copy)]
+pub struct Acceleration {
+    /// Detents further apart than this (in ms) move the position by one.
+    pub slow_ms: u64,
+    /// Detents closer together than this (in ms) move the position by `max_multiplier`.
+    pub fast_ms: u64,
+    pub max_multiplier: i32,
+}
+
+impl Acceleration {
+    /// Always move the position by one per detent.
+    pub const NONE: Self = Self {
+        slow_ms: 0,
+        fast_ms: 0,
+        max_multiplier: 1,
+    };
+
+    /// Return how far to move the position for a detent reached `interval_ms` after the previous one. The multiplier ramps linearly between the slow and fast intervals.
+    pub fn multiplier(&self, interval_ms: u64) -> i32 {
+        if interval_ms >= self.slow_ms || self.max_multiplier <= 1 {
+            1
+        } else if interval_ms <= self.fast_ms {
+            self.max_multiplier
+        } else {
+            let range = (self.slow_ms - self.fast_ms) as i64;
+            let gain = (self.slow_ms - interval_ms) as i64 * (self.max_multiplier as i64 - 1);
+            1 + (gain / range) as i32
+        }
+    }
+}
+
+impl Default for Acceleration {
+    fn default() -> Self {
+        Self {
+            slow_ms: 100,
+            fast_ms: 15,
+            max_multiplier: 5,
+        }
+    }
+}
+
+struct EncoderState {
+    decoder: QuadratureDecoder,
+    last_detent: Option<Instant>,
+}
+
+pub struct RotaryEncoder<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> {
+    extia: Mutex<M, T>,
+    extib: Mutex<M, T>,
+    state: Mutex<M, EncoderState>,
+    acceleration: Acceleration,
+    /// The accumulated position.
+    position: AtomicI32,
+    /// The position when [`RotaryEncoder::delta_since_last`] was last called.
+    last_position: AtomicI32,
+    changed: Signal<M, i32>,
+}
+
 impl<M: RawMutex, T: InputPin<Error = Infallible> + Wait<Error = Infallible>> RotaryEncoder<M, T> {
+    /// Create an encoder that moves one position per full quadrature cycle without acceleration.
     pub fn new(extia: T, extib: T) -> Self {
+        Self::new_with_config(extia, extib, 4, Acceleration::NONE)
+    }
+
+    /// Create an encoder with the number of quarter steps between detents and acceleration.
+    pub fn new_with_config(
+        mut extia: T,
+        mut extib: T,
+        steps_per_detent: i8,
+        acceleration: Acceleration,
+    ) -> Self {
+        let decoder = QuadratureDecoder::new(
+            extia.is_high().unwrap(),
+            extib.is_high().unwrap(),
+            steps_per_detent,
+        );
         Self {
             extia: Mutex::new(extia),
             extib: Mutex::new(extib),
+            state: Mutex::new(EncoderState {
+                decoder,
+                last_detent: None,
+            }),
+            acceleration,
+            position: AtomicI32::new(0),
+            last_position: AtomicI32::new(0),
+            changed: Signal::new(),
         }
     }
 
+    /// Track the position of the encoder. This never returns so should be run alongside the code reading the position.
+    pub async fn run(&self) -> ! {
+        let mut extia = self.extia.lock().await;
+        let mut extib = self.extib.lock().await;
+        loop {
+            self.next_detent(&mut extia, &mut extib).await;
+        }
+    }
+
+    /// Track the position of the encoder. Only returns if the channels are in use.
+    pub async fn try_run(&self) -> Result<Infallible, TryLockError> {
+        let mut extia = self.extia.try_lock()?;
+        let mut extib = self.extib.try_lock()?;
+        loop {
+            self.next_detent(&mut extia, &mut extib).await;
+        }
+    }
+
+    /// Wait for the next detent, updating the position.
+    pub async fn spun(&self) -> Direction {
+        let mut extia = self.extia.lock().await;
+        let mut extib = self.extib.lock().await;
+        self.next_detent(&mut extia, &mut extib).await
+    }
+
+    /// Wait for the next detent, updating the position.
     pub async fn try_spun(&self) -> Result<Direction, TryLockError> {
         let mut extia = self.extia.try_lock()?;
         let mut extib = self.extib.try_lock()?;
-        extia.wait_for_rising_edge().await.unwrap();
-        if extib.is_high().unwrap() {
-            Ok(Direction::CounterClockwise)
-        } else {
-            Ok(Direction::Clockwise)
+        Ok(self.next_detent(&mut extia, &mut extib).await)
+    }
+
+    async fn next_detent(
+        &self,
+        extia: &mut MutexGuard<'_, M, T>,
+        extib: &mut MutexGuard<'_, M, T>,
+    ) -> Direction {
+        loop {
+            select(extia.wait_for_any_edge(), extib.wait_for_any_edge()).await;
+            let a = extia.is_high().unwrap();
+            let b = extib.is_high().unwrap();
+            let mut state = self.state.lock().await;
+            if let Some(dir) = state.decoder.update(a, b) {
+                let now = Instant::now();
+                let multiplier = state
+                    .last_detent
+                    .map(|t| self.acceleration.multiplier((now - t).as_millis()))
+                    .unwrap_or(1);
+                state.last_detent = Some(now);
+                let step = match dir {
+                    Direction::Clockwise => multiplier,
+                    Direction::CounterClockwise => -multiplier,
+                };
+                let position = self
+                    .position
+                    .fetch_add(step, Ordering::Relaxed)
+                    .wrapping_add(step);
+                self.changed.signal(position);
+                return dir;
+            }
+        }
+    }
+
+    /// Return the accumulated position. Clockwise is positive.
+    pub fn position(&self) -> i32 {
+        self.position.load(Ordering::Relaxed)
+    }
+
+    /// Set the accumulated position.
+    pub fn set_position(&self, position: i32) {
+        self.position.store(position, Ordering::Relaxed);
+        self.last_position.store(position, Ordering::Relaxed);
+    }
+
+    /// Return how far the position has moved since this was last called. The position wraps at the ends of its range so the delta stays correct across the wrap.
+    pub fn delta_since_last(&self) -> i32 {
+        let position = self.position();
+        position.wrapping_sub(self.last_position.swap(position, Ordering::Relaxed))
+    }
+
+    /// Wait for the position to change, returning the new position. Changes made while nobody is waiting are coalesced into one.
+    pub async fn changed(&self) -> i32 {
+        self.changed.wait().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::mock::MockPin;
+
+    /// The AB states of one clockwise quadrature cycle starting from rest at 00.
+    const CW: [(bool, bool); 4] = [(true, false), (true, true), (false, true), (false, false)];
+
+    /// Feed the states to a decoder resting at 00, returning the detents it reports.
+    fn run(steps_per_detent: i8, states: &[(bool, bool)]) -> Vec<Direction> {
+        let mut decoder = QuadratureDecoder::new(false, false, steps_per_detent);
+        states
+            .iter()
+            .filter_map(|(a, b)| decoder.update(*a, *b))
+            .collect()
+    }
+
+    fn ccw() -> Vec<(bool, bool)> {
+        let mut states: Vec<_> = CW[..3].iter().rev().copied().collect();
+        states.push((false, false));
+        states
+    }
+
+    #[test]
+    fn transition_table() {
+        // Each valid transition is a quarter step and the rest are ignored.
+        let cases = [
+            (0b00, 0b10, 1),
+            (0b10, 0b11, 1),
+            (0b11, 0b01, 1),
+            (0b01, 0b00, 1),
+            (0b00, 0b01, -1),
+            (0b01, 0b11, -1),
+            (0b11, 0b10, -1),
+            (0b10, 0b00, -1),
+            (0b00, 0b11, 0),
+            (0b11, 0b00, 0),
+            (0b01, 0b10, 0),
+            (0b10, 0b01, 0),
+        ];
+        for (prev, curr, step) in cases {
+            assert_eq!(
+                TRANSITIONS[(prev << 2) | curr],
+                step,
+                "{prev:02b} -> {curr:02b}"
+            );
+        }
+        for state in 0..4 {
+            assert_eq!(TRANSITIONS[(state << 2) | state], 0);
+        }
+    }
+
+    #[test]
+    fn both_directions() {
+        let cw = CW.repeat(3);
+        assert_eq!(run(4, &cw), [Direction::Clockwise; 3]);
+        assert_eq!(run(4, &ccw().repeat(3)), [Direction::CounterClockwise; 3]);
+    }
+
+    #[test]
+    fn detent_division() {
+        let cases: [(i8, usize); 4] = [(1, 8), (2, 4), (4, 2), (0, 8)];
+        for (steps_per_detent, detents) in cases {
+            let cw = CW.repeat(2);
+            assert_eq!(
+                run(steps_per_detent, &cw),
+                vec![Direction::Clockwise; detents],
+                "{steps_per_detent} steps per detent"
+            );
         }
+        // Half a cycle is not a detent with four steps per detent.
+        assert!(run(4, &CW[..2]).is_empty());
+    }
+
+    #[test]
+    fn bounces_do_not_count() {
+        // Contact bounce on A going back and forth between two states.
+        let states = [
+            (true, false),
+            (false, false),
+            (true, false),
+            (true, true),
+            (true, false),
+            (true, true),
+            (false, true),
+            (false, false),
+        ];
+        assert_eq!(run(4, &states), [Direction::Clockwise]);
+    }
+
+    #[test]
+    fn invalid_double_steps_are_ignored() {
+        // 00 -> 11 skips a state so the direction is unknown.
+        let states = [(true, true), (false, true), (false, false)];
+        assert!(run(4, &states).is_empty());
+        let mut decoder = QuadratureDecoder::new(false, false, 4);
+        decoder.update(true, false);
+        decoder.update(false, true);
+        assert_eq!(decoder.steps, 1);
+    }
+
+    #[test]
+    fn springing_back_forgets_partial_steps() {
+        // Nudge three quarter steps clockwise, spring back to rest, then turn anticlockwise.
+        let mut states = vec![(true, false), (true, true), (false, true)];
+        states.extend([(true, true), (true, false), (false, false)]);
+        states.extend(ccw());
+        assert_eq!(run(4, &states), [Direction::CounterClockwise]);
+    }
+
+    #[test]
+    fn acceleration_ramps_between_the_intervals() {
+        let acceleration = Acceleration::default();
+        let cases = [
+            (500, 1),
+            (100, 1),
+            (99, 1),
+            (58, 2),
+            (30, 4),
+            (15, 5),
+            (0, 5),
+        ];
+        for (interval_ms, multiplier) in cases {
+            assert_eq!(
+                acceleration.multiplier(interval_ms),
+                multiplier,
+                "{interval_ms}ms"
+            );
+        }
+        assert_eq!(Acceleration::NONE.multiplier(0), 1);
+    }
+
+    #[test]
+    fn delta_survives_the_position_wrapping() {
+        let encoder: RotaryEncoder<NoopRawMutex, _> =
+            RotaryEncoder::new(MockPin { high: false }, MockPin { high: false });
+        encoder.set_position(i32::MAX - 1);
+        encoder.position.store(i32::MIN + 2, Ordering::Relaxed);
+        assert_eq!(encoder.delta_since_last(), 4);
+        encoder.position.store(i32::MAX, Ordering::Relaxed);
+        assert_eq!(encoder.delta_since_last(), -3);
+        assert_eq!(encoder.delta_since_last(), 0);
     }
 }
@@ -1,4 +1,5 @@
 /// The direction the driver steps the motor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
 pub enum Direction {
     CounterClockwise,
     Clockwise,
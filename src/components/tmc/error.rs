@@ -1,3 +1,5 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use thiserror::Error;
 
 /// The set of errors that may occur when interacting with the TMC driver.
@@ -30,3 +32,112 @@ pub enum TMCError {
     #[error("Timeout Error")]
     Timeout,
 }
+
+/// How many times each kind of error has been returned by the drivers, indexed by [`TMCError::index`].
+static ERROR_COUNTS: [AtomicU32; TMCError::COUNT] = [const { AtomicU32::new(0) }; TMCError::COUNT];
+
+impl TMCError {
+    /// The number of kinds of error.
+    pub const COUNT: usize = 13;
+
+    /// The name of each kind of error in snake case, in the order of [`TMCError::index`].
+    pub const NAMES: [&'static str; Self::COUNT] = [
+        "invalid_driver_address",
+        "invalid_sync_byte",
+        "invalid_master_address",
+        "crc_does_not_match",
+        "addr_does_not_match",
+        "register_addr_does_not_match",
+        "datagram_length",
+        "packing_error",
+        "unpacking_error",
+        "usart_error",
+        "no_usart",
+        "write_error",
+        "timeout",
+    ];
+
+    /// The position of the kind of error, ignoring its fields.
+    pub fn index(&self) -> usize {
+        match self {
+            Self::InvalidDriverAddress(_) => 0,
+            Self::InvalidSyncByte(_) => 1,
+            Self::InvalidMasterAddress(_) => 2,
+            Self::CrcDoesNotMatch => 3,
+            Self::AddrDoesNotMatch(_, _) => 4,
+            Self::RegisterAddrDoesNotMatch(_, _) => 5,
+            Self::DatagramLength(_) => 6,
+            Self::PackingError => 7,
+            Self::UnpackingError => 8,
+            Self::UsartError => 9,
+            Self::NoUsart => 10,
+            Self::WriteError(_, _) => 11,
+            Self::Timeout => 12,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[self.index()]
+    }
+
+    pub(crate) fn count(&self) {
+        ERROR_COUNTS[self.index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// How many times each kind of error has been returned by a register read or write since the board started.
+    pub fn counts() -> [u32; Self::COUNT] {
+        core::array::from_fn(|i| ERROR_COUNTS[i].load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all() -> [TMCError; TMCError::COUNT] {
+        [
+            TMCError::InvalidDriverAddress(4),
+            TMCError::InvalidSyncByte(0),
+            TMCError::InvalidMasterAddress(0),
+            TMCError::CrcDoesNotMatch,
+            TMCError::AddrDoesNotMatch(0, 1),
+            TMCError::RegisterAddrDoesNotMatch(0, 1),
+            TMCError::DatagramLength(4),
+            TMCError::PackingError,
+            TMCError::UnpackingError,
+            TMCError::UsartError,
+            TMCError::NoUsart,
+            TMCError::WriteError(1, 1),
+            TMCError::Timeout,
+        ]
+    }
+
+    #[test]
+    fn indexes_every_kind_once() {
+        for (i, e) in all().iter().enumerate() {
+            assert_eq!(e.index(), i, "{e:?}");
+        }
+        assert_eq!(TMCError::Timeout.name(), "timeout");
+        assert_eq!(TMCError::WriteError(3, 3).name(), "write_error");
+        for name in TMCError::NAMES {
+            assert_eq!(TMCError::NAMES.iter().filter(|n| **n == name).count(), 1);
+        }
+    }
+
+    #[test]
+    fn counts_each_kind() {
+        let before = TMCError::counts();
+        TMCError::Timeout.count();
+        TMCError::Timeout.count();
+        TMCError::CrcDoesNotMatch.count();
+        let after = TMCError::counts();
+        for (i, (before, after)) in before.iter().zip(after).enumerate() {
+            let expected = match i {
+                3 => 1,
+                12 => 2,
+                _ => 0,
+            };
+            assert_eq!(after - before, expected, "{}", TMCError::NAMES[i]);
+        }
+    }
+}
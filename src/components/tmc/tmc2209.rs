@@ -218,6 +218,17 @@ impl<'a, R: RawMutex, O, I: Wait<Error = Infallible>, U> TMC2209<'a, R, O, I, U>
 impl<'a, R: RawMutex, O, I, U: Read + Write + ReadReady> TMC2209<'a, R, O, I, U> {
     /// Performs a read request on the given driver usart and address. Expects ReadBack mode on the usart.
     pub async fn read_register(&self, register: &mut impl Datagram) -> Result<(), TMCError> {
+        self.read(register).await.inspect_err(TMCError::count)
+    }
+
+    /// Writes a register to the TMC2209
+    pub async fn write_register(&self, register: &mut impl Datagram) -> Result<(), TMCError> {
+        self.write(register).await.inspect_err(TMCError::count)
+    }
+
+    /// Performs a read request like [`TMC2209::read_register`] without counting a failure, for polls that expect
+    /// an unpowered driver not to answer.
+    pub(crate) async fn read(&self, register: &mut impl Datagram) -> Result<(), TMCError> {
         let datagram = register.read_request(self.addr)?;
         info!("[TMC2209] Read Request: {}", datagram);
         let mut usart = self.usart.unwrap().lock().await;
@@ -242,10 +253,9 @@ impl<'a, R: RawMutex, O, I, U: Read + Write + ReadReady> TMC2209<'a, R, O, I, U>
         Ok(())
     }
 
-    /// Writes a register to the TMC2209
-    pub async fn write_register(&self, register: &mut impl Datagram) -> Result<(), TMCError> {
+    async fn write(&self, register: &mut impl Datagram) -> Result<(), TMCError> {
         let mut ifcnt_before = IfCnt::default();
-        self.read_register(&mut ifcnt_before).await?;
+        self.read(&mut ifcnt_before).await?;
 
         let usart = self.usart.unwrap();
         let mut usart = usart.lock().await;
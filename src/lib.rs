@@ -1,5 +1,5 @@
 #![doc = include_str!("../README.md")]
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![allow(static_mut_refs, clippy::too_many_arguments)]
 
 use embassy_executor::Spawner;
@@ -16,19 +16,32 @@ use embassy_stm32::{
 use thiserror::Error;
 
 use crate::components::{
-    adc::BuddyAdc, bed_power_monitor, buzzer, display, eeprom, ethernet::build_ethernet, fans::Fan,
-    filament_sensor, flash, heaters::Heater, pinda, rotary_button, rotary_encoder, steppers,
+    adc::BuddyAdc,
+    bed_power_monitor, buzzer, display, eeprom,
+    eeprom::{BOARD_CONFIG_ADDR, ConfigSource, ConfigStore},
+    ethernet::{EthernetResources, build_ethernet},
+    fans::Fan,
+    filament_sensor, flash,
+    heaters::Heater,
+    pinda, rotary_button, rotary_encoder, steppers,
     thermistors::Thermistor,
 };
 
 pub mod components;
+pub(crate) mod crc;
 pub(crate) mod fmt;
-use crate::fmt::info;
+#[cfg(test)]
+pub(crate) mod mock;
+pub mod net;
+pub mod probing;
+pub mod ui;
+use crate::fmt::{error, info};
 
 pub use crate::components::bed_power_monitor::BuddyBedPowerMonitor;
 pub use crate::components::buzzer::BuddyBuzzer;
 pub use crate::components::display::BuddyDisplay;
-pub use crate::components::eeprom::BuddyEeprom;
+pub use crate::components::eeprom::{BoardConfig, BuddyEeprom};
+pub use crate::components::ethernet::{NetworkConfig, NetworkResources};
 pub use crate::components::fans::BuddyFan;
 pub use crate::components::filament_sensor::BuddyFilamentSensor;
 pub use crate::components::flash::BuddyFlash;
@@ -80,6 +93,7 @@ pub struct Board<'a> {
     pub eeprom: Option<BuddyEeprom<'a>>,
     pub flash: Option<BuddyFlash<'a>>,
     pub display: Option<BuddyDisplay<'a>>,
+    pub config: Option<BoardConfig>,
 }
 
 /// The peripherals that are not used by default but are, for example, available through expansion headers on the board.
@@ -152,9 +166,12 @@ pub struct BoardBuilder<'a> {
     z_stepper: bool,
     e_stepper: bool,
     eeprom: bool,
+    load_config: bool,
     flash: bool,
     display: bool,
     mac_addr: [u8; 6],
+    network: NetworkConfig,
+    ethernet_resources: Option<&'static mut dyn EthernetResources>,
     spawner: Option<&'a Spawner>,
 }
 
@@ -191,10 +208,25 @@ impl<'a> BoardBuilder<'a> {
         buzzer::build_buzzer(ch, tim)
     }
 
-    pub fn ethernet(mut self, spawner: &'a Spawner, mac_addr: [u8; 6]) -> BoardBuilder<'a> {
+    pub fn ethernet(
+        mut self,
+        spawner: &'a Spawner,
+        mac_addr: [u8; 6],
+        network: NetworkConfig,
+    ) -> BoardBuilder<'a> {
         self.ethernet = true;
         self.spawner = Some(spawner);
         self.mac_addr = mac_addr;
+        self.network = network;
+        self
+    }
+
+    /// Build the network stack in `resources` in place of the default of 3 sockets and 4 packets each way.
+    pub fn ethernet_resources(
+        mut self,
+        resources: &'static mut dyn EthernetResources,
+    ) -> BoardBuilder<'a> {
+        self.ethernet_resources = Some(resources);
         self
     }
 
@@ -212,9 +244,12 @@ impl<'a> BoardBuilder<'a> {
         tx_d1: PB13,
         tx_en: PB11,
         mac_addr: [u8; 6],
+        network: &NetworkConfig,
+        resources: Option<&'static mut dyn EthernetResources>,
     ) -> Stack<'a> {
         build_ethernet(
-            spawner, rng, eth, ref_clk, mdio, mdc, crs, rx_d0, rx_d1, tx_d0, tx_d1, tx_en, mac_addr,
+            spawner, rng, eth, ref_clk, mdio, mdc, crs, rx_d0, rx_d1, tx_d0, tx_d1, tx_en,
+            mac_addr, network, resources,
         )
         .await
     }
@@ -357,6 +392,29 @@ impl<'a> BoardBuilder<'a> {
         self
     }
 
+    /// Load the [`BoardConfig`] from the EEPROM and use its MAC address, network config and thermistor calibration in place of the builder values. Implies building the EEPROM.
+    pub fn load_config(mut self, load: bool) -> BoardBuilder<'a> {
+        self.load_config = load;
+        if load {
+            self.eeprom = true;
+        }
+        self
+    }
+
+    fn apply_config(&mut self, config: &BoardConfig) {
+        self.mac_addr = config.mac_addr;
+        self.network = config.network.clone();
+        self.bed_beta = config.bed_thermistor.beta;
+        self.bed_r_ref = config.bed_thermistor.r_ref;
+        self.bed_t_ref = config.bed_thermistor.t_ref;
+        self.board_beta = config.board_thermistor.beta;
+        self.board_r_ref = config.board_thermistor.r_ref;
+        self.board_t_ref = config.board_thermistor.t_ref;
+        self.hotend_beta = config.hotend_thermistor.beta;
+        self.hotend_r_ref = config.hotend_thermistor.r_ref;
+        self.hotend_t_ref = config.hotend_thermistor.t_ref;
+    }
+
     pub fn flash(mut self, build: bool) -> BoardBuilder<'a> {
         self.flash = build;
         self
@@ -371,7 +429,7 @@ impl<'a> BoardBuilder<'a> {
         BuddyAdc::new_static_adc1(adc)
     }
 
-    pub async fn build(self) -> Board<'a> {
+    pub async fn build(mut self) -> Board<'a> {
         let mut board = Board::default();
         let mut config = Config::default();
         if self.ethernet {
@@ -393,6 +451,27 @@ impl<'a> BoardBuilder<'a> {
             config.rcc.sys = Sysclk::PLL1_P;
         }
         let p = embassy_stm32::init(config);
+        if self.eeprom {
+            info!("[BUDDY] Building EEPROM");
+            let eeprom = eeprom::build_eeprom(p.I2C1, p.PB8, p.PB9, p.DMA1_CH6, p.DMA1_CH5);
+            if self.load_config {
+                info!("[BUDDY] Loading Config");
+                match ConfigStore::new(&eeprom, BOARD_CONFIG_ADDR)
+                    .load::<BoardConfig>()
+                    .await
+                {
+                    Ok((config, source)) => {
+                        info!("[BUDDY] Config Source: {}", source);
+                        if source != ConfigSource::Default {
+                            self.apply_config(&config);
+                        }
+                        board.config = Some(config);
+                    }
+                    Err(_) => error!("[BUDDY] Failed to load the config"),
+                }
+            }
+            board.eeprom = Some(eeprom);
+        }
         if self.ethernet {
             let stack = Self::build_ethernet(
                 self.spawner.unwrap(),
@@ -408,6 +487,8 @@ impl<'a> BoardBuilder<'a> {
                 p.PB13,
                 p.PB11,
                 self.mac_addr,
+                &self.network,
+                self.ethernet_resources.take(),
             )
             .await;
             board.stack = Some(stack)
@@ -587,12 +668,6 @@ impl<'a> BoardBuilder<'a> {
             }
         }
 
-        if self.eeprom {
-            info!("[BUDDY] Building EEPROM");
-            let eeprom = eeprom::build_eeprom(p.I2C1, p.PB8, p.PB9, p.DMA1_CH6, p.DMA1_CH5);
-            board.eeprom = Some(eeprom);
-        }
-
         if self.flash {
             info!("[BUDDY] Building FLASH");
             let flash = flash::build_flash(
@@ -603,9 +678,12 @@ impl<'a> BoardBuilder<'a> {
 
         if self.display {
             info!("[BUDDY] Building Display");
-            let display =
-                display::build_display(p.SPI2, p.PB10, p.PC3, p.PC2, p.PC9, p.PD11, p.PC8);
-            board.display = Some(display);
+            match display::build_display(
+                p.SPI2, p.PB10, p.PC3, p.PC2, p.DMA1_CH4, p.DMA1_CH3, p.PC9, p.PD11, p.PC8,
+            ) {
+                Ok(display) => board.display = Some(display),
+                Err(_) => error!("[BUDDY] Failed to build the display"),
+            }
         }
 
         let j10 = J10 {
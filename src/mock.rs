@@ -0,0 +1,515 @@
+//! Test doubles for the peripherals, for the host tests.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    convert::Infallible,
+};
+
+use embedded_graphics::{
+    mock_display::{ColorMapping, MockDisplay},
+    pixelcolor::Rgb565,
+    prelude::*,
+};
+use embedded_hal::digital::{self, InputPin};
+use embedded_hal_async::{
+    digital::Wait,
+    i2c::{self, ErrorKind, I2c, NoAcknowledgeSource, Operation},
+};
+use embedded_storage_async::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+/// The error returned once a [`RamFlash`] has lost power.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerLoss;
+
+impl NorFlashError for PowerLoss {
+    fn kind(&self) -> NorFlashErrorKind {
+        NorFlashErrorKind::Other
+    }
+}
+
+/// A NOR flash held in RAM that loses power part way through the write or erase after its budget runs out.
+///
+/// Like the real chip, a write can only clear bits and an erase sets a whole sector to `0xFF`. The interrupted
+/// operation is left half done and every later operation fails.
+pub struct RamFlash<const N: usize, const SECTOR: usize> {
+    /// Boxed so a flash big enough for a filesystem does not overflow the test thread's stack.
+    pub data: Box<[u8; N]>,
+    /// The writes and erases that complete before power is lost, or `None` to never lose power.
+    pub budget: Option<usize>,
+    pub writes: usize,
+    pub erases: usize,
+    lost_power: bool,
+}
+
+impl<const N: usize, const SECTOR: usize> RamFlash<N, SECTOR> {
+    pub fn new() -> Self {
+        Self::from_image(vec![0xFF; N].into_boxed_slice().try_into().unwrap())
+    }
+
+    /// Power up a flash with the contents left by another.
+    pub fn from_image(data: Box<[u8; N]>) -> Self {
+        Self {
+            data,
+            budget: None,
+            writes: 0,
+            erases: 0,
+            lost_power: false,
+        }
+    }
+
+    /// Whether power was lost part way through an earlier operation.
+    pub fn lost_power(&self) -> bool {
+        self.lost_power
+    }
+
+    /// Spend one operation from the budget, returning `false` if power is lost during it.
+    fn spend(&mut self) -> bool {
+        match &mut self.budget {
+            Some(0) => {
+                self.lost_power = true;
+                false
+            }
+            Some(n) => {
+                *n -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+}
+
+impl<const N: usize, const SECTOR: usize> ErrorType for RamFlash<N, SECTOR> {
+    type Error = PowerLoss;
+}
+
+impl<const N: usize, const SECTOR: usize> ReadNorFlash for RamFlash<N, SECTOR> {
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        if self.lost_power {
+            return Err(PowerLoss);
+        }
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize, const SECTOR: usize> NorFlash for RamFlash<N, SECTOR> {
+    const WRITE_SIZE: usize = 1;
+    const ERASE_SIZE: usize = SECTOR;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        assert!((from as usize).is_multiple_of(SECTOR) && (to as usize).is_multiple_of(SECTOR));
+        let (from, to) = (from as usize, to as usize);
+        if self.lost_power {
+            return Err(PowerLoss);
+        }
+        if self.spend() {
+            self.erases += 1;
+            self.data[from..to].fill(0xFF);
+            Ok(())
+        } else {
+            self.data[from..from + (to - from) / 2].fill(0xFF);
+            Err(PowerLoss)
+        }
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        if self.lost_power {
+            return Err(PowerLoss);
+        }
+        let offset = offset as usize;
+        let len = if self.spend() {
+            self.writes += 1;
+            bytes.len()
+        } else {
+            bytes.len() / 2
+        };
+        for (cell, byte) in self.data[offset..].iter_mut().zip(&bytes[..len]) {
+            *cell &= byte;
+        }
+        if len == bytes.len() {
+            Ok(())
+        } else {
+            Err(PowerLoss)
+        }
+    }
+}
+
+/// The error returned when a [`MockI2c`] device does not acknowledge its address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nack;
+
+impl i2c::Error for Nack {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)
+    }
+}
+
+/// A transaction seen by a [`MockI2c`], including the ones that were not acknowledged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub address: u8,
+    /// The bytes written, starting with the 2-byte memory address.
+    pub write: Vec<u8>,
+    /// The number of bytes read.
+    pub read: usize,
+    pub acked: bool,
+}
+
+/// Called after every acknowledged transaction so a test can play the part of the device's own logic.
+pub type I2cHook = Box<dyn FnMut(&Transaction, &mut BTreeMap<u8, Vec<u8>>)>;
+
+/// An I2C bus holding EEPROM-like devices with 16-bit big-endian memory addresses, such as the ST25DV.
+///
+/// Each device is a 64KB memory that starts zeroed. After a write with data the device is busy programming and does
+/// not acknowledge the next `busy_polls` transactions, so drivers have to ACK poll.
+pub struct MockI2c {
+    /// The memory behind each 7-bit device address.
+    pub memory: BTreeMap<u8, Vec<u8>>,
+    pub transactions: Vec<Transaction>,
+    /// How many transactions a device ignores after each write, `usize::MAX` for one that never finishes.
+    pub busy_polls: usize,
+    pub hook: Option<I2cHook>,
+    busy: usize,
+    pointer: u16,
+}
+
+impl MockI2c {
+    pub fn new(addresses: &[u8]) -> Self {
+        Self {
+            memory: addresses.iter().map(|a| (*a, vec![0; 0x1_0000])).collect(),
+            transactions: Vec::new(),
+            busy_polls: 0,
+            hook: None,
+            busy: 0,
+            pointer: 0,
+        }
+    }
+
+    /// Return `len` bytes of a device's memory from `addr`.
+    pub fn mem(&self, address: u8, addr: u16, len: usize) -> &[u8] {
+        &self.memory[&address][addr as usize..addr as usize + len]
+    }
+
+    /// Write bytes into a device's memory behind the driver's back.
+    pub fn set(&mut self, address: u8, addr: u16, data: &[u8]) {
+        let addr = addr as usize;
+        self.memory.get_mut(&address).unwrap()[addr..addr + data.len()].copy_from_slice(data);
+    }
+
+    /// The transactions that carried data or a memory address, leaving out the ACK polls.
+    pub fn accesses(&self) -> impl Iterator<Item = &Transaction> {
+        self.transactions
+            .iter()
+            .filter(|t| t.acked && (!t.write.is_empty() || t.read > 0))
+    }
+}
+
+impl i2c::ErrorType for MockI2c {
+    type Error = Nack;
+}
+
+impl I2c for MockI2c {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let mut transaction = Transaction {
+            address,
+            write: Vec::new(),
+            read: 0,
+            acked: false,
+        };
+        for op in operations.iter() {
+            match op {
+                Operation::Write(bytes) => transaction.write.extend_from_slice(bytes),
+                Operation::Read(buf) => transaction.read += buf.len(),
+            }
+        }
+        if !self.memory.contains_key(&address) || self.busy > 0 {
+            self.busy = self.busy.saturating_sub(1);
+            self.transactions.push(transaction);
+            return Err(Nack);
+        }
+        transaction.acked = true;
+        let memory = self.memory.get_mut(&address).unwrap();
+        let mut programmed = false;
+        for op in operations.iter_mut() {
+            match op {
+                Operation::Write(bytes) if bytes.len() >= 2 => {
+                    self.pointer = u16::from_be_bytes([bytes[0], bytes[1]]);
+                    for byte in &bytes[2..] {
+                        memory[self.pointer as usize] = *byte;
+                        self.pointer = self.pointer.wrapping_add(1);
+                        programmed = true;
+                    }
+                }
+                Operation::Write(_) => {}
+                Operation::Read(buf) => {
+                    for byte in buf.iter_mut() {
+                        *byte = memory[self.pointer as usize];
+                        self.pointer = self.pointer.wrapping_add(1);
+                    }
+                }
+            }
+        }
+        if programmed {
+            self.busy = self.busy_polls;
+        }
+        if let Some(hook) = &mut self.hook {
+            hook(&transaction, &mut self.memory);
+        }
+        self.transactions.push(transaction);
+        Ok(())
+    }
+}
+
+/// A GPIO input held at a fixed level. Waiting for the level it is at completes straight away and waiting for
+/// anything else never completes.
+pub struct MockPin {
+    pub high: bool,
+}
+
+impl digital::ErrorType for MockPin {
+    type Error = Infallible;
+}
+
+impl InputPin for MockPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.high)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.high)
+    }
+}
+
+impl Wait for MockPin {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        if !self.high {
+            core::future::pending::<()>().await;
+        }
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        if self.high {
+            core::future::pending::<()>().await;
+        }
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        core::future::pending().await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        core::future::pending().await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        core::future::pending().await
+    }
+}
+
+/// A connection that hands the reader the bytes of each chunk in `input` in turn and records what is written.
+///
+/// A read never crosses into the next chunk, so a test controls how the bytes arrive. Once the input runs out the
+/// peer has closed the connection and reads return 0.
+#[derive(Default)]
+pub struct MockSocket {
+    pub input: VecDeque<Vec<u8>>,
+    pub output: Vec<u8>,
+    pub flushes: usize,
+}
+
+impl MockSocket {
+    pub fn new(chunks: &[&[u8]]) -> Self {
+        Self {
+            input: chunks.iter().map(|c| c.to_vec()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Return what has been written as text.
+    pub fn output_str(&self) -> &str {
+        std::str::from_utf8(&self.output).unwrap()
+    }
+}
+
+impl embedded_io_async::ErrorType for MockSocket {
+    type Error = Infallible;
+}
+
+impl embedded_io_async::Read for MockSocket {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let Some(chunk) = self.input.front_mut() else {
+            return Ok(0);
+        };
+        let n = buf.len().min(chunk.len());
+        buf[..n].copy_from_slice(&chunk[..n]);
+        chunk.drain(..n);
+        if chunk.is_empty() {
+            self.input.pop_front();
+        }
+        Ok(n)
+    }
+}
+
+impl embedded_io_async::Write for MockSocket {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flushes += 1;
+        Ok(())
+    }
+}
+
+/// A view of part of a screen larger than the 64x64 [`MockDisplay`].
+///
+/// The target reports `size` as its bounding box and shifts every pixel by `-offset`, so the part of the screen
+/// from `offset` onwards lands on the mock display and everything outside it is dropped.
+pub struct Window<'a> {
+    pub display: &'a mut MockDisplay<Rgb565>,
+    pub size: Size,
+    pub offset: Point,
+}
+
+impl<'a> Window<'a> {
+    pub fn new(display: &'a mut MockDisplay<Rgb565>, size: Size, offset: Point) -> Self {
+        display.set_allow_overdraw(true);
+        Self {
+            display,
+            size,
+            offset,
+        }
+    }
+}
+
+impl OriginDimensions for Window<'_> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for Window<'_> {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let visible = self.display.bounding_box();
+        for Pixel(point, color) in pixels {
+            let point = point - self.offset;
+            if visible.contains(point) {
+                self.display.draw_pixel(point, color);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Draw a screen `size` big and cut it into strips 64 pixels wide that each fit on a [`MockDisplay`].
+pub fn strips(size: Size, mut draw: impl FnMut(&mut Window)) -> Vec<MockDisplay<Rgb565>> {
+    (0..size.width.div_ceil(64))
+        .map(|i| {
+            let mut display = MockDisplay::new();
+            draw(&mut Window::new(
+                &mut display,
+                size,
+                Point::new(64 * i as i32, 0),
+            ));
+            display
+        })
+        .collect()
+}
+
+/// Compare the displays against the golden image `src/ui/snapshots/<name>.txt`.
+///
+/// Each line of the file is a row of [`MockDisplay`] pattern characters, with a `--` line between displays. Run
+/// the tests with `BLESS=1` to write the current output as the new golden image.
+pub fn assert_snapshot(displays: &[MockDisplay<Rgb565>], name: &str) {
+    let path = format!("{}/src/ui/snapshots/{name}.txt", env!("CARGO_MANIFEST_DIR"));
+    if std::env::var_os("BLESS").is_some() {
+        let patterns: Vec<String> = displays.iter().map(pattern).collect();
+        std::fs::write(&path, patterns.join("--\n")).unwrap();
+    }
+    let golden =
+        std::fs::read_to_string(&path).unwrap_or_else(|_| panic!("missing snapshot {path}"));
+    let golden: Vec<Vec<&str>> = golden
+        .split("--\n")
+        .map(|pattern| pattern.lines().collect())
+        .collect();
+    assert_eq!(golden.len(), displays.len(), "snapshot {name}");
+    for (i, (display, pattern)) in displays.iter().zip(&golden).enumerate() {
+        display.assert_pattern_with_message(pattern, |f| write!(f, "snapshot {name} strip {i}"));
+    }
+}
+
+/// Print the display as pattern lines, without the empty rows at the bottom.
+fn pattern<C: PixelColor + ColorMapping>(display: &MockDisplay<C>) -> String {
+    let size = display.bounding_box().size;
+    let mut rows: Vec<String> = (0..size.height as i32)
+        .map(|y| {
+            (0..size.width as i32)
+                .map(|x| {
+                    display
+                        .get_pixel(Point::new(x, y))
+                        .map_or(' ', C::color_to_char)
+                })
+                .collect()
+        })
+        .collect();
+    while rows.last().is_some_and(|row| row.trim().is_empty()) {
+        rows.pop();
+    }
+    rows.iter().map(|row| format!("{row}\n")).collect()
+}
+
+/// Compare text against the golden file `src/net/snapshots/<name>.txt`. Run the tests with `BLESS=1` to write the
+/// current output as the new golden file.
+pub fn assert_text_snapshot(text: &str, name: &str) {
+    let path = format!(
+        "{}/src/net/snapshots/{name}.txt",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    if std::env::var_os("BLESS").is_some() {
+        std::fs::write(&path, text).unwrap();
+    }
+    let golden =
+        std::fs::read_to_string(&path).unwrap_or_else(|_| panic!("missing snapshot {path}"));
+    assert_eq!(text, golden, "snapshot {name}");
+}
+
+/// Drops defmt output on the host, where there is no probe to send it to.
+#[defmt::global_logger]
+struct NoLogger;
+
+unsafe impl defmt::Logger for NoLogger {
+    fn acquire() {}
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {}
+
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+#[defmt::panic_handler]
+fn defmt_panic() -> ! {
+    panic!("defmt panic")
+}
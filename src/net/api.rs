@@ -0,0 +1,243 @@
+use embedded_io_async::Read;
+
+use crate::net::{
+    GcodeError, GcodeSink, HeaterId, JsonValue, JsonWriter, PrinterStatus, clean_line,
+    http::{Body, Handler, Method, Request, Response, Status},
+    json_field,
+};
+
+/// The longest G-code command accepted in a JSON body, after decoding escapes.
+const MAX_COMMAND: usize = 256;
+
+/// The endpoints of the REST API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Route {
+    /// `GET /api/status`
+    Status,
+    /// `POST /api/heaters/{bed,hotend}`
+    Heater(HeaterId),
+    /// `POST /api/gcode`
+    Gcode,
+}
+
+/// Match a request to an endpoint, or return the status to reply with if there is none.
+pub fn route(method: Method, path: &str) -> Result<Route, Status> {
+    let (route, allowed) = match path.trim_end_matches('/') {
+        "/api/status" => (Route::Status, Method::Get),
+        "/api/heaters/bed" => (Route::Heater(HeaterId::Bed), Method::Post),
+        "/api/heaters/hotend" => (Route::Heater(HeaterId::Hotend), Method::Post),
+        "/api/gcode" => (Route::Gcode, Method::Post),
+        _ => return Err(Status::NotFound),
+    };
+    if method == allowed || (method == Method::Head && allowed == Method::Get) {
+        Ok(route)
+    } else {
+        Err(Status::MethodNotAllowed)
+    }
+}
+
+/// What the REST API needs from the print controller.
+#[allow(async_fn_in_trait)]
+pub trait PrinterApi: GcodeSink {
+    /// Return the current status including the heater targets. [`PrinterStatus::read`] reads the sensors on the board.
+    async fn status(&mut self) -> PrinterStatus;
+
+    /// Set the target of a heater (°C). The target has already been checked against [`HeaterId::max_target`].
+    async fn set_target(&mut self, heater: HeaterId, target: f64);
+}
+
+/// An HTTP [`Handler`] serving the REST API from a [`PrinterApi`].
+///
+/// | Endpoint | Body | Response |
+/// | --- | --- | --- |
+/// | `GET /api/status` | | The [`PrinterStatus`] |
+/// | `POST /api/heaters/{bed,hotend}` | `{"target": 215}` | `{"heater":"hotend","target":215.0}` |
+/// | `POST /api/gcode` | `{"command": "G28\nG1 Z10"}` or plain text | `{"accepted":2}` |
+pub struct RestApi<P: PrinterApi> {
+    printer: P,
+}
+
+impl<P: PrinterApi> RestApi<P> {
+    pub fn new(printer: P) -> Self {
+        Self { printer }
+    }
+
+    pub fn printer(&mut self) -> &mut P {
+        &mut self.printer
+    }
+
+    async fn status(&mut self, body: &mut [u8]) -> Response {
+        let status = self.printer.status().await;
+        let mut json = JsonWriter::new(body);
+        status.write_json(&mut json);
+        finish(json, Status::Ok)
+    }
+
+    async fn heater(
+        &mut self,
+        heater: HeaterId,
+        request: &Request<'_>,
+        body: &mut [u8],
+    ) -> Response {
+        let target = match request
+            .body_str()
+            .ok()
+            .and_then(|b| json_field(b, "target").ok().flatten())
+        {
+            Some(JsonValue::Number(target)) if (0.0..=heater.max_target()).contains(&target) => {
+                target
+            }
+            _ => return error(body, Status::BadRequest, "Expected a target in range."),
+        };
+        self.printer.set_target(heater, target).await;
+        let mut json = JsonWriter::new(body);
+        json.begin_object()
+            .field_str("heater", heater.name())
+            .field_f64("target", target, 1)
+            .end_object();
+        finish(json, Status::Ok)
+    }
+
+    async fn gcode(&mut self, request: &Request<'_>, body: &mut [u8]) -> Response {
+        let Ok(text) = request.body_str() else {
+            return error(body, Status::BadRequest, "The body is not UTF-8.");
+        };
+        let mut command = [0u8; MAX_COMMAND];
+        let text = if request.is_json() {
+            match json_field(text, "command") {
+                Ok(Some(JsonValue::Str(s))) => match s.unescape(&mut command) {
+                    Ok(s) => s,
+                    Err(_) => {
+                        return error(body, Status::PayloadTooLarge, "The command is too long.");
+                    }
+                },
+                _ => return error(body, Status::BadRequest, "Expected a command."),
+            }
+        } else {
+            text
+        };
+        let mut accepted = 0;
+        let mut result = Ok(());
+        for line in text.lines().filter_map(clean_line) {
+            result = self.printer.send(line).await;
+            if result.is_err() {
+                break;
+            }
+            accepted += 1;
+        }
+        let mut json = JsonWriter::new(body);
+        json.begin_object().field_u64("accepted", accepted);
+        let status = match result {
+            Ok(()) => Status::Ok,
+            Err(e) => {
+                json.field_str("error", gcode_error(e));
+                match e {
+                    GcodeError::Busy => Status::ServiceUnavailable,
+                    _ => Status::BadRequest,
+                }
+            }
+        };
+        json.end_object();
+        finish(json, status)
+    }
+}
+
+impl<P: PrinterApi> Handler for RestApi<P> {
+    async fn handle<R: Read>(
+        &mut self,
+        request: &Request<'_>,
+        _body: &mut Body<'_, R>,
+        response: &mut [u8],
+    ) -> Response {
+        if !request.is_complete() {
+            return Response::empty(Status::PayloadTooLarge);
+        }
+        match route(request.method, request.path) {
+            Ok(Route::Status) => self.status(response).await,
+            Ok(Route::Heater(heater)) => self.heater(heater, request, response).await,
+            Ok(Route::Gcode) => self.gcode(request, response).await,
+            Err(status) => Response::empty(status),
+        }
+    }
+}
+
+fn gcode_error(e: GcodeError) -> &'static str {
+    match e {
+        GcodeError::Busy => "busy",
+        GcodeError::UnknownCommand => "unknown command",
+        GcodeError::InvalidParameter => "invalid parameter",
+    }
+}
+
+/// Reply with `{"error": message}`.
+pub(crate) fn error(body: &mut [u8], status: Status, message: &str) -> Response {
+    let mut json = JsonWriter::new(body);
+    json.begin_object().field_str("error", message).end_object();
+    finish(json, status)
+}
+
+/// Reply with the JSON written, or a bare 500 if it did not fit in the response buffer.
+pub(crate) fn finish(json: JsonWriter, status: Status) -> Response {
+    match json.finish() {
+        Ok(len) => Response::json(status, len),
+        Err(_) => Response::empty(Status::InternalServerError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_each_endpoint() {
+        assert_eq!(route(Method::Get, "/api/status"), Ok(Route::Status));
+        assert_eq!(
+            route(Method::Post, "/api/heaters/bed"),
+            Ok(Route::Heater(HeaterId::Bed))
+        );
+        assert_eq!(
+            route(Method::Post, "/api/heaters/hotend"),
+            Ok(Route::Heater(HeaterId::Hotend))
+        );
+        assert_eq!(route(Method::Post, "/api/gcode"), Ok(Route::Gcode));
+    }
+
+    #[test]
+    fn ignores_a_trailing_slash() {
+        assert_eq!(route(Method::Get, "/api/status/"), Ok(Route::Status));
+        assert_eq!(route(Method::Post, "/api/gcode//"), Ok(Route::Gcode));
+    }
+
+    #[test]
+    fn answers_head_like_get() {
+        assert_eq!(route(Method::Head, "/api/status"), Ok(Route::Status));
+        assert_eq!(
+            route(Method::Head, "/api/gcode"),
+            Err(Status::MethodNotAllowed)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_paths_and_methods() {
+        assert_eq!(route(Method::Get, "/"), Err(Status::NotFound));
+        assert_eq!(route(Method::Get, "/api"), Err(Status::NotFound));
+        assert_eq!(
+            route(Method::Post, "/api/heaters/chamber"),
+            Err(Status::NotFound)
+        );
+        // Paths are matched exactly, including case.
+        assert_eq!(route(Method::Get, "/API/status"), Err(Status::NotFound));
+        assert_eq!(
+            route(Method::Post, "/api/status"),
+            Err(Status::MethodNotAllowed)
+        );
+        assert_eq!(
+            route(Method::Get, "/api/gcode"),
+            Err(Status::MethodNotAllowed)
+        );
+        assert_eq!(
+            route(Method::Delete, "/api/heaters/bed"),
+            Err(Status::MethodNotAllowed)
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use core::fmt::Write as _;
+
+use embassy_net::{Stack, tcp::TcpSocket};
+use embassy_time::{Duration, Timer};
+use embedded_io_async::{Read, Write};
+
+use crate::{
+    fmt::{error, info},
+    net::{ConsoleError, GcodeError, GcodeSink, LineError, SliceWriter, clean_line},
+};
+
+/// How often to check a quiet host is still there, and how long it has to answer.
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait before offering a command to a busy sink again.
+const BUSY_RETRY: Duration = Duration::from_millis(100);
+
+/// How many retries between each `busy` message, so the host knows the printer is still there.
+const BUSY_MESSAGE_RETRIES: u32 = 20;
+
+/// Check the line number and checksum of a line from a host, e.g. `N12 G1 X5*97`, and return the line number and the
+/// command. Lines without a number are returned as they are. The line must already have its comment removed.
+///
+/// A numbered line must carry the XOR of the bytes before the `*`, and follow `last_line` unless it is an `M110`.
+pub fn check_line(line: &str, last_line: i32) -> Result<(Option<i32>, &str), LineError> {
+    let Some(numbered) = line.strip_prefix('N') else {
+        if line.contains('*') {
+            return Err(LineError::NoLineNumber);
+        }
+        return Ok((None, line));
+    };
+    let (body, checksum) = line.rsplit_once('*').ok_or(LineError::NoChecksum)?;
+    let checksum: u8 = checksum
+        .trim()
+        .parse()
+        .map_err(|_| LineError::ChecksumMismatch)?;
+    if body.bytes().fold(0, |sum, b| sum ^ b) != checksum {
+        return Err(LineError::ChecksumMismatch);
+    }
+    let digits = numbered
+        .bytes()
+        .enumerate()
+        .take_while(|&(i, b)| b.is_ascii_digit() || (i == 0 && b == b'-'))
+        .count();
+    let number: i32 = numbered[..digits]
+        .parse()
+        .map_err(|_| LineError::LineNumber)?;
+    // The body starts with the `N`.
+    let command = body[1 + digits..].trim();
+    if number != last_line.wrapping_add(1) && !is_m110(command) {
+        return Err(LineError::LineNumber);
+    }
+    Ok((Some(number), command))
+}
+
+fn is_m110(command: &str) -> bool {
+    command
+        .split_ascii_whitespace()
+        .next()
+        .is_some_and(|code| code.eq_ignore_ascii_case("M110"))
+}
+
+/// Answers G-code from a host the way Marlin does over serial, so terminals and hosts such as Pronterface can drive
+/// the printer over the network.
+///
+/// Each line is passed to the [`GcodeSink`] and answered with `ok` once it is accepted, which tells the host to send
+/// the next. A busy sink is retried with an `echo:busy: processing` message every couple of seconds. Numbered lines are
+/// checked and a bad one is answered with `Resend:` and the line the host should send again.
+///
+/// ```text
+/// > N1 G28*18
+/// < ok
+/// > N3 G1 X5*102
+/// < Error:Line Number is not Last Line Number+1, Last Line: 1
+/// < Resend: 2
+/// < ok
+/// > G999
+/// < echo:Unknown command: "G999"
+/// < ok
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcodeConsole {
+    last_line: i32,
+}
+
+impl GcodeConsole {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of the last numbered line accepted.
+    pub fn last_line(&self) -> i32 {
+        self.last_line
+    }
+
+    /// Answer one line from the host.
+    pub async fn handle_line<W: Write, G: GcodeSink>(
+        &mut self,
+        line: &str,
+        sink: &mut G,
+        out: &mut W,
+    ) -> Result<(), ConsoleError> {
+        let Some(line) = clean_line(line) else {
+            return Ok(());
+        };
+        let command = match check_line(line, self.last_line) {
+            Ok((number, command)) => {
+                if let Some(number) = number {
+                    self.last_line = number;
+                }
+                command
+            }
+            Err(e) => {
+                let message = match e {
+                    LineError::NoChecksum => "No Checksum with line number",
+                    LineError::NoLineNumber => "No Line Number with checksum",
+                    LineError::ChecksumMismatch => "checksum mismatch",
+                    LineError::LineNumber => "Line Number is not Last Line Number+1",
+                };
+                let mut buf = [0u8; 96];
+                let mut w = SliceWriter::new(&mut buf);
+                // The longest message and two numbers fit in the buffer.
+                let _ = write!(
+                    w,
+                    "Error:{}, Last Line: {}\nResend: {}\nok\n",
+                    message,
+                    self.last_line,
+                    self.last_line.wrapping_add(1)
+                );
+                let len = w.len();
+                return reply(out, &[&buf[..len]]).await;
+            }
+        };
+        if is_m110(command) {
+            if let Some(number) = command
+                .split_ascii_whitespace()
+                .skip(1)
+                .find_map(|p| p.strip_prefix(['N', 'n']))
+                .and_then(|n| n.parse().ok())
+            {
+                self.last_line = number;
+            }
+            return reply(out, &[b"ok\n"]).await;
+        }
+        let mut retries = 0;
+        loop {
+            match sink.send(command).await {
+                Ok(()) => return reply(out, &[b"ok\n"]).await,
+                Err(GcodeError::Busy) => {
+                    if retries % BUSY_MESSAGE_RETRIES == 0 {
+                        reply(out, &[b"echo:busy: processing\n"]).await?;
+                    }
+                    retries += 1;
+                    Timer::after(BUSY_RETRY).await;
+                }
+                Err(GcodeError::UnknownCommand) => {
+                    return reply(
+                        out,
+                        &[b"echo:Unknown command: \"", command.as_bytes(), b"\"\nok\n"],
+                    )
+                    .await;
+                }
+                Err(GcodeError::InvalidParameter) => {
+                    return reply(
+                        out,
+                        &[
+                            b"Error:Invalid parameter: \"",
+                            command.as_bytes(),
+                            b"\"\nok\n",
+                        ],
+                    )
+                    .await;
+                }
+            }
+        }
+    }
+
+    /// Read and answer lines until the host disconnects. `line_buffer` must hold the longest line, and longer lines
+    /// are answered with an error.
+    pub async fn handle_connection<S: Read + Write, G: GcodeSink>(
+        &mut self,
+        socket: &mut S,
+        sink: &mut G,
+        line_buffer: &mut [u8],
+    ) -> Result<(), ConsoleError> {
+        self.last_line = 0;
+        let mut len = 0;
+        let mut overflow = false;
+        let mut chunk = [0u8; 64];
+        loop {
+            let n = socket
+                .read(&mut chunk)
+                .await
+                .map_err(|_| ConsoleError::Socket)?;
+            if n == 0 {
+                return Ok(());
+            }
+            for &b in &chunk[..n] {
+                if b != b'\n' && b != b'\r' {
+                    match line_buffer.get_mut(len) {
+                        Some(slot) => {
+                            *slot = b;
+                            len += 1;
+                        }
+                        None => overflow = true,
+                    }
+                    continue;
+                }
+                if overflow {
+                    reply(socket, &[b"Error:Line too long\nok\n"]).await?;
+                } else if let Ok(line) = core::str::from_utf8(&line_buffer[..len]) {
+                    self.handle_line(line, sink, socket).await?;
+                } else {
+                    reply(socket, &[b"Error:Line is not UTF-8\nok\n"]).await?;
+                }
+                len = 0;
+                overflow = false;
+            }
+        }
+    }
+}
+
+/// Write the parts of a response and flush them to the host.
+async fn reply<W: Write>(out: &mut W, parts: &[&[u8]]) -> Result<(), ConsoleError> {
+    for part in parts {
+        out.write_all(part)
+            .await
+            .map_err(|_| ConsoleError::Socket)?;
+    }
+    out.flush().await.map_err(|_| ConsoleError::Socket)
+}
+
+/// Accept one host at a time on `port` and pass its G-code to `sink`.
+///
+/// ```sh
+/// nc 192.168.0.50 23
+/// ```
+pub async fn serve<G: GcodeSink>(
+    stack: Stack<'_>,
+    port: u16,
+    sink: &mut G,
+    rx_buffer: &mut [u8],
+    tx_buffer: &mut [u8],
+    line_buffer: &mut [u8],
+) -> ! {
+    let mut console = GcodeConsole::new();
+    loop {
+        let mut socket = TcpSocket::new(stack, rx_buffer, tx_buffer);
+        socket.set_keep_alive(Some(KEEP_ALIVE));
+        socket.set_timeout(Some(TIMEOUT));
+        if socket.accept(port).await.is_err() {
+            error!("[Console] Failed to accept a connection");
+            continue;
+        }
+        info!("[Console] Connected");
+        if let Err(e) = console
+            .handle_connection(&mut socket, sink, line_buffer)
+            .await
+        {
+            error!("[Console] {}", e);
+        }
+        socket.close();
+        let _ = socket.flush().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::MockSocket;
+
+    /// Records each command and answers with the scripted results, then `Ok`.
+    #[derive(Default)]
+    struct Sink {
+        lines: Vec<String>,
+        results: VecDeque<Result<(), GcodeError>>,
+    }
+
+    impl GcodeSink for Sink {
+        async fn send(&mut self, line: &str) -> Result<(), GcodeError> {
+            self.lines.push(line.to_string());
+            self.results.pop_front().unwrap_or(Ok(()))
+        }
+    }
+
+    fn run(console: &mut GcodeConsole, sink: &mut Sink, chunks: &[&[u8]]) -> MockSocket {
+        let mut socket = MockSocket::new(chunks);
+        let mut line_buffer = [0; 16];
+        block_on(console.handle_connection(&mut socket, sink, &mut line_buffer)).unwrap();
+        socket
+    }
+
+    fn session(chunks: &[&[u8]]) -> (String, Vec<String>) {
+        let mut sink = Sink::default();
+        let socket = run(&mut GcodeConsole::new(), &mut sink, chunks);
+        (socket.output_str().to_string(), sink.lines)
+    }
+
+    #[test]
+    fn checks_line_numbers_and_checksums() {
+        assert_eq!(check_line("G28", 0), Ok((None, "G28")));
+        assert_eq!(check_line("N1 G28*18", 0), Ok((Some(1), "G28")));
+        assert_eq!(check_line("N2 G1 X5*103", 1), Ok((Some(2), "G1 X5")));
+        assert_eq!(check_line("N1 G28*19", 0), Err(LineError::ChecksumMismatch));
+        assert_eq!(check_line("N1 G28*x", 0), Err(LineError::ChecksumMismatch));
+        assert_eq!(check_line("N1 G28", 0), Err(LineError::NoChecksum));
+        assert_eq!(check_line("G28*18", 0), Err(LineError::NoLineNumber));
+        assert_eq!(check_line("N3 G1 X5*102", 1), Err(LineError::LineNumber));
+        // M110 sets the line number, so it may carry any number.
+        assert_eq!(
+            check_line("N10 M110 N10*125", 0),
+            Ok((Some(10), "M110 N10"))
+        );
+    }
+
+    #[test]
+    fn assembles_lines_across_reads() {
+        let (output, lines) = session(&[b"G2", b"8\r\nG1 X5", b" Y5\n", b"M104 S215\r"]);
+        assert_eq!(lines, ["G28", "G1 X5 Y5", "M104 S215"]);
+        assert_eq!(output, "ok\nok\nok\n");
+        // A line without an ending is dropped when the host goes.
+        let (output, lines) = session(&[b"G28\nG1 X5"]);
+        assert_eq!(lines, ["G28"]);
+        assert_eq!(output, "ok\n");
+    }
+
+    #[test]
+    fn ignores_comments_and_blank_lines() {
+        let (output, lines) = session(&[b"; start\n\n   \nG28 ; home\r\n;\nG1 X5;move\n"]);
+        assert_eq!(lines, ["G28", "G1 X5"]);
+        assert_eq!(output, "ok\nok\n");
+    }
+
+    #[test]
+    fn answers_overlong_lines() {
+        let (output, lines) = session(&[b"G1 X100 Y100 Z10 E5\nG28\n"]);
+        assert_eq!(lines, ["G28"]);
+        assert_eq!(output, "Error:Line too long\nok\nok\n");
+        // A line exactly as long as the buffer is kept.
+        let (output, lines) = session(&[b"G1 X100 Y100 Z10\n"]);
+        assert_eq!(lines, ["G1 X100 Y100 Z10"]);
+        assert_eq!(output, "ok\n");
+        let (output, lines) = session(&[b"G1 X\xFF\nG28\n"]);
+        assert_eq!(lines, ["G28"]);
+        assert_eq!(output, "Error:Line is not UTF-8\nok\nok\n");
+    }
+
+    #[test]
+    fn asks_for_bad_lines_again() {
+        let (output, lines) = session(&[b"N1 G28*18\nN3 G1 X5*102\nN2 G1 X5*103\nN3 G28*19\n"]);
+        assert_eq!(lines, ["G28", "G1 X5"]);
+        assert_eq!(
+            output,
+            "ok\n\
+             Error:Line Number is not Last Line Number+1, Last Line: 1\nResend: 2\nok\n\
+             ok\n\
+             Error:checksum mismatch, Last Line: 2\nResend: 3\nok\n"
+        );
+    }
+
+    #[test]
+    fn sets_the_line_number() {
+        let mut console = GcodeConsole::new();
+        let mut sink = Sink::default();
+        let socket = run(
+            &mut console,
+            &mut sink,
+            &[b"N10 M110 N10*125\nN11 G1 X5*85\n"],
+        );
+        // M110 is answered by the console and not passed on.
+        assert_eq!(sink.lines, ["G1 X5"]);
+        assert_eq!(socket.output_str(), "ok\nok\n");
+        assert_eq!(console.last_line(), 11);
+
+        // Each host starts counting again.
+        let socket = run(&mut console, &mut sink, &[b"N1 G28*18\n"]);
+        assert_eq!(socket.output_str(), "ok\n");
+        assert_eq!(console.last_line(), 1);
+    }
+
+    #[test]
+    fn replies_like_marlin() {
+        let mut sink = Sink {
+            results: [
+                Err(GcodeError::UnknownCommand),
+                Err(GcodeError::InvalidParameter),
+                Err(GcodeError::Busy),
+                Ok(()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let socket = run(
+            &mut GcodeConsole::new(),
+            &mut sink,
+            &[b"G999\nM104 S-5\nG28\n"],
+        );
+        assert_eq!(
+            socket.output_str(),
+            "echo:Unknown command: \"G999\"\nok\n\
+             Error:Invalid parameter: \"M104 S-5\"\nok\n\
+             echo:busy: processing\nok\n"
+        );
+        // The busy command is offered again until it is taken.
+        assert_eq!(sink.lines, ["G999", "M104 S-5", "G28", "G28"]);
+        // Each reply is flushed so the host is not left waiting.
+        assert_eq!(socket.flushes, 4);
+    }
+}
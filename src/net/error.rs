@@ -0,0 +1,118 @@
+use thiserror::Error;
+
+/// The set of errors that may occur when writing or reading JSON.
+#[derive(Debug, Error, PartialEq, Eq, defmt::Format)]
+pub enum JsonError {
+    #[error("The buffer is too small to hold the JSON.")]
+    BufferFull,
+    #[error("The JSON is malformed.")]
+    Invalid,
+}
+
+/// The set of errors that may occur when running a G-code command.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum GcodeError {
+    #[error("The printer is busy.")]
+    Busy,
+    #[error("Unknown command.")]
+    UnknownCommand,
+    #[error("Invalid parameter.")]
+    InvalidParameter,
+}
+
+/// The set of errors that may occur while serving an HTTP request.
+#[derive(Debug, Error, PartialEq, Eq, defmt::Format)]
+pub enum HttpError {
+    #[error("The socket failed.")]
+    Socket,
+    #[error("The connection was closed before the request was complete.")]
+    ConnectionClosed,
+    #[error("The request is malformed.")]
+    BadRequest,
+    #[error("The request headers are too large for the buffer.")]
+    TooLarge,
+}
+
+/// The set of errors that may occur when storing an uploaded file.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum StorageError {
+    #[error("The file name cannot be stored.")]
+    InvalidName,
+    #[error("The file does not exist.")]
+    NotFound,
+    #[error("The storage is full.")]
+    Full,
+    #[error("No file is open for writing.")]
+    NotOpen,
+    #[error("The storage device failed.")]
+    Device,
+}
+
+/// The set of errors that may occur when talking to an MQTT broker.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum MqttError {
+    #[error("The socket failed.")]
+    Socket,
+    #[error("The broker closed the connection.")]
+    ConnectionClosed,
+    #[error("The packet is too large for the buffer.")]
+    BufferFull,
+    #[error("The packet is malformed.")]
+    Malformed,
+    #[error("The broker refused the connection. Code: {0}")]
+    Refused(u8),
+    #[error("The broker rejected the subscription.")]
+    SubscribeFailed,
+    #[error("The broker did not respond in time.")]
+    Timeout,
+}
+
+/// The set of errors that may occur while serving the G-code console.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ConsoleError {
+    #[error("The socket failed.")]
+    Socket,
+}
+
+/// The checks a numbered line of G-code can fail.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum LineError {
+    #[error("The line has a number but no checksum.")]
+    NoChecksum,
+    #[error("The line has a checksum but no number.")]
+    NoLineNumber,
+    #[error("The checksum does not match the line.")]
+    ChecksumMismatch,
+    #[error("The line number does not follow the last line.")]
+    LineNumber,
+}
+
+/// The set of errors that may occur when reading or writing a DNS message.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum DnsError {
+    #[error("The message is too large for the buffer.")]
+    BufferFull,
+    #[error("The message is malformed.")]
+    Malformed,
+    #[error("A name or TXT entry is too long.")]
+    InvalidName,
+}
+
+/// The set of errors that may occur when asking a server for the time.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SntpError {
+    #[error("The socket failed.")]
+    Socket,
+    #[error("The server did not respond in time.")]
+    Timeout,
+    #[error("The packet is too large for the buffer.")]
+    BufferFull,
+    #[error("The packet is malformed.")]
+    Malformed,
+    #[error("The response is not for the last request.")]
+    UnexpectedResponse,
+    #[error("The server is not synchronised.")]
+    Unsynchronised,
+    #[error("The server asked the client to stop.")]
+    Denied,
+}
@@ -0,0 +1,297 @@
+use embedded_sdmmc::{
+    Error, Mode, RawDirectory, RawFile, RawVolume, TimeSource, VolumeIdx, VolumeManager,
+};
+use embedded_storage_async::nor_flash::NorFlash;
+
+use crate::{components::flash::FlashBlockDevice, net::StorageError};
+
+/// Somewhere to store files uploaded over the network. One file is written at a time.
+///
+/// The methods are async but an implementation may still block, as [`FlashFileStore`] does.
+#[allow(async_fn_in_trait)]
+pub trait FileStore {
+    async fn exists(&mut self, name: &str) -> Result<bool, StorageError>;
+
+    /// Create a file, or truncate an existing one, and open it for writing.
+    async fn create(&mut self, name: &str) -> Result<(), StorageError>;
+
+    /// Append to the open file.
+    async fn write(&mut self, data: &[u8]) -> Result<(), StorageError>;
+
+    /// Close the open file.
+    async fn close(&mut self) -> Result<(), StorageError>;
+
+    /// Close and remove a partly written file.
+    async fn abort(&mut self, name: &str);
+
+    /// Return the name a file is stored under, which is the name to print it by. Stores that keep the name as given
+    /// return it unchanged.
+    fn stored_name<'b>(
+        &self,
+        name: &'b str,
+        _buf: &'b mut [u8; 12],
+    ) -> Result<&'b str, StorageError> {
+        Ok(name)
+    }
+}
+
+/// Turn a long file name into an 8.3 name for FAT, e.g. `Box v2.gcode` into `BOXV2.GCO`.
+///
+/// Characters that cannot be stored are dropped and the name and extension are cut to length, so different long names
+/// can give the same short name.
+pub fn short_name<'b>(name: &str, buf: &'b mut [u8; 12]) -> Result<&'b str, StorageError> {
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((base, ext)) if !base.is_empty() => (base, ext),
+        _ => (name, ""),
+    };
+    let valid = |c: &u8| c.is_ascii_alphanumeric() || matches!(c, b'_' | b'-' | b'~');
+    let mut len = 0;
+    for c in base.bytes().filter(valid).take(8) {
+        buf[len] = c.to_ascii_uppercase();
+        len += 1;
+    }
+    if len == 0 {
+        return Err(StorageError::InvalidName);
+    }
+    let mut ext = ext.bytes().filter(valid).take(3).peekable();
+    if ext.peek().is_some() {
+        buf[len] = b'.';
+        len += 1;
+        for c in ext {
+            buf[len] = c.to_ascii_uppercase();
+            len += 1;
+        }
+    }
+    // Only ASCII was written.
+    Ok(core::str::from_utf8(&buf[..len]).unwrap())
+}
+
+/// A [`FileStore`] keeping files in the root directory of the FAT volume on the flash, under their [`short_name`].
+///
+/// The FAT layer is blocking, so every method runs to completion on the [`FlashBlockDevice`] without yielding and a
+/// write or close can hold the executor for the hundreds of milliseconds a sector erase takes. Only use the store from
+/// an executor that does not run the heater or motion tasks.
+///
+/// ```rust,ignore
+/// let device = FlashBlockDevice::<_>::new(flash, FILESYSTEM_START, FLASH_CAPACITY - FILESYSTEM_START)?;
+/// let files = FlashFileStore::open(VolumeManager::new(device, FixedTimeSource))?;
+/// ```
+pub struct FlashFileStore<F: NorFlash, T: TimeSource> {
+    volume_mgr: VolumeManager<FlashBlockDevice<F>, T>,
+    volume: RawVolume,
+    root: RawDirectory,
+    file: Option<RawFile>,
+}
+
+impl<F: NorFlash, T: TimeSource> FlashFileStore<F, T> {
+    /// Open the first volume, which must already be formatted.
+    pub fn open(
+        mut volume_mgr: VolumeManager<FlashBlockDevice<F>, T>,
+    ) -> Result<Self, StorageError> {
+        let volume = volume_mgr
+            .open_raw_volume(VolumeIdx(0))
+            .map_err(storage_error)?;
+        let root = volume_mgr.open_root_dir(volume).map_err(storage_error)?;
+        Ok(Self {
+            volume_mgr,
+            volume,
+            root,
+            file: None,
+        })
+    }
+
+    /// Close the volume and return the volume manager, e.g. to read a job back.
+    pub fn release(mut self) -> Result<VolumeManager<FlashBlockDevice<F>, T>, StorageError> {
+        if let Some(file) = self.file.take() {
+            self.volume_mgr.close_file(file).map_err(storage_error)?;
+        }
+        self.volume_mgr
+            .close_dir(self.root)
+            .map_err(storage_error)?;
+        self.volume_mgr
+            .close_volume(self.volume)
+            .map_err(storage_error)?;
+        self.volume_mgr
+            .device()
+            .flush()
+            .map_err(|_| StorageError::Device)?;
+        Ok(self.volume_mgr)
+    }
+}
+
+impl<F: NorFlash, T: TimeSource> FileStore for FlashFileStore<F, T> {
+    async fn exists(&mut self, name: &str) -> Result<bool, StorageError> {
+        let mut buf = [0u8; 12];
+        let name = short_name(name, &mut buf)?;
+        match self.volume_mgr.find_directory_entry(self.root, name) {
+            Ok(_) => Ok(true),
+            Err(Error::NotFound) => Ok(false),
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+
+    async fn create(&mut self, name: &str) -> Result<(), StorageError> {
+        if let Some(file) = self.file.take() {
+            self.volume_mgr.close_file(file).map_err(storage_error)?;
+        }
+        let mut buf = [0u8; 12];
+        let name = short_name(name, &mut buf)?;
+        let file = self
+            .volume_mgr
+            .open_file_in_dir(self.root, name, Mode::ReadWriteCreateOrTruncate)
+            .map_err(storage_error)?;
+        self.file = Some(file);
+        Ok(())
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), StorageError> {
+        let file = self.file.ok_or(StorageError::NotOpen)?;
+        self.volume_mgr.write(file, data).map_err(storage_error)
+    }
+
+    async fn close(&mut self) -> Result<(), StorageError> {
+        let file = self.file.take().ok_or(StorageError::NotOpen)?;
+        self.volume_mgr.close_file(file).map_err(storage_error)?;
+        self.volume_mgr
+            .device()
+            .flush()
+            .map_err(|_| StorageError::Device)
+    }
+
+    async fn abort(&mut self, name: &str) {
+        if let Some(file) = self.file.take() {
+            let _ = self.volume_mgr.close_file(file);
+        }
+        let mut buf = [0u8; 12];
+        if let Ok(name) = short_name(name, &mut buf) {
+            let _ = self.volume_mgr.delete_file_in_dir(self.root, name);
+        }
+        let _ = self.volume_mgr.device().flush();
+    }
+
+    fn stored_name<'b>(
+        &self,
+        name: &'b str,
+        buf: &'b mut [u8; 12],
+    ) -> Result<&'b str, StorageError> {
+        short_name(name, buf)
+    }
+}
+
+fn storage_error<E: core::fmt::Debug>(e: Error<E>) -> StorageError {
+    match e {
+        Error::NotFound => StorageError::NotFound,
+        Error::FilenameError(_) => StorageError::InvalidName,
+        Error::NotEnoughSpace | Error::DiskFull => StorageError::Full,
+        _ => StorageError::Device,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use embedded_sdmmc::BlockDevice;
+
+    use super::*;
+    use crate::{
+        components::flash::{FixedTimeSource, MIN_FILESYSTEM_SIZE, SECTOR_SIZE},
+        mock::RamFlash,
+    };
+
+    const SECTOR: usize = SECTOR_SIZE as usize;
+    type Flash = RamFlash<{ 1024 * SECTOR }, SECTOR>;
+
+    fn store(flash: &mut Flash) -> FlashFileStore<&mut Flash, FixedTimeSource> {
+        let device: FlashBlockDevice<_> =
+            FlashBlockDevice::new(flash, 0, MIN_FILESYSTEM_SIZE).unwrap();
+        device.format().unwrap();
+        FlashFileStore::open(VolumeManager::new(device, FixedTimeSource)).unwrap()
+    }
+
+    fn read<D: BlockDevice>(
+        volume_mgr: &mut VolumeManager<D, FixedTimeSource>,
+        name: &str,
+    ) -> Vec<u8>
+    where
+        D::Error: core::fmt::Debug,
+    {
+        let mut volume = volume_mgr.open_volume(VolumeIdx(0)).unwrap();
+        let mut root = volume.open_root_dir().unwrap();
+        let mut file = root.open_file_in_dir(name, Mode::ReadOnly).unwrap();
+        let mut data = vec![0; file.length() as usize];
+        assert_eq!(file.read(&mut data).unwrap(), data.len());
+        data
+    }
+
+    #[test]
+    fn shortens_names() {
+        let mut buf = [0; 12];
+        assert_eq!(short_name("box.gcode", &mut buf).unwrap(), "BOX.GCO");
+        assert_eq!(short_name("Box v2.gcode", &mut buf).unwrap(), "BOXV2.GCO");
+        assert_eq!(
+            short_name("a_very-long~name.bgcode", &mut buf).unwrap(),
+            "A_VERY-L.BGC"
+        );
+        assert_eq!(short_name("README", &mut buf).unwrap(), "README");
+        assert_eq!(short_name("box.", &mut buf).unwrap(), "BOX");
+        assert_eq!(short_name("a.b.gcode", &mut buf).unwrap(), "AB.GCO");
+        // A leading dot is part of the name, not an extension.
+        assert_eq!(short_name(".gcode", &mut buf).unwrap(), "GCODE");
+        assert_eq!(short_name("café.gcode", &mut buf).unwrap(), "CAF.GCO");
+        for name in ["", "...", "é.gcode", "  .txt"] {
+            assert_eq!(
+                short_name(name, &mut buf),
+                Err(StorageError::InvalidName),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn stores_files_under_their_short_name() {
+        let mut flash = Flash::new();
+        let mut files = store(&mut flash);
+        let job = "G1 X10 Y10 E0.5\n".repeat(100);
+        block_on(async {
+            assert_eq!(files.exists("Box v2.gcode").await, Ok(false));
+            files.create("Box v2.gcode").await.unwrap();
+            for chunk in job.as_bytes().chunks(300) {
+                files.write(chunk).await.unwrap();
+            }
+            files.close().await.unwrap();
+            assert_eq!(files.exists("box v2.gcode").await, Ok(true));
+            assert_eq!(files.exists("BOXV2.GCO").await, Ok(true));
+
+            // Creating it again truncates it.
+            files.create("boxv2.gco").await.unwrap();
+            files.write(b"G28\n").await.unwrap();
+            files.close().await.unwrap();
+        });
+        let mut volume_mgr = files.release().unwrap();
+        assert_eq!(read(&mut volume_mgr, "BOXV2.GCO"), b"G28\n");
+    }
+
+    #[test]
+    fn aborting_removes_the_file() {
+        let mut flash = Flash::new();
+        let mut files = store(&mut flash);
+        block_on(async {
+            files.create("cut.gcode").await.unwrap();
+            files.write(b"G28\n").await.unwrap();
+            files.abort("cut.gcode").await;
+            assert_eq!(files.exists("cut.gcode").await, Ok(false));
+            assert_eq!(files.write(b"G28\n").await, Err(StorageError::NotOpen));
+            assert_eq!(files.close().await, Err(StorageError::NotOpen));
+        });
+    }
+
+    #[test]
+    fn rejects_names_that_cannot_be_stored() {
+        let mut flash = Flash::new();
+        let mut files = store(&mut flash);
+        block_on(async {
+            assert_eq!(files.exists("...").await, Err(StorageError::InvalidName));
+            assert_eq!(files.create("é").await, Err(StorageError::InvalidName));
+        });
+    }
+}
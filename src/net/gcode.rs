@@ -0,0 +1,34 @@
+use crate::net::GcodeError;
+
+/// Somewhere to send G-code received over the network, e.g. the queue of the print controller.
+#[allow(async_fn_in_trait)]
+pub trait GcodeSink {
+    /// Run or queue a single command. The line has had its comments and surrounding whitespace removed.
+    async fn send(&mut self, line: &str) -> Result<(), GcodeError>;
+}
+
+/// Strip the comment and surrounding whitespace from a line of G-code, returning `None` if nothing is left.
+pub fn clean_line(line: &str) -> Option<&str> {
+    let line = match line.find(';') {
+        Some(i) => &line[..i],
+        None => line,
+    };
+    let line = line.trim();
+    if line.is_empty() { None } else { Some(line) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_comments_and_whitespace() {
+        assert_eq!(clean_line("G28"), Some("G28"));
+        assert_eq!(clean_line("  G1 X5 Y5\t\r"), Some("G1 X5 Y5"));
+        assert_eq!(clean_line("G1 X5 ; move ; twice"), Some("G1 X5"));
+        assert_eq!(clean_line("M117 Hi;"), Some("M117 Hi"));
+        assert_eq!(clean_line("; only a comment"), None);
+        assert_eq!(clean_line("   ;"), None);
+        assert_eq!(clean_line(""), None);
+    }
+}
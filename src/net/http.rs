@@ -0,0 +1,624 @@
+use core::fmt::Write as _;
+
+use embassy_net::{Stack, tcp::TcpSocket};
+use embassy_time::Duration;
+use embedded_io_async::{Read, Write};
+
+use crate::{
+    fmt::error,
+    net::{HttpError, SliceWriter},
+};
+
+/// How long a client may leave the connection idle before it is dropped.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Options,
+    Other,
+}
+
+impl Method {
+    fn parse(s: &str) -> Self {
+        match s {
+            "GET" => Self::Get,
+            "HEAD" => Self::Head,
+            "POST" => Self::Post,
+            "PUT" => Self::Put,
+            "DELETE" => Self::Delete,
+            "OPTIONS" => Self::Options,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// An HTTP request held in the request buffer.
+#[derive(Debug)]
+pub struct Request<'a> {
+    pub method: Method,
+    /// The path without the query string.
+    pub path: &'a str,
+    pub query: Option<&'a str>,
+    headers: &'a str,
+    /// The part of the body that fits in the request buffer. See [`Request::is_complete`].
+    pub body: &'a [u8],
+    pub content_length: usize,
+}
+
+impl<'a> Request<'a> {
+    /// Parse a request from the request line, the headers and as much of the body as has been read.
+    pub fn parse(buf: &'a [u8]) -> Result<Self, HttpError> {
+        let end = head_end(buf).ok_or(HttpError::BadRequest)?;
+        let head = core::str::from_utf8(&buf[..end]).map_err(|_| HttpError::BadRequest)?;
+        let (line, headers) = head.split_once("\r\n").ok_or(HttpError::BadRequest)?;
+        let mut parts = line.split(' ');
+        let method = Method::parse(parts.next().ok_or(HttpError::BadRequest)?);
+        let target = parts.next().ok_or(HttpError::BadRequest)?;
+        if !parts.next().is_some_and(|v| v.starts_with("HTTP/1.")) {
+            return Err(HttpError::BadRequest);
+        }
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (target, None),
+        };
+        let mut request = Self {
+            method,
+            path,
+            query,
+            headers,
+            body: &[],
+            content_length: 0,
+        };
+        request.content_length = match request.header("Content-Length") {
+            Some(len) => len.parse().map_err(|_| HttpError::BadRequest)?,
+            None => 0,
+        };
+        let body = &buf[end..];
+        request.body = &body[..body.len().min(request.content_length)];
+        Ok(request)
+    }
+
+    /// Return the value of a header. Names are matched ignoring case.
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        self.headers
+            .split("\r\n")
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+    }
+
+    /// Return whether the whole body fits in the request buffer. Larger bodies are read from the [`Body`].
+    pub fn is_complete(&self) -> bool {
+        self.body.len() == self.content_length
+    }
+
+    /// Return whether the body is declared as JSON.
+    pub fn is_json(&self) -> bool {
+        self.header("Content-Type")
+            .is_some_and(|t| t.starts_with("application/json"))
+    }
+
+    pub fn body_str(&self) -> Result<&'a str, HttpError> {
+        core::str::from_utf8(self.body).map_err(|_| HttpError::BadRequest)
+    }
+}
+
+/// The body of a request, starting with the part held in the request buffer and continuing from the socket.
+pub struct Body<'a, R> {
+    buffered: &'a [u8],
+    reader: &'a mut R,
+    /// The bytes still to come from the socket.
+    unread: usize,
+}
+
+impl<'a, R: Read> Body<'a, R> {
+    pub fn new(request: &Request<'a>, reader: &'a mut R) -> Self {
+        Self {
+            buffered: request.body,
+            reader,
+            unread: request.content_length - request.body.len(),
+        }
+    }
+
+    /// Return the number of bytes left in the body.
+    pub fn remaining(&self) -> usize {
+        self.buffered.len() + self.unread
+    }
+
+    /// Read the next part of the body, returning 0 at the end.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, HttpError> {
+        if !self.buffered.is_empty() {
+            let n = buf.len().min(self.buffered.len());
+            buf[..n].copy_from_slice(&self.buffered[..n]);
+            self.buffered = &self.buffered[n..];
+            return Ok(n);
+        }
+        if self.unread == 0 {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.unread);
+        let n = read_some(self.reader, &mut buf[..len], 0).await?;
+        self.unread -= n;
+        Ok(n)
+    }
+}
+
+/// Decode the `%XX` escapes in a path segment.
+pub fn percent_decode<'b>(s: &str, buf: &'b mut [u8]) -> Result<&'b str, HttpError> {
+    let bytes = s.as_bytes();
+    let mut out = SliceWriter::new(buf);
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3).ok_or(HttpError::BadRequest)?;
+            i += 3;
+            u8::from_str_radix(hex, 16).map_err(|_| HttpError::BadRequest)?
+        } else {
+            i += 1;
+            bytes[i - 1]
+        };
+        out.write_bytes(&[b]).map_err(|_| HttpError::TooLarge)?;
+    }
+    let len = out.len();
+    core::str::from_utf8(&buf[..len]).map_err(|_| HttpError::BadRequest)
+}
+
+/// Return the index of the first byte after the blank line ending the headers.
+fn head_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|w| w == b"\r\n\r\n").map(|i| i + 4)
+}
+
+/// The response status codes used by the crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Status {
+    Ok,
+    Created,
+    NoContent,
+    BadRequest,
+    Unauthorized,
+    NotFound,
+    MethodNotAllowed,
+    Conflict,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    InternalServerError,
+    ServiceUnavailable,
+    InsufficientStorage,
+}
+
+impl Status {
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::Ok => 200,
+            Self::Created => 201,
+            Self::NoContent => 204,
+            Self::BadRequest => 400,
+            Self::Unauthorized => 401,
+            Self::NotFound => 404,
+            Self::MethodNotAllowed => 405,
+            Self::Conflict => 409,
+            Self::PayloadTooLarge => 413,
+            Self::UnsupportedMediaType => 415,
+            Self::InternalServerError => 500,
+            Self::ServiceUnavailable => 503,
+            Self::InsufficientStorage => 507,
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Ok => "OK",
+            Self::Created => "Created",
+            Self::NoContent => "No Content",
+            Self::BadRequest => "Bad Request",
+            Self::Unauthorized => "Unauthorized",
+            Self::NotFound => "Not Found",
+            Self::MethodNotAllowed => "Method Not Allowed",
+            Self::Conflict => "Conflict",
+            Self::PayloadTooLarge => "Payload Too Large",
+            Self::UnsupportedMediaType => "Unsupported Media Type",
+            Self::InternalServerError => "Internal Server Error",
+            Self::ServiceUnavailable => "Service Unavailable",
+            Self::InsufficientStorage => "Insufficient Storage",
+        }
+    }
+}
+
+/// The status and content type of a response whose body has been written to the start of the response buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Response {
+    pub status: Status,
+    pub content_type: Option<&'static str>,
+    /// The length of the body in the response buffer.
+    pub len: usize,
+}
+
+impl Response {
+    pub fn empty(status: Status) -> Self {
+        Self {
+            status,
+            content_type: None,
+            len: 0,
+        }
+    }
+
+    pub fn json(status: Status, len: usize) -> Self {
+        Self {
+            status,
+            content_type: Some("application/json"),
+            len,
+        }
+    }
+
+    pub fn text(status: Status, len: usize) -> Self {
+        Self {
+            status,
+            content_type: Some("text/plain; charset=utf-8"),
+            len,
+        }
+    }
+}
+
+/// Turns a request into a response.
+#[allow(async_fn_in_trait)]
+pub trait Handler {
+    /// Handle a request, writing the response body to the start of `response`. Bodies larger than the request
+    /// buffer are read from `body`.
+    async fn handle<R: Read>(
+        &mut self,
+        request: &Request<'_>,
+        body: &mut Body<'_, R>,
+        response: &mut [u8],
+    ) -> Response;
+}
+
+/// Read the head of a request and as much of the body as fits into the buffer.
+///
+/// A client that sent `Expect: 100-continue` is told to continue once the head has been read.
+pub async fn read_request<'b, S: Read + Write>(
+    socket: &mut S,
+    buf: &'b mut [u8],
+) -> Result<Request<'b>, HttpError> {
+    let mut len = 0;
+    let head = loop {
+        if let Some(end) = head_end(&buf[..len]) {
+            break end;
+        }
+        len += read_some(socket, buf, len).await?;
+    };
+    let request = Request::parse(&buf[..head])?;
+    // The length comes from the client, so it may be anything up to `usize::MAX`.
+    let total = head.saturating_add(request.content_length).min(buf.len());
+    if len < total
+        && request
+            .header("Expect")
+            .is_some_and(|v| v.eq_ignore_ascii_case("100-continue"))
+    {
+        socket
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .await
+            .map_err(|_| HttpError::Socket)?;
+        socket.flush().await.map_err(|_| HttpError::Socket)?;
+    }
+    while len < total {
+        len += read_some(socket, buf, len).await?;
+    }
+    Request::parse(&buf[..total])
+}
+
+async fn read_some<R: Read>(
+    reader: &mut R,
+    buf: &mut [u8],
+    len: usize,
+) -> Result<usize, HttpError> {
+    if len == buf.len() {
+        return Err(HttpError::TooLarge);
+    }
+    match reader.read(&mut buf[len..]).await {
+        Ok(0) => Err(HttpError::ConnectionClosed),
+        Ok(n) => Ok(n),
+        Err(_) => Err(HttpError::Socket),
+    }
+}
+
+/// Write the status line, headers and body of a response. The connection is closed after each response.
+pub async fn write_response<W: Write>(
+    writer: &mut W,
+    response: &Response,
+    body: &[u8],
+) -> Result<(), HttpError> {
+    write_head(writer, response).await?;
+    writer
+        .write_all(&body[..response.len])
+        .await
+        .map_err(|_| HttpError::Socket)?;
+    writer.flush().await.map_err(|_| HttpError::Socket)
+}
+
+async fn write_head<W: Write>(writer: &mut W, response: &Response) -> Result<(), HttpError> {
+    let mut head = [0u8; 160];
+    let mut out = SliceWriter::new(&mut head);
+    let _ = write!(
+        out,
+        "HTTP/1.1 {} {}\r\n",
+        response.status.code(),
+        response.status.reason()
+    );
+    if let Some(content_type) = response.content_type {
+        let _ = write!(out, "Content-Type: {content_type}\r\n");
+    }
+    let _ = write!(
+        out,
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.len
+    );
+    writer
+        .write_all(out.as_bytes())
+        .await
+        .map_err(|_| HttpError::Socket)
+}
+
+/// Serve a single request on a connection. Malformed and oversized requests are answered with an error status.
+pub async fn handle_connection<S: Read + Write, H: Handler>(
+    socket: &mut S,
+    handler: &mut H,
+    request_buffer: &mut [u8],
+    response_buffer: &mut [u8],
+) -> Result<(), HttpError> {
+    let response = match read_request(socket, request_buffer).await {
+        Ok(request) => {
+            let mut body = Body::new(&request, socket);
+            let response = handler.handle(&request, &mut body, response_buffer).await;
+            if request.method == Method::Head {
+                // The headers describe the body that a GET would have returned.
+                write_head(socket, &response).await?;
+                return socket.flush().await.map_err(|_| HttpError::Socket);
+            }
+            response
+        }
+        Err(HttpError::BadRequest) => Response::empty(Status::BadRequest),
+        Err(HttpError::TooLarge) => Response::empty(Status::PayloadTooLarge),
+        Err(e) => return Err(e),
+    };
+    write_response(socket, &response, response_buffer).await
+}
+
+/// Accept connections on a port, one at a time, and pass each request to the handler.
+pub async fn serve<H: Handler>(
+    stack: Stack<'_>,
+    port: u16,
+    handler: &mut H,
+    rx_buffer: &mut [u8],
+    tx_buffer: &mut [u8],
+    request_buffer: &mut [u8],
+    response_buffer: &mut [u8],
+) -> ! {
+    loop {
+        let mut socket = TcpSocket::new(stack, rx_buffer, tx_buffer);
+        socket.set_timeout(Some(TIMEOUT));
+        if socket.accept(port).await.is_err() {
+            error!("[HTTP] Failed to accept a connection");
+            continue;
+        }
+        if let Err(e) =
+            handle_connection(&mut socket, handler, request_buffer, response_buffer).await
+        {
+            error!("[HTTP] {}", e);
+        }
+        socket.close();
+        let _ = socket.flush().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::MockSocket;
+
+    /// Replies to every request with its path and the body it read.
+    struct Echo;
+
+    impl Handler for Echo {
+        async fn handle<R: Read>(
+            &mut self,
+            request: &Request<'_>,
+            body: &mut Body<'_, R>,
+            response: &mut [u8],
+        ) -> Response {
+            let mut out = SliceWriter::new(response);
+            let _ = write!(out, "{} ", request.path);
+            let mut chunk = [0u8; 4];
+            loop {
+                match body.read(&mut chunk).await {
+                    Ok(0) => break,
+                    Ok(n) => out.write_bytes(&chunk[..n]).unwrap(),
+                    Err(_) => return Response::empty(Status::BadRequest),
+                }
+            }
+            Response::text(Status::Ok, out.len())
+        }
+    }
+
+    fn serve(chunks: &[&[u8]], request_buffer: usize) -> String {
+        let mut socket = MockSocket::new(chunks);
+        let mut request = vec![0; request_buffer];
+        let mut response = [0; 128];
+        block_on(handle_connection(
+            &mut socket,
+            &mut Echo,
+            &mut request,
+            &mut response,
+        ))
+        .unwrap();
+        socket.output_str().to_string()
+    }
+
+    #[test]
+    fn parses_the_request_line_headers_and_body() {
+        let request = Request::parse(
+            b"POST /api/gcode?dry=1 HTTP/1.1\r\nHost: buddy\r\ncontent-type: application/json\r\nContent-Length: 4\r\n\r\n{}\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.method, Method::Post);
+        assert_eq!(request.path, "/api/gcode");
+        assert_eq!(request.query, Some("dry=1"));
+        assert_eq!(request.header("HOST"), Some("buddy"));
+        assert_eq!(request.header("Accept"), None);
+        assert!(request.is_json());
+        assert_eq!(request.body, b"{}\r\n");
+        assert!(request.is_complete());
+    }
+
+    #[test]
+    fn parses_a_partial_body() {
+        let request = Request::parse(b"PUT /f HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc").unwrap();
+        assert_eq!(request.content_length, 10);
+        assert_eq!(request.body, b"abc");
+        assert!(!request.is_complete());
+        // Bytes past the declared length belong to no one.
+        let request = Request::parse(b"PUT /f HTTP/1.1\r\nContent-Length: 2\r\n\r\nabc").unwrap();
+        assert_eq!(request.body, b"ab");
+    }
+
+    #[test]
+    fn rejects_partial_and_malformed_heads() {
+        for head in [
+            &b"GET / HTTP/1.1\r\nHost: buddy\r\n"[..],
+            b"GET / HTTP/1.1\r\n",
+            b"\r\n\r\n",
+            b"GET /\r\n\r\n",
+            b"GET / SPDY/3\r\n\r\n",
+            b"GET / HTTP/1.1\r\nContent-Length: ten\r\n\r\n",
+            b"GET / HTTP/1.1\r\nContent-Length: -1\r\n\r\n",
+            b"GET /\xff HTTP/1.1\r\n\r\n",
+        ] {
+            assert_eq!(
+                Request::parse(head).unwrap_err(),
+                HttpError::BadRequest,
+                "{head:?}"
+            );
+        }
+        assert_eq!(
+            Request::parse(b"BREW /pot HTTP/1.1\r\n\r\n")
+                .unwrap()
+                .method,
+            Method::Other
+        );
+    }
+
+    #[test]
+    fn reads_a_request_arriving_in_pieces() {
+        let mut socket = MockSocket::new(&[
+            b"POST /a HTTP/1.1\r\nCont",
+            b"ent-Length: 5\r\n",
+            b"\r\nhel",
+            b"lo",
+        ]);
+        let mut buf = [0; 128];
+        let request = block_on(read_request(&mut socket, &mut buf)).unwrap();
+        assert_eq!(request.body, b"hello");
+        assert!(socket.output.is_empty());
+    }
+
+    #[test]
+    fn does_not_overflow_on_a_huge_content_length() {
+        let head = format!(
+            "POST /a HTTP/1.1\r\nContent-Length: {}\r\n\r\nabc",
+            usize::MAX
+        );
+        let mut socket = MockSocket::new(&[head.as_bytes()]);
+        let mut buf = [0; 128];
+        // The buffer is filled with what the client sends until it gives up.
+        assert_eq!(
+            block_on(read_request(&mut socket, &mut buf)).unwrap_err(),
+            HttpError::ConnectionClosed
+        );
+
+        let mut socket = MockSocket::new(&[head.as_bytes(), &[b'x'; 200]]);
+        let request = block_on(read_request(&mut socket, &mut buf)).unwrap();
+        assert_eq!(request.content_length, usize::MAX);
+        assert!(!request.is_complete());
+    }
+
+    #[test]
+    fn tells_the_client_to_continue() {
+        let mut socket = MockSocket::new(&[
+            b"PUT /f HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 2\r\n\r\n",
+            b"ok",
+        ]);
+        let mut buf = [0; 128];
+        let request = block_on(read_request(&mut socket, &mut buf)).unwrap();
+        assert_eq!(request.body, b"ok");
+        assert_eq!(socket.output_str(), "HTTP/1.1 100 Continue\r\n\r\n");
+    }
+
+    #[test]
+    fn streams_bodies_larger_than_the_buffer() {
+        let body = "0123456789".repeat(5);
+        let head = format!("PUT /f HTTP/1.1\r\nContent-Length: {}\r\n\r\n", body.len());
+        let response = serve(&[head.as_bytes(), body.as_bytes()], 64);
+        assert!(
+            response.ends_with(&format!("\r\n\r\n/f {body}")),
+            "{response}"
+        );
+    }
+
+    #[test]
+    fn answers_oversized_and_malformed_requests() {
+        let response = serve(
+            &[b"GET /a-very-long-path HTTP/1.1\r\nHost: buddy\r\n\r\n"],
+            32,
+        );
+        assert_eq!(
+            response,
+            "HTTP/1.1 413 Payload Too Large\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+        let response = serve(&[b"GET /\r\n\r\n"], 64);
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn writes_the_response() {
+        let response = serve(&[b"GET /hi HTTP/1.1\r\n\r\n"], 64);
+        assert_eq!(
+            response,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 4\r\nConnection: close\r\n\r\n/hi "
+        );
+        // HEAD describes the body without sending it.
+        let response = serve(&[b"HEAD /hi HTTP/1.1\r\n\r\n"], 64);
+        assert!(response.ends_with("Content-Length: 4\r\nConnection: close\r\n\r\n"));
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        let mut buf = [0; 16];
+        assert_eq!(
+            percent_decode("benchy%20v2.gcode", &mut buf).unwrap(),
+            "benchy v2.gcode"
+        );
+        assert_eq!(percent_decode("%C3%A9", &mut buf).unwrap(), "é");
+        assert_eq!(
+            percent_decode("%2", &mut buf).unwrap_err(),
+            HttpError::BadRequest
+        );
+        assert_eq!(
+            percent_decode("%zz", &mut buf).unwrap_err(),
+            HttpError::BadRequest
+        );
+        assert_eq!(
+            percent_decode("%ff", &mut buf).unwrap_err(),
+            HttpError::BadRequest
+        );
+        assert_eq!(
+            percent_decode("a-name-that-is-too-long", &mut buf).unwrap_err(),
+            HttpError::TooLarge
+        );
+    }
+}
@@ -0,0 +1,623 @@
+use core::fmt::Write;
+
+use crate::net::JsonError;
+
+/// A `core::fmt::Write` into a fixed buffer. Writes that do not fit fail and leave the buffer unchanged.
+pub struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SliceWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// Return the number of bytes written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return the bytes written.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> core::fmt::Result {
+        let end = self.len + bytes.len();
+        if end > self.buf.len() {
+            return Err(core::fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+}
+
+impl Write for SliceWriter<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_bytes(s.as_bytes())
+    }
+}
+
+/// Writes JSON into a fixed buffer without allocating.
+///
+/// Commas are placed automatically, and the first write that does not fit is remembered and returned by
+/// [`JsonWriter::finish`] so calls can be chained without checking each one.
+///
+/// ```rust,ignore
+/// let mut json = JsonWriter::new(&mut buf);
+/// json.begin_object().field_f64("target", 215.0, 1).field_bool("on", true).end_object();
+/// let len = json.finish()?;
+/// ```
+pub struct JsonWriter<'a> {
+    out: SliceWriter<'a>,
+    needs_comma: bool,
+    full: bool,
+}
+
+impl<'a> JsonWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            out: SliceWriter::new(buf),
+            needs_comma: false,
+            full: false,
+        }
+    }
+
+    /// Return the number of bytes written, or an error if any write did not fit.
+    pub fn finish(self) -> Result<usize, JsonError> {
+        if self.full {
+            Err(JsonError::BufferFull)
+        } else {
+            Ok(self.out.len())
+        }
+    }
+
+    pub fn begin_object(&mut self) -> &mut Self {
+        self.separate();
+        self.raw("{");
+        self.needs_comma = false;
+        self
+    }
+
+    pub fn end_object(&mut self) -> &mut Self {
+        self.raw("}");
+        self.needs_comma = true;
+        self
+    }
+
+    pub fn begin_array(&mut self) -> &mut Self {
+        self.separate();
+        self.raw("[");
+        self.needs_comma = false;
+        self
+    }
+
+    pub fn end_array(&mut self) -> &mut Self {
+        self.raw("]");
+        self.needs_comma = true;
+        self
+    }
+
+    /// Write the key of the next value in an object.
+    pub fn key(&mut self, key: &str) -> &mut Self {
+        self.separate();
+        self.escaped(key);
+        self.raw(":");
+        self.needs_comma = false;
+        self
+    }
+
+    pub fn str(&mut self, value: &str) -> &mut Self {
+        self.separate();
+        self.escaped(value);
+        self.needs_comma = true;
+        self
+    }
+
+    /// Write a string built from format arguments.
+    pub fn fmt_str(&mut self, args: core::fmt::Arguments) -> &mut Self {
+        self.separate();
+        self.raw("\"");
+        if !self.full && Escape(self).write_fmt(args).is_err() {
+            self.full = true;
+        }
+        self.raw("\"");
+        self.needs_comma = true;
+        self
+    }
+
+    /// Write a number with a fixed number of decimal places. NaN and infinity are written as `null`.
+    pub fn f64(&mut self, value: f64, decimals: usize) -> &mut Self {
+        self.separate();
+        if value.is_finite() {
+            self.fmt(format_args!("{value:.decimals$}"));
+        } else {
+            self.raw("null");
+        }
+        self.needs_comma = true;
+        self
+    }
+
+    pub fn i64(&mut self, value: i64) -> &mut Self {
+        self.separate();
+        self.fmt(format_args!("{value}"));
+        self.needs_comma = true;
+        self
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.separate();
+        self.fmt(format_args!("{value}"));
+        self.needs_comma = true;
+        self
+    }
+
+    pub fn bool(&mut self, value: bool) -> &mut Self {
+        self.separate();
+        self.raw(if value { "true" } else { "false" });
+        self.needs_comma = true;
+        self
+    }
+
+    pub fn null(&mut self) -> &mut Self {
+        self.separate();
+        self.raw("null");
+        self.needs_comma = true;
+        self
+    }
+
+    pub fn field_str(&mut self, key: &str, value: &str) -> &mut Self {
+        self.key(key).str(value)
+    }
+
+    pub fn field_f64(&mut self, key: &str, value: f64, decimals: usize) -> &mut Self {
+        self.key(key).f64(value, decimals)
+    }
+
+    pub fn field_i64(&mut self, key: &str, value: i64) -> &mut Self {
+        self.key(key).i64(value)
+    }
+
+    pub fn field_u64(&mut self, key: &str, value: u64) -> &mut Self {
+        self.key(key).u64(value)
+    }
+
+    pub fn field_bool(&mut self, key: &str, value: bool) -> &mut Self {
+        self.key(key).bool(value)
+    }
+
+    pub fn field_null(&mut self, key: &str) -> &mut Self {
+        self.key(key).null()
+    }
+
+    fn separate(&mut self) {
+        if self.needs_comma {
+            self.raw(",");
+        }
+    }
+
+    fn raw(&mut self, s: &str) {
+        if !self.full && self.out.write_str(s).is_err() {
+            self.full = true;
+        }
+    }
+
+    fn fmt(&mut self, args: core::fmt::Arguments) {
+        if !self.full && self.out.write_fmt(args).is_err() {
+            self.full = true;
+        }
+    }
+
+    fn escaped(&mut self, s: &str) {
+        self.raw("\"");
+        self.escape(s);
+        self.raw("\"");
+    }
+
+    fn escape(&mut self, s: &str) {
+        let mut start = 0;
+        for (i, c) in s.char_indices() {
+            let escape = match c {
+                '"' => "\\\"",
+                '\\' => "\\\\",
+                '\n' => "\\n",
+                '\r' => "\\r",
+                '\t' => "\\t",
+                c if (c as u32) < 0x20 => "",
+                _ => continue,
+            };
+            self.raw(&s[start..i]);
+            if escape.is_empty() {
+                self.fmt(format_args!("\\u{:04x}", c as u32));
+            } else {
+                self.raw(escape);
+            }
+            start = i + c.len_utf8();
+        }
+        self.raw(&s[start..]);
+    }
+}
+
+/// Escapes formatted text written into a JSON string.
+struct Escape<'w, 'a>(&'w mut JsonWriter<'a>);
+
+impl Write for Escape<'_, '_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0.escape(s);
+        if self.0.full {
+            Err(core::fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A value read from the top level of a JSON object. Nested objects and arrays are skipped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JsonValue<'a> {
+    Str(JsonStr<'a>),
+    Number(f64),
+    Bool(bool),
+    Null,
+    /// An object or array.
+    Nested,
+}
+
+/// A JSON string as it appears in the source, with any escapes still in place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JsonStr<'a>(&'a str);
+
+impl<'a> JsonStr<'a> {
+    /// Return the string as written in the source.
+    pub fn raw(&self) -> &'a str {
+        self.0
+    }
+
+    /// Return the string if it has no escapes.
+    pub fn as_str(&self) -> Option<&'a str> {
+        if self.0.contains('\\') {
+            None
+        } else {
+            Some(self.0)
+        }
+    }
+
+    /// Decode the escapes into a buffer.
+    pub fn unescape<'b>(&self, buf: &'b mut [u8]) -> Result<&'b str, JsonError> {
+        let mut out = SliceWriter::new(buf);
+        let mut chars = self.0.chars();
+        while let Some(c) = chars.next() {
+            let c = if c == '\\' {
+                match chars.next().ok_or(JsonError::Invalid)? {
+                    '"' => '"',
+                    '\\' => '\\',
+                    '/' => '/',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'u' => {
+                        let hex = chars.as_str().get(..4).ok_or(JsonError::Invalid)?;
+                        let code = u32::from_str_radix(hex, 16).map_err(|_| JsonError::Invalid)?;
+                        chars = chars.as_str()[4..].chars();
+                        // Surrogate pairs are not supported.
+                        char::from_u32(code).ok_or(JsonError::Invalid)?
+                    }
+                    _ => return Err(JsonError::Invalid),
+                }
+            } else {
+                c
+            };
+            out.write_char(c).map_err(|_| JsonError::BufferFull)?;
+        }
+        let len = out.len();
+        // Only whole characters were written.
+        Ok(core::str::from_utf8(&buf[..len]).unwrap())
+    }
+}
+
+/// Find a key at the top level of a JSON object.
+///
+/// Returns `Ok(None)` if the object does not have the key and an error if the JSON is malformed before the key is
+/// found.
+pub fn json_field<'a>(json: &'a str, key: &str) -> Result<Option<JsonValue<'a>>, JsonError> {
+    let mut p = Parser { s: json, i: 0 };
+    p.expect(b'{')?;
+    if p.peek() == Some(b'}') {
+        return Ok(None);
+    }
+    loop {
+        let name = p.string()?;
+        p.expect(b':')?;
+        let value = p.value()?;
+        if name.0 == key {
+            return Ok(Some(value));
+        }
+        match p.next() {
+            Some(b',') => continue,
+            Some(b'}') => return Ok(None),
+            _ => return Err(JsonError::Invalid),
+        }
+    }
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    i: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\r' | b'\n') = self.s.as_bytes().get(self.i) {
+            self.i += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.s.as_bytes().get(self.i).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.i += 1;
+        Some(b)
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), JsonError> {
+        if self.next() == Some(b) {
+            Ok(())
+        } else {
+            Err(JsonError::Invalid)
+        }
+    }
+
+    fn string(&mut self) -> Result<JsonStr<'a>, JsonError> {
+        self.expect(b'"')?;
+        let start = self.i;
+        let bytes = self.s.as_bytes();
+        while let Some(&b) = bytes.get(self.i) {
+            match b {
+                b'"' => {
+                    self.i += 1;
+                    return Ok(JsonStr(&self.s[start..self.i - 1]));
+                }
+                b'\\' => self.i += 2,
+                _ => self.i += 1,
+            }
+        }
+        Err(JsonError::Invalid)
+    }
+
+    fn value(&mut self) -> Result<JsonValue<'a>, JsonError> {
+        match self.peek().ok_or(JsonError::Invalid)? {
+            b'"' => Ok(JsonValue::Str(self.string()?)),
+            b'{' | b'[' => {
+                self.skip_nested()?;
+                Ok(JsonValue::Nested)
+            }
+            _ => {
+                let start = self.i;
+                let bytes = self.s.as_bytes();
+                while let Some(b) = bytes.get(self.i) {
+                    if matches!(b, b',' | b'}' | b']' | b' ' | b'\t' | b'\r' | b'\n') {
+                        break;
+                    }
+                    self.i += 1;
+                }
+                match &self.s[start..self.i] {
+                    "true" => Ok(JsonValue::Bool(true)),
+                    "false" => Ok(JsonValue::Bool(false)),
+                    "null" => Ok(JsonValue::Null),
+                    // Rust also parses `inf` and `NaN`, which are not JSON.
+                    n if !n.starts_with(|c: char| c == '-' || c.is_ascii_digit()) => {
+                        Err(JsonError::Invalid)
+                    }
+                    n => n
+                        .parse::<f64>()
+                        .map(JsonValue::Number)
+                        .map_err(|_| JsonError::Invalid),
+                }
+            }
+        }
+    }
+
+    fn skip_nested(&mut self) -> Result<(), JsonError> {
+        let mut depth = 0usize;
+        loop {
+            match self.peek().ok_or(JsonError::Invalid)? {
+                b'"' => {
+                    self.string()?;
+                    continue;
+                }
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' => depth -= 1,
+                _ => {}
+            }
+            self.i += 1;
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(f: impl FnOnce(&mut JsonWriter)) -> String {
+        let mut buf = [0u8; 256];
+        let mut json = JsonWriter::new(&mut buf);
+        f(&mut json);
+        let len = json.finish().unwrap();
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn places_commas_between_values() {
+        let json = write(|json| {
+            json.begin_object()
+                .field_str("state", "printing")
+                .key("temps")
+                .begin_array()
+                .f64(215.04, 1)
+                .f64(60.0, 0)
+                .end_array()
+                .key("fan")
+                .begin_object()
+                .field_u64("rpm", 4200)
+                .field_bool("on", true)
+                .end_object()
+                .key("empty")
+                .begin_array()
+                .end_array()
+                .field_i64("offset", -3)
+                .field_null("job")
+                .end_object();
+        });
+        assert_eq!(
+            json,
+            r#"{"state":"printing","temps":[215.0,60],"fan":{"rpm":4200,"on":true},"empty":[],"offset":-3,"job":null}"#
+        );
+    }
+
+    #[test]
+    fn writes_arrays_of_objects() {
+        let json = write(|json| {
+            json.begin_array();
+            for i in 0..3 {
+                json.begin_object().field_u64("i", i).end_object();
+            }
+            json.end_array();
+        });
+        assert_eq!(json, r#"[{"i":0},{"i":1},{"i":2}]"#);
+    }
+
+    #[test]
+    fn escapes_strings() {
+        let json = write(|json| {
+            json.begin_object()
+                .field_str("a\"b", "line\nnext\t\\ \u{1} é")
+                .key("path")
+                .fmt_str(format_args!("{}\\{}", "usb", "\"x\""))
+                .end_object();
+        });
+        assert_eq!(
+            json,
+            r#"{"a\"b":"line\nnext\t\\ \u0001 é","path":"usb\\\"x\""}"#
+        );
+    }
+
+    #[test]
+    fn writes_non_finite_numbers_as_null() {
+        let json = write(|json| {
+            json.begin_array()
+                .f64(f64::NAN, 1)
+                .f64(f64::INFINITY, 1)
+                .end_array();
+        });
+        assert_eq!(json, "[null,null]");
+    }
+
+    #[test]
+    fn reports_a_full_buffer() {
+        let mut buf = [0u8; 8];
+        let mut json = JsonWriter::new(&mut buf);
+        json.begin_object().field_str("key", "value").end_object();
+        assert_eq!(json.finish(), Err(JsonError::BufferFull));
+
+        let mut buf = [0u8; 8];
+        let mut json = JsonWriter::new(&mut buf);
+        json.begin_array()
+            .fmt_str(format_args!("{}", "too long"))
+            .end_array();
+        assert_eq!(json.finish(), Err(JsonError::BufferFull));
+    }
+
+    #[test]
+    fn finds_top_level_fields() {
+        let json = r#" { "target" : 215.5, "on": true, "name": "PLA \"silk\"", "extra": null } "#;
+        assert_eq!(
+            json_field(json, "target"),
+            Ok(Some(JsonValue::Number(215.5)))
+        );
+        assert_eq!(json_field(json, "on"), Ok(Some(JsonValue::Bool(true))));
+        assert_eq!(json_field(json, "extra"), Ok(Some(JsonValue::Null)));
+        assert_eq!(json_field(json, "missing"), Ok(None));
+        let Ok(Some(JsonValue::Str(name))) = json_field(json, "name") else {
+            panic!("expected a string");
+        };
+        assert_eq!(name.raw(), r#"PLA \"silk\""#);
+        assert_eq!(name.as_str(), None);
+        assert_eq!(json_field("{}", "target"), Ok(None));
+    }
+
+    #[test]
+    fn skips_nested_values() {
+        let json = r#"{"job": {"target": 1, "files": ["a}", "]b"]}, "target": -2e1}"#;
+        assert_eq!(json_field(json, "job"), Ok(Some(JsonValue::Nested)));
+        assert_eq!(
+            json_field(json, "target"),
+            Ok(Some(JsonValue::Number(-20.0)))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        for json in [
+            "",
+            "[]",
+            r#"{"target"}"#,
+            r#"{"target": }"#,
+            r#"{"target": inf}"#,
+            r#"{"target": NaN}"#,
+            r#"{"target": "open"#,
+            r#"{"a": [1, 2}"#,
+            r#"{"a": 1 "target": 2}"#,
+        ] {
+            assert_eq!(
+                json_field(json, "target"),
+                Err(JsonError::Invalid),
+                "{json}"
+            );
+        }
+        // Anything after the key is found is not looked at.
+        assert_eq!(
+            json_field(r#"{"target": 1 "on": true, oops"#, "target"),
+            Ok(Some(JsonValue::Number(1.0)))
+        );
+    }
+
+    #[test]
+    fn unescapes_strings() {
+        let mut buf = [0u8; 32];
+        let json = r#"{"command": "G28\nM117 \"Hi\" é\/\t\\"}"#;
+        let Ok(Some(JsonValue::Str(command))) = json_field(json, "command") else {
+            panic!("expected a string");
+        };
+        assert_eq!(
+            command.unescape(&mut buf).unwrap(),
+            "G28\nM117 \"Hi\" é/\t\\"
+        );
+        assert_eq!(JsonStr("a\\x").unescape(&mut buf), Err(JsonError::Invalid));
+        assert_eq!(JsonStr("a\\").unescape(&mut buf), Err(JsonError::Invalid));
+        assert_eq!(JsonStr("\\u12").unescape(&mut buf), Err(JsonError::Invalid));
+        // Lone surrogates are not characters.
+        assert_eq!(
+            JsonStr("\\ud83d").unescape(&mut buf),
+            Err(JsonError::Invalid)
+        );
+        assert_eq!(
+            JsonStr("0123456789").unescape(&mut buf[..4]),
+            Err(JsonError::BufferFull)
+        );
+    }
+}
@@ -0,0 +1,670 @@
+use core::net::Ipv4Addr;
+
+use crate::net::DnsError;
+
+/// The length of the message header.
+pub const HEADER_LEN: usize = 12;
+
+/// The record types used by mDNS and DNS-SD.
+pub const TYPE_A: u16 = 1;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_ANY: u16 = 255;
+
+const CLASS_IN: u16 = 1;
+/// Set on the class of a question to ask for a unicast response, and on the class of a record to tell caches to
+/// replace what they hold for the name.
+const CLASS_TOP_BIT: u16 = 0x8000;
+
+/// How many compression pointers are followed before a name is treated as malformed.
+const MAX_POINTERS: usize = 16;
+
+/// The fixed fields at the start of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub id: u16,
+    pub flags: u16,
+    pub questions: u16,
+    pub answers: u16,
+    pub authorities: u16,
+    pub additional: u16,
+}
+
+impl Header {
+    pub fn parse(msg: &[u8]) -> Result<Self, DnsError> {
+        let field = |i: usize| u16::from_be_bytes([msg[i], msg[i + 1]]);
+        if msg.len() < HEADER_LEN {
+            return Err(DnsError::Malformed);
+        }
+        Ok(Self {
+            id: field(0),
+            flags: field(2),
+            questions: field(4),
+            answers: field(6),
+            authorities: field(8),
+            additional: field(10),
+        })
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.flags & 0x8000 != 0
+    }
+
+    /// The kind of message. Zero is a standard query.
+    pub fn opcode(&self) -> u8 {
+        ((self.flags >> 11) & 0x0F) as u8
+    }
+}
+
+/// A name in a received message, which may point to labels earlier in the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Name<'a> {
+    msg: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Name<'a> {
+    /// Return the labels of the name in order.
+    pub fn labels(&self) -> Labels<'a> {
+        Labels {
+            msg: self.msg,
+            offset: self.offset,
+            pointers: 0,
+            failed: false,
+        }
+    }
+
+    /// Compare the name to a list of labels, ignoring ASCII case.
+    pub fn matches(&self, labels: &[&str]) -> bool {
+        let mut mine = self.labels();
+        for label in labels {
+            match mine.next() {
+                Some(Ok(l)) if l.eq_ignore_ascii_case(label.as_bytes()) => {}
+                _ => return false,
+            }
+        }
+        mine.next().is_none()
+    }
+}
+
+/// The labels of a [`Name`]. Yields an error and stops if the name is malformed.
+pub struct Labels<'a> {
+    msg: &'a [u8],
+    offset: usize,
+    pointers: usize,
+    failed: bool,
+}
+
+impl Labels<'_> {
+    fn fail<T>(&mut self) -> Option<Result<T, DnsError>> {
+        self.failed = true;
+        Some(Err(DnsError::Malformed))
+    }
+}
+
+impl<'a> Iterator for Labels<'a> {
+    type Item = Result<&'a [u8], DnsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            let Some(&len) = self.msg.get(self.offset) else {
+                return self.fail();
+            };
+            match len {
+                0 => return None,
+                1..=63 => {
+                    let start = self.offset + 1;
+                    let end = start + len as usize;
+                    let Some(label) = self.msg.get(start..end) else {
+                        return self.fail();
+                    };
+                    self.offset = end;
+                    return Some(Ok(label));
+                }
+                0xC0..=0xFF if self.pointers < MAX_POINTERS => {
+                    let Some(&low) = self.msg.get(self.offset + 1) else {
+                        return self.fail();
+                    };
+                    self.offset = (((len & 0x3F) as usize) << 8) | low as usize;
+                    self.pointers += 1;
+                }
+                _ => return self.fail(),
+            }
+        }
+    }
+}
+
+/// Return the offset just after the name starting at `offset`, without following pointers.
+fn skip_name(msg: &[u8], mut offset: usize) -> Result<usize, DnsError> {
+    loop {
+        match *msg.get(offset).ok_or(DnsError::Malformed)? {
+            0 => return Ok(offset + 1),
+            len @ 1..=63 => offset += 1 + len as usize,
+            0xC0..=0xFF => return Ok(offset + 2),
+            _ => return Err(DnsError::Malformed),
+        }
+    }
+}
+
+/// A question in a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Question<'a> {
+    pub name: Name<'a>,
+    pub qtype: u16,
+    /// The asker would like the answer sent straight to it rather than to the group.
+    pub unicast: bool,
+}
+
+impl Question<'_> {
+    /// Return whether an answer of `rtype` answers the question.
+    pub fn wants(&self, rtype: u16) -> bool {
+        self.qtype == rtype || self.qtype == TYPE_ANY
+    }
+}
+
+/// The questions of a message, in order.
+pub struct Questions<'a> {
+    msg: &'a [u8],
+    offset: usize,
+    remaining: u16,
+}
+
+impl<'a> Questions<'a> {
+    pub fn new(msg: &'a [u8]) -> Result<Self, DnsError> {
+        let header = Header::parse(msg)?;
+        Ok(Self {
+            msg,
+            offset: HEADER_LEN,
+            remaining: header.questions,
+        })
+    }
+
+    /// Return the offset just after the questions read so far, i.e. the end of the question section once all have
+    /// been read.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for Questions<'a> {
+    type Item = Result<Question<'a>, DnsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let name = Name {
+            msg: self.msg,
+            offset: self.offset,
+        };
+        let question = skip_name(self.msg, self.offset).and_then(|end| {
+            let fields = self.msg.get(end..end + 4).ok_or(DnsError::Malformed)?;
+            self.offset = end + 4;
+            let class = u16::from_be_bytes([fields[2], fields[3]]);
+            Ok(Question {
+                name,
+                qtype: u16::from_be_bytes([fields[0], fields[1]]),
+                unicast: class & CLASS_TOP_BIT != 0,
+            })
+        });
+        if question.is_err() {
+            self.remaining = 0;
+        }
+        Some(question)
+    }
+}
+
+/// The data of a resource record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordData<'a> {
+    A(Ipv4Addr),
+    Ptr(&'a [&'a str]),
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: &'a [&'a str],
+    },
+    /// `key=value` strings. An empty list is written as a single empty string.
+    Txt(&'a [&'a str]),
+}
+
+impl RecordData<'_> {
+    pub fn rtype(&self) -> u16 {
+        match self {
+            Self::A(_) => TYPE_A,
+            Self::Ptr(_) => TYPE_PTR,
+            Self::Srv { .. } => TYPE_SRV,
+            Self::Txt(_) => TYPE_TXT,
+        }
+    }
+}
+
+/// A resource record to write into a response. Names are given as their labels, e.g. `["buddy", "local"]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    pub name: &'a [&'a str],
+    /// How long the record may be cached (s).
+    pub ttl: u32,
+    /// The record is the only one of its type for the name, so caches should drop any others.
+    pub cache_flush: bool,
+    pub data: RecordData<'a>,
+}
+
+/// Writes a response into a fixed buffer. Answers must be written before additional records.
+pub struct ResponseWriter<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+    questions: u16,
+    answers: u16,
+    additional: u16,
+}
+
+impl<'b> ResponseWriter<'b> {
+    /// Start an authoritative response with the given ID. mDNS responses use zero unless they answer a legacy
+    /// unicast query.
+    pub fn new(buf: &'b mut [u8], id: u16) -> Result<Self, DnsError> {
+        let header = buf.get_mut(..HEADER_LEN).ok_or(DnsError::BufferFull)?;
+        header.fill(0);
+        header[..2].copy_from_slice(&id.to_be_bytes());
+        header[2..4].copy_from_slice(&0x8400u16.to_be_bytes());
+        Ok(Self {
+            buf,
+            len: HEADER_LEN,
+            questions: 0,
+            answers: 0,
+            additional: 0,
+        })
+    }
+
+    /// Copy the question section of `query`, which legacy unicast responses must repeat. Names in it may point into
+    /// the header or earlier questions, so it must be copied before any record to keep the offsets the same.
+    pub fn questions_from(&mut self, query: &[u8]) -> Result<(), DnsError> {
+        debug_assert_eq!(self.len, HEADER_LEN);
+        let mut questions = Questions::new(query)?;
+        let mut count = 0;
+        for question in questions.by_ref() {
+            question?;
+            count += 1;
+        }
+        self.bytes(&query[HEADER_LEN..questions.offset()])?;
+        self.questions = count;
+        Ok(())
+    }
+
+    /// Add a record to the answer section.
+    pub fn answer(&mut self, record: &Record) -> Result<(), DnsError> {
+        debug_assert_eq!(self.additional, 0);
+        self.record(record)?;
+        self.answers += 1;
+        Ok(())
+    }
+
+    /// Add a record to the additional section.
+    pub fn additional(&mut self, record: &Record) -> Result<(), DnsError> {
+        self.record(record)?;
+        self.additional += 1;
+        Ok(())
+    }
+
+    /// Return the number of answers written so far.
+    pub fn answers(&self) -> u16 {
+        self.answers
+    }
+
+    /// Write the record counts into the header and return the length of the message.
+    pub fn finish(self) -> usize {
+        self.buf[4..6].copy_from_slice(&self.questions.to_be_bytes());
+        self.buf[6..8].copy_from_slice(&self.answers.to_be_bytes());
+        self.buf[10..12].copy_from_slice(&self.additional.to_be_bytes());
+        self.len
+    }
+
+    fn record(&mut self, record: &Record) -> Result<(), DnsError> {
+        let start = self.len;
+        let result = self.write_record(record);
+        if result.is_err() {
+            // Leave the message as it was so it can still be sent.
+            self.len = start;
+        }
+        result
+    }
+
+    fn write_record(&mut self, record: &Record) -> Result<(), DnsError> {
+        self.name(record.name)?;
+        self.u16(record.data.rtype())?;
+        let class = if record.cache_flush {
+            CLASS_IN | CLASS_TOP_BIT
+        } else {
+            CLASS_IN
+        };
+        self.u16(class)?;
+        self.bytes(&record.ttl.to_be_bytes())?;
+        let len_at = self.len;
+        self.u16(0)?;
+        match record.data {
+            RecordData::A(address) => self.bytes(&address.octets())?,
+            RecordData::Ptr(name) => self.name(name)?,
+            RecordData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                self.u16(priority)?;
+                self.u16(weight)?;
+                self.u16(port)?;
+                self.name(target)?;
+            }
+            RecordData::Txt([]) => self.bytes(&[0])?,
+            RecordData::Txt(entries) => {
+                for entry in entries {
+                    let len = u8::try_from(entry.len()).map_err(|_| DnsError::InvalidName)?;
+                    self.bytes(&[len])?;
+                    self.bytes(entry.as_bytes())?;
+                }
+            }
+        }
+        let data_len = (self.len - len_at - 2) as u16;
+        self.buf[len_at..len_at + 2].copy_from_slice(&data_len.to_be_bytes());
+        Ok(())
+    }
+
+    fn name(&mut self, labels: &[&str]) -> Result<(), DnsError> {
+        for label in labels {
+            if label.is_empty() || label.len() > 63 {
+                return Err(DnsError::InvalidName);
+            }
+            self.bytes(&[label.len() as u8])?;
+            self.bytes(label.as_bytes())?;
+        }
+        self.bytes(&[0])
+    }
+
+    fn u16(&mut self, v: u16) -> Result<(), DnsError> {
+        self.bytes(&v.to_be_bytes())
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), DnsError> {
+        let end = self.len + bytes.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(DnsError::BufferFull)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INSTANCE: &[&str] = &["Buddy", "_http", "_tcp", "local"];
+    const HOST: &[&str] = &["buddy", "local"];
+
+    /// A query for `buddy.local` A, then ANY for the same name by pointer with the unicast bit set, then TXT for
+    /// `Buddy._http._tcp.local` with `local` pointing into the first name.
+    fn query() -> Vec<u8> {
+        [
+            &b"\x12\x34\x00\x00\x00\x03\x00\x00\x00\x00\x00\x00"[..],
+            b"\x05buddy\x05local\x00\x00\x01\x00\x01",
+            b"\xC0\x0C\x00\xFF\x80\x01",
+            b"\x05Buddy\x05_http\x04_tcp\xC0\x12\x00\x10\x00\x01",
+        ]
+        .concat()
+    }
+
+    /// What an mDNS responder sends for the HTTP service: the PTR answer, then its SRV, TXT and A records.
+    fn captured_response() -> Vec<u8> {
+        [
+            &b"\x00\x00\x84\x00\x00\x00\x00\x01\x00\x00\x00\x03"[..],
+            b"\x05_http\x04_tcp\x05local\x00\x00\x0C\x00\x01\x00\x00\x11\x94\x00\x18",
+            b"\x05Buddy\x05_http\x04_tcp\x05local\x00",
+            b"\x05Buddy\x05_http\x04_tcp\x05local\x00\x00\x21\x80\x01\x00\x00\x00\x78\x00\x13",
+            b"\x00\x00\x00\x00\x00\x50\x05buddy\x05local\x00",
+            b"\x05Buddy\x05_http\x04_tcp\x05local\x00\x00\x10\x80\x01\x00\x00\x11\x94\x00\x07",
+            b"\x06path=/",
+            b"\x05buddy\x05local\x00\x00\x01\x80\x01\x00\x00\x00\x78\x00\x04",
+            b"\xC0\xA8\x00\x32",
+        ]
+        .concat()
+    }
+
+    fn records() -> [Record<'static>; 4] {
+        [
+            Record {
+                name: &["_http", "_tcp", "local"],
+                ttl: 4500,
+                cache_flush: false,
+                data: RecordData::Ptr(INSTANCE),
+            },
+            Record {
+                name: INSTANCE,
+                ttl: 120,
+                cache_flush: true,
+                data: RecordData::Srv {
+                    priority: 0,
+                    weight: 0,
+                    port: 80,
+                    target: HOST,
+                },
+            },
+            Record {
+                name: INSTANCE,
+                ttl: 4500,
+                cache_flush: true,
+                data: RecordData::Txt(&["path=/"]),
+            },
+            Record {
+                name: HOST,
+                ttl: 120,
+                cache_flush: true,
+                data: RecordData::A(Ipv4Addr::new(192, 168, 0, 50)),
+            },
+        ]
+    }
+
+    fn write(buf: &mut [u8]) -> (Result<(), DnsError>, usize) {
+        let [ptr, srv, txt, a] = records();
+        let mut response = ResponseWriter::new(buf, 0).unwrap();
+        response.answer(&ptr).unwrap();
+        response.additional(&srv).unwrap();
+        response.additional(&txt).unwrap();
+        let result = response.additional(&a);
+        (result, response.finish())
+    }
+
+    fn labels(name: Name) -> Vec<Vec<u8>> {
+        name.labels().map(|l| l.unwrap().to_vec()).collect()
+    }
+
+    #[test]
+    fn parses_the_header() {
+        let query = query();
+        let header = Header::parse(&query).unwrap();
+        assert_eq!(header.id, 0x1234);
+        assert_eq!(header.questions, 3);
+        assert!(!header.is_response());
+        assert_eq!(header.opcode(), 0);
+        let response = Header::parse(&captured_response()).unwrap();
+        assert!(response.is_response());
+        assert_eq!((response.answers, response.additional), (1, 3));
+        assert_eq!(Header::parse(&query[..11]), Err(DnsError::Malformed));
+    }
+
+    #[test]
+    fn parses_questions_with_compressed_names() {
+        let query = query();
+        let mut questions = Questions::new(&query).unwrap();
+        let host = questions.next().unwrap().unwrap();
+        assert_eq!(labels(host.name), [b"buddy".to_vec(), b"local".to_vec()]);
+        assert_eq!((host.qtype, host.unicast), (TYPE_A, false));
+
+        let any = questions.next().unwrap().unwrap();
+        assert!(any.name.matches(HOST));
+        assert!(any.unicast);
+        assert!(any.wants(TYPE_A) && any.wants(TYPE_SRV));
+
+        let txt = questions.next().unwrap().unwrap();
+        assert!(txt.name.matches(INSTANCE));
+        assert!(txt.wants(TYPE_TXT) && !txt.wants(TYPE_SRV));
+
+        assert!(questions.next().is_none());
+        assert_eq!(questions.offset(), query.len());
+    }
+
+    #[test]
+    fn matches_names_ignoring_case() {
+        let query = query();
+        let host = Questions::new(&query).unwrap().next().unwrap().unwrap();
+        assert!(host.name.matches(&["BUDDY", "Local"]));
+        assert!(!host.name.matches(&["buddy"]));
+        assert!(!host.name.matches(&["buddy", "local", "lan"]));
+        assert!(!host.name.matches(&["budd", "local"]));
+        assert!(!host.name.matches(&[]));
+    }
+
+    #[test]
+    fn limits_compression_pointers() {
+        // `a` followed by a chain of pointers, each to the one before.
+        let mut msg = vec![0; HEADER_LEN];
+        msg.extend_from_slice(b"\x01a\x00");
+        let mut previous = HEADER_LEN;
+        for _ in 0..=MAX_POINTERS {
+            let at = msg.len();
+            msg.extend_from_slice(&[0xC0 | (previous >> 8) as u8, previous as u8]);
+            previous = at;
+        }
+        let through = |pointers: usize| Name {
+            msg: &msg,
+            offset: HEADER_LEN + 3 + 2 * (pointers - 1),
+        };
+        assert!(through(MAX_POINTERS).matches(&["a"]));
+        assert_eq!(
+            through(MAX_POINTERS + 1).labels().collect::<Vec<_>>(),
+            [Err(DnsError::Malformed)]
+        );
+
+        // A name pointing at itself.
+        let msg = [&[0; HEADER_LEN][..], b"\xC0\x0C"].concat();
+        let name = Name {
+            msg: &msg,
+            offset: HEADER_LEN,
+        };
+        assert_eq!(
+            name.labels().collect::<Vec<_>>(),
+            [Err(DnsError::Malformed)]
+        );
+        assert!(!name.matches(&[]));
+    }
+
+    #[test]
+    fn rejects_malformed_questions() {
+        let header = b"\x00\x00\x00\x00\x00\x02\x00\x00\x00\x00\x00\x00";
+        for question in [
+            // The fields are cut short.
+            &b"\x05buddy\x00\x00\x01\x00"[..],
+            // The label runs past the end.
+            b"\x09buddy",
+            // Label types 01 and 10 are reserved.
+            b"\x45buddy\x00\x00\x01\x00\x01",
+            b"\x85buddy\x00\x00\x01\x00\x01",
+            b"",
+        ] {
+            let msg = [&header[..], question].concat();
+            let mut questions = Questions::new(&msg).unwrap();
+            assert_eq!(
+                questions.next().map(|q| q.map(|_| ())),
+                Some(Err(DnsError::Malformed)),
+                "{question:?}"
+            );
+            // The second question is not read after the first fails.
+            assert!(questions.next().is_none());
+        }
+        // The name is read lazily, so a label past the end shows up when it is compared.
+        let msg = [&header[..], b"\x05buddy\xC0\x40\x00\x01\x00\x01"].concat();
+        let question = Questions::new(&msg).unwrap().next().unwrap().unwrap();
+        assert!(!question.name.matches(&["buddy"]));
+    }
+
+    #[test]
+    fn writes_a_response() {
+        let mut buf = [0; 512];
+        let (result, len) = write(&mut buf);
+        assert_eq!(result, Ok(()));
+        assert_eq!(&buf[..len], captured_response());
+    }
+
+    #[test]
+    fn rolls_back_a_record_that_does_not_fit() {
+        let expected = captured_response();
+        let mut buf = vec![0; expected.len() - 1];
+        let (result, len) = write(&mut buf);
+        assert_eq!(result, Err(DnsError::BufferFull));
+        // The message is complete without the A record.
+        let without_a = expected.len() - 27;
+        assert_eq!(len, without_a);
+        assert_eq!(&buf[..10], &expected[..10]);
+        assert_eq!(&buf[10..12], [0, 2]);
+        assert_eq!(&buf[12..len], &expected[12..without_a]);
+
+        let mut buf = [0; 64];
+        let mut response = ResponseWriter::new(&mut buf, 0).unwrap();
+        let bad = Record {
+            name: &["buddy", ""],
+            ..records()[3]
+        };
+        assert_eq!(response.answer(&bad), Err(DnsError::InvalidName));
+        let long = "x".repeat(64);
+        let bad = Record {
+            name: &[&long],
+            ..records()[3]
+        };
+        assert_eq!(response.answer(&bad), Err(DnsError::InvalidName));
+        assert_eq!(response.answers(), 0);
+        assert_eq!(response.finish(), HEADER_LEN);
+        assert!(ResponseWriter::new(&mut buf[..11], 0).is_err());
+    }
+
+    #[test]
+    fn writes_an_empty_txt_record() {
+        let mut buf = [0; 64];
+        let mut response = ResponseWriter::new(&mut buf, 0).unwrap();
+        let txt = Record {
+            data: RecordData::Txt(&[]),
+            ..records()[2]
+        };
+        response.answer(&txt).unwrap();
+        let len = response.finish();
+        assert_eq!(&buf[len - 3..len], b"\x00\x01\x00");
+    }
+
+    #[test]
+    fn repeats_the_questions_of_a_legacy_query() {
+        let query = query();
+        let mut buf = [0; 128];
+        let mut response = ResponseWriter::new(&mut buf, 0x1234).unwrap();
+        response.questions_from(&query).unwrap();
+        response.answer(&records()[3]).unwrap();
+        let len = response.finish();
+        assert_eq!(&buf[..4], b"\x12\x34\x84\x00");
+        assert_eq!(&buf[4..8], b"\x00\x03\x00\x01");
+        // The pointers in the copied questions still point at the same names.
+        assert_eq!(&buf[HEADER_LEN..query.len()], &query[HEADER_LEN..]);
+        let questions = Questions::new(&buf[..len]).unwrap();
+        assert!(
+            questions
+                .map(|q| q.unwrap())
+                .last()
+                .unwrap()
+                .name
+                .matches(INSTANCE)
+        );
+    }
+}
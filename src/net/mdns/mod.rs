@@ -0,0 +1,5 @@
+mod dns;
+mod responder;
+
+pub use dns::*;
+pub use responder::*;
@@ -0,0 +1,309 @@
+use core::net::Ipv4Addr;
+
+use embassy_net::{
+    IpEndpoint, Ipv4Address, Stack,
+    udp::{PacketMetadata, UdpSocket},
+};
+use embassy_time::{Duration, Timer};
+
+use crate::{
+    fmt::{error, info},
+    net::{
+        DnsError,
+        mdns::{
+            Header, Questions, Record, RecordData, ResponseWriter, TYPE_A, TYPE_PTR, TYPE_SRV,
+            TYPE_TXT,
+        },
+    },
+};
+
+pub const MDNS_PORT: u16 = 5353;
+pub const MDNS_GROUP: Ipv4Address = Ipv4Address::new(224, 0, 0, 251);
+
+/// The most services one responder advertises.
+pub const MAX_SERVICES: usize = 8;
+
+/// How long the address record may be cached (s).
+const HOST_TTL: u32 = 120;
+/// How long the service records may be cached (s).
+const SERVICE_TTL: u32 = 4500;
+/// How long records sent to a plain DNS resolver may be cached (s).
+const LEGACY_TTL: u32 = 10;
+
+/// The name that lists the service types on the network.
+const SERVICE_TYPES: [&str; 4] = ["_services", "_dns-sd", "_udp", "local"];
+
+/// A service advertised with DNS-SD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Service<'a> {
+    /// The name shown to users, e.g. `Buddy`.
+    pub instance: &'a str,
+    /// The service type and protocol, e.g. `_http._tcp`.
+    pub service: &'a str,
+    pub port: u16,
+    /// `key=value` metadata, e.g. `path=/`.
+    pub txt: &'a [&'a str],
+}
+
+impl<'a> Service<'a> {
+    fn type_labels(&self) -> [&'a str; 3] {
+        let (service, protocol) = self
+            .service
+            .split_once('.')
+            .unwrap_or((self.service, "_tcp"));
+        [service, protocol, "local"]
+    }
+
+    fn instance_labels(&self) -> [&'a str; 4] {
+        let [service, protocol, local] = self.type_labels();
+        [self.instance, service, protocol, local]
+    }
+}
+
+/// The records to write, as one flag for the host and a bit per service for the others.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Wanted {
+    host: bool,
+    types: bool,
+    ptr: u8,
+    srv: u8,
+    txt: u8,
+}
+
+impl Wanted {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A response to send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reply {
+    pub len: usize,
+    /// Send the response to the asker rather than to the group.
+    pub unicast: bool,
+}
+
+/// Answers mDNS queries for `<hostname>.local` and for the services.
+#[derive(Debug, Clone, Copy)]
+pub struct Responder<'a> {
+    hostname: &'a str,
+    services: &'a [Service<'a>],
+}
+
+impl<'a> Responder<'a> {
+    /// Services beyond [`MAX_SERVICES`] are not advertised.
+    pub fn new(hostname: &'a str, services: &'a [Service<'a>]) -> Self {
+        Self {
+            hostname,
+            services: &services[..services.len().min(MAX_SERVICES)],
+        }
+    }
+
+    /// Write the response to `query` into `out`, or return `None` if none of the questions are for this host.
+    ///
+    /// `legacy` marks a query from a port other than 5353, i.e. a plain DNS resolver, which is answered directly with
+    /// the query ID and questions.
+    pub fn respond(
+        &self,
+        query: &[u8],
+        address: Ipv4Addr,
+        legacy: bool,
+        out: &mut [u8],
+    ) -> Result<Option<Reply>, DnsError> {
+        let header = Header::parse(query)?;
+        if header.is_response() || header.opcode() != 0 {
+            return Ok(None);
+        }
+        let host = [self.hostname, "local"];
+        let mut answers = Wanted::default();
+        let mut unicast = true;
+        for question in Questions::new(query)? {
+            let question = question?;
+            unicast &= question.unicast;
+            if question.wants(TYPE_A) && question.name.matches(&host) {
+                answers.host = true;
+            }
+            if question.wants(TYPE_PTR) && question.name.matches(&SERVICE_TYPES) {
+                answers.types = true;
+            }
+            for (i, service) in self.services.iter().enumerate() {
+                let bit = 1 << i;
+                if question.wants(TYPE_PTR) && question.name.matches(&service.type_labels()) {
+                    answers.ptr |= bit;
+                }
+                if question.name.matches(&service.instance_labels()) {
+                    if question.wants(TYPE_SRV) {
+                        answers.srv |= bit;
+                    }
+                    if question.wants(TYPE_TXT) {
+                        answers.txt |= bit;
+                    }
+                }
+            }
+        }
+        if answers.is_empty() {
+            return Ok(None);
+        }
+
+        let mut w = ResponseWriter::new(out, if legacy { header.id } else { 0 })?;
+        if legacy {
+            w.questions_from(query)?;
+        }
+        self.records(&answers, address, legacy, |record| w.answer(record))?;
+        // Save the asker a query by adding what it will look up next. These are optional so are left out if they do
+        // not fit.
+        let additional = Wanted {
+            host: !answers.host && (answers.ptr | answers.srv) != 0,
+            types: false,
+            ptr: 0,
+            srv: answers.ptr & !answers.srv,
+            txt: answers.ptr & !answers.txt,
+        };
+        let _ = self.records(&additional, address, legacy, |record| w.additional(record));
+        Ok(Some(Reply {
+            len: w.finish(),
+            unicast: legacy || unicast,
+        }))
+    }
+
+    /// Write an unsolicited response with every record, sent when the responder starts so others learn of it.
+    pub fn announce(&self, address: Ipv4Addr, out: &mut [u8]) -> Result<usize, DnsError> {
+        let all = (1u16 << self.services.len()).wrapping_sub(1) as u8;
+        let everything = Wanted {
+            host: true,
+            types: false,
+            ptr: all,
+            srv: all,
+            txt: all,
+        };
+        let mut w = ResponseWriter::new(out, 0)?;
+        self.records(&everything, address, false, |record| w.answer(record))?;
+        Ok(w.finish())
+    }
+
+    fn records(
+        &self,
+        wanted: &Wanted,
+        address: Ipv4Addr,
+        legacy: bool,
+        mut write: impl FnMut(&Record) -> Result<(), DnsError>,
+    ) -> Result<(), DnsError> {
+        let ttl = |ttl: u32| if legacy { LEGACY_TTL } else { ttl };
+        let host = [self.hostname, "local"];
+        if wanted.host {
+            write(&Record {
+                name: &host,
+                ttl: ttl(HOST_TTL),
+                cache_flush: !legacy,
+                data: RecordData::A(address),
+            })?;
+        }
+        for (i, service) in self.services.iter().enumerate() {
+            let bit = 1 << i;
+            let type_labels = service.type_labels();
+            let instance = service.instance_labels();
+            if wanted.types {
+                write(&Record {
+                    name: &SERVICE_TYPES,
+                    ttl: ttl(SERVICE_TTL),
+                    cache_flush: false,
+                    data: RecordData::Ptr(&type_labels),
+                })?;
+            }
+            if wanted.ptr & bit != 0 {
+                write(&Record {
+                    name: &type_labels,
+                    ttl: ttl(SERVICE_TTL),
+                    cache_flush: false,
+                    data: RecordData::Ptr(&instance),
+                })?;
+            }
+            if wanted.srv & bit != 0 {
+                write(&Record {
+                    name: &instance,
+                    ttl: ttl(HOST_TTL),
+                    cache_flush: !legacy,
+                    data: RecordData::Srv {
+                        priority: 0,
+                        weight: 0,
+                        port: service.port,
+                        target: &host,
+                    },
+                })?;
+            }
+            if wanted.txt & bit != 0 {
+                write(&Record {
+                    name: &instance,
+                    ttl: ttl(SERVICE_TTL),
+                    cache_flush: !legacy,
+                    data: RecordData::Txt(service.txt),
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Answer mDNS queries on the network for as long as the board runs. `packet_buffer` and `response_buffer` must
+/// hold a whole message, and 512 bytes is enough for a few services.
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+    stack: Stack<'_>,
+    responder: &Responder<'_>,
+    rx_meta: &mut [PacketMetadata],
+    rx_buffer: &mut [u8],
+    tx_meta: &mut [PacketMetadata],
+    tx_buffer: &mut [u8],
+    packet_buffer: &mut [u8],
+    response_buffer: &mut [u8],
+) -> ! {
+    stack.wait_config_up().await;
+    let mut socket = UdpSocket::new(stack, rx_meta, rx_buffer, tx_meta, tx_buffer);
+    if socket.bind(MDNS_PORT).is_err() {
+        error!("[mDNS] Failed to bind the socket");
+        core::future::pending::<()>().await;
+    }
+    if stack.join_multicast_group(MDNS_GROUP).is_err() {
+        error!("[mDNS] Failed to join the multicast group");
+    }
+    let group = IpEndpoint::new(MDNS_GROUP.into(), MDNS_PORT);
+    if let Some(config) = stack.config_v4() {
+        info!("[mDNS] Announcing {}", config.address.address());
+        // Announcements are sent twice a second apart in case the first is lost.
+        for _ in 0..2 {
+            if let Ok(len) = responder.announce(config.address.address(), response_buffer) {
+                let _ = socket.send_to(&response_buffer[..len], group).await;
+            }
+            Timer::after(Duration::from_secs(1)).await;
+        }
+    }
+    loop {
+        let Ok((n, meta)) = socket.recv_from(packet_buffer).await else {
+            continue;
+        };
+        let Some(config) = stack.config_v4() else {
+            continue;
+        };
+        let legacy = meta.endpoint.port != MDNS_PORT;
+        match responder.respond(
+            &packet_buffer[..n],
+            config.address.address(),
+            legacy,
+            response_buffer,
+        ) {
+            Ok(Some(reply)) => {
+                let to = if reply.unicast { meta.endpoint } else { group };
+                if socket
+                    .send_to(&response_buffer[..reply.len], to)
+                    .await
+                    .is_err()
+                {
+                    error!("[mDNS] Failed to send a response");
+                }
+            }
+            Ok(None) => {}
+            Err(e) => error!("[mDNS] {}", e),
+        }
+    }
+}
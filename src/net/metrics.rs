@@ -0,0 +1,415 @@
+use core::fmt::{self, Write as _};
+
+use embassy_time::Instant;
+use embedded_io_async::Read;
+
+use crate::{
+    Board, TMCError,
+    net::{
+        PrinterStatus, SliceWriter,
+        http::{Body, Handler, Method, Request, Response, Status},
+    },
+};
+
+/// The content type of the Prometheus text exposition format.
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// The gauges and counters exported to Prometheus.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PrinterMetrics {
+    pub status: PrinterStatus,
+    /// The duty cycle of the hotend and bed heaters, from 0 to 1.
+    pub heater_duty: [f64; 2],
+    pub pinda_contact: bool,
+    /// The errors returned by the TMC drivers, indexed by [`TMCError::index`].
+    pub tmc_errors: [u32; TMCError::COUNT],
+    /// The times a heater was shut down for a thermal fault, e.g. thermal runaway.
+    pub thermal_faults: u32,
+    /// The time since the board started (s).
+    pub uptime: u64,
+}
+
+impl PrinterMetrics {
+    /// Read the sensors, heaters and error counts on the board. The heater targets and thermal faults are left at
+    /// zero as the board does not know them.
+    pub async fn read(board: &Board<'_>) -> Self {
+        let mut metrics = Self {
+            status: PrinterStatus::read(board).await,
+            tmc_errors: TMCError::counts(),
+            uptime: Instant::now().as_secs(),
+            ..Self::default()
+        };
+        if let Some(heater) = &board.hotend_heater {
+            metrics.heater_duty[0] = heater.duty_cycle() as f64;
+        }
+        if let Some(heater) = &board.bed_heater {
+            metrics.heater_duty[1] = heater.duty_cycle() as f64;
+        }
+        if let Some(pinda) = &board.pinda {
+            metrics.pinda_contact = pinda.in_contact().await;
+        }
+        metrics
+    }
+
+    /// Write the metrics in the Prometheus text exposition format.
+    ///
+    /// ```text
+    /// # HELP buddy_temperature_celsius The measured temperature.
+    /// # TYPE buddy_temperature_celsius gauge
+    /// buddy_temperature_celsius{sensor="hotend"} 215.2
+    /// ...
+    /// # HELP buddy_tmc_uart_errors_total The errors returned by the TMC drivers over the UART.
+    /// # TYPE buddy_tmc_uart_errors_total counter
+    /// buddy_tmc_uart_errors_total{error="crc_does_not_match"} 3
+    /// ```
+    pub fn write(&self, out: &mut SliceWriter) -> fmt::Result {
+        let status = &self.status;
+        let mut w = MetricWriter { out };
+        w.header(
+            "buddy_temperature_celsius",
+            "gauge",
+            "The measured temperature.",
+        )?;
+        for (sensor, value) in [
+            ("hotend", status.hotend.actual),
+            ("bed", status.bed.actual),
+            ("board", status.board_temperature),
+        ] {
+            w.sample(
+                "buddy_temperature_celsius",
+                Some(("sensor", sensor)),
+                value,
+                1,
+            )?;
+        }
+        w.header(
+            "buddy_target_temperature_celsius",
+            "gauge",
+            "The target temperature. Zero when the heater is off.",
+        )?;
+        for (heater, value) in [("hotend", status.hotend.target), ("bed", status.bed.target)] {
+            w.sample(
+                "buddy_target_temperature_celsius",
+                Some(("heater", heater)),
+                value,
+                1,
+            )?;
+        }
+        w.header(
+            "buddy_heater_duty_ratio",
+            "gauge",
+            "The duty cycle of the heater, from 0 to 1.",
+        )?;
+        for (heater, value) in ["hotend", "bed"].into_iter().zip(self.heater_duty) {
+            w.sample(
+                "buddy_heater_duty_ratio",
+                Some(("heater", heater)),
+                value,
+                3,
+            )?;
+        }
+        w.header(
+            "buddy_fan_speed_rpm",
+            "gauge",
+            "The speed of the fan. Zero when it is not turning.",
+        )?;
+        for (fan, rpm) in ["0", "1"].into_iter().zip(status.fans) {
+            w.sample(
+                "buddy_fan_speed_rpm",
+                Some(("fan", fan)),
+                rpm.unwrap_or(0.0),
+                0,
+            )?;
+        }
+        w.header(
+            "buddy_bed_voltage_volts",
+            "gauge",
+            "The voltage supplied to the bed heater.",
+        )?;
+        w.sample("buddy_bed_voltage_volts", None, status.bed_voltage, 1)?;
+        w.header(
+            "buddy_filament_present",
+            "gauge",
+            "1 if the filament sensor detects filament.",
+        )?;
+        w.flag("buddy_filament_present", status.filament_present)?;
+        w.header(
+            "buddy_pinda_contact",
+            "gauge",
+            "1 if the PINDA probe is triggered.",
+        )?;
+        w.flag("buddy_pinda_contact", self.pinda_contact)?;
+        w.header(
+            "buddy_tmc_uart_errors_total",
+            "counter",
+            "The errors returned by the TMC drivers over the UART.",
+        )?;
+        for (error, count) in TMCError::NAMES.into_iter().zip(self.tmc_errors) {
+            w.count(
+                "buddy_tmc_uart_errors_total",
+                Some(("error", error)),
+                count as u64,
+            )?;
+        }
+        w.header(
+            "buddy_thermal_faults_total",
+            "counter",
+            "The times a heater was shut down for a thermal fault.",
+        )?;
+        w.count(
+            "buddy_thermal_faults_total",
+            None,
+            self.thermal_faults as u64,
+        )?;
+        w.header(
+            "buddy_uptime_seconds_total",
+            "counter",
+            "The time since the board started.",
+        )?;
+        w.count("buddy_uptime_seconds_total", None, self.uptime)
+    }
+}
+
+/// Writes the lines of the exposition format.
+struct MetricWriter<'w, 'b> {
+    out: &'w mut SliceWriter<'b>,
+}
+
+impl MetricWriter<'_, '_> {
+    fn header(&mut self, name: &str, kind: &str, help: &str) -> fmt::Result {
+        write!(self.out, "# HELP {name} {help}\n# TYPE {name} {kind}\n")
+    }
+
+    fn name(&mut self, name: &str, label: Option<(&str, &str)>) -> fmt::Result {
+        self.out.write_str(name)?;
+        if let Some((key, value)) = label {
+            write!(self.out, "{{{key}=\"{value}\"}}")?;
+        }
+        self.out.write_char(' ')
+    }
+
+    fn sample(
+        &mut self,
+        name: &str,
+        label: Option<(&str, &str)>,
+        value: f64,
+        decimals: usize,
+    ) -> fmt::Result {
+        self.name(name, label)?;
+        // Prometheus spells the values that are not numbers differently to Rust.
+        if value.is_nan() {
+            self.out.write_str("NaN\n")
+        } else if value.is_infinite() {
+            self.out
+                .write_str(if value > 0.0 { "+Inf\n" } else { "-Inf\n" })
+        } else {
+            writeln!(self.out, "{value:.decimals$}")
+        }
+    }
+
+    fn flag(&mut self, name: &str, value: bool) -> fmt::Result {
+        self.count(name, None, value as u64)
+    }
+
+    fn count(&mut self, name: &str, label: Option<(&str, &str)>, value: u64) -> fmt::Result {
+        self.name(name, label)?;
+        writeln!(self.out, "{value}")
+    }
+}
+
+/// What the metrics endpoint needs from the print controller.
+#[allow(async_fn_in_trait)]
+pub trait MetricsSource {
+    /// Return the current metrics including the heater targets and thermal faults. [`PrinterMetrics::read`] reads
+    /// the rest from the board.
+    async fn metrics(&mut self) -> PrinterMetrics;
+}
+
+/// An HTTP [`Handler`] serving `GET /metrics` for Prometheus and passing any other request on to another handler,
+/// e.g. the [`RestApi`](crate::net::RestApi).
+pub struct Metrics<S: MetricsSource, H: Handler> {
+    source: S,
+    inner: H,
+}
+
+impl<S: MetricsSource, H: Handler> Metrics<S, H> {
+    pub fn new(source: S, inner: H) -> Self {
+        Self { source, inner }
+    }
+
+    pub fn source(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    pub fn inner(&mut self) -> &mut H {
+        &mut self.inner
+    }
+}
+
+impl<S: MetricsSource, H: Handler> Handler for Metrics<S, H> {
+    async fn handle<R: Read>(
+        &mut self,
+        request: &Request<'_>,
+        body: &mut Body<'_, R>,
+        response: &mut [u8],
+    ) -> Response {
+        if request.path != "/metrics" {
+            return self.inner.handle(request, body, response).await;
+        }
+        if !matches!(request.method, Method::Get | Method::Head) {
+            return Response::empty(Status::MethodNotAllowed);
+        }
+        let metrics = self.source.metrics().await;
+        let mut out = SliceWriter::new(response);
+        match metrics.write(&mut out) {
+            Ok(()) => Response {
+                status: Status::Ok,
+                content_type: Some(METRICS_CONTENT_TYPE),
+                len: out.len(),
+            },
+            // The response buffer is too small for the metrics.
+            Err(_) => Response::empty(Status::InternalServerError),
+        }
+    }
+}
+
+/// A [`Handler`] that answers every request with `404 Not Found`, for serving [`Metrics`] on its own.
+pub struct NotFound;
+
+impl Handler for NotFound {
+    async fn handle<R: Read>(
+        &mut self,
+        _request: &Request<'_>,
+        _body: &mut Body<'_, R>,
+        _response: &mut [u8],
+    ) -> Response {
+        Response::empty(Status::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::{
+        mock::{MockSocket, assert_text_snapshot},
+        net::{HeaterStatus, http::handle_connection},
+    };
+
+    struct Source(PrinterMetrics);
+
+    impl MetricsSource for Source {
+        async fn metrics(&mut self) -> PrinterMetrics {
+            self.0
+        }
+    }
+
+    fn metrics() -> PrinterMetrics {
+        let mut tmc_errors = [0; TMCError::COUNT];
+        tmc_errors[TMCError::CrcDoesNotMatch.index()] = 3;
+        tmc_errors[TMCError::Timeout.index()] = 12;
+        tmc_errors[TMCError::WriteError(0, 0).index()] = 1;
+        PrinterMetrics {
+            status: PrinterStatus {
+                hotend: HeaterStatus {
+                    actual: 214.96,
+                    target: 215.0,
+                },
+                bed: HeaterStatus {
+                    actual: 59.94,
+                    target: 60.0,
+                },
+                board_temperature: 31.25,
+                bed_voltage: 23.96,
+                fans: [Some(4100.4), None],
+                filament_present: true,
+                ..Default::default()
+            },
+            heater_duty: [0.4567, 1.0],
+            pinda_contact: false,
+            tmc_errors,
+            thermal_faults: 2,
+            uptime: 3_600,
+        }
+    }
+
+    fn write(metrics: &PrinterMetrics) -> String {
+        let mut buf = [0; 4096];
+        let mut out = SliceWriter::new(&mut buf);
+        metrics.write(&mut out).unwrap();
+        let len = out.len();
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    fn get(
+        handler: &mut Metrics<Source, NotFound>,
+        request: &[u8],
+        response_buffer: usize,
+    ) -> String {
+        let mut socket = MockSocket::new(&[request]);
+        let mut request = [0; 128];
+        let mut response = vec![0; response_buffer];
+        block_on(handle_connection(
+            &mut socket,
+            handler,
+            &mut request,
+            &mut response,
+        ))
+        .unwrap();
+        socket.output_str().to_string()
+    }
+
+    #[test]
+    fn writes_the_exposition_format() {
+        assert_text_snapshot(&write(&metrics()), "metrics");
+    }
+
+    #[test]
+    fn writes_values_that_are_not_numbers() {
+        let mut metrics = metrics();
+        metrics.status.hotend.actual = f64::NAN;
+        metrics.status.bed.actual = f64::INFINITY;
+        metrics.status.board_temperature = f64::NEG_INFINITY;
+        let text = write(&metrics);
+        assert!(text.contains("buddy_temperature_celsius{sensor=\"hotend\"} NaN\n"));
+        assert!(text.contains("buddy_temperature_celsius{sensor=\"bed\"} +Inf\n"));
+        assert!(text.contains("buddy_temperature_celsius{sensor=\"board\"} -Inf\n"));
+    }
+
+    #[test]
+    fn reports_a_full_buffer() {
+        let text = write(&metrics());
+        let mut buf = vec![0; text.len() - 1];
+        let mut out = SliceWriter::new(&mut buf);
+        assert!(metrics().write(&mut out).is_err());
+    }
+
+    #[test]
+    fn serves_the_metrics() {
+        let mut handler = Metrics::new(Source(metrics()), NotFound);
+        let response = get(&mut handler, b"GET /metrics HTTP/1.1\r\n\r\n", 4096);
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains(&format!("Content-Type: {METRICS_CONTENT_TYPE}\r\n")));
+        assert_eq!(body, write(&metrics()));
+
+        let response = get(&mut handler, b"HEAD /metrics HTTP/1.1\r\n\r\n", 4096);
+        assert!(response.ends_with(&format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )));
+    }
+
+    #[test]
+    fn answers_other_requests() {
+        let mut handler = Metrics::new(Source(metrics()), NotFound);
+        let response = get(&mut handler, b"POST /metrics HTTP/1.1\r\n\r\n", 4096);
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        let response = get(&mut handler, b"GET /api/status HTTP/1.1\r\n\r\n", 4096);
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        // The metrics do not fit in the response buffer.
+        let response = get(&mut handler, b"GET /metrics HTTP/1.1\r\n\r\n", 512);
+        assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    }
+}
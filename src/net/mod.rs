@@ -0,0 +1,36 @@
+#![doc = include_str!("../../docs/net.md")]
+#[cfg(feature = "http-api")]
+mod api;
+#[cfg(feature = "gcode-console")]
+pub mod console;
+mod error;
+#[cfg(feature = "http-api")]
+mod files;
+mod gcode;
+#[cfg(feature = "http-api")]
+pub mod http;
+mod json;
+#[cfg(feature = "mdns")]
+pub mod mdns;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "http-api")]
+mod prusa_link;
+#[cfg(feature = "sntp")]
+pub mod sntp;
+mod status;
+
+#[cfg(feature = "http-api")]
+pub use api::*;
+pub use error::*;
+#[cfg(feature = "http-api")]
+pub use files::*;
+pub use gcode::*;
+pub use json::*;
+#[cfg(feature = "metrics")]
+pub use metrics::*;
+#[cfg(feature = "http-api")]
+pub use prusa_link::*;
+pub use status::*;
@@ -0,0 +1,330 @@
+use embassy_time::{Duration, Instant, WithTimeout};
+use embedded_io_async::{Read, Write};
+
+use crate::net::{
+    MqttError,
+    mqtt::{
+        ConnectOptions, DISCONNECT, PINGREQ, Packet, QoS, decode, encode_connect, encode_puback,
+        encode_publish, encode_subscribe,
+    },
+};
+
+/// How long to wait for the broker to acknowledge a packet.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A message published to a topic the client subscribed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Message<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+    pub retain: bool,
+    /// The packet ID of a QoS 1 message, which the client acknowledges.
+    pub packet_id: Option<u16>,
+}
+
+/// The packets received from the broker that have not been handled yet.
+///
+/// Acknowledgements can arrive while a PUBLISH is waiting to be read, so `take` picks them out and leaves the PUBLISH
+/// packets in order for `hold`.
+pub struct Inbox<'b> {
+    buf: &'b mut [u8],
+    filled: usize,
+    /// The length of the PUBLISH returned by `held`, which is dropped on the next call.
+    held: usize,
+}
+
+impl<'b> Inbox<'b> {
+    pub fn new(buf: &'b mut [u8]) -> Self {
+        Self {
+            buf,
+            filled: 0,
+            held: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.filled = 0;
+        self.held = 0;
+    }
+
+    /// Return the free space at the end of the buffer to receive into.
+    pub fn space(&mut self) -> &mut [u8] {
+        self.release();
+        &mut self.buf[self.filled..]
+    }
+
+    /// Mark `n` bytes of the space as received.
+    pub fn fill(&mut self, n: usize) {
+        self.filled = (self.filled + n).min(self.buf.len());
+    }
+
+    /// Remove every whole packet other than PUBLISH, passing each to `f`. Returns whether `f` returned `true` for any
+    /// of them.
+    pub fn take(&mut self, mut f: impl FnMut(&Packet) -> bool) -> Result<bool, MqttError> {
+        self.release();
+        let mut found = false;
+        let mut offset = 0;
+        while let Some((packet, len)) = decode(&self.buf[offset..self.filled])? {
+            if matches!(packet, Packet::Publish { .. }) {
+                offset += len;
+                continue;
+            }
+            found |= f(&packet);
+            self.buf.copy_within(offset + len..self.filled, offset);
+            self.filled -= len;
+        }
+        Ok(found)
+    }
+
+    /// Hold the first packet if it is a whole PUBLISH, so `held` can return it until the buffer is next used. Returns
+    /// whether there was one.
+    pub fn hold(&mut self) -> Result<bool, MqttError> {
+        self.release();
+        if let Some((Packet::Publish { .. }, len)) = decode(&self.buf[..self.filled])? {
+            self.held = len;
+        }
+        Ok(self.held > 0)
+    }
+
+    /// Return the message held by `hold`.
+    pub fn held(&self) -> Option<Message<'_>> {
+        match decode(&self.buf[..self.held]) {
+            Ok(Some((
+                Packet::Publish {
+                    topic,
+                    payload,
+                    retain,
+                    packet_id,
+                    ..
+                },
+                _,
+            ))) => Some(Message {
+                topic,
+                payload,
+                retain,
+                packet_id,
+            }),
+            _ => None,
+        }
+    }
+
+    fn release(&mut self) {
+        if self.held > 0 {
+            self.buf.copy_within(self.held..self.filled, 0);
+            self.filled -= self.held;
+            self.held = 0;
+        }
+    }
+}
+
+/// An MQTT 3.1.1 client over a connected socket. Messages are sent at QoS 0 or 1 and one packet is in flight at a
+/// time, so the buffers only need to hold the largest packet.
+///
+/// ```rust,ignore
+/// let mut client = MqttClient::new(&mut socket, &mut rx, &mut tx);
+/// client.connect(&options).await?;
+/// client.subscribe("buddy/gcode", QoS::AtLeastOnce).await?;
+/// client.publish("buddy/temperature/bed", b"60.1", QoS::AtMostOnce, false).await?;
+/// if let Some(message) = client.receive(Instant::now() + Duration::from_secs(5)).await? {
+///     info!("{}", message.topic);
+/// }
+/// ```
+pub struct MqttClient<'b, T: Read + Write> {
+    socket: T,
+    inbox: Inbox<'b>,
+    tx: &'b mut [u8],
+    keep_alive: Duration,
+    last_sent: Instant,
+    ping_pending: bool,
+    next_id: u16,
+}
+
+impl<'b, T: Read + Write> MqttClient<'b, T> {
+    pub fn new(socket: T, rx_buffer: &'b mut [u8], tx_buffer: &'b mut [u8]) -> Self {
+        Self {
+            socket,
+            inbox: Inbox::new(rx_buffer),
+            tx: tx_buffer,
+            keep_alive: Duration::from_secs(0),
+            last_sent: Instant::now(),
+            ping_pending: false,
+            next_id: 1,
+        }
+    }
+
+    /// Open a session with the broker. Returns whether the broker still held a session for the client.
+    pub async fn connect(&mut self, options: &ConnectOptions<'_>) -> Result<bool, MqttError> {
+        self.inbox.clear();
+        self.ping_pending = false;
+        let len = encode_connect(self.tx, options)?;
+        self.send(len).await?;
+        let mut result = None;
+        self.wait_for(|packet| match *packet {
+            Packet::ConnAck {
+                session_present,
+                code,
+            } => {
+                result = Some((session_present, code));
+                true
+            }
+            _ => false,
+        })
+        .await?;
+        let (session_present, code) = result.ok_or(MqttError::Malformed)?;
+        if code != 0 {
+            return Err(MqttError::Refused(code));
+        }
+        self.keep_alive = Duration::from_secs(options.keep_alive as u64);
+        Ok(session_present)
+    }
+
+    /// Publish a message. QoS 1 messages wait for the broker to acknowledge them.
+    pub async fn publish(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        qos: QoS,
+        retain: bool,
+    ) -> Result<(), MqttError> {
+        let packet_id = match qos {
+            QoS::AtMostOnce => 0,
+            QoS::AtLeastOnce => self.next_id(),
+        };
+        let len = encode_publish(self.tx, topic, payload, qos, retain, packet_id, false)?;
+        self.send(len).await?;
+        if qos == QoS::AtLeastOnce {
+            self.wait_for(|packet| *packet == Packet::PubAck { packet_id })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Subscribe to a topic filter and wait for the broker to accept it.
+    pub async fn subscribe(&mut self, filter: &str, qos: QoS) -> Result<(), MqttError> {
+        let id = self.next_id();
+        let len = encode_subscribe(self.tx, id, filter, qos)?;
+        self.send(len).await?;
+        let mut granted = None;
+        self.wait_for(|packet| match *packet {
+            Packet::SubAck {
+                packet_id,
+                granted: g,
+            } if packet_id == id => {
+                granted = g;
+                true
+            }
+            _ => false,
+        })
+        .await?;
+        granted.map(|_| ()).ok_or(MqttError::SubscribeFailed)
+    }
+
+    /// Wait until `until` for a message, pinging the broker to keep the connection alive. Returns `None` if no
+    /// message arrived in time.
+    pub async fn receive(&mut self, until: Instant) -> Result<Option<Message<'_>>, MqttError> {
+        loop {
+            let ping_pending = &mut self.ping_pending;
+            self.inbox.take(|packet| {
+                if *packet == Packet::PingResp {
+                    *ping_pending = false;
+                }
+                false
+            })?;
+            if self.inbox.hold()? {
+                if let Some(packet_id) = self.inbox.held().and_then(|m| m.packet_id) {
+                    let len = encode_puback(self.tx, packet_id)?;
+                    self.send(len).await?;
+                }
+                return Ok(self.inbox.held());
+            }
+            if Instant::now() >= until {
+                return Ok(None);
+            }
+            self.poll(until).await?;
+        }
+    }
+
+    /// Tell the broker the client is leaving and give the socket back.
+    pub async fn disconnect(mut self) -> Result<T, MqttError> {
+        self.tx[..DISCONNECT.len()].copy_from_slice(&DISCONNECT);
+        self.send(DISCONNECT.len()).await?;
+        Ok(self.socket)
+    }
+
+    fn next_id(&mut self) -> u16 {
+        let id = self.next_id;
+        // Zero is not a valid packet ID.
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        id
+    }
+
+    async fn send(&mut self, len: usize) -> Result<(), MqttError> {
+        self.socket
+            .write_all(&self.tx[..len])
+            .await
+            .map_err(|_| MqttError::Socket)?;
+        self.socket.flush().await.map_err(|_| MqttError::Socket)?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    async fn read(&mut self) -> Result<(), MqttError> {
+        let space = self.inbox.space();
+        if space.is_empty() {
+            return Err(MqttError::BufferFull);
+        }
+        match self.socket.read(space).await {
+            Ok(0) => Err(MqttError::ConnectionClosed),
+            Ok(n) => {
+                self.inbox.fill(n);
+                Ok(())
+            }
+            Err(_) => Err(MqttError::Socket),
+        }
+    }
+
+    /// Read until `f` matches a packet from the broker.
+    async fn wait_for(&mut self, mut f: impl FnMut(&Packet) -> bool) -> Result<(), MqttError> {
+        async {
+            loop {
+                let ping_pending = &mut self.ping_pending;
+                let found = self.inbox.take(|packet| {
+                    if *packet == Packet::PingResp {
+                        *ping_pending = false;
+                    }
+                    f(packet)
+                })?;
+                if found {
+                    return Ok(());
+                }
+                self.read().await?;
+            }
+        }
+        .with_timeout(RESPONSE_TIMEOUT)
+        .await
+        .map_err(|_| MqttError::Timeout)?
+    }
+
+    /// Read until `until`, sending a PINGREQ after half the keep alive without sending anything. The broker has
+    /// until the next ping is due to answer.
+    async fn poll(&mut self, until: Instant) -> Result<(), MqttError> {
+        let ping_at =
+            (self.keep_alive.as_ticks() > 0).then(|| self.last_sent + self.keep_alive / 2);
+        let deadline = match ping_at {
+            Some(ping_at) if ping_at < until => ping_at,
+            _ => until,
+        };
+        match self.read().with_deadline(deadline).await {
+            Ok(result) => result,
+            Err(_) if Some(deadline) == ping_at => {
+                if self.ping_pending {
+                    return Err(MqttError::Timeout);
+                }
+                self.ping_pending = true;
+                self.tx[..PINGREQ.len()].copy_from_slice(&PINGREQ);
+                self.send(PINGREQ.len()).await
+            }
+            Err(_) => Ok(()),
+        }
+    }
+}
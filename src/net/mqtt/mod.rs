@@ -0,0 +1,7 @@
+mod client;
+mod packet;
+mod telemetry;
+
+pub use client::*;
+pub use packet::*;
+pub use telemetry::*;
@@ -0,0 +1,507 @@
+use crate::net::MqttError;
+
+/// The quality of service of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum QoS {
+    /// Sent once with no acknowledgement.
+    AtMostOnce = 0,
+    /// Resent until the receiver acknowledges it. May arrive more than once.
+    AtLeastOnce = 1,
+}
+
+/// The fields of a CONNECT packet.
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectOptions<'a> {
+    pub client_id: &'a str,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a [u8]>,
+    /// The longest time between packets from the client before the broker drops it (s). Zero disables the keep
+    /// alive.
+    pub keep_alive: u16,
+    /// Discard any session the broker holds for the client.
+    pub clean_session: bool,
+}
+
+/// A packet received from the broker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packet<'a> {
+    ConnAck {
+        session_present: bool,
+        code: u8,
+    },
+    Publish {
+        topic: &'a str,
+        payload: &'a [u8],
+        qos: QoS,
+        retain: bool,
+        packet_id: Option<u16>,
+    },
+    PubAck {
+        packet_id: u16,
+    },
+    SubAck {
+        packet_id: u16,
+        /// The QoS granted, or `None` if the broker rejected the subscription.
+        granted: Option<QoS>,
+    },
+    PingResp,
+    /// Any other packet type. These are not expected from a broker and are ignored.
+    Other(u8),
+}
+
+/// Writes packets into a fixed buffer.
+struct Writer<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn u8(&mut self, b: u8) -> Result<(), MqttError> {
+        self.bytes(&[b])
+    }
+
+    fn u16(&mut self, v: u16) -> Result<(), MqttError> {
+        self.bytes(&v.to_be_bytes())
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), MqttError> {
+        let end = self.len + bytes.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(MqttError::BufferFull)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    /// Write a length prefixed string or binary field.
+    fn field(&mut self, bytes: &[u8]) -> Result<(), MqttError> {
+        let len = u16::try_from(bytes.len()).map_err(|_| MqttError::BufferFull)?;
+        self.u16(len)?;
+        self.bytes(bytes)
+    }
+
+    fn header(&mut self, first: u8, remaining: usize) -> Result<(), MqttError> {
+        if remaining > MAX_REMAINING {
+            return Err(MqttError::BufferFull);
+        }
+        self.u8(first)?;
+        let mut remaining = remaining;
+        loop {
+            let mut b = (remaining % 128) as u8;
+            remaining /= 128;
+            if remaining > 0 {
+                b |= 0x80;
+            }
+            self.u8(b)?;
+            if remaining == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// The largest remaining length that fits in the four length bytes.
+const MAX_REMAINING: usize = 268_435_455;
+
+/// Encode a CONNECT packet, returning its length.
+pub fn encode_connect(buf: &mut [u8], options: &ConnectOptions) -> Result<usize, MqttError> {
+    let mut flags = 0;
+    let mut remaining = 10 + 2 + options.client_id.len();
+    if let Some(username) = options.username {
+        flags |= 0x80;
+        remaining += 2 + username.len();
+    }
+    if let Some(password) = options.password {
+        flags |= 0x40;
+        remaining += 2 + password.len();
+    }
+    if options.clean_session {
+        flags |= 0x02;
+    }
+    let mut w = Writer { buf, len: 0 };
+    w.header(0x10, remaining)?;
+    w.field(b"MQTT")?;
+    // Protocol level 4 is MQTT 3.1.1.
+    w.u8(4)?;
+    w.u8(flags)?;
+    w.u16(options.keep_alive)?;
+    w.field(options.client_id.as_bytes())?;
+    if let Some(username) = options.username {
+        w.field(username.as_bytes())?;
+    }
+    if let Some(password) = options.password {
+        w.field(password)?;
+    }
+    Ok(w.len)
+}
+
+/// Encode a PUBLISH packet, returning its length. QoS 1 messages need a packet ID.
+pub fn encode_publish(
+    buf: &mut [u8],
+    topic: &str,
+    payload: &[u8],
+    qos: QoS,
+    retain: bool,
+    packet_id: u16,
+    dup: bool,
+) -> Result<usize, MqttError> {
+    let with_id = qos != QoS::AtMostOnce;
+    let remaining = 2 + topic.len() + if with_id { 2 } else { 0 } + payload.len();
+    let first = 0x30 | ((dup as u8) << 3) | ((qos as u8) << 1) | retain as u8;
+    let mut w = Writer { buf, len: 0 };
+    w.header(first, remaining)?;
+    w.field(topic.as_bytes())?;
+    if with_id {
+        w.u16(packet_id)?;
+    }
+    w.bytes(payload)?;
+    Ok(w.len)
+}
+
+/// Encode a SUBSCRIBE packet for a single topic filter, returning its length.
+pub fn encode_subscribe(
+    buf: &mut [u8],
+    packet_id: u16,
+    filter: &str,
+    qos: QoS,
+) -> Result<usize, MqttError> {
+    let mut w = Writer { buf, len: 0 };
+    w.header(0x82, 2 + 2 + filter.len() + 1)?;
+    w.u16(packet_id)?;
+    w.field(filter.as_bytes())?;
+    w.u8(qos as u8)?;
+    Ok(w.len)
+}
+
+/// Encode a PUBACK packet, returning its length.
+pub fn encode_puback(buf: &mut [u8], packet_id: u16) -> Result<usize, MqttError> {
+    let mut w = Writer { buf, len: 0 };
+    w.header(0x40, 2)?;
+    w.u16(packet_id)?;
+    Ok(w.len)
+}
+
+pub const PINGREQ: [u8; 2] = [0xC0, 0x00];
+pub const DISCONNECT: [u8; 2] = [0xE0, 0x00];
+
+/// Decode the packet at the start of the buffer, returning it and its length, or `None` if the buffer does not yet
+/// hold the whole packet.
+pub fn decode(buf: &[u8]) -> Result<Option<(Packet<'_>, usize)>, MqttError> {
+    let Some(&first) = buf.first() else {
+        return Ok(None);
+    };
+    let mut remaining = 0usize;
+    let mut header = 1;
+    loop {
+        let Some(&b) = buf.get(header) else {
+            return Ok(None);
+        };
+        remaining |= ((b & 0x7F) as usize) << (7 * (header - 1));
+        header += 1;
+        if b & 0x80 == 0 {
+            break;
+        }
+        if header == 5 {
+            return Err(MqttError::Malformed);
+        }
+    }
+    let len = header + remaining;
+    let Some(body) = buf.get(header..len) else {
+        return Ok(None);
+    };
+    let u16_at = |i: usize| -> Result<u16, MqttError> {
+        body.get(i..i + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or(MqttError::Malformed)
+    };
+    let packet = match first >> 4 {
+        2 => Packet::ConnAck {
+            session_present: body.first().ok_or(MqttError::Malformed)? & 0x01 != 0,
+            code: *body.get(1).ok_or(MqttError::Malformed)?,
+        },
+        3 => {
+            let qos = match (first >> 1) & 0x03 {
+                0 => QoS::AtMostOnce,
+                1 => QoS::AtLeastOnce,
+                // QoS 2 is never requested so the broker will not send it.
+                _ => return Err(MqttError::Malformed),
+            };
+            let topic_len = u16_at(0)? as usize;
+            let topic = body.get(2..2 + topic_len).ok_or(MqttError::Malformed)?;
+            let topic = core::str::from_utf8(topic).map_err(|_| MqttError::Malformed)?;
+            let mut offset = 2 + topic_len;
+            let packet_id = if qos == QoS::AtMostOnce {
+                None
+            } else {
+                offset += 2;
+                Some(u16_at(offset - 2)?)
+            };
+            Packet::Publish {
+                topic,
+                payload: &body[offset..],
+                qos,
+                retain: first & 0x01 != 0,
+                packet_id,
+            }
+        }
+        4 => Packet::PubAck {
+            packet_id: u16_at(0)?,
+        },
+        9 => Packet::SubAck {
+            packet_id: u16_at(0)?,
+            granted: match body.get(2).ok_or(MqttError::Malformed)? {
+                0 => Some(QoS::AtMostOnce),
+                1 => Some(QoS::AtLeastOnce),
+                _ => None,
+            },
+        },
+        13 => Packet::PingResp,
+        t => Packet::Other(t),
+    };
+    Ok(Some((packet, len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(remaining: usize) -> Result<Vec<u8>, MqttError> {
+        let mut buf = [0; 5];
+        let mut w = Writer {
+            buf: &mut buf,
+            len: 0,
+        };
+        w.header(0x30, remaining)?;
+        let len = w.len;
+        Ok(buf[1..len].to_vec())
+    }
+
+    #[test]
+    fn encodes_remaining_lengths() {
+        // The examples from section 2.2.3 of the MQTT 3.1.1 specification.
+        assert_eq!(header(0), Ok(vec![0x00]));
+        assert_eq!(header(127), Ok(vec![0x7F]));
+        assert_eq!(header(128), Ok(vec![0x80, 0x01]));
+        assert_eq!(header(16_383), Ok(vec![0xFF, 0x7F]));
+        assert_eq!(header(16_384), Ok(vec![0x80, 0x80, 0x01]));
+        assert_eq!(header(2_097_151), Ok(vec![0xFF, 0xFF, 0x7F]));
+        assert_eq!(header(2_097_152), Ok(vec![0x80, 0x80, 0x80, 0x01]));
+        assert_eq!(header(MAX_REMAINING), Ok(vec![0xFF, 0xFF, 0xFF, 0x7F]));
+        assert_eq!(header(MAX_REMAINING + 1), Err(MqttError::BufferFull));
+    }
+
+    #[test]
+    fn encodes_connect() {
+        let mut buf = [0; 64];
+        let options = ConnectOptions {
+            client_id: "buddy",
+            username: Some("u"),
+            password: Some(b"p"),
+            keep_alive: 60,
+            clean_session: true,
+        };
+        let len = encode_connect(&mut buf, &options).unwrap();
+        assert_eq!(
+            &buf[..len],
+            b"\x10\x17\x00\x04MQTT\x04\xC2\x00\x3C\x00\x05buddy\x00\x01u\x00\x01p"
+        );
+
+        let options = ConnectOptions {
+            username: None,
+            password: None,
+            keep_alive: 0,
+            clean_session: false,
+            ..options
+        };
+        let len = encode_connect(&mut buf, &options).unwrap();
+        assert_eq!(
+            &buf[..len],
+            b"\x10\x11\x00\x04MQTT\x04\x00\x00\x00\x00\x05buddy"
+        );
+        assert_eq!(
+            encode_connect(&mut buf[..len - 1], &options),
+            Err(MqttError::BufferFull)
+        );
+    }
+
+    #[test]
+    fn round_trips_publish() {
+        let mut buf = [0; 300];
+        let len = encode_publish(&mut buf, "a/b", b"hi", QoS::AtMostOnce, false, 0, false).unwrap();
+        assert_eq!(&buf[..len], b"\x30\x07\x00\x03a/bhi");
+        assert_eq!(
+            decode(&buf[..len]),
+            Ok(Some((
+                Packet::Publish {
+                    topic: "a/b",
+                    payload: b"hi",
+                    qos: QoS::AtMostOnce,
+                    retain: false,
+                    packet_id: None,
+                },
+                len
+            )))
+        );
+
+        let len = encode_publish(&mut buf, "a/b", b"hi", QoS::AtLeastOnce, true, 10, true).unwrap();
+        assert_eq!(&buf[..len], b"\x3B\x09\x00\x03a/b\x00\x0Ahi");
+        assert_eq!(
+            decode(&buf[..len]),
+            Ok(Some((
+                Packet::Publish {
+                    topic: "a/b",
+                    payload: b"hi",
+                    qos: QoS::AtLeastOnce,
+                    retain: true,
+                    packet_id: Some(10),
+                },
+                len
+            )))
+        );
+
+        // A payload long enough to need a second length byte.
+        let payload = [b'x'; 200];
+        let len =
+            encode_publish(&mut buf, "t", &payload, QoS::AtMostOnce, false, 0, false).unwrap();
+        assert_eq!(&buf[..4], b"\x30\xCB\x01\x00");
+        assert_eq!(len, 3 + 203);
+        let Ok(Some((
+            Packet::Publish {
+                payload: decoded, ..
+            },
+            n,
+        ))) = decode(&buf[..len])
+        else {
+            panic!("expected a publish");
+        };
+        assert_eq!((decoded, n), (&payload[..], len));
+        assert_eq!(
+            encode_publish(
+                &mut buf[..len - 1],
+                "t",
+                &payload,
+                QoS::AtMostOnce,
+                false,
+                0,
+                false
+            ),
+            Err(MqttError::BufferFull)
+        );
+    }
+
+    #[test]
+    fn encodes_subscribe_and_puback() {
+        let mut buf = [0; 32];
+        let len = encode_subscribe(&mut buf, 1, "buddy/gcode", QoS::AtLeastOnce).unwrap();
+        assert_eq!(&buf[..len], b"\x82\x10\x00\x01\x00\x0Bbuddy/gcode\x01");
+        // Brokers never send SUBSCRIBE, so it comes back as an unknown packet.
+        assert_eq!(decode(&buf[..len]), Ok(Some((Packet::Other(8), len))));
+
+        let len = encode_puback(&mut buf, 0x1234).unwrap();
+        assert_eq!(&buf[..len], b"\x40\x02\x12\x34");
+        assert_eq!(
+            decode(&buf[..len]),
+            Ok(Some((Packet::PubAck { packet_id: 0x1234 }, 4)))
+        );
+    }
+
+    #[test]
+    fn decodes_broker_packets() {
+        assert_eq!(
+            decode(b"\x20\x02\x01\x00"),
+            Ok(Some((
+                Packet::ConnAck {
+                    session_present: true,
+                    code: 0
+                },
+                4
+            )))
+        );
+        assert_eq!(
+            decode(b"\x20\x02\x00\x05"),
+            Ok(Some((
+                Packet::ConnAck {
+                    session_present: false,
+                    code: 5
+                },
+                4
+            )))
+        );
+        assert_eq!(
+            decode(b"\x90\x03\x00\x01\x01"),
+            Ok(Some((
+                Packet::SubAck {
+                    packet_id: 1,
+                    granted: Some(QoS::AtLeastOnce)
+                },
+                5
+            )))
+        );
+        assert_eq!(
+            decode(b"\x90\x03\x00\x01\x80"),
+            Ok(Some((
+                Packet::SubAck {
+                    packet_id: 1,
+                    granted: None
+                },
+                5
+            )))
+        );
+        assert_eq!(decode(&[0xD0, 0x00]), Ok(Some((Packet::PingResp, 2))));
+        // Only the first of several packets is decoded.
+        assert_eq!(
+            decode(b"\xD0\x00\x40\x02\x00\x01"),
+            Ok(Some((Packet::PingResp, 2)))
+        );
+    }
+
+    #[test]
+    fn waits_for_the_whole_packet() {
+        let mut buf = [0; 300];
+        let len = encode_publish(
+            &mut buf,
+            "t",
+            &[b'x'; 200],
+            QoS::AtLeastOnce,
+            false,
+            1,
+            false,
+        )
+        .unwrap();
+        for end in 0..len {
+            assert_eq!(decode(&buf[..end]), Ok(None), "{end}");
+        }
+        // Up to four length bytes may arrive before the body.
+        assert_eq!(decode(&[0x30, 0xFF, 0xFF, 0xFF]), Ok(None));
+        assert_eq!(decode(&[0x30, 0xFF, 0xFF, 0xFF, 0x7F, 0x00]), Ok(None));
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        // A fifth length byte is not allowed, so a fourth with the continuation bit set is an error.
+        assert_eq!(
+            decode(&[0x30, 0x80, 0x80, 0x80, 0x80]),
+            Err(MqttError::Malformed)
+        );
+        assert_eq!(
+            decode(&[0x30, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]),
+            Err(MqttError::Malformed)
+        );
+        for packet in [
+            // The topic runs past the end of the packet.
+            &b"\x30\x03\x00\x05a"[..],
+            // QoS 1 without a packet ID.
+            b"\x32\x03\x00\x01a",
+            // QoS 2.
+            b"\x34\x05\x00\x01a\x00\x01",
+            // The topic is not UTF-8.
+            b"\x30\x03\x00\x01\xFF",
+            b"\x20\x01\x00",
+            b"\x40\x01\x00",
+            b"\x90\x02\x00\x01",
+        ] {
+            assert_eq!(decode(packet), Err(MqttError::Malformed), "{packet:?}");
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use core::{convert::Infallible, fmt::Write as _};
+
+use embassy_net::{IpEndpoint, Stack, tcp::TcpSocket};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::{
+    Board,
+    fmt::{error, info},
+    net::{
+        GcodeSink, JsonWriter, MqttError, PrinterStatus, SliceWriter, clean_line,
+        mqtt::{ConnectOptions, MqttClient, QoS},
+    },
+};
+
+/// How long the broker may leave the socket without acknowledging data before it is dropped.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A delay that doubles after each failed attempt, up to a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+            current: min,
+        }
+    }
+
+    /// Return the delay before the next attempt.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    /// Go back to the shortest delay after a successful attempt.
+    pub fn reset(&mut self) {
+        self.current = self.min;
+    }
+}
+
+/// The topics the telemetry is published to, and the topic G-code is read from.
+#[derive(Debug, Clone, Copy)]
+pub struct TelemetryTopics<'a> {
+    pub hotend_temperature: &'a str,
+    pub bed_temperature: &'a str,
+    pub board_temperature: &'a str,
+    pub bed_voltage: &'a str,
+    pub fans: [&'a str; 2],
+    /// The driver faults as JSON, in the form of `PrinterStatus::write_drivers_json`.
+    pub drivers: &'a str,
+    /// Each line of a message to this topic is sent to the `GcodeSink`.
+    pub command: &'a str,
+}
+
+impl Default for TelemetryTopics<'static> {
+    fn default() -> Self {
+        Self {
+            hotend_temperature: "buddy/temperature/hotend",
+            bed_temperature: "buddy/temperature/bed",
+            board_temperature: "buddy/temperature/board",
+            bed_voltage: "buddy/bed/voltage",
+            fans: ["buddy/fan/0", "buddy/fan/1"],
+            drivers: "buddy/drivers",
+            command: "buddy/gcode",
+        }
+    }
+}
+
+/// Publish the board's sensors to a broker every `interval` and send G-code from the command topic to `gcode`. The
+/// connection is retried with a growing delay whenever it fails.
+///
+/// `rx_buffer` and `tx_buffer` are for the socket, and `mqtt_rx_buffer` and `mqtt_tx_buffer` must each hold the
+/// largest packet.
+#[allow(clippy::too_many_arguments)]
+pub async fn run<G: GcodeSink>(
+    stack: Stack<'_>,
+    broker: IpEndpoint,
+    options: &ConnectOptions<'_>,
+    topics: &TelemetryTopics<'_>,
+    interval: Duration,
+    board: &Board<'_>,
+    gcode: &mut G,
+    rx_buffer: &mut [u8],
+    tx_buffer: &mut [u8],
+    mqtt_rx_buffer: &mut [u8],
+    mqtt_tx_buffer: &mut [u8],
+) -> ! {
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+    loop {
+        let mut socket = TcpSocket::new(stack, rx_buffer, tx_buffer);
+        socket.set_timeout(Some(TIMEOUT));
+        if socket.connect(broker).await.is_err() {
+            error!("[MQTT] Failed to connect to the broker");
+        } else {
+            let mut client = MqttClient::new(&mut socket, mqtt_rx_buffer, mqtt_tx_buffer);
+            let Err(e) = session(
+                &mut client,
+                options,
+                topics,
+                interval,
+                board,
+                gcode,
+                &mut backoff,
+            )
+            .await;
+            error!("[MQTT] {}", e);
+            socket.abort();
+            let _ = socket.flush().await;
+        }
+        Timer::after(backoff.next_delay()).await;
+    }
+}
+
+async fn session<G: GcodeSink>(
+    client: &mut MqttClient<'_, &mut TcpSocket<'_>>,
+    options: &ConnectOptions<'_>,
+    topics: &TelemetryTopics<'_>,
+    interval: Duration,
+    board: &Board<'_>,
+    gcode: &mut G,
+    backoff: &mut Backoff,
+) -> Result<Infallible, MqttError> {
+    client.connect(options).await?;
+    client.subscribe(topics.command, QoS::AtLeastOnce).await?;
+    info!("[MQTT] Connected");
+    backoff.reset();
+    let mut next_publish = Instant::now();
+    loop {
+        if Instant::now() >= next_publish {
+            let status = PrinterStatus::read(board).await;
+            publish_status(client, topics, &status).await?;
+            next_publish = (next_publish + interval).max(Instant::now());
+        }
+        if let Some(message) = client.receive(next_publish).await? {
+            if message.topic != topics.command {
+                continue;
+            }
+            let Ok(payload) = core::str::from_utf8(message.payload) else {
+                error!("[MQTT] The G-code is not UTF-8");
+                continue;
+            };
+            for line in payload.lines().filter_map(clean_line) {
+                if let Err(e) = gcode.send(line).await {
+                    error!("[MQTT] {}: {}", line, e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn publish_status(
+    client: &mut MqttClient<'_, &mut TcpSocket<'_>>,
+    topics: &TelemetryTopics<'_>,
+    status: &PrinterStatus,
+) -> Result<(), MqttError> {
+    let mut buf = [0u8; 256];
+    let fans = status.fans.map(|rpm| rpm.unwrap_or(0.0));
+    for (topic, value, decimals) in [
+        (topics.hotend_temperature, status.hotend.actual, 1),
+        (topics.bed_temperature, status.bed.actual, 1),
+        (topics.board_temperature, status.board_temperature, 1),
+        (topics.bed_voltage, status.bed_voltage, 1),
+        (topics.fans[0], fans[0], 0),
+        (topics.fans[1], fans[1], 0),
+    ] {
+        let mut w = SliceWriter::new(&mut buf);
+        // Sensor readings are far shorter than the buffer.
+        let _ = write!(w, "{:.*}", decimals, value);
+        let len = w.len();
+        client
+            .publish(topic, &buf[..len], QoS::AtMostOnce, false)
+            .await?;
+    }
+    let mut json = JsonWriter::new(&mut buf);
+    status.write_drivers_json(&mut json);
+    let len = json.finish().map_err(|_| MqttError::BufferFull)?;
+    client
+        .publish(topics.drivers, &buf[..len], QoS::AtMostOnce, false)
+        .await
+}
@@ -0,0 +1,842 @@
+use embedded_io_async::Read;
+
+use crate::net::{
+    FileStore, GcodeError, JsonWriter, PrinterApi, PrinterStatus, RestApi, StorageError,
+    api::{error, finish},
+    http::{Body, Handler, Method, Request, Response, Status, percent_decode},
+};
+
+/// The version of the PrusaLink API implemented.
+const API_VERSION: &str = "2.0.0";
+
+/// The states reported by PrusaLink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum PrinterState {
+    Idle,
+    Busy,
+    Printing,
+    Paused,
+    Finished,
+    Stopped,
+    Error,
+    Attention,
+    Ready,
+}
+
+impl PrinterState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Idle => "IDLE",
+            Self::Busy => "BUSY",
+            Self::Printing => "PRINTING",
+            Self::Paused => "PAUSED",
+            Self::Finished => "FINISHED",
+            Self::Stopped => "STOPPED",
+            Self::Error => "ERROR",
+            Self::Attention => "ATTENTION",
+            Self::Ready => "READY",
+        }
+    }
+
+    /// Return whether a new print can be started.
+    pub fn can_print(&self) -> bool {
+        matches!(
+            self,
+            Self::Idle | Self::Finished | Self::Stopped | Self::Ready
+        )
+    }
+}
+
+/// The file being printed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JobFile<'a> {
+    /// The name on the storage.
+    pub name: &'a str,
+    /// The name as uploaded.
+    pub display_name: &'a str,
+    /// The directory on the storage, e.g. `/usb`.
+    pub path: &'a str,
+    pub size: u32,
+}
+
+/// The print job reported by PrusaLink.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JobInfo<'a> {
+    pub id: u32,
+    /// How far through the job (%).
+    pub progress: f32,
+    /// The time spent printing (s).
+    pub time_printing: u32,
+    /// The estimated time left (s).
+    pub time_remaining: Option<u32>,
+    pub file: JobFile<'a>,
+}
+
+/// What PrusaLink needs from the print controller on top of the [`PrinterApi`].
+#[allow(async_fn_in_trait)]
+pub trait PrusaLinkPrinter: PrinterApi {
+    fn state(&self) -> PrinterState;
+
+    /// Return the job being printed, if any.
+    fn job(&self) -> Option<JobInfo<'_>>;
+
+    /// Start printing an uploaded file.
+    async fn print(&mut self, name: &str) -> Result<(), GcodeError>;
+}
+
+/// The details the printer reports about itself.
+#[derive(Debug, Clone, Copy)]
+pub struct PrusaLinkConfig<'a> {
+    /// The key clients must send in the `X-Api-Key` header.
+    pub api_key: &'a str,
+    pub hostname: &'a str,
+    /// The name of the storage in file paths, e.g. `usb` for `/api/v1/files/usb/box.gcode`.
+    pub storage: &'a str,
+    /// The nozzle diameter (mm).
+    pub nozzle_diameter: f32,
+}
+
+/// The endpoints of the PrusaLink API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkRoute<'a> {
+    /// `GET /api/version`
+    Version,
+    /// `GET /api/v1/status`
+    Status,
+    /// `GET /api/v1/job`
+    Job,
+    /// `PUT /api/v1/files/{storage}/{path}`
+    Upload { storage: &'a str, path: &'a str },
+}
+
+/// Match a request to a PrusaLink endpoint. Returns `None` if the path is not part of PrusaLink.
+pub fn link_route(method: Method, path: &str) -> Option<Result<LinkRoute<'_>, Status>> {
+    let (route, allowed) = match path {
+        "/api/version" => (LinkRoute::Version, Method::Get),
+        "/api/v1/status" => (LinkRoute::Status, Method::Get),
+        "/api/v1/job" => (LinkRoute::Job, Method::Get),
+        _ => {
+            let file = path.strip_prefix("/api/v1/files/")?;
+            let route = match file.split_once('/') {
+                Some((storage, path)) if !path.is_empty() => LinkRoute::Upload { storage, path },
+                _ => return Some(Err(Status::NotFound)),
+            };
+            (route, Method::Put)
+        }
+    };
+    if method == allowed || (method == Method::Head && allowed == Method::Get) {
+        Some(Ok(route))
+    } else {
+        Some(Err(Status::MethodNotAllowed))
+    }
+}
+
+/// Write the `/api/version` response.
+pub fn write_version(config: &PrusaLinkConfig, json: &mut JsonWriter) {
+    json.begin_object()
+        .field_str("api", API_VERSION)
+        .field_str("server", env!("CARGO_PKG_VERSION"))
+        .field_f64("nozzle_diameter", config.nozzle_diameter as f64, 2)
+        .field_str("text", "PrusaLink")
+        .field_str("hostname", config.hostname)
+        .key("capabilities")
+        .begin_object()
+        .field_bool("upload-by-put", true)
+        .end_object()
+        .end_object();
+}
+
+/// Write the `/api/v1/status` response. The hotend fan is fan 1 and the print fan is fan 0.
+pub fn write_status(
+    config: &PrusaLinkConfig,
+    status: &PrinterStatus,
+    state: PrinterState,
+    job: Option<&JobInfo>,
+    json: &mut JsonWriter,
+) {
+    json.begin_object();
+    if let Some(job) = job {
+        json.key("job").begin_object();
+        write_job_progress(job, json);
+        json.end_object();
+    }
+    json.key("storage")
+        .begin_object()
+        .key("path")
+        .fmt_str(format_args!("/{}/", config.storage))
+        .field_str("name", config.storage)
+        .field_bool("read_only", false)
+        .end_object();
+    json.key("printer")
+        .begin_object()
+        .field_str("state", state.as_str())
+        .field_f64("temp_bed", status.bed.actual, 1)
+        .field_f64("target_bed", status.bed.target, 1)
+        .field_f64("temp_nozzle", status.hotend.actual, 1)
+        .field_f64("target_nozzle", status.hotend.target, 1)
+        .field_f64("fan_hotend", status.fans[1].unwrap_or(0.0), 0)
+        .field_f64("fan_print", status.fans[0].unwrap_or(0.0), 0)
+        .end_object()
+        .end_object();
+}
+
+/// Write the `/api/v1/job` response.
+pub fn write_job(
+    config: &PrusaLinkConfig,
+    state: PrinterState,
+    job: &JobInfo,
+    json: &mut JsonWriter,
+) {
+    json.begin_object();
+    write_job_progress(job, json);
+    json.field_str("state", state.as_str())
+        .key("file")
+        .begin_object()
+        .field_str("name", job.file.name)
+        .field_str("display_name", job.file.display_name)
+        .key("path");
+    if job.file.path.is_empty() {
+        json.fmt_str(format_args!("/{}", config.storage));
+    } else {
+        json.str(job.file.path);
+    }
+    json.field_u64("size", job.file.size as u64)
+        .end_object()
+        .end_object();
+}
+
+fn write_job_progress(job: &JobInfo, json: &mut JsonWriter) {
+    json.field_u64("id", job.id as u64)
+        .field_f64("progress", job.progress as f64, 1)
+        .field_u64("time_printing", job.time_printing as u64);
+    if let Some(remaining) = job.time_remaining {
+        json.field_u64("time_remaining", remaining as u64);
+    }
+}
+
+/// An HTTP [`Handler`] serving a subset of the PrusaLink API. Every request must carry the API key in the `X-Api-Key`
+/// header, and requests outside PrusaLink are passed on to the [`RestApi`].
+///
+/// | Endpoint | Response |
+/// | --- | --- |
+/// | `GET /api/version` | The API version, hostname and nozzle diameter |
+/// | `GET /api/v1/status` | The printer state, temperatures, fans and job progress |
+/// | `GET /api/v1/job` | The job and the file being printed, or `204` when there is none |
+/// | `PUT /api/v1/files/{storage}/{path}` | `201` once the body has been stored |
+///
+/// Uploads honour the `Overwrite: ?1` and `Print-After-Upload: ?1` headers.
+pub struct PrusaLink<'a, P: PrusaLinkPrinter, S: FileStore> {
+    api: RestApi<P>,
+    files: S,
+    config: PrusaLinkConfig<'a>,
+}
+
+impl<'a, P: PrusaLinkPrinter, S: FileStore> PrusaLink<'a, P, S> {
+    pub fn new(printer: P, files: S, config: PrusaLinkConfig<'a>) -> Self {
+        Self {
+            api: RestApi::new(printer),
+            files,
+            config,
+        }
+    }
+
+    pub fn printer(&mut self) -> &mut P {
+        self.api.printer()
+    }
+
+    pub fn files(&mut self) -> &mut S {
+        &mut self.files
+    }
+
+    async fn status(&mut self, response: &mut [u8]) -> Response {
+        let printer = self.api.printer();
+        let status = printer.status().await;
+        let mut json = JsonWriter::new(response);
+        write_status(
+            &self.config,
+            &status,
+            printer.state(),
+            printer.job().as_ref(),
+            &mut json,
+        );
+        finish(json, Status::Ok)
+    }
+
+    fn job(&mut self, response: &mut [u8]) -> Response {
+        let printer = self.api.printer();
+        let Some(job) = printer.job() else {
+            return Response::empty(Status::NoContent);
+        };
+        let mut json = JsonWriter::new(response);
+        write_job(&self.config, printer.state(), &job, &mut json);
+        finish(json, Status::Ok)
+    }
+
+    async fn upload<R: Read>(
+        &mut self,
+        request: &Request<'_>,
+        storage: &str,
+        path: &str,
+        body: &mut Body<'_, R>,
+        response: &mut [u8],
+    ) -> Response {
+        if storage != self.config.storage {
+            return error(response, Status::NotFound, "Unknown storage.");
+        }
+        let mut decoded = [0u8; 64];
+        let Ok(name) = percent_decode(path, &mut decoded) else {
+            return error(response, Status::BadRequest, "Invalid file name.");
+        };
+        // Files are kept in a single directory.
+        if name.contains('/') {
+            return error(response, Status::BadRequest, "Invalid file name.");
+        }
+        let mut stored = [0u8; 12];
+        let stored = match self.files.stored_name(name, &mut stored) {
+            Ok(stored) => stored,
+            Err(e) => return storage_error(response, e),
+        };
+        let print = request.header("Print-After-Upload") == Some("?1");
+        if print && !self.api.printer().state().can_print() {
+            return error(response, Status::Conflict, "The printer is busy.");
+        }
+        match self.files.exists(name).await {
+            Ok(true) if request.header("Overwrite") != Some("?1") => {
+                return error(response, Status::Conflict, "The file already exists.");
+            }
+            Ok(_) => {}
+            Err(e) => return storage_error(response, e),
+        }
+        if let Err(e) = self.files.create(name).await {
+            return storage_error(response, e);
+        }
+        loop {
+            let n = match body.read(response).await {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(_) => {
+                    self.files.abort(name).await;
+                    return Response::empty(Status::BadRequest);
+                }
+            };
+            if let Err(e) = self.files.write(&response[..n]).await {
+                self.files.abort(name).await;
+                return storage_error(response, e);
+            }
+        }
+        if let Err(e) = self.files.close().await {
+            return storage_error(response, e);
+        }
+        if print && self.api.printer().print(stored).await.is_err() {
+            return error(
+                response,
+                Status::Conflict,
+                "The print could not be started.",
+            );
+        }
+        Response::empty(Status::Created)
+    }
+}
+
+impl<P: PrusaLinkPrinter, S: FileStore> Handler for PrusaLink<'_, P, S> {
+    async fn handle<R: Read>(
+        &mut self,
+        request: &Request<'_>,
+        body: &mut Body<'_, R>,
+        response: &mut [u8],
+    ) -> Response {
+        if request.header("X-Api-Key") != Some(self.config.api_key) {
+            return Response::empty(Status::Unauthorized);
+        }
+        match link_route(request.method, request.path) {
+            Some(Ok(LinkRoute::Version)) => {
+                let mut json = JsonWriter::new(response);
+                write_version(&self.config, &mut json);
+                finish(json, Status::Ok)
+            }
+            Some(Ok(LinkRoute::Status)) => self.status(response).await,
+            Some(Ok(LinkRoute::Job)) => self.job(response),
+            Some(Ok(LinkRoute::Upload { storage, path })) => {
+                self.upload(request, storage, path, body, response).await
+            }
+            Some(Err(status)) => Response::empty(status),
+            None => self.api.handle(request, body, response).await,
+        }
+    }
+}
+
+fn storage_error(response: &mut [u8], e: StorageError) -> Response {
+    let status = match e {
+        StorageError::InvalidName => Status::BadRequest,
+        StorageError::NotFound => Status::NotFound,
+        StorageError::Full => Status::InsufficientStorage,
+        StorageError::NotOpen | StorageError::Device => Status::InternalServerError,
+    };
+    error(response, status, "The file could not be stored.")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use embassy_futures::block_on;
+
+    use super::*;
+    use embedded_sdmmc::VolumeManager;
+
+    use crate::{
+        components::flash::{FixedTimeSource, FlashBlockDevice, MIN_FILESYSTEM_SIZE, SECTOR_SIZE},
+        mock::{MockSocket, RamFlash},
+        net::{FlashFileStore, GcodeSink, HeaterId, HeaterStatus, http::handle_connection},
+    };
+
+    const SECTOR: usize = SECTOR_SIZE as usize;
+
+    const CONFIG: PrusaLinkConfig = PrusaLinkConfig {
+        api_key: "secret",
+        hostname: "buddy",
+        storage: "usb",
+        nozzle_diameter: 0.4,
+    };
+
+    const JOB: JobInfo = JobInfo {
+        id: 7,
+        progress: 42.5,
+        time_printing: 600,
+        time_remaining: Some(900),
+        file: JobFile {
+            name: "BOX.GCO",
+            display_name: "box.gcode",
+            path: "",
+            size: 1234,
+        },
+    };
+
+    struct Printer {
+        status: PrinterStatus,
+        state: PrinterState,
+        job: Option<JobInfo<'static>>,
+        lines: Vec<String>,
+        printing: Option<String>,
+    }
+
+    impl Default for Printer {
+        fn default() -> Self {
+            Self {
+                status: PrinterStatus::default(),
+                state: PrinterState::Idle,
+                job: None,
+                lines: Vec::new(),
+                printing: None,
+            }
+        }
+    }
+
+    impl GcodeSink for Printer {
+        async fn send(&mut self, line: &str) -> Result<(), GcodeError> {
+            self.lines.push(line.to_string());
+            Ok(())
+        }
+    }
+
+    impl PrinterApi for Printer {
+        async fn status(&mut self) -> PrinterStatus {
+            self.status
+        }
+
+        async fn set_target(&mut self, _heater: HeaterId, _target: f64) {}
+    }
+
+    impl PrusaLinkPrinter for Printer {
+        fn state(&self) -> PrinterState {
+            self.state
+        }
+
+        fn job(&self) -> Option<JobInfo<'_>> {
+            self.job
+        }
+
+        async fn print(&mut self, name: &str) -> Result<(), GcodeError> {
+            self.printing = Some(name.to_string());
+            self.state = PrinterState::Printing;
+            Ok(())
+        }
+    }
+
+    /// Keeps the files in memory and runs out of space after `capacity` bytes in a file.
+    struct Files {
+        files: BTreeMap<String, Vec<u8>>,
+        open: Option<(String, Vec<u8>)>,
+        capacity: usize,
+    }
+
+    impl Default for Files {
+        fn default() -> Self {
+            Self {
+                files: BTreeMap::new(),
+                open: None,
+                capacity: usize::MAX,
+            }
+        }
+    }
+
+    impl FileStore for Files {
+        async fn exists(&mut self, name: &str) -> Result<bool, StorageError> {
+            Ok(self.files.contains_key(name))
+        }
+
+        async fn create(&mut self, name: &str) -> Result<(), StorageError> {
+            self.open = Some((name.to_string(), Vec::new()));
+            Ok(())
+        }
+
+        async fn write(&mut self, data: &[u8]) -> Result<(), StorageError> {
+            let (_, file) = self.open.as_mut().ok_or(StorageError::NotOpen)?;
+            if file.len() + data.len() > self.capacity {
+                return Err(StorageError::Full);
+            }
+            file.extend_from_slice(data);
+            Ok(())
+        }
+
+        async fn close(&mut self) -> Result<(), StorageError> {
+            let (name, file) = self.open.take().ok_or(StorageError::NotOpen)?;
+            self.files.insert(name, file);
+            Ok(())
+        }
+
+        async fn abort(&mut self, name: &str) {
+            self.open = None;
+            self.files.remove(name);
+        }
+    }
+
+    fn link_with(printer: Printer, files: Files) -> PrusaLink<'static, Printer, Files> {
+        PrusaLink::new(printer, files, CONFIG)
+    }
+
+    /// Send a request in the given pieces and return the status line and body of the response.
+    fn request<S: FileStore>(
+        link: &mut PrusaLink<Printer, S>,
+        chunks: &[&[u8]],
+    ) -> (String, String) {
+        let mut socket = MockSocket::new(chunks);
+        let mut request = [0; 256];
+        let mut response = [0; 512];
+        block_on(handle_connection(
+            &mut socket,
+            link,
+            &mut request,
+            &mut response,
+        ))
+        .unwrap();
+        let output = socket.output_str();
+        let (head, body) = output.split_once("\r\n\r\n").unwrap();
+        let status = head.lines().next().unwrap();
+        (status.to_string(), body.to_string())
+    }
+
+    fn get(link: &mut PrusaLink<Printer, Files>, path: &str) -> (String, String) {
+        let head = format!("GET {path} HTTP/1.1\r\nX-Api-Key: secret\r\n\r\n");
+        request(link, &[head.as_bytes()])
+    }
+
+    fn put<S: FileStore>(
+        link: &mut PrusaLink<Printer, S>,
+        path: &str,
+        headers: &str,
+        body: &[u8],
+    ) -> (String, String) {
+        let head = format!(
+            "PUT {path} HTTP/1.1\r\nX-Api-Key: secret\r\n{headers}Content-Length: {}\r\n\r\n",
+            body.len()
+        );
+        request(link, &[head.as_bytes(), body])
+    }
+
+    fn status() -> PrinterStatus {
+        PrinterStatus {
+            hotend: HeaterStatus {
+                actual: 214.96,
+                target: 215.0,
+            },
+            bed: HeaterStatus {
+                actual: 60.04,
+                target: 60.0,
+            },
+            fans: [Some(4100.4), None],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn routes_prusa_link_paths() {
+        assert_eq!(
+            link_route(Method::Get, "/api/version"),
+            Some(Ok(LinkRoute::Version))
+        );
+        assert_eq!(
+            link_route(Method::Head, "/api/v1/status"),
+            Some(Ok(LinkRoute::Status))
+        );
+        assert_eq!(
+            link_route(Method::Put, "/api/v1/files/usb/jobs/box.gcode"),
+            Some(Ok(LinkRoute::Upload {
+                storage: "usb",
+                path: "jobs/box.gcode"
+            }))
+        );
+        assert_eq!(
+            link_route(Method::Get, "/api/v1/files/usb/box.gcode"),
+            Some(Err(Status::MethodNotAllowed))
+        );
+        assert_eq!(
+            link_route(Method::Post, "/api/v1/job"),
+            Some(Err(Status::MethodNotAllowed))
+        );
+        for path in ["/api/v1/files/usb", "/api/v1/files/usb/", "/api/v1/files/"] {
+            assert_eq!(
+                link_route(Method::Put, path),
+                Some(Err(Status::NotFound)),
+                "{path}"
+            );
+        }
+        // Everything else is left to the REST API.
+        assert_eq!(link_route(Method::Get, "/api/status"), None);
+        assert_eq!(link_route(Method::Get, "/api/v1/other"), None);
+    }
+
+    #[test]
+    fn requires_the_api_key() {
+        let mut link = link_with(Printer::default(), Files::default());
+        let (status, body) = request(&mut link, &[b"GET /api/version HTTP/1.1\r\n\r\n"]);
+        assert_eq!(status, "HTTP/1.1 401 Unauthorized");
+        assert_eq!(body, "");
+        let (status, _) = request(
+            &mut link,
+            &[b"GET /api/status HTTP/1.1\r\nX-Api-Key: Secret\r\n\r\n"],
+        );
+        assert_eq!(status, "HTTP/1.1 401 Unauthorized");
+    }
+
+    #[test]
+    fn reports_the_version() {
+        let mut link = link_with(Printer::default(), Files::default());
+        let (status, body) = get(&mut link, "/api/version");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(
+            body,
+            format!(
+                r#"{{"api":"2.0.0","server":"{}","nozzle_diameter":0.40,"text":"PrusaLink","hostname":"buddy","capabilities":{{"upload-by-put":true}}}}"#,
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+
+    #[test]
+    fn reports_the_status() {
+        let printer = Printer {
+            status: status(),
+            ..Default::default()
+        };
+        let mut link = link_with(printer, Files::default());
+        let (status, body) = get(&mut link, "/api/v1/status");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(
+            body,
+            r#"{"storage":{"path":"/usb/","name":"usb","read_only":false},"printer":{"state":"IDLE","temp_bed":60.0,"target_bed":60.0,"temp_nozzle":215.0,"target_nozzle":215.0,"fan_hotend":0,"fan_print":4100}}"#
+        );
+
+        let printer = link.printer();
+        printer.state = PrinterState::Printing;
+        printer.job = Some(JOB);
+        let (_, body) = get(&mut link, "/api/v1/status");
+        assert!(
+            body.starts_with(
+                r#"{"job":{"id":7,"progress":42.5,"time_printing":600,"time_remaining":900},"storage":"#
+            ),
+            "{body}"
+        );
+        assert!(body.contains(r#""state":"PRINTING""#));
+    }
+
+    #[test]
+    fn reports_the_job() {
+        let mut link = link_with(Printer::default(), Files::default());
+        let (status, body) = get(&mut link, "/api/v1/job");
+        assert_eq!(status, "HTTP/1.1 204 No Content");
+        assert_eq!(body, "");
+
+        let printer = link.printer();
+        printer.state = PrinterState::Printing;
+        printer.job = Some(JOB);
+        let (status, body) = get(&mut link, "/api/v1/job");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(
+            body,
+            r#"{"id":7,"progress":42.5,"time_printing":600,"time_remaining":900,"state":"PRINTING","file":{"name":"BOX.GCO","display_name":"box.gcode","path":"/usb","size":1234}}"#
+        );
+
+        // The estimate is left out until there is one.
+        link.printer().job = Some(JobInfo {
+            time_remaining: None,
+            file: JobFile {
+                path: "/usb/jobs",
+                ..JOB.file
+            },
+            ..JOB
+        });
+        let (_, body) = get(&mut link, "/api/v1/job");
+        assert!(body.starts_with(r#"{"id":7,"progress":42.5,"time_printing":600,"state""#));
+        assert!(body.contains(r#""path":"/usb/jobs""#));
+    }
+
+    #[test]
+    fn passes_other_requests_to_the_rest_api() {
+        let mut link = link_with(Printer::default(), Files::default());
+        let (status, body) = get(&mut link, "/api/status");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(body.starts_with(r#"{"temperature":"#));
+
+        let (status, body) = request(
+            &mut link,
+            &[
+                b"POST /api/gcode HTTP/1.1\r\nX-Api-Key: secret\r\nContent-Type: application/json\r\nContent-Length: 25\r\n\r\n",
+                br#"{"command": "G28\nM104"}"#,
+                b"  ",
+            ],
+        );
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body, r#"{"accepted":2}"#);
+        assert_eq!(link.printer().lines, ["G28", "M104"]);
+    }
+
+    #[test]
+    fn stores_an_upload() {
+        let mut link = link_with(Printer::default(), Files::default());
+        // Larger than both the request and response buffers.
+        let job = "G1 X10 Y10 E0.5\n".repeat(100);
+        let (status, body) = put(
+            &mut link,
+            "/api/v1/files/usb/my%20box.gcode",
+            "",
+            job.as_bytes(),
+        );
+        assert_eq!(status, "HTTP/1.1 201 Created");
+        assert_eq!(body, "");
+        assert_eq!(link.files().files["my box.gcode"], job.as_bytes());
+        assert_eq!(link.printer().printing, None);
+    }
+
+    #[test]
+    fn only_overwrites_when_asked() {
+        let mut files = Files::default();
+        files.files.insert("box.gcode".into(), b"old".to_vec());
+        let mut link = link_with(Printer::default(), files);
+        let (status, body) = put(&mut link, "/api/v1/files/usb/box.gcode", "", b"new");
+        assert_eq!(status, "HTTP/1.1 409 Conflict");
+        assert_eq!(body, r#"{"error":"The file already exists."}"#);
+        assert_eq!(link.files().files["box.gcode"], b"old");
+
+        let (status, _) = put(
+            &mut link,
+            "/api/v1/files/usb/box.gcode",
+            "Overwrite: ?1\r\n",
+            b"new",
+        );
+        assert_eq!(status, "HTTP/1.1 201 Created");
+        assert_eq!(link.files().files["box.gcode"], b"new");
+    }
+
+    #[test]
+    fn rejects_bad_upload_paths() {
+        let mut link = link_with(Printer::default(), Files::default());
+        let (status, body) = put(&mut link, "/api/v1/files/sd/box.gcode", "", b"G28");
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+        assert_eq!(body, r#"{"error":"Unknown storage."}"#);
+
+        for path in [
+            "/api/v1/files/usb/box%2",
+            "/api/v1/files/usb/%ff.gcode",
+            "/api/v1/files/usb/jobs/box.gcode",
+            "/api/v1/files/usb/jobs%2Fbox.gcode",
+        ] {
+            let (status, body) = put(&mut link, path, "", b"G28");
+            assert_eq!(status, "HTTP/1.1 400 Bad Request", "{path}");
+            assert_eq!(body, r#"{"error":"Invalid file name."}"#);
+        }
+        // Longer than the buffer the name is decoded into.
+        let path = format!("/api/v1/files/usb/{}.gcode", "a".repeat(64));
+        let (status, _) = put(&mut link, &path, "", b"G28");
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+        assert!(link.files().files.is_empty());
+    }
+
+    #[test]
+    fn prints_after_upload() {
+        let mut link = link_with(Printer::default(), Files::default());
+        let (status, _) = put(
+            &mut link,
+            "/api/v1/files/usb/box.gcode",
+            "Print-After-Upload: ?1\r\n",
+            b"G28",
+        );
+        assert_eq!(status, "HTTP/1.1 201 Created");
+        assert_eq!(link.printer().printing.as_deref(), Some("box.gcode"));
+
+        // A busy printer refuses before anything is stored.
+        let (status, body) = put(
+            &mut link,
+            "/api/v1/files/usb/next.gcode",
+            "Print-After-Upload: ?1\r\n",
+            b"G28",
+        );
+        assert_eq!(status, "HTTP/1.1 409 Conflict");
+        assert_eq!(body, r#"{"error":"The printer is busy."}"#);
+        assert!(!link.files().files.contains_key("next.gcode"));
+    }
+
+    #[test]
+    fn prints_the_stored_name() {
+        let mut flash = RamFlash::<{ 1024 * SECTOR }, SECTOR>::new();
+        let device: FlashBlockDevice<_> =
+            FlashBlockDevice::new(&mut flash, 0, MIN_FILESYSTEM_SIZE).unwrap();
+        device.format().unwrap();
+        let files = FlashFileStore::open(VolumeManager::new(device, FixedTimeSource)).unwrap();
+        let mut link = PrusaLink::new(Printer::default(), files, CONFIG);
+        let (status, _) = put(
+            &mut link,
+            "/api/v1/files/usb/Box%20v2.gcode",
+            "Print-After-Upload: ?1\r\n",
+            b"G28",
+        );
+        assert_eq!(status, "HTTP/1.1 201 Created");
+        assert_eq!(link.printer().printing.as_deref(), Some("BOXV2.GCO"));
+    }
+
+    #[test]
+    fn removes_a_failed_upload() {
+        let files = Files {
+            capacity: 100,
+            ..Default::default()
+        };
+        let mut link = link_with(Printer::default(), files);
+        let (status, body) = put(&mut link, "/api/v1/files/usb/big.gcode", "", &[b'G'; 300]);
+        assert_eq!(status, "HTTP/1.1 507 Insufficient Storage");
+        assert_eq!(body, r#"{"error":"The file could not be stored."}"#);
+        assert!(link.files().files.is_empty());
+        assert!(link.files().open.is_none());
+
+        // The client goes away part way through a body larger than the request buffer.
+        let mut link = link_with(Printer::default(), Files::default());
+        let (status, _) = request(
+            &mut link,
+            &[
+                b"PUT /api/v1/files/usb/cut.gcode HTTP/1.1\r\nX-Api-Key: secret\r\nContent-Length: 500\r\n\r\n",
+                &[b'G'; 300],
+            ],
+        );
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+        assert!(link.files().files.is_empty());
+    }
+}
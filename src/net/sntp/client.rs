@@ -0,0 +1,124 @@
+use core::cell::Cell;
+
+use embassy_net::{
+    IpEndpoint, Stack,
+    udp::{PacketMetadata, UdpSocket},
+};
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::{Duration, Instant, Timer, WithTimeout};
+
+use crate::{
+    fmt::{error, info},
+    net::{
+        SntpError,
+        sntp::{Clock, NtpTimestamp, PACKET_LEN, Response, Sample, UtcTime, encode_request},
+    },
+};
+
+/// How long to wait for the server to respond.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait before trying again after a failed sync.
+const RETRY: Duration = Duration::from_secs(15);
+
+/// The clock set by [`run`], shared with [`now_utc`].
+static CLOCK: Mutex<CriticalSectionRawMutex, Cell<Option<Clock>>> = Mutex::new(Cell::new(None));
+
+/// The current time in UTC, or `None` until the clock has been synced.
+///
+/// ```rust,ignore
+/// if let Some(now) = sntp::now_utc() {
+///     info!("[Job] Started at {}", now);
+/// }
+/// ```
+pub fn now_utc() -> Option<UtcTime> {
+    CLOCK
+        .lock(Cell::get)
+        .map(|clock| clock.utc_at(Instant::now().as_micros()))
+}
+
+/// The last sync, or `None` until the clock has been synced.
+pub fn clock() -> Option<Clock> {
+    CLOCK.lock(Cell::get)
+}
+
+/// Ask `server` for the time over `socket`, which must already be bound, and return the measured offset.
+pub async fn request(socket: &mut UdpSocket<'_>, server: IpEndpoint) -> Result<Sample, SntpError> {
+    let mut buf = [0u8; PACKET_LEN];
+    let sent = Instant::now().as_micros();
+    // The local time is only used to tell responses apart, so it is sent as is rather than as UTC.
+    let origin = NtpTimestamp {
+        seconds: (sent >> 32) as u32,
+        fraction: sent as u32,
+    };
+    let len = encode_request(&mut buf, origin)?;
+    socket
+        .send_to(&buf[..len], server)
+        .await
+        .map_err(|_| SntpError::Socket)?;
+    let deadline = Instant::now() + RESPONSE_TIMEOUT;
+    loop {
+        let (n, meta) = socket
+            .recv_from(&mut buf)
+            .with_deadline(deadline)
+            .await
+            .map_err(|_| SntpError::Timeout)?
+            .map_err(|_| SntpError::Socket)?;
+        let received = Instant::now().as_micros();
+        if meta.endpoint != server {
+            continue;
+        }
+        match Response::parse(&buf[..n], origin) {
+            // A late response to an earlier request.
+            Err(SntpError::UnexpectedResponse) => continue,
+            Err(e) => return Err(e),
+            Ok(response) => return Ok(response.sample(sent, received)),
+        }
+    }
+}
+
+/// Sync the clock read by [`now_utc`] with `server` every `interval`, retrying sooner when a sync fails.
+pub async fn run(
+    stack: Stack<'_>,
+    server: IpEndpoint,
+    interval: Duration,
+    rx_meta: &mut [PacketMetadata],
+    rx_buffer: &mut [u8],
+    tx_meta: &mut [PacketMetadata],
+    tx_buffer: &mut [u8],
+) -> ! {
+    stack.wait_config_up().await;
+    let mut socket = UdpSocket::new(stack, rx_meta, rx_buffer, tx_meta, tx_buffer);
+    if socket.bind(0).is_err() {
+        error!("[SNTP] Failed to bind the socket");
+        core::future::pending::<()>().await;
+    }
+    loop {
+        match request(&mut socket, server).await {
+            Ok(sample) => {
+                let at = Instant::now().as_micros();
+                let clock = CLOCK.lock(|cell| {
+                    let clock = match cell.get() {
+                        Some(mut clock) => {
+                            clock.update(&sample, at);
+                            clock
+                        }
+                        None => Clock::new(&sample, at),
+                    };
+                    cell.set(Some(clock));
+                    clock
+                });
+                info!(
+                    "[SNTP] Synced to {}, delay {} us",
+                    clock.utc_at(at),
+                    sample.delay
+                );
+                Timer::after(interval).await;
+            }
+            Err(e) => {
+                error!("[SNTP] {}", e);
+                Timer::after(RETRY.min(interval)).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use core::fmt;
+
+use crate::net::sntp::Sample;
+
+/// The fastest a crystal is expected to drift (ppm). A larger change in offset between syncs is taken to be a step
+/// in the server's clock rather than drift.
+const MAX_DRIFT_PPM: f32 = 500.0;
+
+/// The shortest time between syncs for the drift to be estimated (µs), as the network delay swamps it sooner.
+const MIN_DRIFT_INTERVAL: u64 = 60_000_000;
+
+/// How much of each new drift estimate is taken, to smooth out the noise in the samples.
+const DRIFT_GAIN: f32 = 0.25;
+
+/// An estimate of UTC as an offset from the local clock, corrected for how fast the local clock drifts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clock {
+    offset: i64,
+    synced_at: u64,
+    drift_ppm: Option<f32>,
+}
+
+impl Clock {
+    /// Start the clock from a sample taken at local time `at` (µs).
+    pub fn new(sample: &Sample, at: u64) -> Self {
+        Self {
+            offset: sample.offset,
+            synced_at: at,
+            drift_ppm: None,
+        }
+    }
+
+    /// Take a new sample and update the drift from how far the offset moved since the last one.
+    pub fn update(&mut self, sample: &Sample, at: u64) {
+        let elapsed = at.saturating_sub(self.synced_at);
+        if elapsed < MIN_DRIFT_INTERVAL {
+            // Too soon to tell drift from noise, so only the offset is updated.
+            self.offset = sample.offset;
+            self.synced_at = at;
+            return;
+        }
+        let drift = (sample.offset - self.offset) as f32 / elapsed as f32 * 1e6;
+        self.drift_ppm = if drift.abs() > MAX_DRIFT_PPM {
+            None
+        } else {
+            Some(match self.drift_ppm {
+                Some(old) => old + (drift - old) * DRIFT_GAIN,
+                None => drift,
+            })
+        };
+        self.offset = sample.offset;
+        self.synced_at = at;
+    }
+
+    /// How fast the local clock runs slow against UTC (ppm), once two syncs far enough apart have been taken.
+    pub fn drift_ppm(&self) -> Option<f32> {
+        self.drift_ppm
+    }
+
+    /// The local time of the last sync (µs).
+    pub fn synced_at(&self) -> u64 {
+        self.synced_at
+    }
+
+    /// UTC at local time `now` (µs).
+    pub fn utc_at(&self, now: u64) -> UtcTime {
+        let elapsed = now as i64 - self.synced_at as i64;
+        let correction = (elapsed as f32 * self.drift_ppm.unwrap_or(0.0) * 1e-6) as i64;
+        UtcTime::from_unix_micros((now as i64 + self.offset + correction).max(0) as u64)
+    }
+}
+
+/// A point in UTC, displayed in ISO 8601, e.g. `2025-06-01T12:34:56Z`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UtcTime {
+    micros: u64,
+}
+
+impl UtcTime {
+    pub fn from_unix_micros(micros: u64) -> Self {
+        Self { micros }
+    }
+
+    pub fn unix_micros(&self) -> u64 {
+        self.micros
+    }
+
+    pub fn unix_seconds(&self) -> u64 {
+        self.micros / 1_000_000
+    }
+
+    /// The year, month, day, hour, minute and second.
+    pub fn fields(&self) -> (u32, u8, u8, u8, u8, u8) {
+        let seconds = self.unix_seconds();
+        let (year, month, day) = civil_from_days(seconds / 86_400);
+        let time = seconds % 86_400;
+        (
+            year,
+            month,
+            day,
+            (time / 3600) as u8,
+            (time / 60 % 60) as u8,
+            (time % 60) as u8,
+        )
+    }
+}
+
+/// Convert days since 1970-01-01 to a date in the Gregorian calendar.
+///  https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: u64) -> (u32, u8, u8) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = (year_of_era + era * 400 + (month <= 2) as u64) as u32;
+    (year, month, day)
+}
+
+impl fmt::Display for UtcTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day, hour, minute, second) = self.fields();
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year, month, day, hour, minute, second
+        )
+    }
+}
+
+impl defmt::Format for UtcTime {
+    fn format(&self, f: defmt::Formatter) {
+        let (year, month, day, hour, minute, second) = self.fields();
+        defmt::write!(
+            f,
+            "{=u32:04}-{=u8:02}-{=u8:02}T{=u8:02}:{=u8:02}:{=u8:02}Z",
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2023-11-14T22:13:20Z, as an offset from a local clock that started at the epoch.
+    const OFFSET: i64 = 1_700_000_000_000_000;
+    const START: u64 = 5_000_000;
+    /// 100s, long enough to estimate the drift.
+    const INTERVAL: u64 = 100_000_000;
+
+    fn sample(offset: i64) -> Sample {
+        Sample { offset, delay: 0 }
+    }
+
+    fn assert_drift(clock: &Clock, ppm: f32) {
+        let drift = clock.drift_ppm().unwrap();
+        assert!((drift - ppm).abs() < 0.01, "{drift} != {ppm}");
+    }
+
+    #[test]
+    fn estimates_the_drift() {
+        let mut clock = Clock::new(&sample(OFFSET), START);
+        assert_eq!(clock.drift_ppm(), None);
+        // The offset grows by 5ms in 100s, so the local clock runs 50ppm slow.
+        clock.update(&sample(OFFSET + 5_000), START + INTERVAL);
+        assert_drift(&clock, 50.0);
+        assert_eq!(clock.synced_at(), START + INTERVAL);
+    }
+
+    #[test]
+    fn keeps_the_drift_for_close_syncs() {
+        let mut clock = Clock::new(&sample(OFFSET), START);
+        clock.update(&sample(OFFSET + 1_000), START + MIN_DRIFT_INTERVAL - 1);
+        assert_eq!(clock.drift_ppm(), None);
+        assert_eq!(clock.offset, OFFSET + 1_000);
+
+        let mut clock = Clock::new(&sample(OFFSET), START);
+        clock.update(&sample(OFFSET + 5_000), START + INTERVAL);
+        clock.update(&sample(OFFSET + 8_000), START + INTERVAL + 30_000_000);
+        assert_drift(&clock, 50.0);
+        assert_eq!(clock.offset, OFFSET + 8_000);
+        assert_eq!(clock.synced_at(), START + INTERVAL + 30_000_000);
+    }
+
+    #[test]
+    fn resets_the_drift_on_a_step() {
+        let mut clock = Clock::new(&sample(OFFSET), START);
+        clock.update(&sample(OFFSET + 5_000), START + INTERVAL);
+        // 60ms in 100s is 600ppm, more than a crystal drifts.
+        clock.update(&sample(OFFSET + 65_000), START + 2 * INTERVAL);
+        assert_eq!(clock.drift_ppm(), None);
+        assert_eq!(clock.offset, OFFSET + 65_000);
+    }
+
+    #[test]
+    fn smooths_the_drift() {
+        let mut clock = Clock::new(&sample(OFFSET), START);
+        clock.update(&sample(OFFSET + 5_000), START + INTERVAL);
+        // 90ppm over the next interval only moves the estimate a quarter of the way from 50ppm.
+        clock.update(&sample(OFFSET + 14_000), START + 2 * INTERVAL);
+        assert_drift(&clock, 60.0);
+    }
+
+    #[test]
+    fn corrects_utc_for_the_drift() {
+        let mut clock = Clock::new(&sample(OFFSET), START);
+        let now = START + 1_000_000_000;
+        assert_eq!(
+            clock.utc_at(now).unix_micros(),
+            (now as i64 + OFFSET) as u64
+        );
+
+        clock.drift_ppm = Some(50.0);
+        // 50ppm of 1000s is 50ms.
+        let utc = clock.utc_at(now).unix_micros() as i64;
+        assert!((utc - (now as i64 + OFFSET + 50_000)).abs() <= 1, "{utc}");
+    }
+
+    #[test]
+    fn converts_days_to_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(10_956), (1999, 12, 31));
+        assert_eq!(civil_from_days(11_017), (2000, 3, 1));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        // 2100 is not a leap year.
+        assert_eq!(civil_from_days(47_540), (2100, 2, 28));
+        assert_eq!(civil_from_days(47_541), (2100, 3, 1));
+    }
+
+    #[test]
+    fn displays_iso_8601() {
+        let utc = |seconds: u64| UtcTime::from_unix_micros(seconds * 1_000_000).to_string();
+        assert_eq!(utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(utc(1_709_164_800 + 45_296), "2024-02-29T12:34:56Z");
+        // The first NTP era ends here.
+        assert_eq!(utc(2_085_978_495), "2036-02-07T06:28:15Z");
+        assert_eq!(utc(2_085_978_496), "2036-02-07T06:28:16Z");
+        // Parts of a second are cut off.
+        assert_eq!(
+            UtcTime::from_unix_micros(999_999).to_string(),
+            "1970-01-01T00:00:00Z"
+        );
+    }
+}
@@ -0,0 +1,7 @@
+mod client;
+mod clock;
+mod packet;
+
+pub use client::*;
+pub use clock::*;
+pub use packet::*;
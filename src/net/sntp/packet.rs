@@ -0,0 +1,352 @@
+use crate::net::SntpError;
+
+pub const NTP_PORT: u16 = 123;
+
+/// The length of a packet without extensions or authentication.
+pub const PACKET_LEN: usize = 48;
+
+/// Seconds from the NTP epoch (1900) to the Unix epoch (1970).
+const UNIX_OFFSET: i64 = 2_208_988_800;
+
+const VERSION: u8 = 4;
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+/// The leap indicator a server sends when its clock is not synchronised.
+const LEAP_UNKNOWN: u8 = 3;
+
+/// A 64-bit NTP timestamp: seconds since 1900 and a binary fraction of a second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NtpTimestamp {
+    pub seconds: u32,
+    pub fraction: u32,
+}
+
+impl NtpTimestamp {
+    fn read(buf: &[u8]) -> Self {
+        Self {
+            seconds: u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            fraction: u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]),
+        }
+    }
+
+    fn write(&self, buf: &mut [u8]) {
+        buf[..4].copy_from_slice(&self.seconds.to_be_bytes());
+        buf[4..8].copy_from_slice(&self.fraction.to_be_bytes());
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.seconds == 0 && self.fraction == 0
+    }
+
+    /// Microseconds since the Unix epoch. The seconds wrap in 2036, so times in the first half of the range are
+    /// taken to be after then.
+    pub fn unix_micros(&self) -> i64 {
+        let mut seconds = self.seconds as i64 - UNIX_OFFSET;
+        if self.seconds < 1 << 31 {
+            seconds += 1 << 32;
+        }
+        let micros = (self.fraction as i64 * 1_000_000) >> 32;
+        seconds * 1_000_000 + micros
+    }
+
+    pub fn from_unix_micros(micros: i64) -> Self {
+        let seconds = micros.div_euclid(1_000_000);
+        let micros = micros.rem_euclid(1_000_000);
+        Self {
+            seconds: (seconds + UNIX_OFFSET) as u32,
+            // Rounded up so that `unix_micros`, which rounds down, gives back the same time.
+            fraction: (((micros << 32) + 999_999) / 1_000_000) as u32,
+        }
+    }
+}
+
+/// Write a client request into `buf` and return its length. `transmit` is echoed back by the server as the origin
+/// of its response, so it should differ between requests.
+pub fn encode_request(buf: &mut [u8], transmit: NtpTimestamp) -> Result<usize, SntpError> {
+    let buf = buf.get_mut(..PACKET_LEN).ok_or(SntpError::BufferFull)?;
+    buf.fill(0);
+    buf[0] = (VERSION << 3) | MODE_CLIENT;
+    transmit.write(&mut buf[40..48]);
+    Ok(PACKET_LEN)
+}
+
+/// The fields of a server's response used to set the clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Response {
+    /// How far the server is from a reference clock. 1 is a server with its own reference.
+    pub stratum: u8,
+    /// How often the server may be polled, as a power of two (s).
+    pub poll: i8,
+    /// The request's transmit timestamp.
+    pub origin: NtpTimestamp,
+    /// When the server received the request.
+    pub receive: NtpTimestamp,
+    /// When the server sent the response.
+    pub transmit: NtpTimestamp,
+}
+
+impl Response {
+    /// Parse a response to the request sent with `origin`. Responses to other requests are rejected, as are those
+    /// from servers that are not synchronised or are asking the client to stop.
+    pub fn parse(buf: &[u8], origin: NtpTimestamp) -> Result<Self, SntpError> {
+        if buf.len() < PACKET_LEN {
+            return Err(SntpError::Malformed);
+        }
+        let leap = buf[0] >> 6;
+        let version = (buf[0] >> 3) & 0x07;
+        let mode = buf[0] & 0x07;
+        if mode != MODE_SERVER || !(1..=VERSION).contains(&version) {
+            return Err(SntpError::Malformed);
+        }
+        let response = Self {
+            stratum: buf[1],
+            poll: buf[2] as i8,
+            origin: NtpTimestamp::read(&buf[24..32]),
+            receive: NtpTimestamp::read(&buf[32..40]),
+            transmit: NtpTimestamp::read(&buf[40..48]),
+        };
+        if response.origin != origin {
+            return Err(SntpError::UnexpectedResponse);
+        }
+        // Stratum 0 is a kiss-o'-death, e.g. `RATE` when polled too often.
+        if response.stratum == 0 {
+            return Err(SntpError::Denied);
+        }
+        if leap == LEAP_UNKNOWN || response.stratum > 15 || response.transmit.is_zero() {
+            return Err(SntpError::Unsynchronised);
+        }
+        Ok(response)
+    }
+
+    /// Work out the clock offset from the local times the request was `sent` and the response `received` (µs).
+    pub fn sample(&self, sent: u64, received: u64) -> Sample {
+        let server_receive = self.receive.unix_micros();
+        let server_transmit = self.transmit.unix_micros();
+        let sent = sent as i64;
+        let received = received as i64;
+        Sample {
+            offset: ((server_receive - sent) + (server_transmit - received)) / 2,
+            delay: (received - sent) - (server_transmit - server_receive),
+        }
+    }
+}
+
+/// One measurement of the local clock against a server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    /// UTC minus local time (µs), so local time plus the offset is UTC.
+    pub offset: i64,
+    /// How long the request and response spent on the network (µs). The offset may be out by up to half of this.
+    pub delay: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2023-11-14T22:13:20Z.
+    const UNIX_2023: i64 = 1_700_000_000;
+    /// The last second of NTP era 0, 2036-02-07T06:28:15Z.
+    const UNIX_ERA_END: i64 = 2_085_978_495;
+
+    fn ntp(seconds: u32, fraction: u32) -> NtpTimestamp {
+        NtpTimestamp { seconds, fraction }
+    }
+
+    fn server(leap: u8, stratum: u8, origin: NtpTimestamp, transmit: NtpTimestamp) -> [u8; 48] {
+        let mut buf = [0; PACKET_LEN];
+        buf[0] = (leap << 6) | (VERSION << 3) | MODE_SERVER;
+        buf[1] = stratum;
+        buf[2] = 6;
+        origin.write(&mut buf[24..32]);
+        ntp(transmit.seconds, 0).write(&mut buf[32..40]);
+        transmit.write(&mut buf[40..48]);
+        buf
+    }
+
+    #[test]
+    fn converts_to_unix_time() {
+        assert_eq!(ntp(UNIX_OFFSET as u32, 0).unix_micros(), 0);
+        assert_eq!(
+            ntp(3_908_988_800, 1 << 31).unix_micros(),
+            UNIX_2023 * 1_000_000 + 500_000
+        );
+        assert_eq!(ntp(0, 1).unix_micros(), (UNIX_ERA_END + 1) * 1_000_000);
+        assert_eq!(
+            ntp(0, u32::MAX).unix_micros(),
+            (UNIX_ERA_END + 1) * 1_000_000 + 999_999
+        );
+    }
+
+    #[test]
+    fn wraps_in_2036() {
+        assert_eq!(ntp(u32::MAX, 0).unix_micros(), UNIX_ERA_END * 1_000_000);
+        assert_eq!(ntp(0, 0).unix_micros(), (UNIX_ERA_END + 1) * 1_000_000);
+        assert_eq!(
+            NtpTimestamp::from_unix_micros((UNIX_ERA_END + 1) * 1_000_000),
+            ntp(0, 0)
+        );
+        // The middle of the range splits 1968 from 2104.
+        assert_eq!(ntp(1 << 31, 0).unix_micros(), -61_505_152_000_000);
+        assert_eq!(ntp((1 << 31) - 1, 0).unix_micros(), 4_233_462_143_000_000);
+    }
+
+    #[test]
+    fn round_trips_unix_time() {
+        for seconds in [
+            -1,
+            0,
+            UNIX_2023,
+            UNIX_ERA_END,
+            UNIX_ERA_END + 1,
+            4_000_000_000,
+        ] {
+            for micros in [0, 1, 2, 499_999, 500_000, 999_998, 999_999] {
+                let micros = seconds * 1_000_000 + micros;
+                let timestamp = NtpTimestamp::from_unix_micros(micros);
+                assert_eq!(timestamp.unix_micros(), micros, "{micros}");
+            }
+        }
+        for micros in 0..10_000 {
+            assert_eq!(NtpTimestamp::from_unix_micros(micros).unix_micros(), micros);
+        }
+    }
+
+    #[test]
+    fn encodes_a_request() {
+        let mut buf = [0xFF; 64];
+        let transmit = ntp(0x0102_0304, 0x0506_0708);
+        assert_eq!(encode_request(&mut buf, transmit), Ok(PACKET_LEN));
+        assert_eq!(buf[0], 0x23);
+        assert!(buf[1..40].iter().all(|&b| b == 0));
+        assert_eq!(buf[40..48], [1, 2, 3, 4, 5, 6, 7, 8]);
+        // Nothing is written past the packet.
+        assert_eq!(buf[48], 0xFF);
+        assert_eq!(
+            encode_request(&mut buf[..47], transmit),
+            Err(SntpError::BufferFull)
+        );
+    }
+
+    #[test]
+    fn parses_a_response() {
+        let origin = ntp(3_908_988_800, 42);
+        let transmit = ntp(3_908_988_801, 7);
+        let response = Response::parse(&server(0, 2, origin, transmit), origin).unwrap();
+        assert_eq!(response.stratum, 2);
+        assert_eq!(response.poll, 6);
+        assert_eq!(response.origin, origin);
+        assert_eq!(response.receive, ntp(3_908_988_801, 0));
+        assert_eq!(response.transmit, transmit);
+        // A leap second is coming, which does not stop the time being used.
+        assert!(Response::parse(&server(1, 2, origin, transmit), origin).is_ok());
+        // NTPv3 servers are still about.
+        let mut v3 = server(0, 2, origin, transmit);
+        v3[0] = (3 << 3) | MODE_SERVER;
+        assert!(Response::parse(&v3, origin).is_ok());
+    }
+
+    #[test]
+    fn rejects_responses_to_other_requests() {
+        let origin = ntp(3_908_988_800, 42);
+        let transmit = ntp(3_908_988_801, 7);
+        let buf = server(0, 2, ntp(3_908_988_800, 43), transmit);
+        assert_eq!(
+            Response::parse(&buf, origin),
+            Err(SntpError::UnexpectedResponse)
+        );
+        // A kiss-o'-death for someone else is not for us either.
+        let buf = server(LEAP_UNKNOWN, 0, ntp(1, 1), ntp(0, 0));
+        assert_eq!(
+            Response::parse(&buf, origin),
+            Err(SntpError::UnexpectedResponse)
+        );
+    }
+
+    #[test]
+    fn rejects_a_kiss_o_death() {
+        let origin = ntp(3_908_988_800, 42);
+        let mut buf = server(LEAP_UNKNOWN, 0, origin, ntp(0, 0));
+        buf[12..16].copy_from_slice(b"RATE");
+        assert_eq!(Response::parse(&buf, origin), Err(SntpError::Denied));
+    }
+
+    #[test]
+    fn rejects_unsynchronised_servers() {
+        let origin = ntp(3_908_988_800, 42);
+        let transmit = ntp(3_908_988_801, 7);
+        for buf in [
+            server(LEAP_UNKNOWN, 2, origin, transmit),
+            server(0, 16, origin, transmit),
+            server(0, 2, origin, ntp(0, 0)),
+        ] {
+            assert_eq!(
+                Response::parse(&buf, origin),
+                Err(SntpError::Unsynchronised)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        let origin = ntp(3_908_988_800, 42);
+        let buf = server(0, 2, origin, ntp(3_908_988_801, 7));
+        assert_eq!(
+            Response::parse(&buf[..47], origin),
+            Err(SntpError::Malformed)
+        );
+        for first in [
+            // A client request.
+            (VERSION << 3) | MODE_CLIENT,
+            // Versions 0 and 5.
+            MODE_SERVER,
+            (5 << 3) | MODE_SERVER,
+        ] {
+            let mut buf = buf;
+            buf[0] = first;
+            assert_eq!(Response::parse(&buf, origin), Err(SntpError::Malformed));
+        }
+    }
+
+    fn response(receive: i64, transmit: i64) -> Response {
+        Response {
+            stratum: 2,
+            poll: 6,
+            origin: NtpTimestamp::default(),
+            receive: NtpTimestamp::from_unix_micros(receive),
+            transmit: NtpTimestamp::from_unix_micros(transmit),
+        }
+    }
+
+    #[test]
+    fn works_out_the_offset_and_delay() {
+        // Sent 10 s after boot, 20 ms each way and 1 ms in the server.
+        let utc = UNIX_2023 * 1_000_000;
+        let sent = 10_000_000;
+        let sample = response(utc + 20_000, utc + 21_000).sample(sent, sent + 41_000);
+        assert_eq!(
+            sample,
+            Sample {
+                offset: utc - sent as i64,
+                delay: 40_000,
+            }
+        );
+
+        // 30 ms out and 10 ms back puts the offset out by half the difference.
+        let sample = response(utc + 30_000, utc + 31_000).sample(sent, sent + 41_000);
+        assert_eq!(sample.offset, utc - sent as i64 + 10_000);
+        assert_eq!(sample.delay, 40_000);
+    }
+
+    #[test]
+    fn works_out_the_offset_across_the_era_wrap() {
+        // The server's clock passes the end of era 0 between receiving and sending.
+        let utc = (UNIX_ERA_END + 1) * 1_000_000 - 20_500;
+        let response = response(utc + 20_000, utc + 21_000);
+        assert!(response.receive.seconds > response.transmit.seconds);
+        let sent = 10_000_000;
+        let sample = response.sample(sent, sent + 41_000);
+        assert_eq!(sample.offset, utc - sent as i64);
+        assert_eq!(sample.delay, 40_000);
+    }
+}
@@ -0,0 +1,198 @@
+use core::convert::Infallible;
+
+use embassy_stm32::{gpio::Output, usart::BufferedUart};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embedded_hal::digital::InputPin;
+
+use crate::{Board, GStat, TMC2209, net::JsonWriter};
+
+/// The heaters that can be given a target temperature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum HeaterId {
+    Bed,
+    Hotend,
+}
+
+impl HeaterId {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Bed => "bed",
+            Self::Hotend => "hotend",
+        }
+    }
+
+    /// The highest target that will be accepted (°C).
+    pub fn max_target(&self) -> f64 {
+        match self {
+            Self::Bed => 100.0,
+            Self::Hotend => 280.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HeaterStatus {
+    /// The measured temperature (°C).
+    pub actual: f64,
+    /// The target temperature (°C). Zero when the heater is off.
+    pub target: f64,
+}
+
+/// The faults reported by a TMC2209 through its DIAG pin and GSTAT register.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DriverStatus {
+    pub diag: bool,
+    pub reset: bool,
+    pub drv_err: bool,
+    pub uv_cp: bool,
+}
+
+impl DriverStatus {
+    pub fn new(diag: bool, gstat: &GStat) -> Self {
+        Self {
+            diag,
+            reset: gstat.reset,
+            drv_err: gstat.drv_err,
+            uv_cp: gstat.uv_cp,
+        }
+    }
+
+    /// Return whether the driver has shut down or is reporting an error. A reset alone is not a fault.
+    pub fn faulted(&self) -> bool {
+        self.diag || self.drv_err || self.uv_cp
+    }
+
+    async fn read<'a, I: InputPin<Error = Infallible>>(
+        stepper: &TMC2209<'a, ThreadModeRawMutex, Output<'a>, I, BufferedUart<'static>>,
+    ) -> Self {
+        let diag = stepper.has_errored().await;
+        let mut gstat = GStat::default();
+        // The register is left clear if the driver does not respond, e.g. when the board is powered over USB. That is
+        // expected, so it is not counted as an error.
+        let _ = stepper.read(&mut gstat).await;
+        Self::new(diag, &gstat)
+    }
+}
+
+/// A snapshot of the printer reported over the network.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PrinterStatus {
+    pub hotend: HeaterStatus,
+    pub bed: HeaterStatus,
+    /// The temperature of the board (°C).
+    pub board_temperature: f64,
+    /// The voltage supplied to the bed heater (V).
+    pub bed_voltage: f64,
+    /// The speed of each fan (RPM), or `None` if it is not turning.
+    pub fans: [Option<f64>; 2],
+    pub filament_present: bool,
+    /// The X, Y, Z and E drivers.
+    pub drivers: [DriverStatus; 4],
+}
+
+impl PrinterStatus {
+    /// Read the sensors on the board. The targets are left at zero as the board does not know them, and any component
+    /// that was not built is left at its default.
+    pub async fn read(board: &Board<'_>) -> Self {
+        let mut status = Self::default();
+        if let Some(thermistor) = &board.hotend_thermistor {
+            status.hotend.actual = thermistor.read().await;
+        }
+        if let Some(thermistor) = &board.bed_thermistor {
+            status.bed.actual = thermistor.read().await;
+        }
+        if let Some(thermistor) = &board.board_thermistor {
+            status.board_temperature = thermistor.read().await;
+        }
+        if let Some(bed_power) = &board.bed_power {
+            status.bed_voltage = bed_power.read().await;
+        }
+        if let Some(fan) = &board.fan_0 {
+            status.fans[0] = fan.rpm().await;
+        }
+        if let Some(fan) = &board.fan_1 {
+            status.fans[1] = fan.rpm().await;
+        }
+        if let Some(sensor) = &board.filament_sensor {
+            status.filament_present = sensor.available().await;
+        }
+        for (i, stepper) in [&board.x_stepper, &board.y_stepper, &board.z_stepper]
+            .into_iter()
+            .enumerate()
+        {
+            if let Some(stepper) = stepper {
+                status.drivers[i] = DriverStatus::read(stepper).await;
+            }
+        }
+        if let Some(stepper) = &board.e_stepper {
+            status.drivers[3] = DriverStatus::read(stepper).await;
+        }
+        status
+    }
+
+    pub fn heater(&self, heater: HeaterId) -> &HeaterStatus {
+        match heater {
+            HeaterId::Bed => &self.bed,
+            HeaterId::Hotend => &self.hotend,
+        }
+    }
+
+    pub fn heater_mut(&mut self, heater: HeaterId) -> &mut HeaterStatus {
+        match heater {
+            HeaterId::Bed => &mut self.bed,
+            HeaterId::Hotend => &mut self.hotend,
+        }
+    }
+
+    /// Write the status as a JSON object.
+    ///
+    /// ```json
+    /// {"temperature":{"hotend":{"actual":215.2,"target":215.0},"bed":{"actual":60.1,"target":60.0},"board":31.4},
+    ///  "bed_voltage":24.1,"fans":[{"rpm":5100},{"rpm":null}],"filament":true,
+    ///  "drivers":{"x":{"fault":false,"diag":false,"reset":false,"drv_err":false,"uv_cp":false},...}}
+    /// ```
+    pub fn write_json(&self, json: &mut JsonWriter) {
+        json.begin_object().key("temperature").begin_object();
+        for heater in [HeaterId::Hotend, HeaterId::Bed] {
+            let status = self.heater(heater);
+            json.key(heater.name())
+                .begin_object()
+                .field_f64("actual", status.actual, 1)
+                .field_f64("target", status.target, 1)
+                .end_object();
+        }
+        json.field_f64("board", self.board_temperature, 1)
+            .end_object();
+        json.field_f64("bed_voltage", self.bed_voltage, 1);
+        json.key("fans").begin_array();
+        for rpm in self.fans {
+            json.begin_object().key("rpm");
+            match rpm {
+                Some(rpm) => json.f64(rpm, 0),
+                None => json.null(),
+            };
+            json.end_object();
+        }
+        json.end_array();
+        json.field_bool("filament", self.filament_present);
+        json.key("drivers");
+        self.write_drivers_json(json);
+        json.end_object();
+    }
+
+    /// Write the driver faults as a JSON object keyed by axis.
+    pub fn write_drivers_json(&self, json: &mut JsonWriter) {
+        json.begin_object();
+        for (name, driver) in ["x", "y", "z", "e"].iter().zip(self.drivers.iter()) {
+            json.key(name)
+                .begin_object()
+                .field_bool("fault", driver.faulted())
+                .field_bool("diag", driver.diag)
+                .field_bool("reset", driver.reset)
+                .field_bool("drv_err", driver.drv_err)
+                .field_bool("uv_cp", driver.uv_cp)
+                .end_object();
+        }
+        json.end_object();
+    }
+}
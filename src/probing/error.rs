@@ -0,0 +1,10 @@
+use thiserror::Error;
+
+/// The set of errors that may occur while probing.
+#[derive(Debug, Error, defmt::Format)]
+pub enum ProbeError {
+    #[error("The probe was triggered before it started moving.")]
+    AlreadyTriggered,
+    #[error("The probe did not trigger within the maximum travel.")]
+    NoContact,
+}
@@ -0,0 +1,204 @@
+/// A grid of bed heights measured at `NX` by `NY` evenly spaced points.
+#[derive(Debug, Clone)]
+pub struct BedMesh<const NX: usize, const NY: usize> {
+    min: (f32, f32),
+    max: (f32, f32),
+    /// The heights indexed by row (y) then column (x).
+    z: [[f32; NX]; NY],
+}
+
+impl<const NX: usize, const NY: usize> BedMesh<NX, NY> {
+    /// Create a flat mesh spanning the rectangle from `min` to `max` (mm).
+    pub fn new(min: (f32, f32), max: (f32, f32)) -> Self {
+        const { assert!(NX >= 2 && NY >= 2, "A mesh needs at least 2x2 points.") };
+        Self {
+            min,
+            max,
+            z: [[0.0; NX]; NY],
+        }
+    }
+
+    /// Return the position of a grid point (mm).
+    pub fn point(&self, ix: usize, iy: usize) -> (f32, f32) {
+        let x = self.min.0 + (self.max.0 - self.min.0) * ix as f32 / (NX - 1) as f32;
+        let y = self.min.1 + (self.max.1 - self.min.1) * iy as f32 / (NY - 1) as f32;
+        (x, y)
+    }
+
+    pub fn get(&self, ix: usize, iy: usize) -> f32 {
+        self.z[iy][ix]
+    }
+
+    pub fn set(&mut self, ix: usize, iy: usize, z: f32) {
+        self.z[iy][ix] = z;
+    }
+
+    /// Return the grid points in a serpentine order so the head never travels back across the bed.
+    pub fn points(&self) -> impl Iterator<Item = (usize, usize)> + use<NX, NY> {
+        (0..NY).flat_map(|iy| {
+            (0..NX).map(move |i| {
+                if iy % 2 == 0 {
+                    (i, iy)
+                } else {
+                    (NX - 1 - i, iy)
+                }
+            })
+        })
+    }
+
+    pub fn mean(&self) -> f32 {
+        self.z.iter().flatten().sum::<f32>() / (NX * NY) as f32
+    }
+
+    /// Return the difference between the highest and lowest points.
+    pub fn range(&self) -> f32 {
+        let (min, max) = self
+            .z
+            .iter()
+            .flatten()
+            .fold((f32::MAX, f32::MIN), |(min, max), &z| {
+                (min.min(z), max.max(z))
+            });
+        max - min
+    }
+
+    /// Shift the mesh so its mean height is zero, leaving only the variation across the bed.
+    pub fn normalise(&mut self) {
+        let mean = self.mean();
+        self.z.iter_mut().flatten().for_each(|z| *z -= mean);
+    }
+
+    /// Return the bed height at a position by bilinear interpolation of the surrounding points. Positions outside the
+    /// grid use the nearest edge.
+    pub fn interpolate(&self, x: f32, y: f32) -> f32 {
+        let (ix, tx) = cell(x, self.min.0, self.max.0, NX);
+        let (iy, ty) = cell(y, self.min.1, self.max.1, NY);
+        let bottom = lerp(self.z[iy][ix], self.z[iy][ix + 1], tx);
+        let top = lerp(self.z[iy + 1][ix], self.z[iy + 1][ix + 1], tx);
+        lerp(bottom, top, ty)
+    }
+}
+
+/// Return the index of the cell containing the position and how far across it the position is.
+fn cell(v: f32, min: f32, max: f32, n: usize) -> (usize, f32) {
+    let f = ((v - min) / (max - min)).clamp(0.0, 1.0) * (n - 1) as f32;
+    let i = (f as usize).min(n - 2);
+    (i, f - i as f32)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x3 mesh over 10..210 x 20..220 where z = x / 100 + y / 1000 at each node, so bilinear interpolation is exact.
+    fn plane() -> BedMesh<3, 3> {
+        let mut mesh = BedMesh::new((10.0, 20.0), (210.0, 220.0));
+        for (ix, iy) in mesh.points() {
+            let (x, y) = mesh.point(ix, iy);
+            mesh.set(ix, iy, x / 100.0 + y / 1000.0);
+        }
+        mesh
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn places_points_evenly() {
+        let mesh = plane();
+        assert_eq!(mesh.point(0, 0), (10.0, 20.0));
+        assert_eq!(mesh.point(1, 2), (110.0, 220.0));
+        assert_eq!(mesh.point(2, 1), (210.0, 120.0));
+    }
+
+    #[test]
+    fn returns_the_measured_height_at_each_node() {
+        let mut mesh = BedMesh::<3, 2>::new((0.0, 0.0), (100.0, 50.0));
+        let heights = [[0.1, -0.2, 0.3], [0.05, 0.0, -0.15]];
+        for (iy, row) in heights.iter().enumerate() {
+            for (ix, z) in row.iter().enumerate() {
+                mesh.set(ix, iy, *z);
+            }
+        }
+        for (iy, row) in heights.iter().enumerate() {
+            for (ix, z) in row.iter().enumerate() {
+                let (x, y) = mesh.point(ix, iy);
+                assert_close(mesh.interpolate(x, y), *z);
+            }
+        }
+    }
+
+    #[test]
+    fn interpolates_within_cells() {
+        let mut mesh = BedMesh::<2, 2>::new((0.0, 0.0), (10.0, 10.0));
+        mesh.set(0, 0, 0.0);
+        mesh.set(1, 0, 1.0);
+        mesh.set(0, 1, 2.0);
+        mesh.set(1, 1, 5.0);
+        // The middle of the cell is the mean of its corners.
+        assert_close(mesh.interpolate(5.0, 5.0), 2.0);
+        // Midpoints of the edges.
+        assert_close(mesh.interpolate(5.0, 0.0), 0.5);
+        assert_close(mesh.interpolate(0.0, 5.0), 1.0);
+        assert_close(mesh.interpolate(10.0, 5.0), 3.0);
+        assert_close(mesh.interpolate(5.0, 10.0), 3.5);
+        assert_close(mesh.interpolate(2.5, 7.5), 2.125);
+
+        let mesh = plane();
+        for (x, y) in [(60.0, 70.0), (160.0, 170.0), (33.0, 201.0), (110.0, 120.0)] {
+            assert_close(mesh.interpolate(x, y), x / 100.0 + y / 1000.0);
+        }
+    }
+
+    #[test]
+    fn clamps_positions_outside_the_grid_to_the_edge() {
+        let mesh = plane();
+        assert_close(mesh.interpolate(0.0, 0.0), mesh.get(0, 0));
+        assert_close(mesh.interpolate(300.0, 400.0), mesh.get(2, 2));
+        assert_close(
+            mesh.interpolate(-50.0, 120.0),
+            mesh.interpolate(10.0, 120.0),
+        );
+        assert_close(
+            mesh.interpolate(110.0, 500.0),
+            mesh.interpolate(110.0, 220.0),
+        );
+        // The far edge belongs to the last cell rather than indexing past it.
+        assert_close(mesh.interpolate(210.0, 220.0), mesh.get(2, 2));
+    }
+
+    #[test]
+    fn visits_points_in_serpentine_order() {
+        let mesh = BedMesh::<3, 3>::new((0.0, 0.0), (1.0, 1.0));
+        let points: Vec<_> = mesh.points().collect();
+        assert_eq!(
+            points,
+            [
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (2, 1),
+                (1, 1),
+                (0, 1),
+                (0, 2),
+                (1, 2),
+                (2, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn normalises_to_a_zero_mean() {
+        let mut mesh = plane();
+        let range = mesh.range();
+        assert_close(range, 2.0 + 0.2);
+        mesh.normalise();
+        assert_close(mesh.mean(), 0.0);
+        assert_close(mesh.range(), range);
+    }
+}
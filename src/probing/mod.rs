@@ -0,0 +1,12 @@
+#![doc = include_str!("../../docs/probing.md")]
+mod error;
+mod mesh;
+mod probe;
+mod stats;
+mod timeline;
+
+pub use error::*;
+pub use mesh::*;
+pub use probe::*;
+pub use stats::*;
+pub use timeline::*;
@@ -0,0 +1,215 @@
+use core::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+
+use embassy_futures::select::{Either, select};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::{
+    components::{pinda::BuddyPinda, steppers::BuddyStepperExti, tmc::Direction},
+    fmt::info,
+    probing::{BedMesh, ProbeError, Repeatability, StepTimeline},
+};
+
+/// The settings used to probe the bed.
+#[derive(Debug, Clone, Copy)]
+pub struct ProbeConfig {
+    pub steps_per_mm: f32,
+    /// The direction that moves the nozzle towards the bed.
+    pub down: Direction,
+    /// The speed the probe approaches the bed (mm/s).
+    pub probe_feedrate: f32,
+    /// The speed of retracts and moves between points (mm/s).
+    pub travel_feedrate: f32,
+    /// How far the probe may move down before giving up (mm).
+    pub max_travel: f32,
+    /// The number of times each point is probed. The result is the mean of the samples.
+    pub samples: u8,
+    /// How far the probe lifts between samples (mm).
+    pub retract: f32,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            steps_per_mm: 400.0,
+            down: Direction::CounterClockwise,
+            probe_feedrate: 2.0,
+            travel_feedrate: 10.0,
+            max_travel: 10.0,
+            samples: 1,
+            retract: 1.0,
+        }
+    }
+}
+
+/// Probes the bed by lowering Z until the PINDA triggers.
+pub struct Prober<'a, 'd> {
+    pinda: &'a BuddyPinda<'d>,
+    z: &'a BuddyStepperExti<'d>,
+    config: ProbeConfig,
+    /// The Z position in steps, increasing upwards.
+    position: AtomicI32,
+}
+
+impl<'a, 'd> Prober<'a, 'd> {
+    pub fn new(
+        pinda: &'a BuddyPinda<'d>,
+        z: &'a BuddyStepperExti<'d>,
+        config: ProbeConfig,
+    ) -> Self {
+        Self {
+            pinda,
+            z,
+            config,
+            position: AtomicI32::new(0),
+        }
+    }
+
+    pub fn config(&self) -> &ProbeConfig {
+        &self.config
+    }
+
+    /// Return the Z position (mm).
+    pub fn position(&self) -> f32 {
+        self.position.load(Ordering::Relaxed) as f32 / self.config.steps_per_mm
+    }
+
+    /// Set the Z position (mm), e.g. after homing.
+    pub fn set_position(&self, z: f32) {
+        self.position.store(self.to_steps(z), Ordering::Relaxed);
+    }
+
+    fn to_steps(&self, mm: f32) -> i32 {
+        libm::roundf(mm * self.config.steps_per_mm) as i32
+    }
+
+    fn step_interval(&self, feedrate: f32) -> Duration {
+        Duration::from_micros((1_000_000.0 / (feedrate * self.config.steps_per_mm)) as u64)
+    }
+
+    fn up(&self) -> Direction {
+        match self.config.down {
+            Direction::Clockwise => Direction::CounterClockwise,
+            Direction::CounterClockwise => Direction::Clockwise,
+        }
+    }
+
+    /// Plan a move of Z by a number of steps (positive is up) at the feedrate, starting now.
+    fn plan_z(&self, steps: i32, feedrate: f32) -> StepTimeline {
+        StepTimeline::new(
+            Instant::now(),
+            self.step_interval(feedrate),
+            self.position.load(Ordering::Relaxed),
+            steps.signum(),
+        )
+    }
+
+    /// Step Z along the timeline, tracking the position and counting the steps made in `taken`.
+    async fn step_z(&self, timeline: &StepTimeline, steps: i32, taken: &AtomicU32) {
+        let (direction, delta) = if steps >= 0 {
+            (self.up(), 1)
+        } else {
+            (self.config.down, -1)
+        };
+        self.z.set_direction(direction).await;
+        for k in 0..steps.unsigned_abs() {
+            Timer::at(timeline.due(k)).await;
+            self.z.step().await;
+            self.position.fetch_add(delta, Ordering::Relaxed);
+            taken.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    async fn travel_z(&self, steps: i32) {
+        let timeline = self.plan_z(steps, self.config.travel_feedrate);
+        self.step_z(&timeline, steps, &AtomicU32::new(0)).await;
+    }
+
+    /// Move Z by a distance (mm), positive is up.
+    pub async fn move_z(&self, distance: f32) {
+        self.travel_z(self.to_steps(distance)).await;
+    }
+
+    /// Move Z to a position (mm).
+    pub async fn move_z_to(&self, z: f32) {
+        let steps = self.to_steps(z) - self.position.load(Ordering::Relaxed);
+        self.travel_z(steps).await;
+    }
+
+    /// Lower Z until the probe triggers, returning the Z position (mm) at contact.
+    ///
+    /// Z keeps stepping until the task sees the edge, so the position at contact is worked out from when the edge
+    /// fired, which is timestamped in the EXTI interrupt, and the step timeline rather than from the steps taken.
+    pub async fn probe_once(&self) -> Result<f32, ProbeError> {
+        if self.pinda.in_contact().await {
+            return Err(ProbeError::AlreadyTriggered);
+        }
+        let steps = -self.to_steps(self.config.max_travel);
+        let timeline = self.plan_z(steps, self.config.probe_feedrate);
+        let taken = AtomicU32::new(0);
+        // The contact future is polled first so it is listening before the first step.
+        match select(
+            self.pinda.wait_for_contact(),
+            self.step_z(&timeline, steps, &taken),
+        )
+        .await
+        {
+            Either::First(at) => {
+                let position = timeline.position_at(at, taken.load(Ordering::Relaxed));
+                Ok(position as f32 / self.config.steps_per_mm)
+            }
+            Either::Second(_) => Err(ProbeError::NoContact),
+        }
+    }
+
+    /// Probe the current point the configured number of times, retracting between samples, and return the mean Z
+    /// position (mm). Z is left retracted above the point.
+    pub async fn probe_point(&self) -> Result<f32, ProbeError> {
+        let samples = self.config.samples.max(1);
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            sum += self.probe_once().await?;
+            self.move_z(self.config.retract).await;
+        }
+        Ok(sum / samples as f32)
+    }
+
+    /// Probe the current point `N` times, retracting between samples, to measure how repeatable the probe is (M48).
+    pub async fn repeatability<const N: usize>(&self) -> Result<Repeatability<N>, ProbeError> {
+        let mut samples = [0.0; N];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample = self.probe_once().await?;
+            info!("[PROBE] Sample {}: {}mm", i + 1, *sample);
+            self.move_z(self.config.retract).await;
+        }
+        let repeatability = Repeatability::new(samples);
+        let stats = repeatability.stats;
+        info!(
+            "[PROBE] Min: {}mm, Max: {}mm, Mean: {}mm, Std Dev: {}mm, Range: {}mm",
+            stats.min(),
+            stats.max(),
+            stats.mean(),
+            stats.std_dev(),
+            stats.range()
+        );
+        Ok(repeatability)
+    }
+
+    /// Probe every point of the mesh. `move_to` moves the head to an XY position (mm) and is called with Z raised
+    /// back to the height probing started from. Z returns to that height once the mesh is complete.
+    pub async fn probe_mesh<const NX: usize, const NY: usize>(
+        &self,
+        mesh: &mut BedMesh<NX, NY>,
+        mut move_to: impl AsyncFnMut(f32, f32),
+    ) -> Result<(), ProbeError> {
+        let start = self.position();
+        for (ix, iy) in mesh.points() {
+            self.move_z_to(start).await;
+            let (x, y) = mesh.point(ix, iy);
+            move_to(x, y).await;
+            let z = self.probe_point().await?;
+            mesh.set(ix, iy, z);
+        }
+        self.move_z_to(start).await;
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+/// Statistics of probe samples (mm), accumulated with Welford's method so the samples do not need storing.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct ProbeStats {
+    count: u32,
+    min: f32,
+    max: f32,
+    mean: f32,
+    /// The sum of squared differences from the mean.
+    m2: f32,
+}
+
+impl ProbeStats {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            min: f32::MAX,
+            max: f32::MIN,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        self.count += 1;
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+        let delta = sample - self.mean;
+        self.mean += delta / self.count as f32;
+        self.m2 += delta * (sample - self.mean);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Return the lowest sample, or 0 before any samples.
+    pub fn min(&self) -> f32 {
+        if self.count == 0 { 0.0 } else { self.min }
+    }
+
+    /// Return the highest sample, or 0 before any samples.
+    pub fn max(&self) -> f32 {
+        if self.count == 0 { 0.0 } else { self.max }
+    }
+
+    pub fn mean(&self) -> f32 {
+        self.mean
+    }
+
+    pub fn range(&self) -> f32 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.max - self.min
+        }
+    }
+
+    /// Return the population standard deviation, as reported by M48.
+    pub fn std_dev(&self) -> f32 {
+        if self.count == 0 {
+            0.0
+        } else {
+            libm::sqrtf(self.m2 / self.count as f32)
+        }
+    }
+}
+
+impl Default for ProbeStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The result of probing the same point `N` times.
+#[derive(Debug, Clone)]
+pub struct Repeatability<const N: usize> {
+    pub samples: [f32; N],
+    pub stats: ProbeStats,
+}
+
+impl<const N: usize> Repeatability<N> {
+    pub fn new(samples: [f32; N]) -> Self {
+        let mut stats = ProbeStats::new();
+        samples.iter().for_each(|sample| stats.push(*sample));
+        Self { samples, stats }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn reports_zero_without_samples() {
+        let stats = ProbeStats::new();
+        assert_eq!(stats.count(), 0);
+        assert_eq!(stats.mean(), 0.0);
+        assert_eq!(stats.min(), 0.0);
+        assert_eq!(stats.max(), 0.0);
+        assert_eq!(stats.range(), 0.0);
+        assert_eq!(stats.std_dev(), 0.0);
+    }
+
+    #[test]
+    fn a_single_sample_has_no_spread() {
+        let mut stats = ProbeStats::new();
+        stats.push(-0.42);
+        assert_eq!(stats.count(), 1);
+        assert_eq!(stats.mean(), -0.42);
+        assert_eq!((stats.min(), stats.max()), (-0.42, -0.42));
+        assert_eq!(stats.range(), 0.0);
+        assert_eq!(stats.std_dev(), 0.0);
+    }
+
+    #[test]
+    fn matches_known_statistics() {
+        // The textbook set with a mean of 5 and a population standard deviation of 2.
+        let stats = Repeatability::new([2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]).stats;
+        assert_eq!(stats.count(), 8);
+        assert_close(stats.mean(), 5.0);
+        assert_close(stats.std_dev(), 2.0);
+        assert_eq!((stats.min(), stats.max(), stats.range()), (2.0, 9.0, 7.0));
+    }
+
+    #[test]
+    fn matches_a_two_pass_calculation_on_probe_samples() {
+        let samples = [
+            0.512, 0.508, 0.515, 0.509, 0.511, 0.507, 0.514, 0.510, 0.512, 0.506,
+        ];
+        let n = samples.len() as f64;
+        let mean = samples.iter().map(|s| *s as f64).sum::<f64>() / n;
+        let variance = samples
+            .iter()
+            .map(|s| (*s as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+
+        let repeatability = Repeatability::new(samples);
+        assert_eq!(repeatability.samples, samples);
+        let stats = repeatability.stats;
+        assert_close(stats.mean(), mean as f32);
+        assert!((stats.std_dev() as f64 - variance.sqrt()).abs() < 1e-6);
+        assert_close(stats.range(), 0.009);
+    }
+}
@@ -0,0 +1,106 @@
+use embassy_time::{Duration, Instant};
+
+/// The schedule of a constant speed move, where step `k` is due at `start + k * interval`. Used to work out where the
+/// axis was at an earlier point in time, such as when the probe triggered.
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct StepTimeline {
+    start: Instant,
+    interval: Duration,
+    /// The position before the first step.
+    origin: i32,
+    /// Whether each step moves the position up (+1) or down (-1).
+    increment: i32,
+}
+
+impl StepTimeline {
+    pub fn new(start: Instant, interval: Duration, origin: i32, increment: i32) -> Self {
+        Self {
+            start,
+            interval,
+            origin,
+            increment: increment.signum(),
+        }
+    }
+
+    /// Return when step `k` (counting from 0) is due.
+    pub fn due(&self, k: u32) -> Instant {
+        self.start + self.interval * k
+    }
+
+    /// Return the number of steps due by `at`.
+    pub fn steps_due(&self, at: Instant) -> u32 {
+        if at < self.start {
+            return 0;
+        }
+        match self.interval.as_ticks() {
+            // Every step is due at the start.
+            0 => u32::MAX,
+            interval => {
+                let due = (at - self.start).as_ticks() / interval + 1;
+                u32::try_from(due).unwrap_or(u32::MAX)
+            }
+        }
+    }
+
+    /// Return the position at `at`, given only `taken` steps have actually been taken. Steps that were due but had not
+    /// been taken yet because the executor was busy are not counted.
+    pub fn position_at(&self, at: Instant, taken: u32) -> i32 {
+        self.origin + self.increment * self.steps_due(at).min(taken) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Z moving down from step 1000 every 250us (2mm/s at 400 steps/mm), starting 1s after boot.
+    fn timeline() -> StepTimeline {
+        StepTimeline::new(Instant::from_secs(1), Duration::from_micros(250), 1000, -1)
+    }
+
+    fn after(us: u64) -> Instant {
+        Instant::from_secs(1) + Duration::from_micros(us)
+    }
+
+    #[test]
+    fn schedules_steps_at_the_interval() {
+        let timeline = timeline();
+        assert_eq!(timeline.due(0), after(0));
+        assert_eq!(timeline.due(4), after(1_000));
+    }
+
+    #[test]
+    fn maps_an_edge_time_to_the_step_due_by_then() {
+        let timeline = timeline();
+        assert_eq!(timeline.steps_due(Instant::from_millis(999)), 0);
+        // Step 0 is due at the start.
+        assert_eq!(timeline.steps_due(after(0)), 1);
+        assert_eq!(timeline.steps_due(after(249)), 1);
+        assert_eq!(timeline.steps_due(after(250)), 2);
+        // An edge 10.1ms into the move sees steps 0 to 40 taken.
+        assert_eq!(timeline.position_at(after(10_100), u32::MAX), 1000 - 41);
+    }
+
+    #[test]
+    fn ignores_steps_the_executor_has_not_taken() {
+        let timeline = timeline();
+        // The edge fired 10ms in but the task had fallen behind and only taken 30 steps.
+        assert_eq!(timeline.position_at(after(10_000), 30), 970);
+        // The task has kept stepping after the edge, which must not count.
+        assert_eq!(timeline.position_at(after(10_000), 60), 959);
+    }
+
+    #[test]
+    fn moves_up_with_a_positive_increment() {
+        let timeline = StepTimeline::new(Instant::from_secs(1), Duration::from_micros(250), -5, 7);
+        assert_eq!(timeline.position_at(after(500), u32::MAX), -2);
+        assert_eq!(timeline.position_at(Instant::from_ticks(0), u32::MAX), -5);
+    }
+
+    #[test]
+    fn an_immediate_move_has_every_step_due() {
+        let timeline = StepTimeline::new(Instant::from_secs(1), Duration::from_ticks(0), 0, 1);
+        assert_eq!(timeline.steps_due(after(0)), u32::MAX);
+        assert_eq!(timeline.position_at(after(0), 12), 12);
+    }
+}
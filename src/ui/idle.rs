@@ -0,0 +1,140 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_futures::select::{Either, select};
+use embassy_sync::{blocking_mutex::raw::RawMutex, mutex::Mutex, signal::Signal};
+use embassy_time::{Duration, Timer};
+
+use crate::fmt::error;
+
+/// A display that can be turned off to save power and back on again, keeping what it shows.
+#[allow(async_fn_in_trait)]
+pub trait BlankableDisplay {
+    type Error;
+
+    /// Turn the display off.
+    async fn blank(&mut self) -> Result<(), Self::Error>;
+
+    /// Turn the display back on.
+    async fn unblank(&mut self) -> Result<(), Self::Error>;
+}
+
+/// Blanks the display after a period without input and wakes it on the next input.
+///
+/// The timer does not read the encoder or button itself so the UI keeps receiving every event. Instead the UI calls
+/// [`IdleTimer::on_input`] for each event it handles.
+pub struct IdleTimer<'a, M: RawMutex, D: BlankableDisplay> {
+    display: &'a Mutex<M, D>,
+    timeout: Duration,
+    activity: Signal<M, ()>,
+    blanked: AtomicBool,
+}
+
+impl<'a, M: RawMutex, D: BlankableDisplay> IdleTimer<'a, M, D> {
+    pub fn new(display: &'a Mutex<M, D>, timeout: Duration) -> Self {
+        Self {
+            display,
+            timeout,
+            activity: Signal::new(),
+            blanked: AtomicBool::new(false),
+        }
+    }
+
+    /// Record user input, restarting the timeout.
+    ///
+    /// Returns `true` if the display was blank. The input should then be ignored as it was only used to wake the
+    /// display.
+    pub fn on_input(&self) -> bool {
+        self.activity.signal(());
+        self.blanked.swap(false, Ordering::Relaxed)
+    }
+
+    /// Return whether the display is blank.
+    pub fn is_blanked(&self) -> bool {
+        self.blanked.load(Ordering::Relaxed)
+    }
+
+    /// Blank and wake the display. Needs to run alongside the UI.
+    pub async fn run(&self) -> ! {
+        let mut asleep = false;
+        loop {
+            if asleep {
+                self.activity.wait().await;
+                asleep = false;
+                if self.display.lock().await.unblank().await.is_err() {
+                    error!("[IDLE] Failed to wake the display");
+                }
+                continue;
+            }
+            if let Either::Second(_) =
+                select(self.activity.wait(), Timer::after(self.timeout)).await
+            {
+                asleep = true;
+                self.blanked.store(true, Ordering::Relaxed);
+                if self.display.lock().await.blank().await.is_err() {
+                    error!("[IDLE] Failed to blank the display");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Panel {
+        on: bool,
+        blanks: usize,
+        unblanks: usize,
+    }
+
+    impl BlankableDisplay for Panel {
+        type Error = ();
+
+        async fn blank(&mut self) -> Result<(), ()> {
+            self.on = false;
+            self.blanks += 1;
+            Ok(())
+        }
+
+        async fn unblank(&mut self) -> Result<(), ()> {
+            self.on = true;
+            self.unblanks += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn blanks_after_the_timeout_and_wakes_on_input() {
+        let display = Mutex::<NoopRawMutex, _>::new(Panel {
+            on: true,
+            ..Default::default()
+        });
+        let idle = IdleTimer::new(&display, Duration::from_millis(40));
+        let ui = async {
+            // Input within the timeout keeps the display on.
+            for _ in 0..3 {
+                Timer::after_millis(20).await;
+                assert!(!idle.on_input());
+            }
+            assert!(display.lock().await.on);
+
+            Timer::after_millis(60).await;
+            assert!(idle.is_blanked());
+            assert!(!display.lock().await.on);
+
+            // The first input only wakes the display.
+            assert!(idle.on_input());
+            Timer::after_millis(5).await;
+            assert!(display.lock().await.on);
+            assert!(!idle.on_input());
+        };
+        block_on(select(idle.run(), ui));
+        let display = display.try_lock().unwrap();
+        assert_eq!((display.blanks, display.unblanks), (1, 1));
+    }
+}
@@ -0,0 +1,545 @@
+use core::cell::Cell;
+
+use embedded_graphics::{
+    mono_font::{MonoTextStyle, ascii::FONT_10X20},
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use crate::{components::rotary_button::ButtonEvent, ui::text::TextBuf};
+
+/// The height of the title bar and each row of the menu.
+pub const ROW_HEIGHT: u32 = 30;
+
+/// The number of submenus that can be opened within each other.
+pub const MAX_DEPTH: usize = 8;
+
+/// What an item does when it is clicked.
+pub enum ItemKind<'a> {
+    /// Open a submenu.
+    Submenu(&'a [MenuItem<'a>]),
+    /// Edit a number with the encoder between `min` and `max` in increments of `step`.
+    Number {
+        value: &'a Cell<f32>,
+        min: f32,
+        max: f32,
+        step: f32,
+        unit: &'a str,
+    },
+    /// Flip a value on or off.
+    Toggle(&'a Cell<bool>),
+    /// Report the item id to the application.
+    Action,
+    /// Return to the parent menu.
+    Back,
+}
+
+/// An entry in the menu tree.
+pub struct MenuItem<'a> {
+    pub id: u16,
+    pub label: &'a str,
+    pub kind: ItemKind<'a>,
+}
+
+impl<'a> MenuItem<'a> {
+    pub const fn submenu(label: &'a str, items: &'a [MenuItem<'a>]) -> Self {
+        Self {
+            id: 0,
+            label,
+            kind: ItemKind::Submenu(items),
+        }
+    }
+
+    pub const fn number(
+        id: u16,
+        label: &'a str,
+        value: &'a Cell<f32>,
+        min: f32,
+        max: f32,
+        step: f32,
+        unit: &'a str,
+    ) -> Self {
+        Self {
+            id,
+            label,
+            kind: ItemKind::Number {
+                value,
+                min,
+                max,
+                step,
+                unit,
+            },
+        }
+    }
+
+    pub const fn toggle(id: u16, label: &'a str, value: &'a Cell<bool>) -> Self {
+        Self {
+            id,
+            label,
+            kind: ItemKind::Toggle(value),
+        }
+    }
+
+    pub const fn action(id: u16, label: &'a str) -> Self {
+        Self {
+            id,
+            label,
+            kind: ItemKind::Action,
+        }
+    }
+
+    pub const fn back() -> Self {
+        Self {
+            id: 0,
+            label: "Back",
+            kind: ItemKind::Back,
+        }
+    }
+}
+
+/// The outcome of an input that the application may need to act upon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum MenuEvent {
+    /// An action item was clicked.
+    Action(u16),
+    /// A number or toggle item was changed.
+    Changed(u16),
+    /// Back was pressed at the top level of the menu.
+    Exit,
+}
+
+/// The colours used to draw the menu.
+#[derive(Debug, Clone, Copy)]
+pub struct MenuStyle {
+    pub background: Rgb565,
+    pub foreground: Rgb565,
+    pub title_background: Rgb565,
+    pub selected_background: Rgb565,
+    pub selected_foreground: Rgb565,
+    pub editing_background: Rgb565,
+}
+
+impl Default for MenuStyle {
+    fn default() -> Self {
+        Self {
+            background: Rgb565::BLACK,
+            foreground: Rgb565::WHITE,
+            title_background: Rgb565::CSS_DARK_SLATE_GRAY,
+            selected_background: Rgb565::WHITE,
+            selected_foreground: Rgb565::BLACK,
+            editing_background: Rgb565::CSS_ORANGE,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Level<'a> {
+    title: &'a str,
+    items: &'a [MenuItem<'a>],
+    selected: usize,
+    /// The first visible item.
+    offset: usize,
+}
+
+/// Navigates a tree of [`MenuItem`]s with the rotary encoder and button and draws it to the display.
+pub struct Menu<'a> {
+    levels: [Level<'a>; MAX_DEPTH],
+    depth: usize,
+    editing: bool,
+    style: MenuStyle,
+}
+
+impl<'a> Menu<'a> {
+    pub fn new(title: &'a str, items: &'a [MenuItem<'a>]) -> Self {
+        let root = Level {
+            title,
+            items,
+            selected: 0,
+            offset: 0,
+        };
+        Self {
+            levels: [root; MAX_DEPTH],
+            depth: 0,
+            editing: false,
+            style: MenuStyle::default(),
+        }
+    }
+
+    pub fn with_style(mut self, style: MenuStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    fn level(&self) -> &Level<'a> {
+        &self.levels[self.depth]
+    }
+
+    fn level_mut(&mut self) -> &mut Level<'a> {
+        &mut self.levels[self.depth]
+    }
+
+    /// Return the currently selected item.
+    pub fn selected(&self) -> Option<&'a MenuItem<'a>> {
+        let level = self.level();
+        level.items.get(level.selected)
+    }
+
+    /// Return whether a number is being edited.
+    pub fn is_editing(&self) -> bool {
+        self.editing
+    }
+
+    /// Return to the top of the menu.
+    pub fn reset(&mut self) {
+        self.depth = 0;
+        self.editing = false;
+        let root = self.level_mut();
+        root.selected = 0;
+        root.offset = 0;
+    }
+
+    /// Move the selection, or change the value being edited, by the encoder delta.
+    pub fn on_scroll(&mut self, delta: i32) -> Option<MenuEvent> {
+        if delta == 0 {
+            return None;
+        }
+        if self.editing {
+            if let Some(MenuItem {
+                kind:
+                    ItemKind::Number {
+                        value,
+                        min,
+                        max,
+                        step,
+                        ..
+                    },
+                ..
+            }) = self.selected()
+            {
+                value.set((value.get() + *step * delta as f32).clamp(*min, *max));
+            }
+            return None;
+        }
+        let level = self.level_mut();
+        let last = level.items.len().saturating_sub(1) as i32;
+        level.selected = (level.selected as i32 + delta).clamp(0, last) as usize;
+        None
+    }
+
+    /// Activate the selected item.
+    pub fn on_click(&mut self) -> Option<MenuEvent> {
+        let item = self.selected()?;
+        match &item.kind {
+            ItemKind::Submenu(items) => {
+                if self.depth + 1 < MAX_DEPTH {
+                    self.depth += 1;
+                    *self.level_mut() = Level {
+                        title: item.label,
+                        items,
+                        selected: 0,
+                        offset: 0,
+                    };
+                }
+                None
+            }
+            ItemKind::Number { .. } => {
+                self.editing = !self.editing;
+                if self.editing {
+                    None
+                } else {
+                    Some(MenuEvent::Changed(item.id))
+                }
+            }
+            ItemKind::Toggle(value) => {
+                value.set(!value.get());
+                Some(MenuEvent::Changed(item.id))
+            }
+            ItemKind::Action => Some(MenuEvent::Action(item.id)),
+            ItemKind::Back => self.on_back(),
+        }
+    }
+
+    /// Stop editing or return to the parent menu.
+    pub fn on_back(&mut self) -> Option<MenuEvent> {
+        if self.editing {
+            self.editing = false;
+            return self.selected().map(|item| MenuEvent::Changed(item.id));
+        }
+        if self.depth == 0 {
+            return Some(MenuEvent::Exit);
+        }
+        self.depth -= 1;
+        None
+    }
+
+    /// Handle a button gesture. A click activates the selected item and a long press goes back.
+    pub fn on_button(&mut self, event: ButtonEvent) -> Option<MenuEvent> {
+        match event {
+            ButtonEvent::Click => self.on_click(),
+            ButtonEvent::LongPress => self.on_back(),
+            _ => None,
+        }
+    }
+
+    /// Draw the menu to fill the display.
+    pub fn draw<D: DrawTarget<Color = Rgb565>>(&mut self, target: &mut D) -> Result<(), D::Error> {
+        let area = target.bounding_box();
+        let rows = (area.size.height / ROW_HEIGHT).saturating_sub(1).max(1) as usize;
+        let style = self.style;
+        let editing = self.editing;
+
+        // Keep the selected row in view.
+        let level = self.level_mut();
+        if level.selected < level.offset {
+            level.offset = level.selected;
+        } else if level.selected >= level.offset + rows {
+            level.offset = level.selected + 1 - rows;
+        }
+        let level = *self.level();
+
+        let title = Rectangle::new(area.top_left, Size::new(area.size.width, ROW_HEIGHT));
+        draw_row(
+            target,
+            title,
+            level.title,
+            "",
+            style.foreground,
+            style.title_background,
+        )?;
+
+        for row in 0..rows {
+            let top_left = area.top_left + Point::new(0, ((row as u32 + 1) * ROW_HEIGHT) as i32);
+            let bounds = Rectangle::new(top_left, Size::new(area.size.width, ROW_HEIGHT));
+            let index = level.offset + row;
+            let Some(item) = level.items.get(index) else {
+                bounds
+                    .into_styled(PrimitiveStyle::with_fill(style.background))
+                    .draw(target)?;
+                continue;
+            };
+            let (foreground, background) = match (index == level.selected, editing) {
+                (true, true) => (style.selected_foreground, style.editing_background),
+                (true, false) => (style.selected_foreground, style.selected_background),
+                _ => (style.foreground, style.background),
+            };
+            let value: TextBuf<16> = match &item.kind {
+                ItemKind::Submenu(_) => TextBuf::format(format_args!(">")),
+                ItemKind::Number { value, unit, .. } => {
+                    TextBuf::format(format_args!("{:.1}{}", value.get(), unit))
+                }
+                ItemKind::Toggle(value) => {
+                    TextBuf::format(format_args!("{}", if value.get() { "On" } else { "Off" }))
+                }
+                ItemKind::Action | ItemKind::Back => TextBuf::new(),
+            };
+            draw_row(
+                target,
+                bounds,
+                item.label,
+                value.as_str(),
+                foreground,
+                background,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Draw a row with a label on the left and a value on the right.
+fn draw_row<D: DrawTarget<Color = Rgb565>>(
+    target: &mut D,
+    bounds: Rectangle,
+    label: &str,
+    value: &str,
+    foreground: Rgb565,
+    background: Rgb565,
+) -> Result<(), D::Error> {
+    bounds
+        .into_styled(PrimitiveStyle::with_fill(background))
+        .draw(target)?;
+    let character_style = MonoTextStyle::new(&FONT_10X20, foreground);
+    let middle = bounds.top_left.y + (bounds.size.height / 2) as i32;
+    let left = TextStyleBuilder::new()
+        .alignment(Alignment::Left)
+        .baseline(Baseline::Middle)
+        .build();
+    Text::with_text_style(
+        label,
+        Point::new(bounds.top_left.x + 8, middle),
+        character_style,
+        left,
+    )
+    .draw(target)?;
+    if !value.is_empty() {
+        let right = TextStyleBuilder::new()
+            .alignment(Alignment::Right)
+            .baseline(Baseline::Middle)
+            .build();
+        Text::with_text_style(
+            value,
+            Point::new(bounds.top_left.x + bounds.size.width as i32 - 8, middle),
+            character_style,
+            right,
+        )
+        .draw(target)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::mock_display::MockDisplay;
+
+    use super::*;
+    use crate::mock::{Window, assert_snapshot};
+
+    /// Colours that the mock display can print.
+    const STYLE: MenuStyle = MenuStyle {
+        background: Rgb565::BLACK,
+        foreground: Rgb565::WHITE,
+        title_background: Rgb565::BLUE,
+        selected_background: Rgb565::WHITE,
+        selected_foreground: Rgb565::BLACK,
+        editing_background: Rgb565::YELLOW,
+    };
+
+    /// Draw the menu on a 64 pixel wide screen `height` tall and return the rows from `offset`.
+    fn render(menu: &mut Menu, height: u32, offset: i32) -> MockDisplay<Rgb565> {
+        let mut display = MockDisplay::new();
+        let mut window = Window::new(&mut display, Size::new(64, height), Point::new(0, offset));
+        menu.draw(&mut window).unwrap();
+        display
+    }
+
+    #[test]
+    fn draws_the_title_and_selected_row() {
+        let fan = Cell::new(true);
+        let temp = Cell::new(5.0);
+        let items = [
+            MenuItem::toggle(1, "F", &fan),
+            MenuItem::number(2, "T", &temp, 0.0, 10.0, 0.5, ""),
+        ];
+        let mut menu = Menu::new("Set", &items).with_style(STYLE);
+        assert_snapshot(&[render(&mut menu, 120, 0)], "menu_top");
+    }
+
+    #[test]
+    fn draws_unselected_and_empty_rows() {
+        let fan = Cell::new(true);
+        let temp = Cell::new(5.0);
+        let items = [
+            MenuItem::toggle(1, "F", &fan),
+            MenuItem::number(2, "T", &temp, 0.0, 10.0, 0.5, ""),
+        ];
+        let mut menu = Menu::new("Set", &items).with_style(STYLE);
+        assert_snapshot(&[render(&mut menu, 120, 60)], "menu_rows");
+    }
+
+    #[test]
+    fn highlights_the_value_being_edited() {
+        let fan = Cell::new(true);
+        let temp = Cell::new(5.0);
+        let items = [
+            MenuItem::toggle(1, "F", &fan),
+            MenuItem::number(2, "T", &temp, 0.0, 10.0, 0.5, ""),
+        ];
+        let mut menu = Menu::new("Set", &items).with_style(STYLE);
+        menu.on_scroll(1);
+        menu.on_click();
+        menu.on_scroll(1);
+        assert_snapshot(&[render(&mut menu, 120, 30)], "menu_editing");
+    }
+
+    #[test]
+    fn scrolls_the_selection_into_view() {
+        let fan = Cell::new(true);
+        let temp = Cell::new(5.0);
+        let items = [
+            MenuItem::toggle(1, "F", &fan),
+            MenuItem::number(2, "T", &temp, 0.0, 10.0, 0.5, ""),
+            MenuItem::back(),
+        ];
+        let mut menu = Menu::new("Set", &items).with_style(STYLE);
+        menu.on_scroll(2);
+        assert_snapshot(&[render(&mut menu, 60, 0)], "menu_scrolled");
+        // Scrolling back up moves the view with the selection.
+        menu.on_scroll(-1);
+        assert_snapshot(&[render(&mut menu, 60, 0)], "menu_scrolled_back");
+    }
+
+    #[test]
+    fn uses_the_default_colours() {
+        let temp = Cell::new(5.0);
+        let items = [
+            MenuItem::number(2, "T", &temp, 0.0, 10.0, 0.5, ""),
+            MenuItem::back(),
+        ];
+        let mut menu = Menu::new("Set", &items);
+        let display = render(&mut menu, 90, 0);
+        assert_eq!(
+            display.get_pixel(Point::new(1, 1)),
+            Some(Rgb565::CSS_DARK_SLATE_GRAY)
+        );
+        assert_eq!(display.get_pixel(Point::new(1, 31)), Some(Rgb565::WHITE));
+        assert_eq!(display.get_pixel(Point::new(1, 61)), Some(Rgb565::BLACK));
+        menu.on_click();
+        let display = render(&mut menu, 90, 0);
+        assert_eq!(
+            display.get_pixel(Point::new(1, 31)),
+            Some(Rgb565::CSS_ORANGE)
+        );
+    }
+
+    #[test]
+    fn clamps_the_selection_and_edited_value() {
+        let temp = Cell::new(5.0);
+        let items = [
+            MenuItem::number(2, "T", &temp, 0.0, 10.0, 0.5, ""),
+            MenuItem::action(3, "Go"),
+        ];
+        let mut menu = Menu::new("Set", &items);
+        menu.on_scroll(5);
+        assert_eq!(menu.selected().unwrap().id, 3);
+        menu.on_scroll(-5);
+        assert_eq!(menu.selected().unwrap().id, 2);
+
+        assert_eq!(menu.on_click(), None);
+        assert!(menu.is_editing());
+        menu.on_scroll(3);
+        assert_eq!(temp.get(), 6.5);
+        menu.on_scroll(100);
+        assert_eq!(temp.get(), 10.0);
+        assert_eq!(
+            menu.on_button(ButtonEvent::Click),
+            Some(MenuEvent::Changed(2))
+        );
+        assert!(!menu.is_editing());
+    }
+
+    #[test]
+    fn opens_submenus_and_goes_back() {
+        let fan = Cell::new(false);
+        let inner = [MenuItem::toggle(1, "Fan", &fan), MenuItem::back()];
+        let items = [MenuItem::submenu("More", &inner), MenuItem::action(7, "Go")];
+        let mut menu = Menu::new("Set", &items);
+
+        assert_eq!(menu.on_click(), None);
+        assert_eq!(menu.selected().unwrap().label, "Fan");
+        assert_eq!(menu.on_click(), Some(MenuEvent::Changed(1)));
+        assert!(fan.get());
+        menu.on_scroll(1);
+        assert_eq!(menu.on_click(), None);
+        assert_eq!(menu.selected().unwrap().label, "More");
+
+        menu.on_scroll(1);
+        assert_eq!(menu.on_click(), Some(MenuEvent::Action(7)));
+        assert_eq!(
+            menu.on_button(ButtonEvent::LongPress),
+            Some(MenuEvent::Exit)
+        );
+        menu.reset();
+        assert_eq!(menu.selected().unwrap().label, "More");
+    }
+}
@@ -0,0 +1,10 @@
+#![doc = include_str!("../../docs/ui.md")]
+mod idle;
+mod menu;
+mod text;
+mod widgets;
+
+pub use idle::*;
+pub use menu::*;
+pub use text::*;
+pub use widgets::*;
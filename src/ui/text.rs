@@ -0,0 +1,83 @@
+use core::fmt::{self, Write};
+
+/// A fixed capacity string for formatting values without allocating. Text beyond the capacity is truncated.
+pub struct TextBuf<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> TextBuf<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Format the arguments into a new buffer, e.g. `TextBuf::<16>::format(format_args!("{:.1}C", t))`.
+    pub fn format(args: fmt::Arguments) -> Self {
+        let mut text = Self::new();
+        let _ = text.write_fmt(args);
+        text
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only whole UTF-8 characters are ever copied in.
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+impl<const N: usize> Default for TextBuf<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Write for TextBuf<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let len = c.len_utf8();
+            if self.len + len > N {
+                return Err(fmt::Error);
+            }
+            c.encode_utf8(&mut self.buf[self.len..self.len + len]);
+            self.len += len;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_into_the_buffer() {
+        let mut text = TextBuf::<16>::format(format_args!("{:.1}C", 215.04));
+        assert_eq!(text.as_str(), "215.0C");
+        text.clear();
+        assert_eq!(text.as_str(), "");
+        write!(text, "{}%", 40).unwrap();
+        assert_eq!(text.as_str(), "40%");
+    }
+
+    #[test]
+    fn truncates_at_the_capacity() {
+        let text = TextBuf::<4>::format(format_args!("No filament"));
+        assert_eq!(text.as_str(), "No f");
+        let mut text = TextBuf::<4>::new();
+        assert!(write!(text, "abcde").is_err());
+        assert_eq!(text.as_str(), "abcd");
+    }
+
+    #[test]
+    fn never_splits_a_character() {
+        // The degree sign takes two bytes so only one fits after "21".
+        let text = TextBuf::<5>::format(format_args!("21°°"));
+        assert_eq!(text.as_str(), "21°");
+    }
+}
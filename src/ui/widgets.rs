@@ -0,0 +1,772 @@
+use embedded_graphics::{
+    mono_font::{MonoTextStyle, ascii::FONT_10X20},
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{Circle, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use crate::ui::text::TextBuf;
+
+/// The colours used to draw the widgets.
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub background: Rgb565,
+    pub foreground: Rgb565,
+    /// The fill of bars.
+    pub accent: Rgb565,
+    /// The outline of bars and the target marker.
+    pub muted: Rgb565,
+    pub ok: Rgb565,
+    pub fault: Rgb565,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            background: Rgb565::BLACK,
+            foreground: Rgb565::WHITE,
+            accent: Rgb565::CSS_ORANGE,
+            muted: Rgb565::CSS_DIM_GRAY,
+            ok: Rgb565::CSS_LIME_GREEN,
+            fault: Rgb565::CSS_RED,
+        }
+    }
+}
+
+/// A part of the screen that only needs redrawing when the value it shows changes.
+pub trait Widget {
+    /// Return the area of the screen the widget draws over.
+    fn bounds(&self) -> Rectangle;
+
+    /// Return whether the value has changed since the widget was last drawn.
+    fn is_dirty(&self) -> bool;
+
+    /// Force the widget to be redrawn, e.g. after the screen has been cleared.
+    fn invalidate(&mut self);
+
+    /// Draw the widget over its bounds.
+    fn draw<D: DrawTarget<Color = Rgb565>>(
+        &mut self,
+        target: &mut D,
+        theme: &Theme,
+    ) -> Result<(), D::Error>;
+
+    /// Draw the widget only if it has changed, returning whether it was drawn.
+    fn redraw<D: DrawTarget<Color = Rgb565>>(
+        &mut self,
+        target: &mut D,
+        theme: &Theme,
+    ) -> Result<bool, D::Error> {
+        if !self.is_dirty() {
+            return Ok(false);
+        }
+        self.draw(target, theme)?;
+        Ok(true)
+    }
+}
+
+/// Implements the dirty tracking shared by all the widgets.
+macro_rules! widget_bounds {
+    () => {
+        fn bounds(&self) -> Rectangle {
+            self.bounds
+        }
+
+        fn is_dirty(&self) -> bool {
+            self.dirty
+        }
+
+        fn invalidate(&mut self) {
+            self.dirty = true;
+        }
+    };
+}
+
+/// Update a value, marking the widget dirty if it changed.
+fn update<T: PartialEq>(current: &mut T, new: T, dirty: &mut bool) {
+    if *current != new {
+        *current = new;
+        *dirty = true;
+    }
+}
+
+fn fill<D: DrawTarget<Color = Rgb565>>(
+    target: &mut D,
+    area: Rectangle,
+    color: Rgb565,
+) -> Result<(), D::Error> {
+    area.into_styled(PrimitiveStyle::with_fill(color))
+        .draw(target)
+}
+
+fn text<D: DrawTarget<Color = Rgb565>>(
+    target: &mut D,
+    text: &str,
+    position: Point,
+    color: Rgb565,
+    alignment: Alignment,
+) -> Result<(), D::Error> {
+    let style = TextStyleBuilder::new()
+        .alignment(alignment)
+        .baseline(Baseline::Middle)
+        .build();
+    Text::with_text_style(
+        text,
+        position,
+        MonoTextStyle::new(&FONT_10X20, color),
+        style,
+    )
+    .draw(target)?;
+    Ok(())
+}
+
+/// Return the points at the left and right of the text row centred vertically in `area`.
+fn row_anchors(area: Rectangle, y_offset: i32) -> (Point, Point) {
+    let y = area.top_left.y + y_offset;
+    (
+        Point::new(area.top_left.x + 4, y),
+        Point::new(area.top_left.x + area.size.width as i32 - 4, y),
+    )
+}
+
+/// Draw a horizontal bar `fraction` full.
+fn bar<D: DrawTarget<Color = Rgb565>>(
+    target: &mut D,
+    area: Rectangle,
+    fraction: f32,
+    theme: &Theme,
+) -> Result<(), D::Error> {
+    area.into_styled(PrimitiveStyle::with_stroke(theme.muted, 1))
+        .draw(target)?;
+    let inner = area.offset(-2);
+    let width = (inner.size.width as f32 * fraction.clamp(0.0, 1.0)) as u32;
+    fill(
+        target,
+        Rectangle::new(inner.top_left, Size::new(width, inner.size.height)),
+        theme.accent,
+    )?;
+    fill(
+        target,
+        Rectangle::new(
+            inner.top_left + Point::new(width as i32, 0),
+            Size::new(inner.size.width - width, inner.size.height),
+        ),
+        theme.background,
+    )
+}
+
+/// A temperature with its target and a bar showing how close it is to the maximum.
+pub struct TemperatureGauge<'a> {
+    bounds: Rectangle,
+    dirty: bool,
+    label: &'a str,
+    /// Whole degrees, as shown, so noise below the displayed precision does not cause a redraw.
+    current: i32,
+    target: i32,
+    max: f32,
+}
+
+impl<'a> TemperatureGauge<'a> {
+    /// The gauge draws a row of text over a bar so needs to be at least 40 pixels tall.
+    pub fn new(bounds: Rectangle, label: &'a str, max: f32) -> Self {
+        Self {
+            bounds,
+            dirty: true,
+            label,
+            current: 0,
+            target: 0,
+            max,
+        }
+    }
+
+    pub fn set(&mut self, current: f64, target: f64) {
+        update(
+            &mut self.current,
+            libm::round(current) as i32,
+            &mut self.dirty,
+        );
+        update(
+            &mut self.target,
+            libm::round(target) as i32,
+            &mut self.dirty,
+        );
+    }
+}
+
+impl Widget for TemperatureGauge<'_> {
+    widget_bounds!();
+
+    fn draw<D: DrawTarget<Color = Rgb565>>(
+        &mut self,
+        target: &mut D,
+        theme: &Theme,
+    ) -> Result<(), D::Error> {
+        let text_row = Rectangle::new(self.bounds.top_left, Size::new(self.bounds.size.width, 24));
+        fill(target, text_row, theme.background)?;
+        let (left, right) = row_anchors(self.bounds, 12);
+        text(target, self.label, left, theme.foreground, Alignment::Left)?;
+        let value: TextBuf<16> = TextBuf::format(format_args!("{}/{}C", self.current, self.target));
+        text(
+            target,
+            value.as_str(),
+            right,
+            theme.foreground,
+            Alignment::Right,
+        )?;
+
+        let bar_area = Rectangle::new(
+            self.bounds.top_left + Point::new(4, 26),
+            Size::new(self.bounds.size.width.saturating_sub(8), 12),
+        );
+        bar(target, bar_area, self.current as f32 / self.max, theme)?;
+        if self.target > 0 {
+            let fraction = (self.target as f32 / self.max).clamp(0.0, 1.0);
+            let x = bar_area.top_left.x + (fraction * (bar_area.size.width - 1) as f32) as i32;
+            fill(
+                target,
+                Rectangle::new(Point::new(x - 1, bar_area.top_left.y - 2), Size::new(3, 16)),
+                theme.foreground,
+            )?;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// The progress of a print with the estimated time remaining.
+pub struct ProgressBar {
+    bounds: Rectangle,
+    dirty: bool,
+    percent: u8,
+    /// Whole minutes so the widget only redraws once a minute.
+    eta_minutes: Option<u32>,
+}
+
+impl ProgressBar {
+    /// The progress bar draws a row of text over a bar so needs to be at least 44 pixels tall.
+    pub fn new(bounds: Rectangle) -> Self {
+        Self {
+            bounds,
+            dirty: true,
+            percent: 0,
+            eta_minutes: None,
+        }
+    }
+
+    pub fn set(&mut self, percent: u8, eta_seconds: Option<u32>) {
+        update(&mut self.percent, percent.min(100), &mut self.dirty);
+        update(
+            &mut self.eta_minutes,
+            eta_seconds.map(|s| s.div_ceil(60)),
+            &mut self.dirty,
+        );
+    }
+}
+
+impl Widget for ProgressBar {
+    widget_bounds!();
+
+    fn draw<D: DrawTarget<Color = Rgb565>>(
+        &mut self,
+        target: &mut D,
+        theme: &Theme,
+    ) -> Result<(), D::Error> {
+        let text_row = Rectangle::new(self.bounds.top_left, Size::new(self.bounds.size.width, 24));
+        fill(target, text_row, theme.background)?;
+        let (left, right) = row_anchors(self.bounds, 12);
+        let percent: TextBuf<8> = TextBuf::format(format_args!("{}%", self.percent));
+        text(
+            target,
+            percent.as_str(),
+            left,
+            theme.foreground,
+            Alignment::Left,
+        )?;
+        if let Some(minutes) = self.eta_minutes {
+            let eta: TextBuf<16> =
+                TextBuf::format(format_args!("{}h{:02}m", minutes / 60, minutes % 60));
+            text(
+                target,
+                eta.as_str(),
+                right,
+                theme.foreground,
+                Alignment::Right,
+            )?;
+        }
+        let bar_area = Rectangle::new(
+            self.bounds.top_left + Point::new(4, 26),
+            Size::new(self.bounds.size.width.saturating_sub(8), 16),
+        );
+        bar(target, bar_area, self.percent as f32 / 100.0, theme)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// The speed of a fan as a percentage.
+pub struct FanWidget<'a> {
+    bounds: Rectangle,
+    dirty: bool,
+    label: &'a str,
+    percent: u8,
+}
+
+impl<'a> FanWidget<'a> {
+    pub fn new(bounds: Rectangle, label: &'a str) -> Self {
+        Self {
+            bounds,
+            dirty: true,
+            label,
+            percent: 0,
+        }
+    }
+
+    pub fn set(&mut self, percent: u8) {
+        update(&mut self.percent, percent.min(100), &mut self.dirty);
+    }
+}
+
+impl Widget for FanWidget<'_> {
+    widget_bounds!();
+
+    fn draw<D: DrawTarget<Color = Rgb565>>(
+        &mut self,
+        target: &mut D,
+        theme: &Theme,
+    ) -> Result<(), D::Error> {
+        fill(target, self.bounds, theme.background)?;
+        let (left, right) = row_anchors(self.bounds, self.bounds.size.height as i32 / 2);
+        text(target, self.label, left, theme.foreground, Alignment::Left)?;
+        let percent: TextBuf<8> = TextBuf::format(format_args!("{}%", self.percent));
+        text(
+            target,
+            percent.as_str(),
+            right,
+            theme.foreground,
+            Alignment::Right,
+        )?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// The position of the X, Y and Z axes.
+pub struct AxisReadout {
+    bounds: Rectangle,
+    dirty: bool,
+    /// Tenths of a millimetre for X and Y and hundredths for Z, as shown, so jitter that is not shown does not cause
+    /// a redraw.
+    position: [i32; 3],
+}
+
+impl AxisReadout {
+    pub fn new(bounds: Rectangle) -> Self {
+        Self {
+            bounds,
+            dirty: true,
+            position: [0; 3],
+        }
+    }
+
+    pub fn set(&mut self, x: f32, y: f32, z: f32) {
+        let position = [
+            libm::roundf(x * 10.0) as i32,
+            libm::roundf(y * 10.0) as i32,
+            libm::roundf(z * 100.0) as i32,
+        ];
+        update(&mut self.position, position, &mut self.dirty);
+    }
+}
+
+impl Widget for AxisReadout {
+    widget_bounds!();
+
+    fn draw<D: DrawTarget<Color = Rgb565>>(
+        &mut self,
+        target: &mut D,
+        theme: &Theme,
+    ) -> Result<(), D::Error> {
+        fill(target, self.bounds, theme.background)?;
+        let [x, y, z] = self.position;
+        let (x, y, z) = (x as f32 / 10.0, y as f32 / 10.0, z as f32 / 100.0);
+        let readout: TextBuf<32> = TextBuf::format(format_args!("X{:.1} Y{:.1} Z{:.2}", x, y, z));
+        let (left, _) = row_anchors(self.bounds, self.bounds.size.height as i32 / 2);
+        text(
+            target,
+            readout.as_str(),
+            left,
+            theme.foreground,
+            Alignment::Left,
+        )?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// Whether filament is loaded.
+pub struct FilamentStatus {
+    bounds: Rectangle,
+    dirty: bool,
+    present: bool,
+}
+
+impl FilamentStatus {
+    pub fn new(bounds: Rectangle) -> Self {
+        Self {
+            bounds,
+            dirty: true,
+            present: false,
+        }
+    }
+
+    pub fn set(&mut self, present: bool) {
+        update(&mut self.present, present, &mut self.dirty);
+    }
+}
+
+impl Widget for FilamentStatus {
+    widget_bounds!();
+
+    fn draw<D: DrawTarget<Color = Rgb565>>(
+        &mut self,
+        target: &mut D,
+        theme: &Theme,
+    ) -> Result<(), D::Error> {
+        fill(target, self.bounds, theme.background)?;
+        let color = if self.present { theme.ok } else { theme.fault };
+        // A spool drawn as a ring around its hub.
+        let diameter = self.bounds.size.height.saturating_sub(4).max(6);
+        let spool = Circle::new(self.bounds.top_left + Point::new(4, 2), diameter);
+        spool
+            .into_styled(PrimitiveStyle::with_stroke(color, 3))
+            .draw(target)?;
+        Circle::with_center(spool.center(), diameter / 3)
+            .into_styled(PrimitiveStyle::with_fill(color))
+            .draw(target)?;
+        let (mut left, _) = row_anchors(self.bounds, self.bounds.size.height as i32 / 2);
+        left.x += diameter as i32 + 6;
+        let label = if self.present {
+            "Filament"
+        } else {
+            "No filament"
+        };
+        text(target, label, left, theme.foreground, Alignment::Left)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// The network link state and IP address.
+pub struct NetworkBar {
+    bounds: Rectangle,
+    dirty: bool,
+    link_up: bool,
+    address: Option<[u8; 4]>,
+}
+
+impl NetworkBar {
+    pub fn new(bounds: Rectangle) -> Self {
+        Self {
+            bounds,
+            dirty: true,
+            link_up: false,
+            address: None,
+        }
+    }
+
+    pub fn set(&mut self, link_up: bool, address: Option<[u8; 4]>) {
+        update(&mut self.link_up, link_up, &mut self.dirty);
+        update(&mut self.address, address, &mut self.dirty);
+    }
+}
+
+impl Widget for NetworkBar {
+    widget_bounds!();
+
+    fn draw<D: DrawTarget<Color = Rgb565>>(
+        &mut self,
+        target: &mut D,
+        theme: &Theme,
+    ) -> Result<(), D::Error> {
+        fill(target, self.bounds, theme.muted)?;
+        let (left, right) = row_anchors(self.bounds, self.bounds.size.height as i32 / 2);
+        let status: TextBuf<24> = match (self.link_up, self.address) {
+            (true, Some([a, b, c, d])) => TextBuf::format(format_args!("{}.{}.{}.{}", a, b, c, d)),
+            (true, None) => TextBuf::format(format_args!("Connecting")),
+            (false, _) => TextBuf::format(format_args!("No network")),
+        };
+        text(
+            target,
+            status.as_str(),
+            left,
+            theme.foreground,
+            Alignment::Left,
+        )?;
+        let color = if self.link_up { theme.ok } else { theme.fault };
+        let dot = Circle::with_center(right - Point::new(6, 0), 10);
+        dot.into_styled(PrimitiveStyle::with_fill(color))
+            .draw(target)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// A banner reporting a fault. Nothing is shown when there is no fault.
+pub struct FaultBanner<'a> {
+    bounds: Rectangle,
+    dirty: bool,
+    message: Option<&'a str>,
+}
+
+impl<'a> FaultBanner<'a> {
+    pub fn new(bounds: Rectangle) -> Self {
+        Self {
+            bounds,
+            dirty: true,
+            message: None,
+        }
+    }
+
+    pub fn set(&mut self, message: Option<&'a str>) {
+        update(&mut self.message, message, &mut self.dirty);
+    }
+}
+
+impl Widget for FaultBanner<'_> {
+    widget_bounds!();
+
+    fn draw<D: DrawTarget<Color = Rgb565>>(
+        &mut self,
+        target: &mut D,
+        theme: &Theme,
+    ) -> Result<(), D::Error> {
+        match self.message {
+            Some(message) => {
+                fill(target, self.bounds, theme.fault)?;
+                text(
+                    target,
+                    message,
+                    self.bounds.center(),
+                    theme.foreground,
+                    Alignment::Center,
+                )?;
+            }
+            None => fill(target, self.bounds, theme.background)?,
+        }
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// The printer status screen laid out for the 240x320 display.
+pub struct StatusScreen<'a> {
+    pub theme: Theme,
+    pub network: NetworkBar,
+    pub hotend: TemperatureGauge<'a>,
+    pub bed: TemperatureGauge<'a>,
+    pub fan: FanWidget<'a>,
+    pub axes: AxisReadout,
+    pub filament: FilamentStatus,
+    pub progress: ProgressBar,
+    pub fault: FaultBanner<'a>,
+}
+
+impl StatusScreen<'_> {
+    pub fn new() -> Self {
+        let row = |y: i32, height: u32| Rectangle::new(Point::new(0, y), Size::new(240, height));
+        Self {
+            theme: Theme::default(),
+            network: NetworkBar::new(row(0, 24)),
+            hotend: TemperatureGauge::new(row(30, 40), "Nozzle", 300.0),
+            bed: TemperatureGauge::new(row(76, 40), "Bed", 120.0),
+            fan: FanWidget::new(row(122, 24), "Fan"),
+            axes: AxisReadout::new(row(152, 24)),
+            filament: FilamentStatus::new(row(182, 24)),
+            progress: ProgressBar::new(row(214, 44)),
+            fault: FaultBanner::new(row(290, 30)),
+        }
+    }
+
+    /// Redraw every widget on the next draw.
+    pub fn invalidate(&mut self) {
+        self.network.invalidate();
+        self.hotend.invalidate();
+        self.bed.invalidate();
+        self.fan.invalidate();
+        self.axes.invalidate();
+        self.filament.invalidate();
+        self.progress.invalidate();
+        self.fault.invalidate();
+    }
+
+    /// Draw the widgets that have changed, returning how many were drawn.
+    pub fn draw<D: DrawTarget<Color = Rgb565>>(
+        &mut self,
+        target: &mut D,
+    ) -> Result<usize, D::Error> {
+        let theme = self.theme;
+        let drawn = [
+            self.network.redraw(target, &theme)?,
+            self.hotend.redraw(target, &theme)?,
+            self.bed.redraw(target, &theme)?,
+            self.fan.redraw(target, &theme)?,
+            self.axes.redraw(target, &theme)?,
+            self.filament.redraw(target, &theme)?,
+            self.progress.redraw(target, &theme)?,
+            self.fault.redraw(target, &theme)?,
+        ];
+        Ok(drawn.iter().filter(|d| **d).count())
+    }
+}
+
+impl Default for StatusScreen<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::mock_display::MockDisplay;
+
+    use super::*;
+    use crate::mock::{Window, assert_snapshot, strips};
+
+    /// Colours that the mock display can print.
+    const THEME: Theme = Theme {
+        background: Rgb565::BLACK,
+        foreground: Rgb565::WHITE,
+        accent: Rgb565::YELLOW,
+        muted: Rgb565::BLUE,
+        ok: Rgb565::GREEN,
+        fault: Rgb565::RED,
+    };
+
+    /// A full width row of the status screen.
+    fn row(height: u32) -> Rectangle {
+        Rectangle::new(Point::zero(), Size::new(240, height))
+    }
+
+    fn render(widget: &mut impl Widget) -> Vec<MockDisplay<Rgb565>> {
+        let size = widget.bounds().size;
+        strips(size, |window| widget.draw(window, &THEME).unwrap())
+    }
+
+    #[test]
+    fn temperature_gauge() {
+        let mut gauge = TemperatureGauge::new(row(40), "Nozzle", 300.0);
+        gauge.set(75.0, 215.0);
+        assert_snapshot(&render(&mut gauge), "temperature_gauge");
+    }
+
+    #[test]
+    fn temperature_gauge_without_a_target() {
+        let mut gauge = TemperatureGauge::new(row(40), "Bed", 120.0);
+        gauge.set(140.0, 0.0);
+        assert_snapshot(&render(&mut gauge), "temperature_gauge_off");
+    }
+
+    #[test]
+    fn progress_bar() {
+        let mut progress = ProgressBar::new(row(44));
+        progress.set(40, Some(3_601));
+        assert_snapshot(&render(&mut progress), "progress_bar");
+    }
+
+    #[test]
+    fn fan() {
+        let mut fan = FanWidget::new(row(24), "Fan");
+        fan.set(150);
+        assert_snapshot(&render(&mut fan), "fan");
+    }
+
+    #[test]
+    fn axis_readout() {
+        let mut axes = AxisReadout::new(row(24));
+        axes.set(12.5, -3.0, 0.2);
+        assert_snapshot(&render(&mut axes), "axis_readout");
+    }
+
+    #[test]
+    fn filament_status() {
+        let mut filament = FilamentStatus::new(row(24));
+        filament.set(true);
+        let present = render(&mut filament);
+        filament.set(false);
+        let absent = render(&mut filament);
+        assert_snapshot(&present, "filament_present");
+        assert_snapshot(&absent, "filament_absent");
+    }
+
+    #[test]
+    fn network_bar() {
+        let mut network = NetworkBar::new(row(24));
+        network.set(true, Some([192, 168, 1, 20]));
+        assert_snapshot(&render(&mut network), "network_up");
+        network.set(false, None);
+        assert_snapshot(&render(&mut network), "network_down");
+    }
+
+    #[test]
+    fn fault_banner() {
+        let mut fault = FaultBanner::new(row(30));
+        fault.set(Some("Thermal runaway"));
+        assert_snapshot(&render(&mut fault), "fault_banner");
+        fault.set(None);
+        assert_snapshot(&render(&mut fault), "fault_banner_clear");
+    }
+
+    #[test]
+    fn redraws_only_when_the_shown_value_changes() {
+        let mut display = MockDisplay::new();
+        let mut window = Window::new(&mut display, Size::new(240, 320), Point::zero());
+        let mut gauge = TemperatureGauge::new(row(40), "Bed", 120.0);
+        gauge.set(60.0, 60.0);
+        assert!(gauge.redraw(&mut window, &THEME).unwrap());
+        assert!(!gauge.redraw(&mut window, &THEME).unwrap());
+        // Whole degrees are shown, so noise around them is not redrawn.
+        gauge.set(60.2, 60.0);
+        assert!(!gauge.is_dirty());
+        gauge.set(59.7, 60.0);
+        assert!(!gauge.is_dirty());
+        gauge.set(60.6, 60.0);
+        assert!(gauge.redraw(&mut window, &THEME).unwrap());
+        gauge.invalidate();
+        assert!(gauge.redraw(&mut window, &THEME).unwrap());
+
+        let mut axes = AxisReadout::new(row(24));
+        axes.set(10.0, 20.0, 0.2);
+        axes.redraw(&mut window, &THEME).unwrap();
+        // X and Y are shown to a tenth and Z to a hundredth of a millimetre.
+        axes.set(10.01, 19.97, 0.204);
+        assert!(!axes.is_dirty());
+        axes.set(10.0, 20.0, 0.21);
+        assert!(axes.redraw(&mut window, &THEME).unwrap());
+        axes.set(10.06, 20.0, 0.21);
+        assert!(axes.is_dirty());
+
+        let mut progress = ProgressBar::new(row(44));
+        progress.set(10, Some(600));
+        progress.redraw(&mut window, &THEME).unwrap();
+        // The ETA is shown in whole minutes.
+        progress.set(10, Some(570));
+        assert!(!progress.is_dirty());
+        progress.set(10, Some(530));
+        assert!(progress.is_dirty());
+    }
+
+    #[test]
+    fn status_screen_draws_only_changed_widgets() {
+        let mut display = MockDisplay::new();
+        let mut window = Window::new(&mut display, Size::new(240, 320), Point::zero());
+        let mut screen = StatusScreen::new();
+        assert_eq!(screen.draw(&mut window).unwrap(), 8);
+        assert_eq!(screen.draw(&mut window).unwrap(), 0);
+        screen.fan.set(50);
+        screen.filament.set(true);
+        assert_eq!(screen.draw(&mut window).unwrap(), 2);
+        screen.invalidate();
+        assert_eq!(screen.draw(&mut window).unwrap(), 8);
+    }
+}